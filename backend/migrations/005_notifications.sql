@@ -0,0 +1,30 @@
+-- Notifications: categories, per-user targeting and delivery channels
+
+-- NULL user_id means the notification is addressed to administrators
+ALTER TABLE notifications ADD COLUMN category TEXT NOT NULL DEFAULT 'system';
+ALTER TABLE notifications ADD COLUMN user_id TEXT REFERENCES users(id) ON DELETE CASCADE;
+
+-- Delivery channels (email, webhook, push)
+CREATE TABLE IF NOT EXISTS notification_channels (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT UNIQUE NOT NULL,
+    channel_type TEXT NOT NULL CHECK(channel_type IN ('smtp', 'webhook', 'ntfy', 'gotify')),
+    config TEXT NOT NULL, -- JSON configuration for the channel type
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Routing rules: which categories go to which channel
+CREATE TABLE IF NOT EXISTS notification_rules (
+    id TEXT PRIMARY KEY NOT NULL,
+    channel_id TEXT NOT NULL REFERENCES notification_channels(id) ON DELETE CASCADE,
+    category TEXT NOT NULL, -- '*' matches every category
+    min_level TEXT NOT NULL DEFAULT 'info' CHECK(min_level IN ('info', 'success', 'warning', 'error')),
+    created_at TEXT NOT NULL,
+    UNIQUE(channel_id, category)
+);
+
+CREATE INDEX IF NOT EXISTS idx_notifications_user_id ON notifications(user_id);
+CREATE INDEX IF NOT EXISTS idx_notifications_category ON notifications(category);
+CREATE INDEX IF NOT EXISTS idx_notification_rules_category ON notification_rules(category);
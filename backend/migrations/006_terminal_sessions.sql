@@ -0,0 +1,24 @@
+-- Interactive terminal sessions audit
+
+-- One row per PTY session, ended_at is NULL while the shell is running
+CREATE TABLE IF NOT EXISTS terminal_sessions (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL,
+    username TEXT NOT NULL,
+    shell TEXT NOT NULL,
+    cwd TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    ended_at TEXT,
+    end_reason TEXT -- 'exit', 'idle_timeout', 'disconnected', 'closed', 'shutdown'
+);
+
+-- Command lines typed in a session, as assembled from the input stream
+CREATE TABLE IF NOT EXISTS terminal_commands (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL REFERENCES terminal_sessions(id) ON DELETE CASCADE,
+    command TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_terminal_sessions_user_id ON terminal_sessions(user_id);
+CREATE INDEX IF NOT EXISTS idx_terminal_commands_session_id ON terminal_commands(session_id);
@@ -0,0 +1,4 @@
+-- Map PiNAS accounts to system accounts used to run their commands
+
+-- NULL means the system account has the same name as the PiNAS user
+ALTER TABLE users ADD COLUMN system_user TEXT;
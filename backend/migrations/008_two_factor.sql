@@ -0,0 +1,25 @@
+-- TOTP two-factor authentication
+
+-- One TOTP secret per user, enabled once the first code was verified
+CREATE TABLE IF NOT EXISTS user_totp (
+    user_id TEXT PRIMARY KEY NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    secret TEXT NOT NULL, -- base32 encoded
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    last_used_step INTEGER NOT NULL DEFAULT 0, -- prevents replaying a code
+    created_at TEXT NOT NULL,
+    confirmed_at TEXT
+);
+
+-- One-time recovery codes, stored as SHA-256 hashes
+CREATE TABLE IF NOT EXISTS user_recovery_codes (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at TEXT,
+    created_at TEXT NOT NULL
+);
+
+-- Members of a group with require_mfa must use two-factor authentication
+ALTER TABLE user_groups ADD COLUMN require_mfa BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_user_recovery_codes_user_id ON user_recovery_codes(user_id);
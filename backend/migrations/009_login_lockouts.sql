@@ -0,0 +1,24 @@
+-- Brute-force protection on login
+
+-- Failed login attempts, kept for the admin view
+CREATE TABLE IF NOT EXISTS login_failures (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    reason TEXT NOT NULL, -- 'password', 'mfa'
+    created_at TEXT NOT NULL
+);
+
+-- Consecutive failure counters per username and per client IP
+CREATE TABLE IF NOT EXISTS login_lockouts (
+    id TEXT PRIMARY KEY NOT NULL,
+    kind TEXT NOT NULL, -- 'username', 'ip'
+    value TEXT NOT NULL,
+    failures INTEGER NOT NULL DEFAULT 0,
+    last_failure_at TEXT NOT NULL,
+    locked_until TEXT, -- set once the failure threshold is reached
+    UNIQUE(kind, value)
+);
+
+CREATE INDEX IF NOT EXISTS idx_login_failures_created_at ON login_failures(created_at);
+CREATE INDEX IF NOT EXISTS idx_login_lockouts_locked_until ON login_lockouts(locked_until);
@@ -0,0 +1,18 @@
+-- Sessions become refresh token families
+-- Access tokens are short-lived JWTs carrying the session id, refresh tokens rotate on use.
+-- Existing sessions stored bare JWTs that are no longer accepted, so they are dropped.
+
+DROP TABLE IF EXISTS sessions;
+
+CREATE TABLE sessions (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    refresh_token_hash TEXT UNIQUE NOT NULL, -- SHA-256 of the current refresh token secret
+    ip TEXT,
+    user_agent TEXT,
+    expires_at TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    last_used_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_user_id ON sessions(user_id);
@@ -0,0 +1,15 @@
+-- Personal API tokens for scripts and integrations
+
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT UNIQUE NOT NULL, -- SHA-256 of the token, shown only once at creation
+    token_prefix TEXT NOT NULL, -- first characters, to recognise a token in the list
+    scopes TEXT NOT NULL, -- space-separated, e.g. "files:read docker:write"
+    expires_at TEXT,
+    last_used_at TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_tokens_user_id ON api_tokens(user_id);
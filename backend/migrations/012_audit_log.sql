@@ -0,0 +1,35 @@
+-- Append-only record of administrative and security-relevant actions
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    actor_id TEXT, -- no foreign key, entries outlive deleted users
+    actor TEXT, -- username, or the submitted one for logins
+    action TEXT NOT NULL, -- e.g. 'users.delete', 'docker.stop'
+    target TEXT,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    ip TEXT,
+    status INTEGER NOT NULL,
+    outcome TEXT NOT NULL, -- 'success', 'failure', 'denied'
+    details TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor ON audit_log(actor);
+CREATE INDEX IF NOT EXISTS idx_audit_log_action ON audit_log(action);
+
+-- Entries are never modified
+CREATE TRIGGER IF NOT EXISTS audit_log_no_update
+BEFORE UPDATE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
+
+-- Only the retention policy removes entries, never recent ones
+CREATE TRIGGER IF NOT EXISTS audit_log_no_recent_delete
+BEFORE DELETE ON audit_log
+WHEN OLD.created_at > strftime('%Y-%m-%dT%H:%M:%S', 'now', '-1 day')
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
@@ -0,0 +1,12 @@
+-- LDAP directory users and groups
+
+-- Where a user authenticates: 'local' (Argon2 hash) or 'ldap' (bind as external_id)
+ALTER TABLE users ADD COLUMN auth_source TEXT NOT NULL DEFAULT 'local';
+ALTER TABLE users ADD COLUMN external_id TEXT; -- directory DN
+
+-- Groups synced from a directory cannot be edited locally
+ALTER TABLE user_groups ADD COLUMN external_source TEXT; -- NULL for local groups, 'ldap'
+ALTER TABLE user_groups ADD COLUMN external_id TEXT; -- directory DN
+
+CREATE INDEX IF NOT EXISTS idx_users_external_id ON users(auth_source, external_id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_user_groups_external_id ON user_groups(external_source, external_id);
@@ -0,0 +1,51 @@
+-- OpenID Connect single sign-on
+
+CREATE TABLE IF NOT EXISTS oidc_providers (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT UNIQUE NOT NULL, -- shown on the login page
+    issuer TEXT NOT NULL,
+    client_id TEXT NOT NULL,
+    client_secret TEXT, -- NULL for public clients
+    token_auth_method TEXT NOT NULL DEFAULT 'client_secret_basic', -- or 'client_secret_post'
+    scopes TEXT NOT NULL DEFAULT 'openid profile email',
+    username_claim TEXT NOT NULL DEFAULT 'preferred_username',
+    groups_claim TEXT NOT NULL DEFAULT 'groups',
+    group_mapping TEXT NOT NULL DEFAULT '{}', -- JSON object, claim value -> PiNAS group name
+    admin_values TEXT NOT NULL DEFAULT '[]', -- JSON array of claim values granting admin
+    allow_provisioning BOOLEAN NOT NULL DEFAULT TRUE,
+    link_by_email BOOLEAN NOT NULL DEFAULT FALSE,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Accounts at a provider linked to a PiNAS user, by subject
+CREATE TABLE IF NOT EXISTS user_identities (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider_id TEXT NOT NULL REFERENCES oidc_providers(id) ON DELETE CASCADE,
+    subject TEXT NOT NULL,
+    email TEXT,
+    created_at TEXT NOT NULL,
+    last_login_at TEXT,
+    UNIQUE(provider_id, subject)
+);
+
+-- Authorization requests waiting for their callback, used once
+CREATE TABLE IF NOT EXISTS oidc_login_states (
+    state TEXT PRIMARY KEY NOT NULL,
+    provider_id TEXT NOT NULL REFERENCES oidc_providers(id) ON DELETE CASCADE,
+    code_verifier TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    redirect_uri TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+);
+
+-- One-time tickets handing a completed sign-in to the web interface, stored as SHA-256 hashes
+CREATE TABLE IF NOT EXISTS oidc_login_tickets (
+    ticket_hash TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_identities_user_id ON user_identities(user_id);
@@ -0,0 +1,5 @@
+-- Dependencies installed along with a package run as child tasks of its install task
+
+ALTER TABLE package_tasks ADD COLUMN parent_task_id TEXT REFERENCES package_tasks(id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS idx_package_tasks_parent ON package_tasks(parent_task_id);
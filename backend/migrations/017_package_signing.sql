@@ -0,0 +1,10 @@
+-- Public keys trusted to sign package catalogs and manifests
+
+CREATE TABLE IF NOT EXISTS package_signing_keys (
+    id TEXT PRIMARY KEY NOT NULL, -- start of the SHA-256 fingerprint of the key
+    name TEXT NOT NULL,
+    public_key TEXT NOT NULL, -- base64 raw ed25519 public key
+    created_at TEXT NOT NULL,
+    expires_at TEXT, -- no longer trusted after this date
+    revoked_at TEXT
+);
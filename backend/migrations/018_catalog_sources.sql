@@ -0,0 +1,27 @@
+-- Catalogs apps are installed from, merged by priority
+
+CREATE TABLE IF NOT EXISTS catalog_sources (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    url TEXT NOT NULL,
+    priority INTEGER NOT NULL DEFAULT 0, -- the highest priority wins when sources list the same app
+    enabled INTEGER NOT NULL DEFAULT 1,
+    etag TEXT, -- validators of the cached copy, for conditional refreshes
+    last_modified TEXT,
+    fetched_at TEXT, -- last time the source was fetched or revalidated
+    last_error TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- PINAS_CATALOG_URL, when set, overrides the URL of this source
+INSERT OR IGNORE INTO catalog_sources (id, name, url, priority, enabled, created_at, updated_at)
+VALUES (
+    'official',
+    'PiNAS catalog',
+    'https://raw.githubusercontent.com/kameka22/pinas-app-catalog/master/catalog.json',
+    0,
+    1,
+    strftime('%Y-%m-%dT%H:%M:%SZ', 'now'),
+    strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+);
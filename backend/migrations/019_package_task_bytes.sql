@@ -0,0 +1,4 @@
+-- Byte progress of the download a package task is running, NULL between downloads
+
+ALTER TABLE package_tasks ADD COLUMN bytes_done INTEGER;
+ALTER TABLE package_tasks ADD COLUMN bytes_total INTEGER; -- NULL when the server does not announce a size
@@ -0,0 +1,45 @@
+-- Cancellable package tasks, and the output of their steps streamed to clients as it is written
+
+-- SQLite cannot alter a CHECK constraint, the table is rebuilt to accept the 'cancelled' status.
+-- Renaming first keeps the parent_task_id references of the copied rows out of the drop's cascade.
+ALTER TABLE package_tasks RENAME TO package_tasks_old;
+
+CREATE TABLE package_tasks (
+    id TEXT PRIMARY KEY NOT NULL,
+    package_id TEXT NOT NULL,
+    task_type TEXT NOT NULL CHECK(task_type IN ('install', 'update', 'uninstall')),
+    status TEXT NOT NULL DEFAULT 'pending' CHECK(status IN ('pending', 'running', 'completed', 'failed', 'cancelled')),
+    progress INTEGER DEFAULT 0,
+    total_steps INTEGER DEFAULT 0,
+    current_step TEXT,
+    error_message TEXT,
+    started_at TEXT,
+    completed_at TEXT,
+    created_at TEXT NOT NULL,
+    parent_task_id TEXT REFERENCES package_tasks(id) ON DELETE CASCADE,
+    bytes_done INTEGER,
+    bytes_total INTEGER
+);
+
+INSERT INTO package_tasks (id, package_id, task_type, status, progress, total_steps, current_step, error_message,
+                           started_at, completed_at, created_at, parent_task_id, bytes_done, bytes_total)
+SELECT id, package_id, task_type, status, progress, total_steps, current_step, error_message,
+       started_at, completed_at, created_at, parent_task_id, bytes_done, bytes_total
+FROM package_tasks_old;
+
+DROP TABLE package_tasks_old;
+
+CREATE INDEX IF NOT EXISTS idx_package_tasks_package_id ON package_tasks(package_id);
+CREATE INDEX IF NOT EXISTS idx_package_tasks_status ON package_tasks(status);
+CREATE INDEX IF NOT EXISTS idx_package_tasks_parent ON package_tasks(parent_task_id);
+
+CREATE TABLE IF NOT EXISTS package_task_logs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    task_id TEXT NOT NULL REFERENCES package_tasks(id) ON DELETE CASCADE,
+    step INTEGER, -- index of the step that wrote the line, NULL for task messages
+    stream TEXT NOT NULL, -- 'stdout', 'stderr' or 'info'
+    line TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_package_task_logs_task ON package_task_logs(task_id, id);
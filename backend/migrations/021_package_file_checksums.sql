@@ -0,0 +1,21 @@
+-- Files and directories created by install steps, with the checksum files had once written
+
+-- SQLite cannot alter a CHECK constraint, the table is rebuilt to accept directories
+ALTER TABLE package_files RENAME TO package_files_old;
+
+CREATE TABLE package_files (
+    id INTEGER PRIMARY KEY AUTOINCREMENT, -- creation order, files are removed in reverse
+    package_id TEXT NOT NULL REFERENCES installed_packages(id) ON DELETE CASCADE,
+    path TEXT NOT NULL,
+    file_type TEXT NOT NULL CHECK(file_type IN ('binary', 'config', 'data', 'symlink', 'service', 'directory')),
+    sha256 TEXT, -- NULL for directories and symlinks
+    created_at TEXT NOT NULL,
+    UNIQUE(package_id, path)
+);
+
+INSERT OR IGNORE INTO package_files (id, package_id, path, file_type, created_at)
+SELECT id, package_id, path, file_type, created_at FROM package_files_old;
+
+DROP TABLE package_files_old;
+
+CREATE INDEX IF NOT EXISTS idx_package_files_package_id ON package_files(package_id);
@@ -0,0 +1,63 @@
+-- Integrity verification of installed packages, and repair tasks restoring what drifted
+
+-- SQLite cannot alter a CHECK constraint, the tasks table is rebuilt to accept the 'repair' type.
+-- Migrations run with foreign keys enforced: renaming would point the logs at the old table and
+-- dropping would cascade, so both tables go through plain copies instead.
+CREATE TABLE package_tasks_copy AS SELECT * FROM package_tasks;
+CREATE TABLE package_task_logs_copy AS SELECT * FROM package_task_logs;
+
+DROP TABLE package_task_logs;
+DROP TABLE package_tasks;
+
+CREATE TABLE package_tasks (
+    id TEXT PRIMARY KEY NOT NULL,
+    package_id TEXT NOT NULL,
+    task_type TEXT NOT NULL CHECK(task_type IN ('install', 'update', 'uninstall', 'repair')),
+    status TEXT NOT NULL DEFAULT 'pending' CHECK(status IN ('pending', 'running', 'completed', 'failed', 'cancelled')),
+    progress INTEGER DEFAULT 0,
+    total_steps INTEGER DEFAULT 0,
+    current_step TEXT,
+    error_message TEXT,
+    started_at TEXT,
+    completed_at TEXT,
+    created_at TEXT NOT NULL,
+    parent_task_id TEXT REFERENCES package_tasks(id) ON DELETE CASCADE,
+    bytes_done INTEGER,
+    bytes_total INTEGER
+);
+
+CREATE TABLE package_task_logs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    task_id TEXT NOT NULL REFERENCES package_tasks(id) ON DELETE CASCADE,
+    step INTEGER, -- index of the step that wrote the line, NULL for task messages
+    stream TEXT NOT NULL, -- 'stdout', 'stderr' or 'info'
+    line TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Child tasks may be copied before their parent
+PRAGMA defer_foreign_keys = ON;
+
+INSERT INTO package_tasks (id, package_id, task_type, status, progress, total_steps, current_step, error_message,
+                           started_at, completed_at, created_at, parent_task_id, bytes_done, bytes_total)
+SELECT id, package_id, task_type, status, progress, total_steps, current_step, error_message,
+       started_at, completed_at, created_at, parent_task_id, bytes_done, bytes_total
+FROM package_tasks_copy;
+
+INSERT INTO package_task_logs (id, task_id, step, stream, line, created_at)
+SELECT id, task_id, step, stream, line, created_at FROM package_task_logs_copy;
+
+DROP TABLE package_task_logs_copy;
+DROP TABLE package_tasks_copy;
+
+CREATE INDEX IF NOT EXISTS idx_package_tasks_package_id ON package_tasks(package_id);
+CREATE INDEX IF NOT EXISTS idx_package_tasks_status ON package_tasks(status);
+CREATE INDEX IF NOT EXISTS idx_package_tasks_parent ON package_tasks(parent_task_id);
+CREATE INDEX IF NOT EXISTS idx_package_task_logs_task ON package_task_logs(task_id, id);
+
+-- Index of the install step that created a file, the one to run again to restore it
+ALTER TABLE package_files ADD COLUMN step INTEGER;
+
+-- Last verification of a package, JSON report, to only notify about new drift
+ALTER TABLE installed_packages ADD COLUMN verify_report TEXT;
+ALTER TABLE installed_packages ADD COLUMN verified_at TEXT;
@@ -0,0 +1,7 @@
+-- The terminal audit records raw input lines, not the commands the shell ran
+
+ALTER TABLE terminal_commands RENAME TO terminal_input;
+ALTER TABLE terminal_input RENAME COLUMN command TO input;
+
+DROP INDEX IF EXISTS idx_terminal_commands_session_id;
+CREATE INDEX IF NOT EXISTS idx_terminal_input_session_id ON terminal_input(session_id);
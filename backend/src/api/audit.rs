@@ -0,0 +1,221 @@
+use axum::{
+    extract::{MatchedPath, Query, Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::api::middleware::{authenticate_token, AdminUser, ClientIp};
+use crate::models::audit::AuditEntry;
+use crate::services::audit::{
+    describe, list_entries, record, to_csv, AuditError, AuditFilter, NewAuditEntry, EXPORT_LIMIT,
+};
+use crate::services::auth::extract_bearer_token;
+use crate::AppState;
+
+/// Mutating routes not worth recording
+const UNAUDITED_ROUTES: &[&str] = &["/api/auth/refresh"];
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_audit_log))
+        .route("/export", get(export_audit_log))
+}
+
+/// Attached to a response by a handler to complete its audit entry
+#[derive(Debug, Clone, Default)]
+pub struct AuditNote {
+    /// Actor of unauthenticated requests, such as the username of a login
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub details: Option<String>,
+}
+
+/// Record every mutating request once it has been handled
+pub async fn record_request(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().clone();
+    if matches!(method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+
+    let path = request.uri().path().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| path.clone());
+    if UNAUDITED_ROUTES.contains(&route.as_str()) {
+        return next.run(request).await;
+    }
+
+    // Resolved before the handler runs, a password change may revoke the session
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(extract_bearer_token)
+        .map(str::to_string);
+    let actor = match token {
+        Some(token) => authenticate_token(&state, &token).await.ok(),
+        None => None,
+    };
+
+    let response = next.run(request).await;
+
+    let note = response.extensions().get::<AuditNote>().cloned().unwrap_or_default();
+    let (action, target) = describe(method.as_str(), &route, &path);
+    let entry = NewAuditEntry {
+        actor_id: actor.as_ref().map(|a| a.id.clone()),
+        actor: actor.map(|a| a.username).or(note.actor),
+        action,
+        target: note.target.or(target),
+        method: method.to_string(),
+        path,
+        ip: Some(ip),
+        status: response.status().as_u16(),
+        details: note.details,
+    };
+    if let Err(e) = record(&state.db, &entry).await {
+        tracing::error!("Failed to record audit entry {}: {}", entry.action, e);
+    }
+
+    response
+}
+
+/// Paging of the list, read next to the filter from the query string
+#[derive(Debug, Deserialize)]
+pub struct PageQuery {
+    #[serde(default = "default_page")]
+    pub page: i64,
+    #[serde(default = "default_per_page")]
+    pub per_page: i64,
+}
+
+fn default_page() -> i64 {
+    1
+}
+
+fn default_per_page() -> i64 {
+    50
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntry>,
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub code: String,
+}
+
+impl From<AuditError> for (StatusCode, Json<ErrorResponse>) {
+    fn from(err: AuditError) -> Self {
+        let (status, code) = match &err {
+            AuditError::InvalidFilter(_) => (StatusCode::BAD_REQUEST, "INVALID_FILTER"),
+            AuditError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR"),
+        };
+
+        (
+            status,
+            Json(ErrorResponse {
+                error: err.to_string(),
+                code: code.to_string(),
+            }),
+        )
+    }
+}
+
+/// List audit entries, newest first (admin only)
+async fn list_audit_log(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Query(filter): Query<AuditFilter>,
+    Query(query): Query<PageQuery>,
+) -> impl IntoResponse {
+    let page = query.page.max(1);
+    let per_page = query.per_page.clamp(1, 500);
+
+    match list_entries(&state.db, &filter, per_page, (page - 1) * per_page).await {
+        Ok((entries, total)) => (
+            StatusCode::OK,
+            Json(AuditPage {
+                entries,
+                total,
+                page,
+                per_page,
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list audit log: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Export matching audit entries as JSON or CSV (admin only)
+async fn export_audit_log(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Query(filter): Query<AuditFilter>,
+    Query(query): Query<ExportQuery>,
+) -> impl IntoResponse {
+    let entries = match list_entries(&state.db, &filter, EXPORT_LIMIT, 0).await {
+        Ok((entries, _)) => entries,
+        Err(e) => {
+            tracing::error!("Failed to export audit log: {}", e);
+            let (status, json) = e.into();
+            return (status, json).into_response();
+        }
+    };
+
+    let (content_type, extension, body) = match query.format {
+        ExportFormat::Json => (
+            "application/json",
+            "json",
+            serde_json::to_string_pretty(&entries).unwrap_or_default(),
+        ),
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv", to_csv(&entries)),
+    };
+
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"audit-log.{}\"", extension),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
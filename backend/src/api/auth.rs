@@ -1,25 +1,63 @@
 use axum::{
-    extract::State,
-    http::{header::AUTHORIZATION, StatusCode},
-    response::IntoResponse,
-    routing::{get, post},
-    Json, Router,
+    extract::{Path, Query, State},
+    http::{
+        header::{AUTHORIZATION, HOST, RETRY_AFTER},
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Redirect},
+    routing::{delete, get, post},
+    Extension, Json, Router,
 };
 use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::api::middleware::{AuthErrorResponse, AuthUser};
-use crate::services::auth::{extract_bearer_token, generate_jwt, verify_password, AuthError};
-use crate::services::session::{create_session, delete_session};
-use crate::services::user::{change_password as change_user_password, get_user_by_id, get_user_by_username};
+use crate::api::audit::AuditNote;
+use crate::api::middleware::{
+    AdminUser, AuthErrorResponse, AuthUser, ClientIp, OptionalAuthUser, PasswordChangeRoute, UserAgent,
+};
+use crate::models::notification::NotificationLevel;
+use crate::models::user::User;
+use crate::services::auth::{
+    extract_bearer_token, generate_jwt, validate_jwt, generate_mfa_pending_token, validate_mfa_pending_token,
+    retired_key_lifetime, verify_password, AuthError, MfaPurpose,
+};
+use crate::services::homes::{self, HomesSettings};
+use crate::services::ldap::{self, LdapError, LdapSettings};
+use crate::services::lockout::{self, LockoutError, LockoutPolicy};
+use crate::services::mfa::{self, MfaError};
+use crate::services::oidc::{self, OidcError};
+use crate::services::notification;
+use crate::services::session::{
+    create_session, delete_session, delete_user_session, delete_user_sessions, list_user_sessions,
+    refresh_session, SessionClient, SessionError,
+};
+use crate::services::password_policy::PasswordPolicy;
+use crate::services::user::{
+    change_password as change_user_password, get_user_by_id, get_user_by_username, UserError,
+};
 use crate::AppState;
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/login", post(login))
-        .route("/logout", post(logout))
-        .route("/me", get(me))
-        .route("/change-password", post(change_password))
+        .route("/refresh", post(refresh))
+        .route("/logout", post(logout).layer(Extension(PasswordChangeRoute)))
+        .route("/me", get(me).layer(Extension(PasswordChangeRoute)))
+        .route("/change-password", post(change_password).layer(Extension(PasswordChangeRoute)))
+        .route("/mfa", get(mfa_status))
+        .route("/mfa/verify", post(mfa_verify))
+        .route("/mfa/enroll", post(mfa_enroll))
+        .route("/mfa/enroll/confirm", post(mfa_enroll_confirm))
+        .route("/mfa/disable", post(mfa_disable))
+        .route("/mfa/recovery-codes", post(mfa_recovery_codes))
+        .route("/sessions", get(list_sessions))
+        .route("/sessions", delete(revoke_other_sessions))
+        .route("/sessions/:id", delete(revoke_session))
+        .route("/keys/rotate", post(rotate_keys))
+        .route("/oidc", get(oidc_providers))
+        .route("/oidc/exchange", post(oidc_exchange))
+        .route("/oidc/:id/authorize", get(oidc_authorize))
+        .route("/oidc/:id/callback", get(oidc_callback))
 }
 
 #[derive(Debug, Deserialize)]
@@ -30,16 +68,128 @@ pub struct LoginRequest {
 
 #[derive(Debug, Serialize)]
 pub struct LoginResponse {
-    pub token: String,
+    #[serde(flatten)]
+    pub tokens: TokenPair,
     pub user: UserInfo,
 }
 
+/// Short-lived access token and the refresh token used to renew it
+#[derive(Debug, Serialize)]
+pub struct TokenPair {
+    pub token: String,
+    pub refresh_token: String,
+    /// Access token lifetime in seconds
+    pub expires_in: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct LogoutRequest {
+    /// Lets clients whose access token expired still end their session
+    pub refresh_token: Option<String>,
+}
+
+/// Session as listed to its owner
+#[derive(Debug, Serialize)]
+pub struct SessionResponse {
+    pub id: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: String,
+    pub last_used_at: String,
+    pub expires_at: String,
+    /// Session of the access token used for this request
+    pub current: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct UserInfo {
     pub id: String,
     pub username: String,
     pub email: Option<String>,
     pub is_admin: bool,
+    /// The client should ask for a new password before anything else
+    pub must_change_password: bool,
+}
+
+/// Returned by login instead of a token when a second factor is needed
+#[derive(Debug, Serialize)]
+pub struct MfaChallengeResponse {
+    /// Submit a code to /mfa/verify
+    pub mfa_required: bool,
+    /// A group requires two-factor authentication, enrol through /mfa/enroll
+    pub mfa_enrolment_required: bool,
+    pub mfa_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MfaVerifyRequest {
+    pub mfa_token: String,
+    /// TOTP code or recovery code
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct MfaEnrollRequest {
+    /// Pending token, when enrolment is forced at login
+    pub mfa_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MfaConfirmRequest {
+    pub code: String,
+    pub mfa_token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MfaConfirmResponse {
+    pub recovery_codes: Vec<String>,
+    /// Session issued when the enrolment completed a login
+    #[serde(flatten)]
+    pub login: Option<LoginResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MfaDisableRequest {
+    /// Not used by LDAP and single sign-on accounts, which have no local password
+    #[serde(default)]
+    pub password: String,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MfaCodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+impl From<MfaError> for (StatusCode, Json<AuthErrorResponse>) {
+    fn from(err: MfaError) -> Self {
+        let (status, code) = match &err {
+            MfaError::NotEnabled => (StatusCode::BAD_REQUEST, "MFA_NOT_ENABLED"),
+            MfaError::AlreadyEnabled => (StatusCode::CONFLICT, "MFA_ALREADY_ENABLED"),
+            MfaError::NoEnrolment => (StatusCode::BAD_REQUEST, "MFA_NO_ENROLMENT"),
+            MfaError::InvalidCode => (StatusCode::UNAUTHORIZED, "INVALID_MFA_CODE"),
+            MfaError::Required => (StatusCode::FORBIDDEN, "MFA_REQUIRED"),
+            MfaError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR"),
+        };
+
+        (
+            status,
+            Json(AuthErrorResponse {
+                error: err.to_string(),
+                code: code.to_string(),
+            }),
+        )
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -51,109 +201,540 @@ pub struct ChangePasswordRequest {
 /// Login endpoint
 async fn login(
     State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
     Json(payload): Json<LoginRequest>,
 ) -> impl IntoResponse {
-    // Get user by username
+    let note = AuditNote {
+        actor: Some(payload.username.clone()),
+        ..Default::default()
+    };
+    (Extension(note), password_login(state, ip, user_agent, payload).await)
+}
+
+/// Check the password, then issue a session or a second factor challenge
+async fn password_login(
+    state: AppState,
+    ip: String,
+    user_agent: Option<String>,
+    payload: LoginRequest,
+) -> axum::response::Response {
+    // Refuse attempts from locked or throttled usernames and IPs
+    if let Err(response) = check_lockout(&state, &payload.username, &ip).await {
+        return response;
+    }
+
+    // Local accounts check their Argon2 hash, directory accounts and unknown names go to LDAP
+    // Accounts created by single sign-on have no password
     let user = match get_user_by_username(&state.db, &payload.username).await {
-        Ok(Some(user)) => user,
-        Ok(None) => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(AuthErrorResponse {
-                    error: "Invalid credentials".to_string(),
-                    code: "INVALID_CREDENTIALS".to_string(),
-                }),
-            )
-                .into_response();
+        Ok(Some(user)) if user.has_local_password() => {
+            match verify_password(&payload.password, &user.password_hash) {
+                Ok(true) => user,
+                Ok(false) => {
+                    register_failure(&state, &payload.username, &ip, "password").await;
+                    return invalid_credentials();
+                }
+                Err(e) => {
+                    tracing::error!("Password verification error: {}", e);
+                    return internal_error();
+                }
+            }
         }
+        Ok(Some(user)) if !user.is_ldap() => {
+            register_failure(&state, &payload.username, &ip, "password").await;
+            return invalid_credentials();
+        }
+        Ok(_) => match ldap_login(&state, &payload).await {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                register_failure(&state, &payload.username, &ip, "password").await;
+                return invalid_credentials();
+            }
+            Err(response) => return response,
+        },
         Err(e) => {
             tracing::error!("Database error during login: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(AuthErrorResponse {
-                    error: "Internal server error".to_string(),
-                    code: "INTERNAL_ERROR".to_string(),
-                }),
-            )
-                .into_response();
+            return internal_error();
         }
     };
 
-    // Verify password
-    match verify_password(&payload.password, &user.password_hash) {
-        Ok(true) => {}
-        Ok(false) => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(AuthErrorResponse {
-                    error: "Invalid credentials".to_string(),
-                    code: "INVALID_CREDENTIALS".to_string(),
-                }),
-            )
-                .into_response();
+    complete_login(&state, user, ip, user_agent).await
+}
+
+/// Issue a second factor challenge when two-factor authentication is enabled or required,
+/// otherwise a session
+async fn complete_login(
+    state: &AppState,
+    user: User,
+    ip: String,
+    user_agent: Option<String>,
+) -> axum::response::Response {
+    // Directory and single sign-on accounts get their home at their first sign-in
+    if let Some(settings) = HomesSettings::from_config(&state.config) {
+        if let Err(e) = homes::ensure_home(&settings, &user) {
+            tracing::warn!("Failed to create home of {}: {}", user.username, e);
         }
+    }
+
+    let mfa_enabled = match mfa::is_enabled(&state.db, &user.id).await {
+        Ok(enabled) => enabled,
         Err(e) => {
-            tracing::error!("Password verification error: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(AuthErrorResponse {
-                    error: "Internal server error".to_string(),
-                    code: "INTERNAL_ERROR".to_string(),
-                }),
-            )
-                .into_response();
+            tracing::error!("Database error during login: {}", e);
+            return internal_error();
+        }
+    };
+    let purpose = if mfa_enabled {
+        Some(MfaPurpose::Verify)
+    } else {
+        match mfa::is_required(&state.db, &user.id).await {
+            Ok(true) => Some(MfaPurpose::Enroll),
+            Ok(false) => None,
+            Err(e) => {
+                tracing::error!("Database error during login: {}", e);
+                return internal_error();
+            }
         }
+    };
+
+    if let Some(purpose) = purpose {
+        return match generate_mfa_pending_token(&user, purpose, &state.config) {
+            Ok(mfa_token) => {
+                let response = MfaChallengeResponse {
+                    mfa_required: purpose == MfaPurpose::Verify,
+                    mfa_enrolment_required: purpose == MfaPurpose::Enroll,
+                    mfa_token,
+                };
+                (StatusCode::OK, Json(response)).into_response()
+            }
+            Err(e) => {
+                tracing::error!("Token generation error: {}", e);
+                internal_error()
+            }
+        };
     }
 
-    // Generate JWT token
-    let token = match generate_jwt(&user, &state.config) {
-        Ok(token) => token,
+    let client = SessionClient {
+        ip: Some(ip),
+        user_agent,
+    };
+    issue_session(state, user, &client).await
+}
+
+/// Store a new session and build the login response
+async fn issue_session(state: &AppState, user: User, client: &SessionClient) -> axum::response::Response {
+    match create_login_response(state, user, client).await {
+        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
         Err(e) => {
             tracing::error!("Token generation error: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(AuthErrorResponse {
-                    error: "Internal server error".to_string(),
-                    code: "INTERNAL_ERROR".to_string(),
-                }),
-            )
-                .into_response();
+            internal_error()
         }
-    };
+    }
+}
+
+async fn create_login_response(
+    state: &AppState,
+    user: User,
+    client: &SessionClient,
+) -> Result<LoginResponse, AuthError> {
+    let tokens = start_session(state, &user, client).await?;
 
-    // Store session in database
-    let expires_at = Utc::now() + Duration::hours(state.config.jwt_expiration_hours as i64);
-    if let Err(e) = create_session(&state.db, &user.id, &token, expires_at).await {
-        tracing::error!("Session creation error: {}", e);
-        // Continue anyway - JWT is still valid
+    if let Err(e) = lockout::record_success(&state.db, &user.username).await {
+        tracing::error!("Failed to reset login failures: {}", e);
     }
 
-    let response = LoginResponse {
-        token,
+    Ok(LoginResponse {
+        tokens,
         user: UserInfo {
             id: user.id,
             username: user.username,
             email: user.email,
             is_admin: user.is_admin,
+            must_change_password: user.must_change_password,
         },
+    })
+}
+
+/// Store a new session for the user and issue its first token pair
+pub(crate) async fn start_session(
+    state: &AppState,
+    user: &User,
+    client: &SessionClient,
+) -> Result<TokenPair, AuthError> {
+    let (session, refresh_token) = create_session(&state.db, &user.id, client, session_expiry(state))
+        .await
+        .map_err(|e| AuthError::SessionError(e.to_string()))?;
+
+    Ok(TokenPair {
+        token: generate_jwt(user, &session.id, &state.config)?,
+        refresh_token,
+        expires_in: state.config.access_token_minutes * 60,
+    })
+}
+
+fn session_expiry(state: &AppState) -> chrono::DateTime<Utc> {
+    Utc::now() + Duration::hours(state.config.jwt_expiration_hours as i64)
+}
+
+/// Reject the attempt with 429 and Retry-After when locked or backing off
+async fn check_lockout(state: &AppState, username: &str, ip: &str) -> Result<(), axum::response::Response> {
+    let policy = LockoutPolicy::from_config(&state.config);
+    match lockout::check(&state.db, &policy, username, ip).await {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let Some(retry_after) = e.retry_after() else {
+                tracing::error!("Failed to check login lockout: {}", e);
+                return Err(internal_error());
+            };
+            tracing::warn!("Login refused for {} from {}: {}", username, ip, e);
+            let code = match e {
+                LockoutError::Locked(_) => "LOGIN_LOCKED",
+                _ => "LOGIN_THROTTLED",
+            };
+            Err((
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, retry_after.to_string())],
+                Json(AuthErrorResponse {
+                    error: e.to_string(),
+                    code: code.to_string(),
+                }),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Count a failed attempt and notify administrators of new lockouts
+async fn register_failure(state: &AppState, username: &str, ip: &str, reason: &str) {
+    let policy = LockoutPolicy::from_config(&state.config);
+    let locked = match lockout::record_failure(&state.db, &policy, username, ip, reason).await {
+        Ok(locked) => locked,
+        Err(e) => {
+            tracing::error!("Failed to record login failure: {}", e);
+            return;
+        }
     };
 
-    (StatusCode::OK, Json(response)).into_response()
+    for lock in locked {
+        tracing::warn!("Login locked for {} {} after {} failures", lock.kind, lock.value, lock.failures);
+        let message = format!(
+            "Login for {} '{}' was locked for {} minutes after {} failed attempts (last from {}).",
+            lock.kind, lock.value, state.config.login_lockout_minutes, lock.failures, ip
+        );
+        if let Err(e) = notification::notify(
+            &state.db,
+            &state.events,
+            NotificationLevel::Warning,
+            "Login locked",
+            &message,
+            "security",
+        )
+        .await
+        {
+            tracing::error!("Failed to send lockout notification: {}", e);
+        }
+    }
+}
+
+/// Authenticate against the LDAP directory, provisioning the local account
+/// None when LDAP is disabled or refuses the credentials
+async fn ldap_login(state: &AppState, payload: &LoginRequest) -> Result<Option<User>, axum::response::Response> {
+    let Some(settings) = LdapSettings::from_config(&state.config) else {
+        return Ok(None);
+    };
+
+    let ldap_user = match ldap::authenticate(&settings, &payload.username, &payload.password).await {
+        Ok(Some(ldap_user)) => ldap_user,
+        Ok(None) => return Ok(None),
+        Err(e) => {
+            tracing::error!("LDAP authentication error: {}", e);
+            return Err(error_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Directory server unavailable",
+                "LDAP_UNAVAILABLE",
+            ));
+        }
+    };
+
+    match ldap::provision_user(&state.db, &settings, &ldap_user).await {
+        Ok(user) => Ok(Some(user)),
+        Err(LdapError::UsernameTaken(username)) => {
+            tracing::warn!("LDAP user {} conflicts with a local account", username);
+            Ok(None)
+        }
+        Err(e) => {
+            tracing::error!("Failed to provision LDAP user {}: {}", ldap_user.username, e);
+            Err(internal_error())
+        }
+    }
+}
+
+/// Identity provider offered on the login page
+#[derive(Debug, Serialize)]
+pub struct OidcLoginOption {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// Set by the provider when the user denied access or the request failed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcExchangeRequest {
+    pub ticket: String,
+}
+
+/// List the enabled identity providers
+async fn oidc_providers(State(state): State<AppState>) -> impl IntoResponse {
+    match oidc::list_providers(&state.db).await {
+        Ok(providers) => {
+            let options: Vec<OidcLoginOption> = providers
+                .into_iter()
+                .filter(|p| p.enabled)
+                .map(|p| OidcLoginOption { id: p.id, name: p.name })
+                .collect();
+            (StatusCode::OK, Json(options)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to list identity providers: {}", e);
+            internal_error()
+        }
+    }
+}
+
+/// Redirect the browser to the identity provider
+async fn oidc_authorize(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let provider = match oidc::get_provider(&state.db, &id).await {
+        Ok(provider) => provider,
+        Err(e) => return oidc_failure(&state, &e),
+    };
+    let redirect_uri = format!("{}/api/auth/oidc/{}/callback", external_base(&state, &headers), id);
+    match oidc::begin_login(&state.db, &provider, &redirect_uri).await {
+        Ok(url) => Redirect::to(&url).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to start sign-in with {}: {}", provider.name, e);
+            oidc_failure(&state, &e)
+        }
+    }
+}
+
+/// Complete the sign-in and hand a one-time ticket to the web interface,
+/// which exchanges it for a session so tokens never appear in URLs
+async fn oidc_callback(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<OidcCallbackQuery>,
+) -> impl IntoResponse {
+    let (Some(code), Some(login_state)) = (&query.code, &query.state) else {
+        tracing::warn!("Identity provider returned an error: {:?}", query.error);
+        return oidc_redirect(&state, "oidc_error", "OIDC_DENIED");
+    };
+
+    let user = match oidc::finish_login(&state.db, &id, code, login_state).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::warn!("Single sign-on failed: {}", e);
+            return oidc_failure(&state, &e);
+        }
+    };
+
+    match oidc::create_ticket(&state.db, &user.id).await {
+        Ok(ticket) => oidc_redirect(&state, "oidc_ticket", &ticket),
+        Err(e) => {
+            tracing::error!("Failed to create sign-in ticket: {}", e);
+            oidc_failure(&state, &e)
+        }
+    }
+}
+
+/// Exchange a sign-in ticket for a session or a second factor challenge
+async fn oidc_exchange(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    Json(payload): Json<OidcExchangeRequest>,
+) -> impl IntoResponse {
+    let user = match oidc::redeem_ticket(&state.db, &payload.ticket).await {
+        Ok(user) => user,
+        Err(OidcError::InvalidTicket) => {
+            return (
+                Extension(AuditNote::default()),
+                error_response(StatusCode::UNAUTHORIZED, "Sign-in ticket expired or unknown", "INVALID_TICKET"),
+            );
+        }
+        Err(e) => {
+            tracing::error!("Failed to redeem sign-in ticket: {}", e);
+            return (Extension(AuditNote::default()), internal_error());
+        }
+    };
+
+    let note = AuditNote {
+        actor: Some(user.username.clone()),
+        ..Default::default()
+    };
+    (Extension(note), complete_login(&state, user, ip, user_agent).await)
+}
+
+/// Base URL browsers reach the server at
+fn external_base(state: &AppState, headers: &HeaderMap) -> String {
+    if let Some(url) = &state.config.public_url {
+        return url.trim_end_matches('/').to_string();
+    }
+    let host = headers
+        .get(HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("localhost");
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|h| h.to_str().ok())
+        .filter(|p| *p == "https")
+        .unwrap_or("http");
+    format!("{}://{}", scheme, host)
+}
+
+/// Send the browser back to the web interface with a fragment parameter
+fn oidc_redirect(state: &AppState, key: &str, value: &str) -> axum::response::Response {
+    let base = state.config.public_url.as_deref().unwrap_or("").trim_end_matches('/');
+    Redirect::to(&format!("{}/#{}={}", base, key, value)).into_response()
+}
+
+fn oidc_failure(state: &AppState, err: &OidcError) -> axum::response::Response {
+    let code = match err {
+        OidcError::NotFound | OidcError::Disabled => "OIDC_UNAVAILABLE",
+        OidcError::InvalidState => "OIDC_EXPIRED",
+        OidcError::NoAccount => "OIDC_NO_ACCOUNT",
+        OidcError::UsernameTaken(_) => "OIDC_USERNAME_TAKEN",
+        OidcError::Provider(_) | OidcError::Http(_) => "OIDC_PROVIDER_ERROR",
+        OidcError::InvalidIdToken(_) => "OIDC_INVALID_TOKEN",
+        _ => "INTERNAL_ERROR",
+    };
+    oidc_redirect(state, "oidc_error", code)
+}
+
+fn invalid_credentials() -> axum::response::Response {
+    error_response(StatusCode::UNAUTHORIZED, "Invalid credentials", "INVALID_CREDENTIALS")
+}
+
+fn internal_error() -> axum::response::Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(AuthErrorResponse {
+            error: "Internal server error".to_string(),
+            code: "INTERNAL_ERROR".to_string(),
+        }),
+    )
+        .into_response()
+}
+
+fn error_response(status: StatusCode, error: &str, code: &str) -> axum::response::Response {
+    (
+        status,
+        Json(AuthErrorResponse {
+            error: error.to_string(),
+            code: code.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+/// Exchange a refresh token for a new token pair
+async fn refresh(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    Json(payload): Json<RefreshRequest>,
+) -> impl IntoResponse {
+    let client = SessionClient {
+        ip: Some(ip),
+        user_agent,
+    };
+
+    let (session, refresh_token) =
+        match refresh_session(&state.db, &payload.refresh_token, &client, session_expiry(&state)).await {
+            Ok(result) => result,
+            Err(SessionError::TokenReused) => {
+                tracing::warn!("Refresh token reuse detected from {:?}, session revoked", client.ip);
+                return error_response(
+                    StatusCode::UNAUTHORIZED,
+                    "Refresh token was already used, session revoked",
+                    "REFRESH_TOKEN_REUSED",
+                );
+            }
+            Err(SessionError::DatabaseError(e)) => {
+                tracing::error!("Session refresh error: {}", e);
+                return internal_error();
+            }
+            Err(_) => {
+                return error_response(
+                    StatusCode::UNAUTHORIZED,
+                    "Invalid or expired refresh token",
+                    "INVALID_REFRESH_TOKEN",
+                );
+            }
+        };
+
+    let user = match get_user_by_id(&state.db, &session.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return error_response(StatusCode::UNAUTHORIZED, "User not found", "USER_NOT_FOUND"),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            return internal_error();
+        }
+    };
+
+    match generate_jwt(&user, &session.id, &state.config) {
+        Ok(token) => {
+            let tokens = TokenPair {
+                token,
+                refresh_token,
+                expires_in: state.config.access_token_minutes * 60,
+            };
+            (StatusCode::OK, Json(tokens)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Token generation error: {}", e);
+            internal_error()
+        }
+    }
 }
 
 /// Logout endpoint
+/// Ends the session of the access token, or of the refresh token when given
 async fn logout(
     State(state): State<AppState>,
     headers: axum::http::HeaderMap,
+    payload: Option<Json<LogoutRequest>>,
 ) -> impl IntoResponse {
-    // Extract token from header
-    if let Some(auth_header) = headers.get(AUTHORIZATION) {
-        if let Ok(header_str) = auth_header.to_str() {
-            if let Some(token) = extract_bearer_token(header_str) {
-                // Delete session from database
-                if let Err(e) = delete_session(&state.db, token).await {
-                    tracing::error!("Session deletion error: {}", e);
-                }
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+
+    // Session of a valid access token; once expired, the refresh token identifies it
+    let session_id = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(extract_bearer_token)
+        .and_then(|token| validate_jwt(token, &state.config).ok())
+        .map(|claims| claims.sid);
+
+    if let Some(session_id) = session_id {
+        if let Err(e) = delete_session(&state.db, &session_id).await {
+            tracing::error!("Session deletion error: {}", e);
+        }
+    }
+
+    // The refresh token must be valid to end its session, a stale one is treated as reuse
+    if let Some(refresh_token) = payload.refresh_token {
+        let client = SessionClient::default();
+        if let Ok((session, _)) = refresh_session(&state.db, &refresh_token, &client, Utc::now()).await {
+            if let Err(e) = delete_session(&state.db, &session.id).await {
+                tracing::error!("Session deletion error: {}", e);
             }
         }
     }
@@ -161,6 +742,60 @@ async fn logout(
     StatusCode::OK
 }
 
+/// List the active sessions of the current user
+async fn list_sessions(State(state): State<AppState>, user: AuthUser) -> impl IntoResponse {
+    match list_user_sessions(&state.db, &user.id).await {
+        Ok(sessions) => {
+            let response: Vec<SessionResponse> = sessions
+                .into_iter()
+                .map(|s| SessionResponse {
+                    current: user.session_id.as_deref() == Some(s.id.as_str()),
+                    id: s.id,
+                    ip: s.ip,
+                    user_agent: s.user_agent,
+                    created_at: s.created_at,
+                    last_used_at: s.last_used_at,
+                    expires_at: s.expires_at,
+                })
+                .collect();
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to list sessions: {}", e);
+            internal_error()
+        }
+    }
+}
+
+/// Revoke one of the current user's sessions (sign out a device)
+async fn revoke_session(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match delete_user_session(&state.db, &user.id, &id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(SessionError::NotFound) => {
+            error_response(StatusCode::NOT_FOUND, "Session not found", "SESSION_NOT_FOUND")
+        }
+        Err(e) => {
+            tracing::error!("Failed to revoke session: {}", e);
+            internal_error()
+        }
+    }
+}
+
+/// Revoke all sessions of the current user except this one
+async fn revoke_other_sessions(State(state): State<AppState>, user: AuthUser) -> impl IntoResponse {
+    match delete_user_sessions(&state.db, &user.id, user.session_id.as_deref()).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            tracing::error!("Failed to revoke sessions: {}", e);
+            internal_error()
+        }
+    }
+}
+
 /// Get current user info
 async fn me(State(state): State<AppState>, user: AuthUser) -> impl IntoResponse {
     // Get full user info from database
@@ -171,6 +806,7 @@ async fn me(State(state): State<AppState>, user: AuthUser) -> impl IntoResponse
                 username: db_user.username,
                 email: db_user.email,
                 is_admin: db_user.is_admin,
+                must_change_password: db_user.must_change_password,
             };
             (StatusCode::OK, Json(user_info)).into_response()
         }
@@ -228,6 +864,14 @@ async fn change_password(
         }
     };
 
+    if !db_user.has_local_password() {
+        return error_response(
+            StatusCode::CONFLICT,
+            "Password is managed by the LDAP directory or the identity provider",
+            "EXTERNAL_ACCOUNT",
+        );
+    }
+
     // Verify current password
     match verify_password(&payload.current_password, &db_user.password_hash) {
         Ok(true) => {}
@@ -254,8 +898,12 @@ async fn change_password(
         }
     }
 
-    // Change password
-    if let Err(e) = change_user_password(&state.db, &user.id, &payload.new_password).await {
+    // Change password, which also lifts a forced change
+    let policy = PasswordPolicy::from_config(&state.config);
+    if let Err(e) = change_user_password(&state.db, &user.id, &payload.new_password, false, &policy).await {
+        if let UserError::WeakPassword(reason) = e {
+            return error_response(StatusCode::BAD_REQUEST, &reason.to_string(), "WEAK_PASSWORD");
+        }
         tracing::error!("Password change error: {}", e);
         return (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -267,5 +915,274 @@ async fn change_password(
             .into_response();
     }
 
+    // Sign out other devices that may have used the old password
+    if let Err(e) = delete_user_sessions(&state.db, &user.id, user.session_id.as_deref()).await {
+        tracing::error!("Failed to revoke sessions after password change: {}", e);
+    }
+
     StatusCode::NO_CONTENT.into_response()
 }
+
+/// Resolve the user a pending two-factor token was issued for
+async fn pending_user(
+    state: &AppState,
+    token: &str,
+    purpose: MfaPurpose,
+) -> Result<User, axum::response::Response> {
+    let claims = validate_mfa_pending_token(token, purpose, &state.config).map_err(|_| {
+        error_response(
+            StatusCode::UNAUTHORIZED,
+            "Invalid or expired two-factor token",
+            "INVALID_MFA_TOKEN",
+        )
+    })?;
+
+    match get_user_by_id(&state.db, &claims.sub).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(error_response(StatusCode::UNAUTHORIZED, "User not found", "USER_NOT_FOUND")),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            Err(internal_error())
+        }
+    }
+}
+
+/// User enrolling: the authenticated user, or the holder of an enrolment token
+async fn enrolling_user(
+    state: &AppState,
+    auth: Option<AuthUser>,
+    mfa_token: Option<&str>,
+) -> Result<(User, bool), axum::response::Response> {
+    if let Some(token) = mfa_token {
+        return pending_user(state, token, MfaPurpose::Enroll)
+            .await
+            .map(|user| (user, true));
+    }
+
+    let Some(auth) = auth else {
+        return Err(error_response(
+            StatusCode::UNAUTHORIZED,
+            "Authentication required",
+            "UNAUTHORIZED",
+        ));
+    };
+    match get_user_by_id(&state.db, &auth.id).await {
+        Ok(Some(user)) => Ok((user, false)),
+        Ok(None) => Err(error_response(StatusCode::NOT_FOUND, "User not found", "USER_NOT_FOUND")),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            Err(internal_error())
+        }
+    }
+}
+
+/// Second login step: exchange a pending token and a code for a session
+async fn mfa_verify(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    Json(payload): Json<MfaVerifyRequest>,
+) -> impl IntoResponse {
+    let user = match pending_user(&state, &payload.mfa_token, MfaPurpose::Verify).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    // Codes are short, guessing them is throttled like passwords
+    if let Err(response) = check_lockout(&state, &user.username, &ip).await {
+        return response;
+    }
+
+    if let Err(e) = mfa::verify_second_factor(&state.db, &user.id, &payload.code).await {
+        tracing::warn!("Two-factor verification failed for {}: {}", user.username, e);
+        if matches!(e, MfaError::InvalidCode) {
+            register_failure(&state, &user.username, &ip, "mfa").await;
+        }
+        let (status, json) = e.into();
+        return (status, json).into_response();
+    }
+
+    let client = SessionClient {
+        ip: Some(ip),
+        user_agent,
+    };
+    issue_session(&state, user, &client).await
+}
+
+/// Two-factor status of the current user
+async fn mfa_status(State(state): State<AppState>, user: AuthUser) -> impl IntoResponse {
+    match mfa::get_status(&state.db, &user.id).await {
+        Ok(status) => (StatusCode::OK, Json(status)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to get two-factor status: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Start a TOTP enrolment, returns the secret and otpauth URI
+async fn mfa_enroll(
+    State(state): State<AppState>,
+    OptionalAuthUser(auth): OptionalAuthUser,
+    payload: Option<Json<MfaEnrollRequest>>,
+) -> impl IntoResponse {
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+    let (user, _) = match enrolling_user(&state, auth, payload.mfa_token.as_deref()).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match mfa::begin_enrolment(&state.db, &user.id, &user.username).await {
+        Ok(enrolment) => (StatusCode::OK, Json(enrolment)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to start two-factor enrolment: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Confirm a TOTP enrolment with a first code, returns the recovery codes
+/// When enrolment was forced at login, the session is issued as well
+async fn mfa_enroll_confirm(
+    State(state): State<AppState>,
+    OptionalAuthUser(auth): OptionalAuthUser,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    Json(payload): Json<MfaConfirmRequest>,
+) -> impl IntoResponse {
+    let (user, pending) = match enrolling_user(&state, auth, payload.mfa_token.as_deref()).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let recovery_codes = match mfa::confirm_enrolment(&state.db, &user.id, &payload.code).await {
+        Ok(codes) => codes,
+        Err(e) => {
+            tracing::warn!("Two-factor enrolment failed for {}: {}", user.username, e);
+            let (status, json) = e.into();
+            return (status, json).into_response();
+        }
+    };
+    tracing::info!("Two-factor authentication enabled for {}", user.username);
+
+    let login = if pending {
+        let client = SessionClient {
+            ip: Some(ip),
+            user_agent,
+        };
+        match create_login_response(&state, user, &client).await {
+            Ok(login) => Some(login),
+            Err(e) => {
+                tracing::error!("Token generation error: {}", e);
+                return internal_error();
+            }
+        }
+    } else {
+        None
+    };
+
+    (StatusCode::OK, Json(MfaConfirmResponse { recovery_codes, login })).into_response()
+}
+
+/// Disable two-factor authentication, requires the password and a code
+async fn mfa_disable(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(payload): Json<MfaDisableRequest>,
+) -> impl IntoResponse {
+    let db_user = match get_user_by_id(&state.db, &user.id).await {
+        Ok(Some(u)) => u,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, "User not found", "USER_NOT_FOUND"),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            return internal_error();
+        }
+    };
+
+    // Accounts without a local password prove themselves with the second factor alone
+    if db_user.has_local_password() {
+        match verify_password(&payload.password, &db_user.password_hash) {
+            Ok(true) => {}
+            Ok(false) => {
+                return error_response(
+                    StatusCode::UNAUTHORIZED,
+                    "Current password is incorrect",
+                    "INVALID_PASSWORD",
+                );
+            }
+            Err(e) => {
+                tracing::error!("Password verification error: {}", e);
+                return internal_error();
+            }
+        }
+    }
+
+    let result = match mfa::verify_second_factor(&state.db, &user.id, &payload.code).await {
+        Ok(()) => mfa::disable(&state.db, &user.id, false).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(()) => {
+            tracing::info!("Two-factor authentication disabled for {}", user.username);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            tracing::warn!("Failed to disable two-factor authentication: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Replace the recovery codes, requires a current code
+async fn mfa_recovery_codes(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(payload): Json<MfaCodeRequest>,
+) -> impl IntoResponse {
+    let result = match mfa::verify_second_factor(&state.db, &user.id, &payload.code).await {
+        Ok(()) => mfa::regenerate_recovery_codes(&state.db, &user.id).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(recovery_codes) => {
+            (StatusCode::OK, Json(RecoveryCodesResponse { recovery_codes })).into_response()
+        }
+        Err(e) => {
+            tracing::warn!("Failed to regenerate recovery codes: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RotateKeysResponse {
+    pub kid: String,
+}
+
+/// Start signing tokens with a new key, tokens already issued stay valid (admin only)
+async fn rotate_keys(State(state): State<AppState>, admin: AdminUser) -> impl IntoResponse {
+    if !state.config.jwt_keys.is_rotatable() {
+        return error_response(
+            StatusCode::CONFLICT,
+            "A configured JWT secret cannot be rotated",
+            "KEY_NOT_ROTATABLE",
+        );
+    }
+
+    match state.config.jwt_keys.rotate(retired_key_lifetime(&state.config)) {
+        Ok(kid) => {
+            tracing::info!("JWT signing key rotated to {} by {}", kid, admin.username);
+            (StatusCode::OK, Json(RotateKeysResponse { kid })).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to rotate JWT signing key: {}", e);
+            internal_error()
+        }
+    }
+}
@@ -10,8 +10,17 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
+use crate::api::middleware::AuthUser;
+use crate::services::homes::{self, HomesSettings};
+use crate::services::user::get_user_by_id;
 use crate::AppState;
 
+/// Path prefix of the caller's home directory
+const HOME_PREFIX: &str = "~";
+
+/// Name of the home directory entry at the top of the file manager
+const HOME_LABEL: &str = "My Files";
+
 /// File or folder item
 #[derive(Debug, Serialize)]
 pub struct FileItem {
@@ -104,6 +113,49 @@ fn validate_path(base: &Path, requested: &str) -> Result<PathBuf, String> {
     Ok(full_path)
 }
 
+/// Base directory of a request and the path within it, `~` paths are in the caller's home
+async fn resolve_base(
+    state: &AppState,
+    user: &AuthUser,
+    requested: &str,
+) -> Result<(PathBuf, String), (StatusCode, Json<ErrorResponse>)> {
+    let Some(in_home) = home_relative(requested) else {
+        return Ok((PathBuf::from(&state.config.files_root), requested.to_string()));
+    };
+
+    let not_available = || {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse { error: "Home directories are not enabled".to_string() }),
+        )
+    };
+    let settings = HomesSettings::from_config(&state.config).ok_or_else(not_available)?;
+    let account = get_user_by_id(&state.db, &user.id)
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(not_available)?;
+    let home = homes::ensure_home(&settings, &account).map_err(|e| {
+        tracing::error!("Failed to create home of {}: {}", account.username, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: format!("Failed to create home directory: {}", e) }),
+        )
+    })?;
+
+    Ok((home, in_home.to_string()))
+}
+
+/// Path within the caller's home of a `~` path, `None` for other paths
+fn home_relative(requested: &str) -> Option<&str> {
+    let trimmed = requested.trim_start_matches('/');
+    let rest = trimmed.strip_prefix(HOME_PREFIX)?;
+    if !rest.is_empty() && !rest.starts_with('/') {
+        return None;
+    }
+    Some(rest.trim_start_matches('/'))
+}
+
 /// Get MIME type from file extension
 fn get_mime_type(path: &Path) -> Option<String> {
     let extension = path.extension()?.to_str()?.to_lowercase();
@@ -167,9 +219,14 @@ fn format_time(time: SystemTime) -> String {
 /// List files in a directory
 async fn list_files(
     State(state): State<AppState>,
+    user: AuthUser,
     Query(query): Query<ListQuery>,
 ) -> impl IntoResponse {
-    let base_path = PathBuf::from(&state.config.files_root);
+    let requested = query.path.unwrap_or_default();
+    let (base_path, rel_path) = match resolve_base(&state, &user, &requested).await {
+        Ok(resolved) => resolved,
+        Err(e) => return e.into_response(),
+    };
 
     // Ensure base directory exists
     if !base_path.exists() {
@@ -181,8 +238,6 @@ async fn list_files(
         }
     }
 
-    let rel_path = query.path.unwrap_or_default();
-
     // Validate path
     let full_path = match validate_path(&base_path, &rel_path) {
         Ok(p) => p,
@@ -243,11 +298,11 @@ async fn list_files(
             .map(format_time)
             .unwrap_or_else(|_| "".to_string());
 
-        // Build relative path from files_root
-        let item_rel_path = if rel_path.is_empty() {
+        // Build the path as requested, from files_root or the home
+        let item_rel_path = if requested.is_empty() {
             name.clone()
         } else {
-            format!("{}/{}", rel_path.trim_end_matches('/'), name)
+            format!("{}/{}", requested.trim_end_matches('/'), name)
         };
 
         let mime_type = if is_dir { None } else { get_mime_type(&path) };
@@ -271,18 +326,34 @@ async fn list_files(
         }
     });
 
+    // The caller's home comes first at the top level
+    if requested.is_empty() && HomesSettings::from_config(&state.config).is_some() {
+        files.insert(0, FileItem {
+            name: HOME_LABEL.to_string(),
+            path: HOME_PREFIX.to_string(),
+            file_type: "folder".to_string(),
+            size: None,
+            modified: String::new(),
+            mime_type: None,
+        });
+    }
+
     Json(files).into_response()
 }
 
 /// Create a new folder
 async fn create_folder(
     State(state): State<AppState>,
+    user: AuthUser,
     Json(payload): Json<CreateFolderRequest>,
 ) -> impl IntoResponse {
-    let base_path = PathBuf::from(&state.config.files_root);
+    let (base_path, rel_parent) = match resolve_base(&state, &user, &payload.path).await {
+        Ok(resolved) => resolved,
+        Err(e) => return e.into_response(),
+    };
 
     // Validate parent path
-    let parent_path = match validate_path(&base_path, &payload.path) {
+    let parent_path = match validate_path(&base_path, &rel_parent) {
         Ok(p) => p,
         Err(e) => {
             return (
@@ -343,12 +414,16 @@ async fn create_folder(
 /// Delete a file or folder
 async fn delete_file(
     State(state): State<AppState>,
+    user: AuthUser,
     Query(query): Query<DeleteQuery>,
 ) -> impl IntoResponse {
-    let base_path = PathBuf::from(&state.config.files_root);
+    let (base_path, rel_path) = match resolve_base(&state, &user, &query.path).await {
+        Ok(resolved) => resolved,
+        Err(e) => return e.into_response(),
+    };
 
     // Validate path
-    let full_path = match validate_path(&base_path, &query.path) {
+    let full_path = match validate_path(&base_path, &rel_path) {
         Ok(p) => p,
         Err(e) => {
             return (
@@ -393,12 +468,16 @@ async fn delete_file(
 /// Rename a file or folder
 async fn rename_file(
     State(state): State<AppState>,
+    user: AuthUser,
     Json(payload): Json<RenameRequest>,
 ) -> impl IntoResponse {
-    let base_path = PathBuf::from(&state.config.files_root);
+    let (base_path, rel_path) = match resolve_base(&state, &user, &payload.path).await {
+        Ok(resolved) => resolved,
+        Err(e) => return e.into_response(),
+    };
 
     // Validate original path
-    let full_path = match validate_path(&base_path, &payload.path) {
+    let full_path = match validate_path(&base_path, &rel_path) {
         Ok(p) => p,
         Err(e) => {
             return (
@@ -424,10 +503,11 @@ async fn rename_file(
         ).into_response();
     }
 
-    // Build new path
+    // Build new path, the root itself cannot be renamed
+    let is_root = full_path.canonicalize().ok() == base_path.canonicalize().ok();
     let parent = match full_path.parent() {
-        Some(p) => p,
-        None => {
+        Some(p) if !is_root => p,
+        _ => {
             return (
                 StatusCode::BAD_REQUEST,
                 Json(ErrorResponse { error: "Cannot rename root".to_string() }),
@@ -482,3 +562,35 @@ async fn rename_file(
         mime_type: if is_dir { None } else { get_mime_type(&new_path) },
     }).into_response()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_home_paths() {
+        assert_eq!(home_relative("~"), Some(""));
+        assert_eq!(home_relative("/~/"), Some(""));
+        assert_eq!(home_relative("~/Documents/notes.txt"), Some("Documents/notes.txt"));
+        assert_eq!(home_relative("/~//Photos"), Some("Photos"));
+
+        // Only a leading `~` segment refers to the home
+        assert_eq!(home_relative("~bob/secret"), None);
+        assert_eq!(home_relative("/storage/~"), None);
+        assert_eq!(home_relative("Documents"), None);
+    }
+
+    #[test]
+    fn test_home_paths_stay_in_the_home() {
+        let root = std::env::temp_dir().join(format!("pinas-files-{}", uuid::Uuid::new_v4()));
+        let home = root.join("alice");
+        std::fs::create_dir_all(home.join("Documents")).unwrap();
+        std::fs::create_dir_all(root.join("bob")).unwrap();
+
+        let path = validate_path(&home, home_relative("~/Documents").unwrap()).unwrap();
+        assert_eq!(path, home.join("Documents"));
+        assert!(validate_path(&home, home_relative("~/../bob").unwrap()).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
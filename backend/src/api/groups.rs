@@ -34,6 +34,9 @@ pub struct GroupResponse {
     pub name: String,
     pub description: Option<String>,
     pub is_system: bool,
+    pub require_mfa: bool,
+    /// Directory the group is synced from, such groups are read-only
+    pub external_source: Option<String>,
     pub member_count: i64,
     pub created_at: String,
     pub updated_at: String,
@@ -49,6 +52,8 @@ pub struct CreateGroupRequest {
 pub struct UpdateGroupRequest {
     pub name: Option<String>,
     pub description: Option<String>,
+    /// Require two-factor authentication for members
+    pub require_mfa: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -80,6 +85,7 @@ impl From<GroupError> for (StatusCode, Json<ErrorResponse>) {
             }
             GroupError::NotAMember => (StatusCode::NOT_FOUND, "NOT_A_MEMBER"),
             GroupError::AlreadyMember => (StatusCode::CONFLICT, "ALREADY_MEMBER"),
+            GroupError::ExternalGroup => (StatusCode::FORBIDDEN, "EXTERNAL_GROUP"),
             GroupError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR"),
         };
 
@@ -107,6 +113,8 @@ async fn list_groups(State(state): State<AppState>, _admin: AdminUser) -> impl I
                     name: group.name,
                     description: group.description,
                     is_system: group.is_system,
+                    require_mfa: group.require_mfa,
+                    external_source: group.external_source,
                     member_count,
                     created_at: group.created_at,
                     updated_at: group.updated_at,
@@ -146,6 +154,8 @@ async fn create_group(
                 name: group.name,
                 description: group.description,
                 is_system: group.is_system,
+                require_mfa: group.require_mfa,
+                external_source: group.external_source,
                 member_count: 0,
                 created_at: group.created_at,
                 updated_at: group.updated_at,
@@ -176,6 +186,8 @@ async fn get_group(
                 name: group.name,
                 description: group.description,
                 is_system: group.is_system,
+                require_mfa: group.require_mfa,
+                external_source: group.external_source,
                 member_count,
                 created_at: group.created_at,
                 updated_at: group.updated_at,
@@ -210,6 +222,7 @@ async fn update_group(
         &id,
         payload.name,
         payload.description.map(Some),
+        payload.require_mfa,
     )
     .await
     {
@@ -222,6 +235,8 @@ async fn update_group(
                 name: group.name,
                 description: group.description,
                 is_system: group.is_system,
+                require_mfa: group.require_mfa,
+                external_source: group.external_source,
                 member_count,
                 created_at: group.created_at,
                 updated_at: group.updated_at,
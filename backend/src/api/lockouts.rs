@@ -0,0 +1,102 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::api::middleware::AdminUser;
+use crate::services::lockout::{list_failures as list_failures_service, list_locked, unlock, LockoutError};
+use crate::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_lockouts))
+        .route("/failures", get(list_failures))
+        .route("/:id", delete(unlock_lockout))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FailuresQuery {
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+}
+
+fn default_limit() -> i64 {
+    100
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub code: String,
+}
+
+impl From<LockoutError> for (StatusCode, Json<ErrorResponse>) {
+    fn from(err: LockoutError) -> Self {
+        let (status, code) = match &err {
+            LockoutError::NotFound => (StatusCode::NOT_FOUND, "LOCKOUT_NOT_FOUND"),
+            LockoutError::Throttled(_) | LockoutError::Locked(_) => {
+                (StatusCode::TOO_MANY_REQUESTS, "LOGIN_LOCKED")
+            }
+            LockoutError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR"),
+        };
+
+        (
+            status,
+            Json(ErrorResponse {
+                error: err.to_string(),
+                code: code.to_string(),
+            }),
+        )
+    }
+}
+
+/// List currently locked usernames and IPs (admin only)
+async fn list_lockouts(State(state): State<AppState>, _admin: AdminUser) -> impl IntoResponse {
+    match list_locked(&state.db).await {
+        Ok(lockouts) => (StatusCode::OK, Json(lockouts)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list lockouts: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// List recent failed login attempts (admin only)
+async fn list_failures(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Query(query): Query<FailuresQuery>,
+) -> impl IntoResponse {
+    match list_failures_service(&state.db, query.limit.clamp(1, 1000)).await {
+        Ok(failures) => (StatusCode::OK, Json(failures)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list login failures: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Unlock a username or IP (admin only)
+async fn unlock_lockout(
+    State(state): State<AppState>,
+    admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match unlock(&state.db, &id).await {
+        Ok(lockout) => {
+            tracing::info!("Login for {} {} unlocked by {}", lockout.kind, lockout.value, admin.username);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to unlock login: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
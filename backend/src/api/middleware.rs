@@ -1,33 +1,57 @@
 use axum::{
     async_trait,
-    extract::FromRequestParts,
-    http::{header::AUTHORIZATION, request::Parts, StatusCode},
+    extract::{ConnectInfo, FromRequestParts},
+    http::{
+        header::{AUTHORIZATION, USER_AGENT},
+        request::Parts,
+        Method, StatusCode, Uri,
+    },
     response::{IntoResponse, Response},
     Json,
 };
 use serde::Serialize;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
 
-use crate::services::auth::{extract_bearer_token, validate_jwt, AuthError, Claims};
+use crate::services::api_token::{self, is_api_token, scope_allows};
+use crate::services::auth::{extract_bearer_token, validate_jwt, AuthError};
+use crate::services::session::get_active_session;
+use crate::services::user::get_user_by_id;
 use crate::AppState;
 
-/// Authenticated user extracted from JWT
+/// Authenticated user extracted from a JWT or a personal API token
 #[derive(Debug, Clone)]
 pub struct AuthUser {
     pub id: String,
     pub username: String,
     pub is_admin: bool,
+    /// Session the access token belongs to, None for API tokens
+    pub session_id: Option<String>,
+    /// Scopes of an API token, None for interactive sessions (full access)
+    pub scopes: Option<Vec<String>>,
+    /// Only routes marked `PasswordChangeRoute` are reachable until the password is changed
+    pub must_change_password: bool,
 }
 
-impl From<Claims> for AuthUser {
-    fn from(claims: Claims) -> Self {
-        Self {
-            id: claims.sub,
-            username: claims.username,
-            is_admin: claims.is_admin,
+impl AuthUser {
+    /// Whether the credential grants access to an API area
+    pub fn has_scope(&self, area: &str, write: bool) -> bool {
+        match &self.scopes {
+            Some(scopes) => scope_allows(scopes, area, write),
+            None => true,
         }
     }
 }
 
+/// API area of a router, checked against the scopes of API tokens
+/// Routers without one cannot be reached with an API token
+#[derive(Debug, Clone, Copy)]
+pub struct ApiScope(pub &'static str);
+
+/// Marks routes still reachable while a password change is required
+#[derive(Debug, Clone, Copy)]
+pub struct PasswordChangeRoute;
+
 /// Error response for authentication failures
 #[derive(Debug, Serialize)]
 pub struct AuthErrorResponse {
@@ -45,6 +69,8 @@ impl IntoResponse for AuthError {
             AuthError::TokenGenerationError(_) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, "AUTH_ERROR")
             }
+            AuthError::SessionRevoked => (StatusCode::UNAUTHORIZED, "SESSION_REVOKED"),
+            AuthError::SessionError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "AUTH_ERROR"),
         };
 
         let body = AuthErrorResponse {
@@ -91,13 +117,89 @@ impl FromRequestParts<AppState> for AuthUser {
                 .into_response()
         })?;
 
-        // Validate JWT
-        let claims = validate_jwt(token, &state.config).map_err(|e| e.into_response())?;
+        let user = authenticate_token(state, token).await.map_err(|e| e.into_response())?;
 
-        Ok(AuthUser::from(claims))
+        if user.scopes.is_some() {
+            let write = !matches!(parts.method, Method::GET | Method::HEAD | Method::OPTIONS);
+            let allowed = parts
+                .extensions
+                .get::<ApiScope>()
+                .is_some_and(|ApiScope(area)| user.has_scope(area, write));
+            if !allowed {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    Json(AuthErrorResponse {
+                        error: "API token does not grant access to this endpoint".to_string(),
+                        code: "INSUFFICIENT_SCOPE".to_string(),
+                    }),
+                )
+                    .into_response());
+            }
+        }
+
+        if user.must_change_password && parts.extensions.get::<PasswordChangeRoute>().is_none() {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(AuthErrorResponse {
+                    error: "Password change required".to_string(),
+                    code: "PASSWORD_CHANGE_REQUIRED".to_string(),
+                }),
+            )
+                .into_response());
+        }
+
+        Ok(user)
     }
 }
 
+/// Validate a raw JWT and return the user it was issued to
+/// The session must still exist, so logout and revocation take effect immediately;
+/// username and role come from the database rather than the token
+/// Used by the extractors and by connections that cannot send headers (WebSocket)
+pub async fn authenticate_token(state: &AppState, token: &str) -> Result<AuthUser, AuthError> {
+    if is_api_token(token) {
+        return authenticate_api_token(state, token).await;
+    }
+
+    let claims = validate_jwt(token, &state.config)?;
+
+    let session = get_active_session(&state.db, &claims.sid)
+        .await
+        .map_err(|e| AuthError::SessionError(e.to_string()))?
+        .filter(|session| session.user_id == claims.sub)
+        .ok_or(AuthError::SessionRevoked)?;
+
+    Ok(AuthUser {
+        id: session.user_id,
+        username: session.username,
+        is_admin: session.is_admin,
+        session_id: Some(claims.sid),
+        scopes: None,
+        must_change_password: session.must_change_password,
+    })
+}
+
+async fn authenticate_api_token(state: &AppState, secret: &str) -> Result<AuthUser, AuthError> {
+    let token = api_token::authenticate(&state.db, secret)
+        .await
+        .map_err(|e| AuthError::SessionError(e.to_string()))?
+        .ok_or(AuthError::InvalidToken)?;
+
+    let user = get_user_by_id(&state.db, &token.user_id)
+        .await
+        .map_err(|e| AuthError::SessionError(e.to_string()))?
+        .ok_or(AuthError::InvalidToken)?;
+
+    Ok(AuthUser {
+        id: user.id,
+        username: user.username,
+        is_admin: user.is_admin,
+        session_id: None,
+        scopes: Some(token.scope_list()),
+        must_change_password: user.must_change_password,
+    })
+}
+
 /// Extractor for admin users only
 /// Wraps AuthUser and requires is_admin = true
 #[derive(Debug, Clone)]
@@ -149,3 +251,150 @@ impl FromRequestParts<AppState> for OptionalAuthUser {
         }
     }
 }
+
+/// Address of the client, taken from X-Forwarded-For only when the peer is a trusted proxy
+#[derive(Debug, Clone)]
+pub struct ClientIp(pub String);
+
+#[async_trait]
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let forwarded_for = parts
+            .headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok());
+
+        let ip = resolve_client_ip(peer, forwarded_for, &state.config.trusted_proxies);
+        Ok(ClientIp(
+            ip.map(|ip| ip.to_string()).unwrap_or_else(|| "unknown".to_string()),
+        ))
+    }
+}
+
+/// User-Agent header of the request, recorded with sessions
+#[derive(Debug, Clone)]
+pub struct UserAgent(pub Option<String>);
+
+#[async_trait]
+impl FromRequestParts<AppState> for UserAgent {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &AppState) -> Result<Self, Self::Rejection> {
+        Ok(UserAgent(
+            parts
+                .headers
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.chars().take(256).collect()),
+        ))
+    }
+}
+
+/// Walk X-Forwarded-For from the right, skipping trusted proxies
+/// The first untrusted address is the client; entries left of it may be forged
+pub fn resolve_client_ip(
+    peer: Option<IpAddr>,
+    forwarded_for: Option<&str>,
+    trusted_proxies: &str,
+) -> Option<IpAddr> {
+    let peer = peer?.to_canonical();
+    let Some(forwarded_for) = forwarded_for.filter(|_| is_trusted_proxy(peer, trusted_proxies)) else {
+        return Some(peer);
+    };
+
+    let mut client = peer;
+    for entry in forwarded_for.split(',').rev() {
+        let Ok(ip) = entry.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = ip.to_canonical();
+        if !is_trusted_proxy(client, trusted_proxies) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+/// Check an address against a comma-separated list of IPs and CIDRs
+fn is_trusted_proxy(ip: IpAddr, trusted_proxies: &str) -> bool {
+    trusted_proxies
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            let (addr, prefix) = match entry.split_once('/') {
+                Some((addr, prefix)) => (addr, prefix.parse::<u32>().ok()),
+                None => (entry, None),
+            };
+            match (addr.parse::<IpAddr>(), ip) {
+                (Ok(IpAddr::V4(net)), IpAddr::V4(ip)) => {
+                    let mask = u32::MAX.checked_shl(32 - prefix.unwrap_or(32).min(32)).unwrap_or(0);
+                    u32::from(net) & mask == u32::from(ip) & mask
+                }
+                (Ok(IpAddr::V6(net)), IpAddr::V6(ip)) => {
+                    let mask = u128::MAX.checked_shl(128 - prefix.unwrap_or(128).min(128)).unwrap_or(0);
+                    u128::from(net) & mask == u128::from(ip) & mask
+                }
+                _ => false,
+            }
+        })
+}
+
+/// Request URI with the value of a `token` query parameter hidden, for logs
+/// WebSocket clients may authenticate with `?token=`
+pub fn redact_token(uri: &Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.to_string();
+    };
+    let query: Vec<&str> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some(("token", _)) => "token=[REDACTED]",
+            _ => pair,
+        })
+        .collect();
+    format!("{}?{}", uri.path(), query.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_forwarded_for_only_from_trusted_proxies() {
+        let trusted = "127.0.0.1, 10.0.0.0/8";
+
+        // Untrusted peers cannot spoof their address
+        assert_eq!(resolve_client_ip(ip("203.0.113.5"), Some("1.2.3.4"), trusted), ip("203.0.113.5"));
+        assert_eq!(resolve_client_ip(ip("127.0.0.1"), Some("1.2.3.4"), ""), ip("127.0.0.1"));
+
+        // Trusted proxies are skipped from the right, forged entries on the left are ignored
+        assert_eq!(
+            resolve_client_ip(ip("127.0.0.1"), Some("6.6.6.6, 198.51.100.7, 10.1.2.3"), trusted),
+            ip("198.51.100.7")
+        );
+        assert_eq!(resolve_client_ip(ip("10.0.0.1"), Some("garbage"), trusted), ip("10.0.0.1"));
+        assert_eq!(resolve_client_ip(ip("::ffff:127.0.0.1"), Some("1.2.3.4"), trusted), ip("1.2.3.4"));
+        assert_eq!(resolve_client_ip(None, Some("1.2.3.4"), trusted), None);
+    }
+
+    #[test]
+    fn test_token_is_redacted_from_logged_uris() {
+        let uri: Uri = "/api/ws?token=eyJ.secret&session_id=abc".parse().unwrap();
+        assert_eq!(redact_token(&uri), "/api/ws?token=[REDACTED]&session_id=abc");
+
+        let uri: Uri = "/api/files?path=~/token=x".parse().unwrap();
+        assert_eq!(redact_token(&uri), "/api/files?path=~/token=x");
+        assert_eq!(redact_token(&"/api/ws".parse().unwrap()), "/api/ws");
+    }
+}
@@ -1,15 +1,21 @@
 pub mod apps;
+pub mod audit;
 pub mod auth;
 pub mod docker;
 pub mod files;
 pub mod groups;
+pub mod lockouts;
 pub mod middleware;
+pub mod notifications;
+pub mod oidc;
 pub mod packages;
 pub mod services;
+pub mod settings;
 pub mod setup;
 pub mod shares;
 pub mod storage;
 pub mod system;
 pub mod terminal;
+pub mod tokens;
 pub mod users;
 pub mod ws;
@@ -0,0 +1,388 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::api::middleware::{AdminUser, AuthUser};
+use crate::models::notification::{ChannelConfig, NotificationLevel};
+use crate::services::notification::{
+    count_unread, create_channel as create_channel_service, delete_channel as delete_channel_service,
+    delete_notification as delete_notification_service, delete_rule as delete_rule_service,
+    list_channels as list_channels_service, list_notifications as list_notifications_service,
+    list_rules as list_rules_service, mark_all_read as mark_all_read_service,
+    mark_read as mark_read_service, set_rule, test_channel as test_channel_service,
+    update_channel as update_channel_service, NotificationError, MASKED_SECRET,
+};
+use crate::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_notifications))
+        .route("/unread-count", get(get_unread_count))
+        .route("/read-all", post(mark_all_read))
+        .route("/:id/read", post(mark_read))
+        .route("/:id", delete(delete_notification))
+        // Delivery channels and routing (admin only)
+        .route("/channels", get(list_channels))
+        .route("/channels", post(create_channel))
+        .route("/channels/:id", put(update_channel))
+        .route("/channels/:id", delete(delete_channel))
+        .route("/channels/:id/test", post(test_channel))
+        .route("/rules", get(list_rules))
+        .route("/rules", post(create_rule))
+        .route("/rules/:id", delete(delete_rule))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    #[serde(default)]
+    pub unread_only: bool,
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+}
+
+fn default_limit() -> i64 {
+    100
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnreadCountResponse {
+    pub count: i64,
+}
+
+/// Channel as returned by the API, with its parsed configuration
+#[derive(Debug, Serialize)]
+pub struct ChannelResponse {
+    pub id: String,
+    pub name: String,
+    pub channel_type: String,
+    pub config: serde_json::Value,
+    pub enabled: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<crate::models::notification::NotificationChannelRecord> for ChannelResponse {
+    fn from(channel: crate::models::notification::NotificationChannelRecord) -> Self {
+        let mut config: serde_json::Value =
+            serde_json::from_str(&channel.config).unwrap_or(serde_json::Value::Null);
+        // Never send secrets back to the client
+        for secret in ["password", "token"] {
+            if let Some(value) = config.get_mut(secret) {
+                if !value.is_null() {
+                    *value = serde_json::Value::String(MASKED_SECRET.to_string());
+                }
+            }
+        }
+        // Webhook headers usually carry credentials, only their names are shown
+        if let Some(headers) = config.get_mut("headers").and_then(|h| h.as_object_mut()) {
+            for value in headers.values_mut() {
+                *value = serde_json::Value::String(MASKED_SECRET.to_string());
+            }
+        }
+
+        Self {
+            id: channel.id,
+            name: channel.name,
+            channel_type: channel.channel_type,
+            config,
+            enabled: channel.enabled,
+            created_at: channel.created_at,
+            updated_at: channel.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateChannelRequest {
+    pub name: String,
+    pub config: ChannelConfig,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateChannelRequest {
+    pub name: Option<String>,
+    pub config: Option<ChannelConfig>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRuleRequest {
+    pub channel_id: String,
+    /// Category name or "*" for all categories
+    pub category: String,
+    #[serde(default = "default_min_level")]
+    pub min_level: NotificationLevel,
+}
+
+fn default_min_level() -> NotificationLevel {
+    NotificationLevel::Info
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub code: String,
+}
+
+impl From<NotificationError> for (StatusCode, Json<ErrorResponse>) {
+    fn from(err: NotificationError) -> Self {
+        let (status, code) = match &err {
+            NotificationError::NotFound => (StatusCode::NOT_FOUND, "NOTIFICATION_NOT_FOUND"),
+            NotificationError::ChannelNotFound => (StatusCode::NOT_FOUND, "CHANNEL_NOT_FOUND"),
+            NotificationError::DuplicateChannelName => (StatusCode::CONFLICT, "DUPLICATE_NAME"),
+            NotificationError::InvalidConfig(_) => (StatusCode::BAD_REQUEST, "INVALID_CONFIG"),
+            NotificationError::InvalidLevel(_) => (StatusCode::BAD_REQUEST, "INVALID_LEVEL"),
+            NotificationError::DeliveryFailed(_) => (StatusCode::BAD_GATEWAY, "DELIVERY_FAILED"),
+            NotificationError::DatabaseError(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR")
+            }
+        };
+
+        (
+            status,
+            Json(ErrorResponse {
+                error: err.to_string(),
+                code: code.to_string(),
+            }),
+        )
+    }
+}
+
+/// List notifications for the current user
+async fn list_notifications(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(query): Query<ListQuery>,
+) -> impl IntoResponse {
+    let limit = query.limit.clamp(1, 500);
+    match list_notifications_service(&state.db, &user.id, user.is_admin, query.unread_only, limit)
+        .await
+    {
+        Ok(notifications) => (StatusCode::OK, Json(notifications)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list notifications: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Count unread notifications for the current user
+async fn get_unread_count(State(state): State<AppState>, user: AuthUser) -> impl IntoResponse {
+    match count_unread(&state.db, &user.id, user.is_admin).await {
+        Ok(count) => (StatusCode::OK, Json(UnreadCountResponse { count })).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to count notifications: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Mark a notification as read
+async fn mark_read(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match mark_read_service(&state.db, &id, &user.id, user.is_admin).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Mark all notifications of the current user as read
+async fn mark_all_read(State(state): State<AppState>, user: AuthUser) -> impl IntoResponse {
+    match mark_all_read_service(&state.db, &user.id, user.is_admin).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            tracing::error!("Failed to mark notifications as read: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Delete a notification
+async fn delete_notification(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match delete_notification_service(&state.db, &id, &user.id, user.is_admin).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// List delivery channels (admin only)
+async fn list_channels(State(state): State<AppState>, _admin: AdminUser) -> impl IntoResponse {
+    match list_channels_service(&state.db).await {
+        Ok(channels) => {
+            let response: Vec<ChannelResponse> = channels.into_iter().map(|c| c.into()).collect();
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to list notification channels: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Create a delivery channel (admin only)
+async fn create_channel(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Json(payload): Json<CreateChannelRequest>,
+) -> impl IntoResponse {
+    if payload.name.trim().is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "Channel name is required".to_string(),
+                code: "VALIDATION_ERROR".to_string(),
+            }),
+        )
+            .into_response();
+    }
+
+    match create_channel_service(&state.db, payload.name.trim(), &payload.config, payload.enabled)
+        .await
+    {
+        Ok(channel) => {
+            let response: ChannelResponse = channel.into();
+            (StatusCode::CREATED, Json(response)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to create notification channel: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Update a delivery channel (admin only)
+async fn update_channel(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateChannelRequest>,
+) -> impl IntoResponse {
+    match update_channel_service(&state.db, &id, payload.name, payload.config, payload.enabled).await
+    {
+        Ok(channel) => {
+            let response: ChannelResponse = channel.into();
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to update notification channel: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Delete a delivery channel (admin only)
+async fn delete_channel(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match delete_channel_service(&state.db, &id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            tracing::error!("Failed to delete notification channel: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Send a test notification through a channel (admin only)
+async fn test_channel(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match test_channel_service(&state.db, &id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            tracing::warn!("Notification channel test failed: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// List routing rules (admin only)
+async fn list_rules(State(state): State<AppState>, _admin: AdminUser) -> impl IntoResponse {
+    match list_rules_service(&state.db).await {
+        Ok(rules) => (StatusCode::OK, Json(rules)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list notification rules: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Create or replace a routing rule (admin only)
+async fn create_rule(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Json(payload): Json<CreateRuleRequest>,
+) -> impl IntoResponse {
+    if payload.category.trim().is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "Category is required".to_string(),
+                code: "VALIDATION_ERROR".to_string(),
+            }),
+        )
+            .into_response();
+    }
+
+    match set_rule(&state.db, &payload.channel_id, payload.category.trim(), payload.min_level).await {
+        Ok(rule) => (StatusCode::CREATED, Json(rule)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to create notification rule: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Delete a routing rule (admin only)
+async fn delete_rule(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match delete_rule_service(&state.db, &id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            tracing::error!("Failed to delete notification rule: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
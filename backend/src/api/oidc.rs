@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use serde::Serialize;
+
+use crate::api::middleware::AdminUser;
+use crate::models::oidc::OidcProvider;
+use crate::services::oidc::{self, OidcError, ProviderInput};
+use crate::AppState;
+
+/// Identity provider management, admin only
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/providers", get(list).post(create))
+        .route("/providers/:id", get(show).put(update).delete(remove))
+        .route("/providers/:id/discovery", get(discovery))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProviderResponse {
+    pub id: String,
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    /// The secret itself is never returned
+    pub has_client_secret: bool,
+    pub token_auth_method: String,
+    pub scopes: String,
+    pub username_claim: String,
+    pub groups_claim: String,
+    pub group_mapping: HashMap<String, String>,
+    pub admin_values: Vec<String>,
+    pub allow_provisioning: bool,
+    pub link_by_email: bool,
+    pub enabled: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<OidcProvider> for ProviderResponse {
+    fn from(provider: OidcProvider) -> Self {
+        Self {
+            group_mapping: provider.group_mapping(),
+            admin_values: provider.admin_values(),
+            has_client_secret: provider.client_secret.is_some(),
+            id: provider.id,
+            name: provider.name,
+            issuer: provider.issuer,
+            client_id: provider.client_id,
+            token_auth_method: provider.token_auth_method,
+            scopes: provider.scopes,
+            username_claim: provider.username_claim,
+            groups_claim: provider.groups_claim,
+            allow_provisioning: provider.allow_provisioning,
+            link_by_email: provider.link_by_email,
+            enabled: provider.enabled,
+            created_at: provider.created_at,
+            updated_at: provider.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub code: String,
+}
+
+impl From<OidcError> for (StatusCode, Json<ErrorResponse>) {
+    fn from(err: OidcError) -> Self {
+        let (status, code) = match &err {
+            OidcError::NotFound => (StatusCode::NOT_FOUND, "PROVIDER_NOT_FOUND"),
+            OidcError::DuplicateName => (StatusCode::CONFLICT, "DUPLICATE_NAME"),
+            OidcError::InvalidSettings(_) => (StatusCode::BAD_REQUEST, "INVALID_SETTINGS"),
+            OidcError::Provider(_) | OidcError::Http(_) => (StatusCode::BAD_GATEWAY, "PROVIDER_ERROR"),
+            OidcError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR"),
+            _ => (StatusCode::BAD_REQUEST, "OIDC_ERROR"),
+        };
+
+        (
+            status,
+            Json(ErrorResponse {
+                error: err.to_string(),
+                code: code.to_string(),
+            }),
+        )
+    }
+}
+
+/// List identity providers
+async fn list(State(state): State<AppState>, _admin: AdminUser) -> impl IntoResponse {
+    match oidc::list_providers(&state.db).await {
+        Ok(providers) => {
+            let providers: Vec<ProviderResponse> = providers.into_iter().map(Into::into).collect();
+            (StatusCode::OK, Json(providers)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to list identity providers: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Get an identity provider
+async fn show(State(state): State<AppState>, _admin: AdminUser, Path(id): Path<String>) -> impl IntoResponse {
+    match oidc::get_provider(&state.db, &id).await {
+        Ok(provider) => (StatusCode::OK, Json(ProviderResponse::from(provider))).into_response(),
+        Err(e) => {
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Add an identity provider
+async fn create(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Json(req): Json<ProviderInput>,
+) -> impl IntoResponse {
+    match oidc::create_provider(&state.db, &req).await {
+        Ok(provider) => {
+            tracing::info!("Identity provider {} added", provider.name);
+            (StatusCode::CREATED, Json(ProviderResponse::from(provider))).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to add identity provider: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Replace the settings of an identity provider
+async fn update(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+    Json(req): Json<ProviderInput>,
+) -> impl IntoResponse {
+    match oidc::update_provider(&state.db, &id, &req).await {
+        Ok(provider) => (StatusCode::OK, Json(ProviderResponse::from(provider))).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to update identity provider: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Delete an identity provider, its users keep their accounts
+async fn remove(State(state): State<AppState>, _admin: AdminUser, Path(id): Path<String>) -> impl IntoResponse {
+    match oidc::delete_provider(&state.db, &id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            tracing::error!("Failed to delete identity provider: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Fetch the discovery document of a provider, to check its settings
+async fn discovery(State(state): State<AppState>, _admin: AdminUser, Path(id): Path<String>) -> impl IntoResponse {
+    let provider = match oidc::get_provider(&state.db, &id).await {
+        Ok(provider) => provider,
+        Err(e) => {
+            let (status, json) = e.into();
+            return (status, json).into_response();
+        }
+    };
+    match oidc::discover(&provider.issuer).await {
+        Ok(discovery) => (StatusCode::OK, Json(discovery)).into_response(),
+        Err(e) => {
+            tracing::warn!("Discovery failed for {}: {}", provider.name, e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
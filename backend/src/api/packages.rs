@@ -1,26 +1,52 @@
+use async_trait::async_trait;
 use axum::{
-    extract::{Path, State},
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
-    routing::{delete, get, post},
-    Json, Router,
+    routing::{delete, get, post, put},
+    Extension, Json, Router,
 };
 use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tokio::sync::OnceCell;
 
+use crate::api::audit::AuditNote;
+use crate::api::middleware::AdminUser;
 use crate::models::manifest::{
-    PackageManifest, Requirements, InstallConfig, UninstallConfig, FrontendConfig, WindowConfig
+    Catalog, PackageManifest, Requirements, InstallConfig, UninstallConfig, FrontendConfig, WindowConfig
 };
-use crate::services::package::PackageService;
+use crate::models::package::{CatalogSource, PreflightCheck, PreflightStatus, SigningKey, VerifyReport};
+use crate::services::package::{self, PackageBusy, PackageService};
+use crate::services::package_bundle::{Bundle, BundleError, BUNDLE_EXTENSION};
+use crate::services::package_catalog::{self, CatalogError, SourceInput, SourceUpdate};
+use crate::services::package_deps::{self, ManifestSource, PlannedPackage, ResolveError};
+use crate::services::package_signing::{self, KeyInput, SigningError, TrustPolicy};
+use crate::services::package_tasks;
 use crate::AppState;
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(list_packages))
         .route("/catalog", get(get_catalog))
+        .route("/catalog/refresh", post(refresh_catalog))
+        .route("/catalog/offline", put(set_catalog_offline))
+        .route("/catalog/sources", get(list_catalog_sources).post(create_catalog_source))
+        .route("/catalog/sources/:id", put(update_catalog_source).delete(delete_catalog_source))
         .route("/install", post(install_package))
+        .route("/sideload", post(sideload_package).layer(DefaultBodyLimit::disable()))
+        .route("/updates", get(list_updates))
         .route("/:id", get(get_package))
         .route("/:id", delete(uninstall_package))
+        .route("/:id/update", post(update_package))
+        .route("/:id/preflight", get(preflight_package))
+        .route("/:id/verify", get(verify_package))
+        .route("/:id/repair", post(repair_package))
+        .route("/trust", get(get_trust).put(update_trust))
+        .route("/trust/keys", post(add_signing_key))
+        .route("/trust/keys/:id", delete(revoke_signing_key))
         .route("/task/:id", get(get_task))
+        .route("/task/:id/cancel", post(cancel_task))
+        .route("/task/:id/logs", get(get_task_logs))
 }
 
 /// List installed packages
@@ -37,40 +63,51 @@ async fn list_packages(State(state): State<AppState>) -> impl IntoResponse {
 }
 
 /// Get package catalog from remote, with built-in fallback
-async fn get_catalog(State(_state): State<AppState>) -> impl IntoResponse {
-    let catalog_url = std::env::var("PINAS_CATALOG_URL")
-        .unwrap_or_else(|_| "https://raw.githubusercontent.com/kameka22/pinas-app-catalog/master/catalog.json".to_string());
-
-    tracing::debug!("Fetching catalog from: {}", catalog_url);
-
-    // Try to fetch remote catalog
-    match reqwest::get(&catalog_url).await {
-        Ok(response) => {
-            tracing::debug!("Catalog response status: {}", response.status());
-            if response.status().is_success() {
-                match response.json::<serde_json::Value>().await {
-                    Ok(catalog) => {
-                        let app_count = catalog.get("apps")
-                            .and_then(|a| a.as_array())
-                            .map(|a| a.len())
-                            .unwrap_or(0);
-                        tracing::info!("Loaded remote catalog with {} apps", app_count);
-                        return Json(catalog).into_response();
-                    }
-                    Err(e) => {
-                        tracing::warn!("Failed to parse catalog JSON: {}", e);
-                    }
-                }
-            }
+async fn get_catalog(State(state): State<AppState>) -> impl IntoResponse {
+    match CatalogManifests::new(state.db.clone()).catalog().await {
+        Ok(catalog) => Json(catalog.clone()).into_response(),
+        Err(e) => {
+            tracing::error!("Refusing catalog: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
         }
+    }
+}
+
+/// Revalidate every catalog source now
+async fn refresh_catalog(State(state): State<AppState>, _admin: AdminUser) -> impl IntoResponse {
+    let result = async {
+        let policy = TrustPolicy::load(&state.db).await?;
+        load_catalog(&state.db, &policy, true).await
+    }
+    .await;
+    match result {
+        Ok(catalog) => (StatusCode::OK, Json(catalog)).into_response(),
         Err(e) => {
-            tracing::warn!("Failed to fetch remote catalog: {}", e);
+            tracing::error!("Failed to refresh catalog: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Merge the catalogs of all sources, falling back to the built-in one when none is available.
+/// A catalog failing signature verification is refused rather than replaced.
+async fn load_catalog(db: &SqlitePool, policy: &TrustPolicy, force: bool) -> Result<serde_json::Value, CatalogError> {
+    let cache_dir = package_catalog::cache_dir(&package::data_dir());
+    let mut catalog = package_catalog::load(db, &cache_dir, policy, force).await?;
+
+    if catalog.apps.is_empty() {
+        // Fallback to built-in catalog
+        tracing::info!("Using built-in catalog (no catalog source available)");
+        if let Some(apps) = get_builtin_catalog().get("apps").and_then(|a| a.as_array()) {
+            catalog.apps = apps.clone();
         }
+    } else {
+        tracing::debug!("Loaded catalog with {} apps from {} sources", catalog.apps.len(), catalog.sources.len());
     }
 
-    // Fallback to built-in catalog
-    tracing::info!("Using built-in catalog (remote unavailable)");
-    Json(get_builtin_catalog()).into_response()
+    Ok(serde_json::to_value(catalog).unwrap_or_else(|_| get_builtin_catalog()))
 }
 
 /// Built-in catalog for when remote is unavailable
@@ -126,14 +163,29 @@ pub struct InstallRequest {
 pub struct InstallResponse {
     pub task_id: String,
     pub package_id: String,
+    /// Missing dependencies installed first, in installation order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
 }
 
 /// Install a package
 async fn install_package(
     State(state): State<AppState>,
+    _admin: AdminUser,
     Json(request): Json<InstallRequest>,
 ) -> impl IntoResponse {
-    let service = PackageService::new(state.db.clone()).await;
+    let note = AuditNote {
+        target: request
+            .package_id
+            .clone()
+            .or_else(|| request.manifest.as_ref().map(|m| m.id.clone())),
+        ..Default::default()
+    };
+    (Extension(note), start_install(state, request).await)
+}
+
+async fn start_install(state: AppState, request: InstallRequest) -> axum::response::Response {
+    let service = PackageService::new(state.db.clone()).await.with_events(state.events.clone());
 
     // Initialize directories
     if let Err(e) = service.init_directories().await {
@@ -143,13 +195,27 @@ async fn install_package(
         }))).into_response();
     }
 
+    let source = CatalogManifests::new(state.db.clone());
+    let policy = match source.policy().await {
+        Ok(policy) => policy,
+        Err(e) => {
+            tracing::error!("Failed to load package trust policy: {}", e);
+            let (status, json) = e.into();
+            return (status, json).into_response();
+        }
+    };
+
     // Get manifest - try multiple methods
     let (manifest, manifest_url) = if let Some(manifest) = request.manifest {
-        // Inline manifest provided
+        // Inline manifests cannot carry a signature
+        if !policy.allow_unsigned {
+            let (status, json) = SigningError::Unsigned(format!("Inline manifest {}", manifest.id)).into();
+            return (status, json).into_response();
+        }
         (manifest, request.manifest_url)
     } else if let Some(url) = &request.manifest_url {
         // Manifest URL provided
-        match fetch_manifest(url).await {
+        match fetch_manifest(policy, url).await {
             Ok(manifest) => (manifest, Some(url.clone())),
             Err(e) => {
                 tracing::error!("Failed to fetch manifest: {}", e);
@@ -160,7 +226,7 @@ async fn install_package(
         }
     } else if let Some(package_id) = &request.package_id {
         // Package ID provided - resolve from catalog or use built-in
-        match resolve_package_manifest(package_id).await {
+        match source.manifest(package_id).await {
             Ok((manifest, url)) => (manifest, url),
             Err(e) => {
                 tracing::error!("Failed to resolve package {}: {}", package_id, e);
@@ -175,49 +241,220 @@ async fn install_package(
         }))).into_response();
     };
 
-    // Install package
-    match service.install(&manifest, manifest_url.as_deref()).await {
-        Ok(task_id) => Json(InstallResponse {
-            task_id,
-            package_id: manifest.id,
-        }).into_response(),
+    install_with_dependencies(&state, service, &source, PlannedPackage { manifest, manifest_url }).await
+}
+
+/// Install a package after its missing dependencies, resolved from the catalog, as a background task.
+/// The bundle of the service, if any, is removed once the install is over.
+async fn install_with_dependencies(
+    state: &AppState,
+    mut service: PackageService,
+    source: &CatalogManifests,
+    root: PlannedPackage,
+) -> axum::response::Response {
+    let (task_id, plan) = match plan_install(&service, source, root).await {
+        Ok(prepared) => prepared,
+        Err(response) => {
+            if let Some(bundle) = service.take_bundle() {
+                bundle.remove().await;
+            }
+            return response;
+        }
+    };
+
+    let response = InstallResponse {
+        task_id: task_id.clone(),
+        package_id: plan[plan.len() - 1].manifest.id.clone(),
+        dependencies: plan[..plan.len() - 1].iter().map(|p| p.manifest.id.clone()).collect(),
+    };
+    state.package_tasks.spawn(&response.task_id, move |cancel| async move {
+        let mut service = service.with_cancel(cancel);
+        if let Err(e) = service.run_install(&task_id, &plan).await {
+            tracing::error!("Failed to install package: {:#}", e);
+        }
+        if let Some(bundle) = service.take_bundle() {
+            bundle.remove().await;
+        }
+    });
+    (StatusCode::ACCEPTED, Json(response)).into_response()
+}
+
+/// Resolve the missing dependencies of a package and create the task installing them along with it
+async fn plan_install(
+    service: &PackageService,
+    source: &CatalogManifests,
+    root: PlannedPackage,
+) -> Result<(String, Vec<PlannedPackage>), axum::response::Response> {
+    let installed = match service.installed_versions().await {
+        Ok(installed) => installed,
+        Err(e) => {
+            tracing::error!("Failed to list installed packages: {}", e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                "error": e.to_string()
+            }))).into_response());
+        }
+    };
+    let package_id = root.manifest.id.clone();
+    let plan = match package_deps::resolve(root, &installed, source).await {
+        Ok(plan) => plan,
+        Err(e) => {
+            tracing::error!("Failed to resolve dependencies of {}: {}", package_id, e);
+            let status = match e {
+                ResolveError::InvalidDependency(_) => StatusCode::BAD_REQUEST,
+                ResolveError::Cycle(_) | ResolveError::Conflict { .. } => StatusCode::CONFLICT,
+                ResolveError::Manifest(..) => StatusCode::BAD_GATEWAY,
+            };
+            return Err((status, Json(serde_json::json!({
+                "error": e.to_string()
+            }))).into_response());
+        }
+    };
+
+    match service.prepare_install(&plan).await {
+        Ok(task_id) => Ok((task_id, plan)),
         Err(e) => {
             tracing::error!("Failed to install package: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
                 "error": e.to_string()
-            }))).into_response()
+            }))).into_response())
         }
     }
 }
 
+/// Install an uploaded `.pinaspkg` bundle, its downloads and images coming from the bundle
+async fn sideload_package(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let service = PackageService::new(state.db.clone()).await.with_events(state.events.clone());
+    if let Err(e) = service.init_directories().await {
+        tracing::error!("Failed to init directories: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+            "error": e.to_string()
+        }))).into_response();
+    }
+
+    let upload_id = uuid::Uuid::new_v4().to_string();
+    let archive = service.sideload_dir().join(format!("{}.{}", upload_id, BUNDLE_EXTENSION));
+    let received = receive_bundle(&mut multipart, &archive).await;
+
+    let source = CatalogManifests::new(state.db.clone());
+    let opened = match received {
+        Ok(()) => match source.policy().await {
+            Ok(policy) => Bundle::open(&archive, &service.sideload_dir().join(&upload_id), policy).await,
+            Err(e) => Err(BundleError::Signing(e)),
+        },
+        Err(e) => Err(e),
+    };
+    if let Err(e) = tokio::fs::remove_file(&archive).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!("Failed to remove uploaded bundle {}: {}", archive.display(), e);
+        }
+    }
+    let bundle = match opened {
+        Ok(bundle) => bundle,
+        Err(e) => {
+            tracing::error!("Refusing package bundle: {}", e);
+            let status = match &e {
+                BundleError::Invalid(_) => StatusCode::BAD_REQUEST,
+                BundleError::Signing(e) => signing_status(e).0,
+                BundleError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            return (status, Json(serde_json::json!({
+                "error": e.to_string()
+            }))).into_response();
+        }
+    };
+
+    tracing::info!("Sideloading {} {}", bundle.manifest.id, bundle.manifest.version);
+    let note = AuditNote {
+        target: Some(bundle.manifest.id.clone()),
+        details: Some(format!("sideloaded {}", bundle.manifest.version)),
+        ..Default::default()
+    };
+    let root = PlannedPackage {
+        manifest: bundle.manifest.clone(),
+        manifest_url: None,
+    };
+    let service = service.with_bundle(bundle);
+    let response = install_with_dependencies(&state, service, &source, root).await;
+    (Extension(note), response).into_response()
+}
+
+/// Write the `bundle` field of an upload to disk as it arrives
+async fn receive_bundle(multipart: &mut Multipart, dest: &std::path::Path) -> Result<(), BundleError> {
+    use tokio::io::AsyncWriteExt;
+
+    let invalid = |e: axum::extract::multipart::MultipartError| BundleError::Invalid(e.to_string());
+    while let Some(mut field) = multipart.next_field().await.map_err(invalid)? {
+        if field.name() != Some("bundle") {
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::File::create(dest).await?;
+        while let Some(chunk) = field.chunk().await.map_err(invalid)? {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        return Ok(());
+    }
+    Err(BundleError::Invalid("the upload has no bundle field".to_string()))
+}
+
 /// Resolve package manifest from package ID
 /// First tries catalog, then falls back to built-in manifests
-async fn resolve_package_manifest(package_id: &str) -> anyhow::Result<(PackageManifest, Option<String>)> {
-    // Try to fetch from catalog first
-    let catalog_url = std::env::var("PINAS_CATALOG_URL")
-        .unwrap_or_else(|_| "https://raw.githubusercontent.com/kameka22/pinas-app-catalog/master/catalog.json".to_string());
-
-    if let Ok(response) = reqwest::get(&catalog_url).await {
-        if response.status().is_success() {
-            if let Ok(catalog) = response.json::<serde_json::Value>().await {
-                if let Some(apps) = catalog.get("apps").and_then(|a| a.as_array()) {
-                    for app in apps {
-                        if app.get("id").and_then(|i| i.as_str()) == Some(package_id) {
-                            if let Some(manifest_url) = app.get("manifest").and_then(|m| m.as_str()) {
-                                let manifest = fetch_manifest(manifest_url).await?;
-                                return Ok((manifest, Some(manifest_url.to_string())));
-                            }
-                        }
-                    }
-                }
-            }
+async fn resolve_package_manifest(db: &SqlitePool, package_id: &str) -> anyhow::Result<(PackageManifest, Option<String>)> {
+    CatalogManifests::new(db.clone()).manifest(package_id).await
+}
+
+/// Manifests of catalog apps, the trust policy and catalog being loaded at most once
+struct CatalogManifests {
+    db: SqlitePool,
+    policy: OnceCell<TrustPolicy>,
+    catalog: OnceCell<serde_json::Value>,
+}
+
+impl CatalogManifests {
+    fn new(db: SqlitePool) -> Self {
+        Self {
+            db,
+            policy: OnceCell::new(),
+            catalog: OnceCell::new(),
         }
     }
 
-    // Fallback to built-in manifests for known packages
-    match package_id {
-        "docker" => Ok((get_docker_manifest(), None)),
-        _ => anyhow::bail!("Unknown package: {}. Catalog unavailable.", package_id),
+    async fn policy(&self) -> Result<&TrustPolicy, SigningError> {
+        self.policy.get_or_try_init(|| TrustPolicy::load(&self.db)).await
+    }
+
+    async fn catalog(&self) -> Result<&serde_json::Value, CatalogError> {
+        let policy = self.policy().await?;
+        self.catalog.get_or_try_init(|| load_catalog(&self.db, policy, false)).await
+    }
+}
+
+#[async_trait]
+impl ManifestSource for CatalogManifests {
+    async fn manifest(&self, package_id: &str) -> anyhow::Result<(PackageManifest, Option<String>)> {
+        let catalog = self.catalog().await?;
+        let manifest_url = catalog.get("apps")
+            .and_then(|a| a.as_array())
+            .and_then(|apps| apps.iter().find(|app| app.get("id").and_then(|i| i.as_str()) == Some(package_id)))
+            .and_then(|app| app.get("manifest"))
+            .and_then(|m| m.as_str());
+        if let Some(manifest_url) = manifest_url {
+            let manifest = fetch_manifest(self.policy().await?, manifest_url).await?;
+            return Ok((manifest, Some(manifest_url.to_string())));
+        }
+
+        // Fallback to built-in manifests for known packages
+        match package_id {
+            "docker" => Ok((get_docker_manifest(), None)),
+            _ => anyhow::bail!("Unknown package: {}. Catalog unavailable.", package_id),
+        }
     }
 }
 
@@ -329,6 +566,7 @@ fn get_docker_manifest() -> PackageManifest {
             image: None,
             container: None,
         },
+        upgrade: None,
         uninstall: UninstallConfig::default(),
         files: HashMap::new(),
         config: HashMap::new(),
@@ -342,25 +580,326 @@ fn get_docker_manifest() -> PackageManifest {
     }
 }
 
-/// Uninstall a package
+/// Uninstall query
+#[derive(Debug, Deserialize)]
+pub struct UninstallQuery {
+    /// Also uninstall the packages depending on this one
+    #[serde(default)]
+    pub cascade: bool,
+    /// Leave the user data of the packages on disk
+    #[serde(default)]
+    pub keep_data: bool,
+}
+
+/// Uninstall package response
+#[derive(Debug, Serialize)]
+pub struct UninstallResponse {
+    pub task_id: String,
+    pub package_id: String,
+    /// Packages removed by the task, in removal order
+    pub removed: Vec<String>,
+}
+
+/// Uninstall a package as a background task
 async fn uninstall_package(
     State(state): State<AppState>,
+    _admin: AdminUser,
     Path(id): Path<String>,
+    Query(query): Query<UninstallQuery>,
 ) -> impl IntoResponse {
+    let service = PackageService::new(state.db.clone()).await.with_events(state.events.clone());
+
+    // Packages others depend on are only removed along with them
+    if !query.cascade {
+        match service.dependents(&id).await {
+            Ok(dependents) if !dependents.is_empty() => {
+                return (StatusCode::CONFLICT, Json(serde_json::json!({
+                    "error": format!("Package {} is required by {}", id, dependents.join(", ")),
+                    "dependents": dependents
+                }))).into_response();
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!("Failed to list dependents of {}: {}", id, e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+            }
+        }
+    }
+
+    let pending = match service.prepare_uninstall(&id, query.cascade).await {
+        Ok(pending) => pending,
+        Err(e) => {
+            tracing::error!("Failed to uninstall package: {:#}", e);
+            return (operation_status(&e), format!("{:#}", e)).into_response();
+        }
+    };
+    let removed = pending.order.clone();
+
+    let note = AuditNote {
+        target: Some(id.clone()),
+        details: query.cascade.then(|| format!("Uninstalled {}", removed.join(", "))),
+        ..Default::default()
+    };
+    let response = UninstallResponse {
+        task_id: pending.task_id.clone(),
+        package_id: id,
+        removed,
+    };
+    state.package_tasks.spawn(&response.task_id, move |cancel| async move {
+        if let Err(e) = service.with_cancel(cancel).run_uninstall(pending, query.keep_data).await {
+            tracing::error!("Failed to uninstall package: {:#}", e);
+        }
+    });
+    (Extension(note), (StatusCode::ACCEPTED, Json(response))).into_response()
+}
+
+/// Status of a failed package operation, a package held by another task being a conflict
+fn operation_status(e: &anyhow::Error) -> StatusCode {
+    if e.chain().any(|cause| cause.is::<PackageBusy>()) {
+        StatusCode::CONFLICT
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// List installed packages with a newer version in the catalog
+async fn list_updates(State(state): State<AppState>, _admin: AdminUser) -> impl IntoResponse {
+    let catalog = match CatalogManifests::new(state.db.clone()).catalog().await {
+        Ok(catalog) => catalog.clone(),
+        Err(e) => {
+            tracing::error!("Refusing catalog: {}", e);
+            let (status, json) = e.into();
+            return (status, json).into_response();
+        }
+    };
+    let catalog: Catalog = match serde_json::from_value(catalog) {
+        Ok(catalog) => catalog,
+        Err(e) => {
+            tracing::error!("Failed to parse catalog: {}", e);
+            return (StatusCode::BAD_GATEWAY, Json(serde_json::json!({
+                "error": format!("Invalid catalog: {}", e)
+            }))).into_response();
+        }
+    };
+
     let service = PackageService::new(state.db.clone()).await;
+    match service.check_updates(&catalog).await {
+        Ok(updates) => Json(updates).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to check package updates: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                "error": e.to_string()
+            }))).into_response()
+        }
+    }
+}
 
-    match service.uninstall(&id).await {
-        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+/// Check a catalog package against the device before installing it, dependencies included
+async fn preflight_package(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let service = PackageService::new(state.db.clone()).await;
+    let source = CatalogManifests::new(state.db.clone());
+
+    let (manifest, manifest_url) = match source.manifest(&id).await {
+        Ok(resolved) => resolved,
         Err(e) => {
-            tracing::error!("Failed to uninstall package: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+            tracing::error!("Failed to resolve package {}: {}", id, e);
+            return (StatusCode::BAD_REQUEST, Json(serde_json::json!({
+                "error": format!("Failed to resolve package: {}", e)
+            }))).into_response();
         }
+    };
+    let mut report = service.preflight(&manifest).await;
+
+    let installed = match service.installed_versions().await {
+        Ok(installed) => installed,
+        Err(e) => {
+            tracing::error!("Failed to list installed packages: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                "error": e.to_string()
+            }))).into_response();
+        }
+    };
+    match package_deps::resolve(PlannedPackage { manifest, manifest_url }, &installed, &source).await {
+        Ok(plan) => {
+            let dependencies = &plan[..plan.len() - 1];
+            for dependency in dependencies {
+                for mut check in service.preflight(&dependency.manifest).await.checks {
+                    check.message = format!("{}: {}", dependency.manifest.name, check.message);
+                    report.push(check);
+                }
+            }
+            report.dependencies = dependencies.iter().map(|p| p.manifest.id.clone()).collect();
+            let message = if report.dependencies.is_empty() {
+                "All dependencies are installed".to_string()
+            } else {
+                format!("Also installs {}", report.dependencies.join(", "))
+            };
+            report.push(PreflightCheck {
+                check: "dependencies".to_string(),
+                status: PreflightStatus::Pass,
+                required: None,
+                actual: None,
+                message,
+            });
+        }
+        Err(e) => report.push(PreflightCheck {
+            check: "dependencies".to_string(),
+            status: PreflightStatus::Fail,
+            required: None,
+            actual: None,
+            message: e.to_string(),
+        }),
     }
+
+    Json(report).into_response()
+}
+
+/// Update an installed package to the catalog version
+async fn update_package(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let note = AuditNote {
+        target: Some(id.clone()),
+        ..Default::default()
+    };
+    (Extension(note), start_update(state, id).await)
+}
+
+async fn start_update(state: AppState, id: String) -> axum::response::Response {
+    let service = PackageService::new(state.db.clone()).await.with_events(state.events.clone());
+
+    let (manifest, manifest_url) = match resolve_package_manifest(&state.db, &id).await {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            tracing::error!("Failed to resolve package {}: {}", id, e);
+            return (StatusCode::BAD_REQUEST, Json(serde_json::json!({
+                "error": format!("Failed to resolve package: {}", e)
+            }))).into_response();
+        }
+    };
+
+    let pending = match service.prepare_update(&manifest).await {
+        Ok(pending) => pending,
+        Err(e) => {
+            tracing::error!("Failed to update package {}: {}", id, e);
+            return (operation_status(&e), Json(serde_json::json!({
+                "error": e.to_string()
+            }))).into_response();
+        }
+    };
+
+    let response = InstallResponse {
+        task_id: pending.task_id.clone(),
+        package_id: manifest.id.clone(),
+        dependencies: Vec::new(),
+    };
+    state.package_tasks.spawn(&response.task_id, move |cancel| async move {
+        let service = service.with_cancel(cancel);
+        if let Err(e) = service.run_update(pending, &manifest, manifest_url.as_deref()).await {
+            tracing::error!("Failed to update package {}: {:#}", id, e);
+        }
+    });
+    (StatusCode::ACCEPTED, Json(response)).into_response()
+}
+
+/// Compare an installed package with what its install created
+async fn verify_package(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let service = PackageService::new(state.db.clone()).await;
+
+    match service.get_installed(&id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "Package not found").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to get package: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    }
+
+    match service.verify(&id).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to verify package {}: {:#}", id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                "error": format!("{:#}", e)
+            }))).into_response()
+        }
+    }
+}
+
+/// Repair package response
+#[derive(Debug, Serialize)]
+pub struct RepairResponse {
+    /// Task restoring the package, none when it is intact
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    pub package_id: String,
+    /// Verification the repair is based on
+    pub report: VerifyReport,
+}
+
+/// Run again the install steps restoring what a package is missing
+async fn repair_package(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let service = PackageService::new(state.db.clone()).await.with_events(state.events.clone());
+
+    match service.get_installed(&id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "Package not found").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to get package: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    }
+
+    let (report, pending) = match service.prepare_repair(&id).await {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            tracing::error!("Failed to repair package {}: {:#}", id, e);
+            return (operation_status(&e), Json(serde_json::json!({
+                "error": format!("{:#}", e)
+            }))).into_response();
+        }
+    };
+    let Some(pending) = pending else {
+        let response = RepairResponse { task_id: None, package_id: id, report };
+        return (StatusCode::OK, Json(response)).into_response();
+    };
+
+    let note = AuditNote {
+        target: Some(id.clone()),
+        ..Default::default()
+    };
+    let task_id = pending.task_id.clone();
+    let response = RepairResponse {
+        task_id: Some(task_id.clone()),
+        package_id: id.clone(),
+        report,
+    };
+    state.package_tasks.spawn(&task_id, move |cancel| async move {
+        if let Err(e) = service.with_cancel(cancel).run_repair(pending).await {
+            tracing::error!("Failed to repair package {}: {:#}", id, e);
+        }
+    });
+    (Extension(note), (StatusCode::ACCEPTED, Json(response))).into_response()
 }
 
 /// Get installation task status
 async fn get_task(
     State(state): State<AppState>,
+    _admin: AdminUser,
     Path(id): Path<String>,
 ) -> impl IntoResponse {
     let service = PackageService::new(state.db.clone()).await;
@@ -375,12 +914,326 @@ async fn get_task(
     }
 }
 
-/// Fetch manifest from URL
-async fn fetch_manifest(url: &str) -> anyhow::Result<PackageManifest> {
-    let response = reqwest::get(url).await?;
-    if !response.status().is_success() {
-        anyhow::bail!("HTTP {}", response.status());
+/// Stop a running task, a child task stopping its whole install
+async fn cancel_task(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let service = PackageService::new(state.db.clone()).await;
+
+    let task = match service.get_task(&id).await {
+        Ok(Some(task)) => task,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Task not found").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to get task: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    };
+
+    let root_id = task.parent_task_id.as_deref().unwrap_or(&task.id);
+    if task.status != "running" || !state.package_tasks.cancel(root_id) {
+        return (StatusCode::CONFLICT, Json(serde_json::json!({
+            "error": format!("Task {} is not running", id)
+        }))).into_response();
+    }
+
+    let note = AuditNote {
+        target: Some(task.package_id.clone()),
+        details: Some(format!("cancelled {} task", task.task_type)),
+        ..Default::default()
+    };
+    (Extension(note), (StatusCode::ACCEPTED, Json(task))).into_response()
+}
+
+/// Task log query
+#[derive(Debug, Deserialize)]
+pub struct TaskLogQuery {
+    /// Only return lines written after this one
+    #[serde(default)]
+    pub after: i64,
+}
+
+/// Get the output of a task's steps, live lines being pushed over WebSocket
+async fn get_task_logs(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+    Query(query): Query<TaskLogQuery>,
+) -> impl IntoResponse {
+    match package_tasks::list_logs(&state.db, &id, query.after).await {
+        Ok(logs) => Json(logs).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to get task logs: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
     }
-    let manifest = response.json::<PackageManifest>().await?;
+}
+
+/// Fetch manifest from URL, verifying its signature
+async fn fetch_manifest(policy: &TrustPolicy, url: &str) -> anyhow::Result<PackageManifest> {
+    let body = package_signing::fetch_verified(policy, url).await?;
+    let manifest = serde_json::from_slice::<PackageManifest>(&body)?;
     Ok(manifest)
 }
+
+/// Trust settings for catalogs and manifests
+#[derive(Debug, Serialize)]
+pub struct TrustResponse {
+    pub allow_unsigned: bool,
+    pub keys: Vec<SigningKey>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrustUpdate {
+    pub allow_unsigned: bool,
+}
+
+/// Get trusted signing keys and whether unsigned packages are allowed
+async fn get_trust(State(state): State<AppState>, _admin: AdminUser) -> impl IntoResponse {
+    let result = async {
+        Ok::<_, SigningError>(TrustResponse {
+            allow_unsigned: package_signing::allow_unsigned(&state.db).await?,
+            keys: package_signing::list_keys(&state.db).await?,
+        })
+    }
+    .await;
+    match result {
+        Ok(trust) => (StatusCode::OK, Json(trust)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to load package trust settings: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Allow or refuse unsigned catalogs and manifests
+async fn update_trust(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Json(req): Json<TrustUpdate>,
+) -> impl IntoResponse {
+    match package_signing::set_allow_unsigned(&state.db, req.allow_unsigned).await {
+        Ok(()) => {
+            if req.allow_unsigned {
+                tracing::warn!("Unsigned package catalogs and manifests are now allowed");
+            }
+            get_trust(State(state), _admin).await.into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to update package trust settings: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Trust a new signing key
+async fn add_signing_key(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Json(req): Json<KeyInput>,
+) -> impl IntoResponse {
+    match package_signing::add_key(&state.db, &req).await {
+        Ok(key) => {
+            tracing::info!("Trusting package signing key {} ({})", key.name, key.id);
+            let note = AuditNote {
+                target: Some(key.id.clone()),
+                details: Some(key.name.clone()),
+                ..Default::default()
+            };
+            (StatusCode::CREATED, Extension(note), Json(key)).into_response()
+        }
+        Err(e) => {
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Revoke a signing key, documents it signed are refused from now on
+async fn revoke_signing_key(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match package_signing::revoke_key(&state.db, &id).await {
+        Ok(key) => {
+            tracing::info!("Revoked package signing key {} ({})", key.name, key.id);
+            (StatusCode::OK, Json(key)).into_response()
+        }
+        Err(e) => {
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Catalog sources and whether they are contacted
+#[derive(Debug, Serialize)]
+pub struct CatalogSourcesResponse {
+    pub offline: bool,
+    pub sources: Vec<CatalogSource>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OfflineUpdate {
+    pub offline: bool,
+}
+
+async fn list_catalog_sources(State(state): State<AppState>, _admin: AdminUser) -> impl IntoResponse {
+    let result = async {
+        Ok::<_, CatalogError>(CatalogSourcesResponse {
+            offline: package_catalog::is_offline(&state.db).await?,
+            sources: package_catalog::list_sources(&state.db).await?,
+        })
+    }
+    .await;
+    match result {
+        Ok(sources) => (StatusCode::OK, Json(sources)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list catalog sources: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+async fn create_catalog_source(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Json(req): Json<SourceInput>,
+) -> impl IntoResponse {
+    match package_catalog::create_source(&state.db, req).await {
+        Ok(source) => {
+            tracing::info!("Added catalog source {} ({})", source.name, source.url);
+            let note = AuditNote {
+                target: Some(source.name.clone()),
+                details: Some(source.url.clone()),
+                ..Default::default()
+            };
+            (StatusCode::CREATED, Extension(note), Json(source)).into_response()
+        }
+        Err(e) => {
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+async fn update_catalog_source(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+    Json(req): Json<SourceUpdate>,
+) -> impl IntoResponse {
+    match package_catalog::update_source(&state.db, &id, req).await {
+        Ok(source) => {
+            let note = AuditNote {
+                target: Some(source.name.clone()),
+                details: Some(source.url.clone()),
+                ..Default::default()
+            };
+            (StatusCode::OK, Extension(note), Json(source)).into_response()
+        }
+        Err(e) => {
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+async fn delete_catalog_source(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let cache_dir = package_catalog::cache_dir(&package::data_dir());
+    match package_catalog::delete_source(&state.db, &cache_dir, &id).await {
+        Ok(()) => {
+            tracing::info!("Removed catalog source {}", id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Serve cached catalogs only, without contacting sources
+async fn set_catalog_offline(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Json(req): Json<OfflineUpdate>,
+) -> impl IntoResponse {
+    match package_catalog::set_offline(&state.db, req.offline).await {
+        Ok(()) => {
+            tracing::info!("Catalog offline mode {}", if req.offline { "enabled" } else { "disabled" });
+            (StatusCode::OK, Json(serde_json::json!({ "offline": req.offline }))).into_response()
+        }
+        Err(e) => {
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub code: String,
+}
+
+impl From<CatalogError> for (StatusCode, Json<ErrorResponse>) {
+    fn from(err: CatalogError) -> Self {
+        let (status, code) = match &err {
+            CatalogError::Signing(e) => signing_status(e),
+            CatalogError::NotFound => (StatusCode::NOT_FOUND, "SOURCE_NOT_FOUND"),
+            CatalogError::Validation(_) => (StatusCode::BAD_REQUEST, "VALIDATION_ERROR"),
+            CatalogError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "CACHE_ERROR"),
+            CatalogError::Settings(_) | CatalogError::DatabaseError(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR")
+            }
+        };
+
+        (
+            status,
+            Json(ErrorResponse {
+                error: err.to_string(),
+                code: code.to_string(),
+            }),
+        )
+    }
+}
+
+fn signing_status(err: &SigningError) -> (StatusCode, &'static str) {
+    match err {
+        SigningError::Unsigned(_) => (StatusCode::FORBIDDEN, "UNSIGNED"),
+        SigningError::UntrustedKey { .. } => (StatusCode::FORBIDDEN, "UNTRUSTED_KEY"),
+        SigningError::InvalidSignature(_) | SigningError::MalformedSignature(_) => {
+            (StatusCode::FORBIDDEN, "INVALID_SIGNATURE")
+        }
+        SigningError::InvalidKey(_) => (StatusCode::BAD_REQUEST, "INVALID_KEY"),
+        SigningError::NotFound => (StatusCode::NOT_FOUND, "KEY_NOT_FOUND"),
+        SigningError::Fetch(_) => (StatusCode::BAD_GATEWAY, "FETCH_FAILED"),
+        SigningError::Settings(_) | SigningError::DatabaseError(_) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR")
+        }
+    }
+}
+
+impl From<SigningError> for (StatusCode, Json<ErrorResponse>) {
+    fn from(err: SigningError) -> Self {
+        let (status, code) = signing_status(&err);
+
+        (
+            status,
+            Json(ErrorResponse {
+                error: err.to_string(),
+                code: code.to_string(),
+            }),
+        )
+    }
+}
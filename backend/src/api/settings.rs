@@ -0,0 +1,76 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, put},
+    Json, Router,
+};
+use serde::Serialize;
+
+use crate::api::middleware::{AdminUser, AuthUser};
+use crate::models::settings::DeviceSettingsUpdate;
+use crate::services::events::WsEvent;
+use crate::services::settings::{get_device_settings, update_device_settings, SettingsError};
+use crate::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_settings))
+        .route("/", put(update_settings))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub code: String,
+}
+
+impl From<SettingsError> for (StatusCode, Json<ErrorResponse>) {
+    fn from(err: SettingsError) -> Self {
+        let (status, code) = match &err {
+            SettingsError::Validation { .. } => (StatusCode::BAD_REQUEST, "VALIDATION_ERROR"),
+            SettingsError::ApplyFailed { .. } => (StatusCode::INTERNAL_SERVER_ERROR, "APPLY_FAILED"),
+            SettingsError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR"),
+        };
+
+        (
+            status,
+            Json(ErrorResponse {
+                error: err.to_string(),
+                code: code.to_string(),
+            }),
+        )
+    }
+}
+
+/// Get device settings
+async fn get_settings(State(state): State<AppState>, _user: AuthUser) -> impl IntoResponse {
+    match get_device_settings(&state.db).await {
+        Ok(settings) => (StatusCode::OK, Json(settings)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to get device settings: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Update device settings (admin only)
+async fn update_settings(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Json(payload): Json<DeviceSettingsUpdate>,
+) -> impl IntoResponse {
+    match update_device_settings(&state.db, state.settings_applier.as_ref(), payload).await {
+        Ok(settings) => {
+            // No subscribers is not an error
+            state.events.publish(WsEvent::SettingsChanged(settings.clone()));
+            (StatusCode::OK, Json(settings)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to update device settings: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
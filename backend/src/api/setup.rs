@@ -5,12 +5,16 @@ use axum::{
     routing::{get, post},
     Json, Router,
 };
-use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::services::auth::generate_jwt;
+use crate::api::auth::{start_session, TokenPair};
+use crate::api::middleware::{ClientIp, UserAgent};
+use crate::models::settings::DeviceSettingsUpdate;
+use crate::services::events::WsEvent;
 use crate::services::group::{add_member, get_group_by_name};
-use crate::services::session::create_session;
+use crate::services::password_policy::PasswordPolicy;
+use crate::services::session::SessionClient;
+use crate::services::settings::{update_device_settings, SettingsError};
 use crate::services::user::{create_user, has_any_users};
 use crate::AppState;
 
@@ -31,11 +35,21 @@ pub struct CompleteSetupRequest {
     pub machine_name: String,
     pub admin_username: String,
     pub admin_password: String,
+    /// Optional device settings, the machine name is used as hostname
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub ntp_servers: Option<Vec<String>>,
+    #[serde(default)]
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub date_format: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct CompleteSetupResponse {
-    pub token: String,
+    #[serde(flatten)]
+    pub tokens: TokenPair,
     pub user: UserInfo,
 }
 
@@ -79,6 +93,8 @@ async fn get_setup_status(State(state): State<AppState>) -> impl IntoResponse {
 /// Complete setup - creates the initial admin user
 async fn complete_setup(
     State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
     Json(payload): Json<CompleteSetupRequest>,
 ) -> impl IntoResponse {
     // Check if setup is already complete
@@ -130,11 +146,12 @@ async fn complete_setup(
             .into_response();
     }
 
-    if payload.admin_password.len() < 6 {
+    let password_policy = PasswordPolicy::from_config(&state.config);
+    if let Err(e) = password_policy.check(&payload.admin_username, &payload.admin_password) {
         return (
             StatusCode::BAD_REQUEST,
             Json(ErrorResponse {
-                error: "Password must be at least 6 characters".to_string(),
+                error: e.to_string(),
                 code: "VALIDATION_ERROR".to_string(),
             }),
         )
@@ -152,6 +169,35 @@ async fn complete_setup(
             .into_response();
     }
 
+    // Apply device settings before creating the admin so invalid values abort the setup
+    let settings_update = DeviceSettingsUpdate {
+        hostname: Some(payload.machine_name.clone()),
+        timezone: payload.timezone.clone(),
+        ntp_servers: payload.ntp_servers.clone(),
+        locale: payload.locale.clone(),
+        date_format: payload.date_format.clone(),
+        ..Default::default()
+    };
+    match update_device_settings(&state.db, state.settings_applier.as_ref(), settings_update).await {
+        Ok(settings) => {
+            state.events.publish(WsEvent::SettingsChanged(settings));
+        }
+        Err(e @ SettingsError::Validation { .. }) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                    code: "VALIDATION_ERROR".to_string(),
+                }),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            tracing::warn!("Failed to apply device settings during setup: {}", e);
+            // Continue anyway - settings can be changed later
+        }
+    }
+
     // Create the admin user
     let user = match create_user(
         &state.db,
@@ -159,6 +205,7 @@ async fn complete_setup(
         &payload.admin_password,
         Some(format!("{}@pinas.local", payload.admin_username)),
         true,
+        &password_policy,
     )
     .await
     {
@@ -184,13 +231,15 @@ async fn complete_setup(
         }
     }
 
-    // TODO: Save machine_name to settings table (for future use)
-    // For now, we just log it
     tracing::info!("Setup complete. Machine name: {}", payload.machine_name);
 
-    // Generate JWT token for auto-login
-    let token = match generate_jwt(&user, &state.config) {
-        Ok(token) => token,
+    // Start a session for auto-login
+    let client = SessionClient {
+        ip: Some(ip),
+        user_agent,
+    };
+    let tokens = match start_session(&state, &user, &client).await {
+        Ok(tokens) => tokens,
         Err(e) => {
             tracing::error!("Failed to generate token: {}", e);
             return (
@@ -204,15 +253,8 @@ async fn complete_setup(
         }
     };
 
-    // Create session
-    let expires_at = Utc::now() + Duration::hours(state.config.jwt_expiration_hours as i64);
-    if let Err(e) = create_session(&state.db, &user.id, &token, expires_at).await {
-        tracing::error!("Failed to create session: {}", e);
-        // Continue anyway - token is still valid
-    }
-
     let response = CompleteSetupResponse {
-        token,
+        tokens,
         user: UserInfo {
             id: user.id,
             username: user.username,
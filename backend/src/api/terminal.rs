@@ -1,14 +1,42 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::post, Json, Router};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use futures_util::{stream::SplitSink, SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::process::Command;
-
+use tokio::sync::broadcast;
+
+use crate::api::audit::AuditNote;
+use crate::api::middleware::{authenticate_token, AdminUser, AuthUser, ClientIp};
+use crate::api::ws::{recheck_session, wait_for_auth, SESSION_CHECK_INTERVAL};
+use crate::models::terminal::TerminalEndReason;
+use crate::services::audit::{self, NewAuditEntry};
+use crate::services::homes::{self, HomesSettings};
+use crate::services::system_user::{
+    allowed_roots, apply_identity, is_within_roots, resolve_for_user, SystemIdentity,
+};
+use crate::services::terminal::{
+    list_session_input, list_session_records, TerminalError, TerminalSession,
+};
+use crate::services::user::get_user_by_id;
 use crate::AppState;
 
 /// Virtual root shown to frontend (always /storage)
 const VIRTUAL_ROOT: &str = "/storage";
 
+/// Virtual path of the caller's home directory ("My Files")
+const VIRTUAL_HOME: &str = "~";
+
 /// Real root in production
 const PROD_ROOT: &str = "/storage";
 
@@ -42,6 +70,33 @@ struct ErrorResponse {
     error: String,
 }
 
+/// Error response for terminal session endpoints
+#[derive(Debug, Serialize)]
+pub struct SessionErrorResponse {
+    pub error: String,
+    pub code: String,
+}
+
+impl From<TerminalError> for (StatusCode, Json<SessionErrorResponse>) {
+    fn from(err: TerminalError) -> Self {
+        let (status, code) = match &err {
+            TerminalError::NotFound => (StatusCode::NOT_FOUND, "SESSION_NOT_FOUND"),
+            TerminalError::TooManySessions(_) => (StatusCode::TOO_MANY_REQUESTS, "TOO_MANY_SESSIONS"),
+            TerminalError::SpawnFailed(_) => (StatusCode::INTERNAL_SERVER_ERROR, "SPAWN_FAILED"),
+            TerminalError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "TERMINAL_IO_ERROR"),
+            TerminalError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR"),
+        };
+
+        (
+            status,
+            Json(SessionErrorResponse {
+                error: err.to_string(),
+                code: code.to_string(),
+            }),
+        )
+    }
+}
+
 /// Dangerous commands/patterns that should be blocked
 const BLOCKED_PATTERNS: &[&str] = &[
     "rm -rf /",
@@ -85,8 +140,17 @@ fn get_real_root(dev_mode: bool) -> PathBuf {
     }
 }
 
-/// Convert virtual path (/storage/...) to real path
-fn virtual_to_real(virtual_path: &str, real_root: &PathBuf) -> PathBuf {
+/// Convert virtual path (/storage/... or ~/...) to real path
+fn virtual_to_real(virtual_path: &str, real_root: &PathBuf, home: Option<&FsPath>) -> PathBuf {
+    if let Some(home) = home {
+        if virtual_path == VIRTUAL_HOME {
+            return home.to_path_buf();
+        }
+        if let Some(suffix) = virtual_path.strip_prefix("~/") {
+            return home.join(suffix);
+        }
+    }
+
     if virtual_path == VIRTUAL_ROOT {
         real_root.clone()
     } else if let Some(suffix) = virtual_path.strip_prefix(&format!("{}/", VIRTUAL_ROOT)) {
@@ -97,9 +161,18 @@ fn virtual_to_real(virtual_path: &str, real_root: &PathBuf) -> PathBuf {
     }
 }
 
-/// Convert real path to virtual path (/storage/...)
-fn real_to_virtual(real_path: &PathBuf, real_root: &PathBuf) -> String {
+/// Convert real path to virtual path (/storage/... or ~/...)
+fn real_to_virtual(real_path: &PathBuf, real_root: &PathBuf, home: Option<&FsPath>) -> String {
     if let Ok(canonical_real) = real_path.canonicalize() {
+        // The home may lie within the root, it is shown as ~ anyway
+        if let Some(Ok(canonical_home)) = home.map(FsPath::canonicalize) {
+            if let Ok(suffix) = canonical_real.strip_prefix(&canonical_home) {
+                if suffix.as_os_str().is_empty() {
+                    return VIRTUAL_HOME.to_string();
+                }
+                return format!("{}/{}", VIRTUAL_HOME, suffix.to_string_lossy());
+            }
+        }
         if let Ok(canonical_root) = real_root.canonicalize() {
             if canonical_real == canonical_root {
                 return VIRTUAL_ROOT.to_string();
@@ -114,7 +187,7 @@ fn real_to_virtual(real_path: &PathBuf, real_root: &PathBuf) -> String {
 }
 
 /// Check if a path is within the allowed root
-fn is_path_within_root(path: &PathBuf, root: &PathBuf) -> bool {
+fn is_path_within_root(path: &FsPath, root: &FsPath) -> bool {
     if let (Ok(canonical_path), Ok(canonical_root)) = (path.canonicalize(), root.canonicalize()) {
         canonical_path.starts_with(&canonical_root)
     } else {
@@ -122,11 +195,40 @@ fn is_path_within_root(path: &PathBuf, root: &PathBuf) -> bool {
     }
 }
 
-/// Execute a terminal command
+/// Check if a path is within the root or the caller's home
+fn is_path_within_roots(path: &FsPath, root: &FsPath, home: Option<&FsPath>) -> bool {
+    is_path_within_root(path, root) || home.is_some_and(|home| is_path_within_root(path, home))
+}
+
+/// Home directory of the caller, created when missing, None when homes are disabled
+async fn caller_home(state: &AppState, user_id: &str) -> Option<PathBuf> {
+    let settings = HomesSettings::from_config(&state.config)?;
+    let user = get_user_by_id(&state.db, user_id).await.ok().flatten()?;
+    match homes::ensure_home(&settings, &user) {
+        Ok(home) => Some(home),
+        Err(e) => {
+            tracing::warn!("Failed to create home of {}: {}", user.username, e);
+            None
+        }
+    }
+}
+
+/// Execute a terminal command, recorded in the audit log
 pub async fn execute(
     State(state): State<AppState>,
+    user: AuthUser,
     Json(req): Json<ExecRequest>,
 ) -> impl IntoResponse {
+    let note = AuditNote {
+        details: Some(req.command.trim().to_string()),
+        ..Default::default()
+    };
+    (Extension(note), run_command(state, user, req).await)
+}
+
+/// Admins run commands as the daemon; other users as their mapped system
+/// account, confined to their home and the shares they are granted
+async fn run_command(state: AppState, user: AuthUser, req: ExecRequest) -> impl IntoResponse {
     let command = req.command.trim();
     let dev_mode = state.config.dev_mode;
     let real_root = get_real_root(dev_mode);
@@ -136,17 +238,40 @@ pub async fn execute(
         let _ = std::fs::create_dir_all(&real_root);
     }
 
-    // Convert virtual cwd to real cwd
-    let real_cwd = virtual_to_real(&req.cwd, &real_root);
+    let home = caller_home(&state, &user.id).await;
+    let home = home.as_deref();
 
-    // Ensure cwd exists and is within root, fallback to root
-    let real_cwd = if real_cwd.exists() && is_path_within_root(&real_cwd, &real_root) {
-        real_cwd
+    let confinement = if user.is_admin {
+        None
     } else {
-        real_root.clone()
+        match user_confinement(&state, &user, &real_root, home).await {
+            Ok(confinement) => Some(confinement),
+            Err(error) => {
+                return (
+                    StatusCode::FORBIDDEN,
+                    Json(ExecResponse {
+                        output: format!("Error: {}", error),
+                        exit_code: 1,
+                        dev_mode,
+                        cwd: VIRTUAL_ROOT.to_string(),
+                    }),
+                );
+            }
+        }
+    };
+
+    // Convert virtual cwd to real cwd
+    let real_cwd = virtual_to_real(&req.cwd, &real_root, home);
+
+    // Ensure cwd exists and is allowed, fallback to the default directory
+    let real_cwd = match &confinement {
+        Some(c) if is_within_roots(&real_cwd, &c.roots) => real_cwd,
+        Some(c) => c.roots[0].clone(),
+        None if real_cwd.exists() && is_path_within_roots(&real_cwd, &real_root, home) => real_cwd,
+        None => real_root.clone(),
     };
 
-    let virtual_cwd = real_to_virtual(&real_cwd, &real_root);
+    let virtual_cwd = real_to_virtual(&real_cwd, &real_root, home);
 
     // Validate command is not empty
     if command.is_empty() {
@@ -176,7 +301,12 @@ pub async fn execute(
 
     // Handle cd command specially
     if command == "cd" || command.starts_with("cd ") {
-        let new_virtual_cwd = handle_cd_command(command, &virtual_cwd, &real_root);
+        let mut new_virtual_cwd = handle_cd_command(command, &virtual_cwd, &real_root, home);
+        if let Some(c) = &confinement {
+            if !is_within_roots(&virtual_to_real(&new_virtual_cwd, &real_root, home), &c.roots) {
+                new_virtual_cwd = virtual_cwd;
+            }
+        }
         return (
             StatusCode::OK,
             Json(ExecResponse {
@@ -189,7 +319,8 @@ pub async fn execute(
     }
 
     // Execute the command with timeout
-    match execute_command(command, &real_cwd).await {
+    let identity = confinement.as_ref().and_then(|c| c.identity.as_ref());
+    match execute_command(command, &real_cwd, identity).await {
         Ok((output, exit_code)) => (
             StatusCode::OK,
             Json(ExecResponse {
@@ -212,7 +343,15 @@ pub async fn execute(
 }
 
 /// Handle cd command and return new virtual working directory
-fn handle_cd_command(command: &str, current_virtual_cwd: &str, real_root: &PathBuf) -> String {
+fn handle_cd_command(
+    command: &str,
+    current_virtual_cwd: &str,
+    real_root: &PathBuf,
+    home: Option<&FsPath>,
+) -> String {
+    // Without a home, ~ stands for the root as before
+    let default_cwd = if home.is_some() { VIRTUAL_HOME } else { VIRTUAL_ROOT };
+
     let target = if command == "cd" {
         // cd without argument goes to root
         String::new()
@@ -223,7 +362,7 @@ fn handle_cd_command(command: &str, current_virtual_cwd: &str, real_root: &PathB
 
     // Calculate new virtual path
     let new_virtual_path = if target.is_empty() || target == "~" {
-        VIRTUAL_ROOT.to_string()
+        default_cwd.to_string()
     } else if target == "-" {
         // cd - not supported, stay in current
         return current_virtual_cwd.to_string();
@@ -237,7 +376,7 @@ fn handle_cd_command(command: &str, current_virtual_cwd: &str, real_root: &PathB
         }
     } else if target.starts_with("~/") {
         // Home-relative path
-        format!("{}/{}", VIRTUAL_ROOT, target.strip_prefix("~/").unwrap())
+        format!("{}/{}", default_cwd, target.strip_prefix("~/").unwrap())
     } else {
         // Relative path
         if current_virtual_cwd == VIRTUAL_ROOT {
@@ -248,35 +387,82 @@ fn handle_cd_command(command: &str, current_virtual_cwd: &str, real_root: &PathB
     };
 
     // Convert to real path to verify it exists and is within root
-    let real_path = virtual_to_real(&new_virtual_path, real_root);
+    let real_path = virtual_to_real(&new_virtual_path, real_root, home);
 
     // Canonicalize to resolve .. and . and verify within root
     match real_path.canonicalize() {
         Ok(canonical) => {
-            if is_path_within_root(&canonical, real_root) {
-                real_to_virtual(&canonical, real_root)
+            if is_path_within_roots(&canonical, real_root, home) {
+                real_to_virtual(&canonical, real_root, home)
             } else {
-                // Trying to escape root, stay at root
-                VIRTUAL_ROOT.to_string()
+                // Trying to escape root, go back to the default directory
+                default_cwd.to_string()
             }
         }
         Err(_) => current_virtual_cwd.to_string(), // Path doesn't exist, stay in current
     }
 }
 
+/// Where a non-admin user's commands may run and as whom
+struct Confinement {
+    /// None in dev mode, commands then run as the daemon's account
+    identity: Option<SystemIdentity>,
+    /// Allowed directories, the first one is the default working directory
+    roots: Vec<PathBuf>,
+}
+
+/// Resolve the system account and allowed directories of a non-admin user
+async fn user_confinement(
+    state: &AppState,
+    user: &AuthUser,
+    real_root: &FsPath,
+    pinas_home: Option<&FsPath>,
+) -> Result<Confinement, String> {
+    let identity = match resolve_for_user(&state.db, &user.id).await {
+        Ok(identity) => Some(identity),
+        Err(e) if state.config.dev_mode => {
+            tracing::debug!("Dev mode: running commands of {} as the daemon ({})", user.username, e);
+            None
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let home = identity.as_ref().map(|i| i.home.as_path());
+    let mut roots = allowed_roots(&state.db, &user.id, home)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // My Files comes first, as the default working directory
+    if let Some(Ok(pinas_home)) = pinas_home.map(FsPath::canonicalize) {
+        roots.retain(|root| *root != pinas_home);
+        roots.insert(0, pinas_home);
+    }
+
+    if roots.is_empty() && state.config.dev_mode {
+        roots.push(real_root.canonicalize().map_err(|e| e.to_string())?);
+    }
+    if roots.is_empty() {
+        return Err("No home directory or share available to this user".to_string());
+    }
+
+    Ok(Confinement { identity, roots })
+}
+
 /// Execute a shell command with timeout in specified directory
-async fn execute_command(command: &str, cwd: &PathBuf) -> Result<(String, i32), String> {
+async fn execute_command(
+    command: &str,
+    cwd: &PathBuf,
+    identity: Option<&SystemIdentity>,
+) -> Result<(String, i32), String> {
     let timeout = Duration::from_secs(30);
 
-    let result = tokio::time::timeout(timeout, async {
-        Command::new("sh")
-            .arg("-c")
-            .arg(command)
-            .current_dir(cwd)
-            .output()
-            .await
-    })
-    .await;
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command).current_dir(cwd).kill_on_drop(true);
+    if let Some(identity) = identity {
+        apply_identity(&mut cmd, identity);
+    }
+
+    let result = tokio::time::timeout(timeout, cmd.output()).await;
 
     match result {
         Ok(Ok(output)) => {
@@ -300,7 +486,355 @@ async fn execute_command(command: &str, cwd: &PathBuf) -> Result<(String, i32),
     }
 }
 
+/// Query parameters of the interactive terminal WebSocket
+#[derive(Debug, Deserialize)]
+pub struct TerminalWsQuery {
+    /// JWT, for clients that authenticate in the URL
+    pub token: Option<String>,
+    /// Reattach to a running session instead of starting a new one
+    pub session_id: Option<String>,
+    #[serde(default = "default_cols")]
+    pub cols: u16,
+    #[serde(default = "default_rows")]
+    pub rows: u16,
+}
+
+fn default_cols() -> u16 {
+    80
+}
+
+fn default_rows() -> u16 {
+    24
+}
+
+/// Control messages sent by the terminal client (raw input may also be sent as binary frames)
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TerminalClientMessage {
+    Input { data: String },
+    Resize { cols: u16, rows: u16 },
+    Close,
+    Ping,
+}
+
+/// Control messages sent by the server (shell output is sent as binary frames)
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TerminalServerMessage {
+    Session { session_id: String, shell: String },
+    Exit { reason: TerminalEndReason },
+    Pong,
+    Error { code: String, error: String },
+}
+
+/// Interactive terminal WebSocket (admin only)
+/// Authentication is done with `?token=` or a first `{"type": "auth"}` message
+pub async fn terminal_ws(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Query(query): Query<TerminalWsQuery>,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| handle_terminal_socket(socket, state, ip, query))
+}
+
+async fn handle_terminal_socket(socket: WebSocket, state: AppState, ip: String, query: TerminalWsQuery) {
+    let (mut sender, mut receiver) = socket.split();
+
+    let auth = match &query.token {
+        Some(token) => authenticate_token(&state, token).await.ok().map(|user| (user, token.clone())),
+        None => wait_for_auth(&state, &mut receiver).await,
+    };
+    let (user, token) = match auth {
+        Some((user, _)) if user.scopes.is_some() => {
+            send_error(&mut sender, "INSUFFICIENT_SCOPE", "API tokens cannot open terminal sessions").await;
+            return;
+        }
+        Some((user, _)) if user.must_change_password => {
+            send_error(&mut sender, "PASSWORD_CHANGE_REQUIRED", "Password change required").await;
+            return;
+        }
+        Some((user, token)) if user.is_admin => (user, token),
+        Some(_) => {
+            send_error(&mut sender, "FORBIDDEN", "Admin access required").await;
+            return;
+        }
+        None => {
+            send_error(&mut sender, "UNAUTHORIZED", "Authentication required").await;
+            return;
+        }
+    };
+
+    let session = match &query.session_id {
+        Some(id) => state.terminals.get(id, &user.id),
+        None => {
+            // Shells start in My Files when home directories are enabled
+            let real_root = get_real_root(state.config.dev_mode);
+            if !real_root.exists() {
+                let _ = std::fs::create_dir_all(&real_root);
+            }
+            let cwd = caller_home(&state, &user.id).await.unwrap_or(real_root);
+            state
+                .terminals
+                .open(&user.id, &user.username, &cwd, query.cols, query.rows)
+                .await
+        }
+    };
+    let session = match session {
+        Ok(session) => session,
+        Err(e) => {
+            let (_, Json(body)) = <(StatusCode, Json<SessionErrorResponse>)>::from(e);
+            send_error(&mut sender, &body.code, &body.error).await;
+            return;
+        }
+    };
+
+    if query.session_id.is_some() {
+        let _ = session.resize(query.cols, query.rows);
+    }
+
+    // Not a mutating request, so not seen by the audit layer
+    let action = match query.session_id {
+        Some(_) => "terminal.session.attach",
+        None => "terminal.session.open",
+    };
+    let entry = NewAuditEntry {
+        actor_id: Some(user.id.clone()),
+        actor: Some(user.username.clone()),
+        action: action.to_string(),
+        target: Some(session.id.clone()),
+        method: "GET".to_string(),
+        path: "/api/terminal/ws".to_string(),
+        ip: Some(ip),
+        status: StatusCode::SWITCHING_PROTOCOLS.as_u16(),
+        details: None,
+    };
+    if let Err(e) = audit::record(&state.db, &entry).await {
+        tracing::error!("Failed to record terminal session in audit log: {}", e);
+    }
+
+    let (scrollback, output, ended) = session.attach();
+    let access = SocketAccess { state: &state, token: &token, user_id: &user.id };
+    run_terminal_socket(&mut sender, &mut receiver, &session, &access, scrollback, output, ended).await;
+    session.detach();
+}
+
+/// Credentials of an attached socket, checked again while it stays open
+struct SocketAccess<'a> {
+    state: &'a AppState,
+    token: &'a str,
+    user_id: &'a str,
+}
+
+impl SocketAccess<'_> {
+    /// Whether the session is still active and still allowed to use a shell
+    async fn is_allowed(&self) -> bool {
+        recheck_session(self.state, self.token, self.user_id)
+            .await
+            .is_some_and(|user| user.is_admin && !user.must_change_password && user.scopes.is_none())
+    }
+}
+
+/// Relay a session to a WebSocket until either side goes away
+async fn run_terminal_socket(
+    sender: &mut SplitSink<WebSocket, Message>,
+    receiver: &mut futures_util::stream::SplitStream<WebSocket>,
+    session: &Arc<TerminalSession>,
+    access: &SocketAccess<'_>,
+    scrollback: Vec<u8>,
+    mut output: broadcast::Receiver<Vec<u8>>,
+    mut ended: tokio::sync::watch::Receiver<Option<TerminalEndReason>>,
+) {
+    let hello = TerminalServerMessage::Session {
+        session_id: session.id.clone(),
+        shell: session.shell.clone(),
+    };
+    if send_control(sender, &hello).await.is_err() {
+        return;
+    }
+    if !scrollback.is_empty() && sender.send(Message::Binary(scrollback)).await.is_err() {
+        return;
+    }
+
+    // The shell may have exited while no client was attached
+    let already_ended = ended.borrow().is_some();
+    if !already_ended && !relay(sender, receiver, session, access, &mut output, &mut ended).await {
+        return;
+    }
+
+    let reason = ended.borrow().unwrap_or(TerminalEndReason::Exit);
+    let _ = send_control(sender, &TerminalServerMessage::Exit { reason }).await;
+    let _ = sender.send(Message::Close(None)).await;
+}
+
+/// Forward input and output until the client leaves, loses access or the shell ends
+/// Returns true when the shell ended
+async fn relay(
+    sender: &mut SplitSink<WebSocket, Message>,
+    receiver: &mut futures_util::stream::SplitStream<WebSocket>,
+    session: &Arc<TerminalSession>,
+    access: &SocketAccess<'_>,
+    output: &mut broadcast::Receiver<Vec<u8>>,
+    ended: &mut tokio::sync::watch::Receiver<Option<TerminalEndReason>>,
+) -> bool {
+    let mut access_check = tokio::time::interval(SESSION_CHECK_INTERVAL);
+    access_check.tick().await;
+
+    loop {
+        tokio::select! {
+            // Pending output is flushed before the end notification
+            biased;
+
+            msg = receiver.next() => {
+                let input = match msg {
+                    Some(Ok(Message::Binary(data))) => data,
+                    Some(Ok(Message::Text(text))) => match serde_json::from_str::<TerminalClientMessage>(&text) {
+                        Ok(TerminalClientMessage::Input { data }) => data.into_bytes(),
+                        Ok(TerminalClientMessage::Resize { cols, rows }) => {
+                            if let Err(e) = session.resize(cols, rows) {
+                                tracing::warn!("Failed to resize terminal {}: {}", session.id, e);
+                            }
+                            continue;
+                        }
+                        Ok(TerminalClientMessage::Close) => {
+                            session.terminate(TerminalEndReason::Closed);
+                            continue;
+                        }
+                        Ok(TerminalClientMessage::Ping) => {
+                            if send_control(sender, &TerminalServerMessage::Pong).await.is_err() {
+                                return false;
+                            }
+                            continue;
+                        }
+                        Err(e) => {
+                            send_error(sender, "INVALID_MESSAGE", &e.to_string()).await;
+                            continue;
+                        }
+                    },
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => return false,
+                    _ => continue,
+                };
+
+                if let Err(e) = session.write_input(&input).await {
+                    tracing::warn!("Failed to write to terminal {}: {}", session.id, e);
+                }
+            }
+            chunk = output.recv() => {
+                match chunk {
+                    Ok(data) => {
+                        if sender.send(Message::Binary(data)).await.is_err() {
+                            return false;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Terminal client lagging, {} chunks dropped", skipped);
+                    }
+                    // Wait for the end notification below
+                    Err(broadcast::error::RecvError::Closed) => {}
+                }
+            }
+            // The value only ever changes once, when the shell is gone
+            _ = ended.changed() => return true,
+            // Logging out, revoking the session or losing admin rights detaches the shell
+            _ = access_check.tick() => {
+                if !access.is_allowed().await {
+                    tracing::info!("Terminal {} detached, its session is no longer allowed", session.id);
+                    send_error(sender, "SESSION_EXPIRED", "Session expired or revoked").await;
+                    let _ = sender.send(Message::Close(None)).await;
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+async fn send_control(
+    sender: &mut SplitSink<WebSocket, Message>,
+    message: &TerminalServerMessage,
+) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(message).map_err(axum::Error::new)?;
+    sender.send(Message::Text(text)).await
+}
+
+/// Send an error message to the client
+async fn send_error(sender: &mut SplitSink<WebSocket, Message>, code: &str, error: &str) {
+    let message = TerminalServerMessage::Error {
+        code: code.to_string(),
+        error: error.to_string(),
+    };
+    let _ = send_control(sender, &message).await;
+}
+
+/// List the running terminal sessions of the current admin
+async fn list_sessions(State(state): State<AppState>, admin: AdminUser) -> impl IntoResponse {
+    (StatusCode::OK, Json(state.terminals.list(&admin.id)))
+}
+
+/// Close a running terminal session
+async fn close_session(
+    State(state): State<AppState>,
+    admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match state.terminals.close(&id, &admin.id) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub user_id: Option<String>,
+    #[serde(default = "default_audit_limit")]
+    pub limit: i64,
+}
+
+fn default_audit_limit() -> i64 {
+    100
+}
+
+/// List recorded terminal sessions (audit log)
+async fn list_audit(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Query(query): Query<AuditQuery>,
+) -> impl IntoResponse {
+    let limit = query.limit.clamp(1, 1000);
+    match list_session_records(&state.db, query.user_id.as_deref(), limit).await {
+        Ok(records) => (StatusCode::OK, Json(records)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list terminal sessions: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// List the raw input lines typed in a recorded terminal session
+async fn list_audit_input(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match list_session_input(&state.db, &id).await {
+        Ok(input) => (StatusCode::OK, Json(input)).into_response(),
+        Err(e) => {
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
 /// Create the terminal router
 pub fn router() -> Router<AppState> {
-    Router::new().route("/exec", post(execute))
+    Router::new()
+        .route("/exec", post(execute))
+        .route("/ws", get(terminal_ws))
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/:id", delete(close_session))
+        .route("/audit", get(list_audit))
+        .route("/audit/:id/input", get(list_audit_input))
 }
@@ -0,0 +1,111 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::api::middleware::AuthUser;
+use crate::models::api_token::ApiToken;
+use crate::services::api_token::{create_token, delete_token, list_tokens, ApiTokenError};
+use crate::AppState;
+
+/// Token management is not scoped, so API tokens cannot create more tokens
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list).post(create))
+        .route("/:id", delete(revoke))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTokenRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateTokenResponse {
+    #[serde(flatten)]
+    pub token: ApiToken,
+    /// Only returned once, at creation
+    pub secret: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub code: String,
+}
+
+impl From<ApiTokenError> for (StatusCode, Json<ErrorResponse>) {
+    fn from(err: ApiTokenError) -> Self {
+        let (status, code) = match &err {
+            ApiTokenError::NotFound => (StatusCode::NOT_FOUND, "TOKEN_NOT_FOUND"),
+            ApiTokenError::InvalidScope(_) => (StatusCode::BAD_REQUEST, "INVALID_SCOPE"),
+            ApiTokenError::InvalidName => (StatusCode::BAD_REQUEST, "INVALID_NAME"),
+            ApiTokenError::InvalidExpiry => (StatusCode::BAD_REQUEST, "INVALID_EXPIRY"),
+            ApiTokenError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR"),
+        };
+
+        (
+            status,
+            Json(ErrorResponse {
+                error: err.to_string(),
+                code: code.to_string(),
+            }),
+        )
+    }
+}
+
+/// List the current user's API tokens
+async fn list(State(state): State<AppState>, user: AuthUser) -> impl IntoResponse {
+    match list_tokens(&state.db, &user.id).await {
+        Ok(tokens) => (StatusCode::OK, Json(tokens)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list API tokens: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Create an API token for the current user
+async fn create(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<CreateTokenRequest>,
+) -> impl IntoResponse {
+    match create_token(&state.db, &user.id, &req.name, &req.scopes, req.expires_in_days).await {
+        Ok((token, secret)) => {
+            tracing::info!("API token '{}' created by {}", token.name, user.username);
+            (StatusCode::CREATED, Json(CreateTokenResponse { token, secret })).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to create API token: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
+
+/// Revoke one of the current user's API tokens
+async fn revoke(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match delete_token(&state.db, &user.id, &id).await {
+        Ok(()) => {
+            tracing::info!("API token {} revoked by {}", id, user.username);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to revoke API token: {}", e);
+            let (status, json) = e.into();
+            (status, json).into_response()
+        }
+    }
+}
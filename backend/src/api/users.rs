@@ -1,18 +1,23 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     routing::{delete, get, post, put},
-    Json, Router,
+    Extension, Json, Router,
 };
 use serde::{Deserialize, Serialize};
 
+use crate::api::audit::AuditNote;
 use crate::api::middleware::{AdminUser, AuthErrorResponse, AuthUser};
+use crate::services::homes::{self, HomeRemoval, HomesSettings};
 use crate::services::user::{
     self, change_password, create_user as create_user_service, delete_user as delete_user_service,
-    get_user_by_id, list_users as list_users_service, update_user as update_user_service,
-    UserError, UserUpdate,
+    get_user_by_id, list_users as list_users_service, set_must_change_password,
+    update_user as update_user_service, UserError, UserUpdate,
 };
+use crate::services::mfa::{self, MfaError};
+use crate::services::password_policy::PasswordPolicy;
+use crate::services::session::delete_user_sessions;
 use crate::AppState;
 
 pub fn router() -> Router<AppState> {
@@ -23,6 +28,14 @@ pub fn router() -> Router<AppState> {
         .route("/:id", put(update_user))
         .route("/:id", delete(delete_user))
         .route("/:id/password", put(change_user_password))
+        .route("/:id/mfa", delete(reset_user_mfa))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteUserQuery {
+    /// What to do with the user's home directory, archived by default
+    #[serde(default)]
+    pub home: HomeRemoval,
 }
 
 #[derive(Debug, Serialize)]
@@ -31,6 +44,10 @@ pub struct UserResponse {
     pub username: String,
     pub email: Option<String>,
     pub is_admin: bool,
+    pub system_user: Option<String>,
+    pub must_change_password: bool,
+    /// 'local', 'ldap' or 'oidc'
+    pub auth_source: String,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -42,6 +59,9 @@ impl From<crate::models::user::User> for UserResponse {
             username: user.username,
             email: user.email,
             is_admin: user.is_admin,
+            system_user: user.system_user,
+            must_change_password: user.must_change_password,
+            auth_source: user.auth_source,
             created_at: user.created_at,
             updated_at: user.updated_at,
         }
@@ -55,17 +75,31 @@ pub struct CreateUserRequest {
     pub email: Option<String>,
     #[serde(default)]
     pub is_admin: bool,
+    /// Require a password change at first sign-in
+    #[serde(default)]
+    pub must_change_password: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateUserRequest {
     pub email: Option<String>,
     pub is_admin: Option<bool>,
+    /// System account mapping, empty string resets it to the username
+    pub system_user: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ChangePasswordRequest {
     pub password: String,
+    /// Require the user to choose their own password at next sign-in
+    #[serde(default)]
+    pub must_change_password: bool,
+}
+
+/// Body of a user deletion that could not remove the home directory
+#[derive(Debug, Serialize)]
+pub struct DeleteUserResponse {
+    pub warning: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -81,6 +115,8 @@ impl From<UserError> for (StatusCode, Json<ErrorResponse>) {
             UserError::DuplicateUsername => (StatusCode::CONFLICT, "DUPLICATE_USERNAME"),
             UserError::CannotDeleteSelf => (StatusCode::FORBIDDEN, "CANNOT_DELETE_SELF"),
             UserError::CannotDeleteLastAdmin => (StatusCode::FORBIDDEN, "CANNOT_DELETE_LAST_ADMIN"),
+            UserError::WeakPassword(_) => (StatusCode::BAD_REQUEST, "WEAK_PASSWORD"),
+            UserError::ExternalAccount => (StatusCode::CONFLICT, "EXTERNAL_ACCOUNT"),
             UserError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR"),
             UserError::AuthError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "AUTH_ERROR"),
         };
@@ -131,27 +167,31 @@ async fn create_user(
             .into_response();
     }
 
-    if payload.password.len() < 6 {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Password must be at least 6 characters".to_string(),
-                code: "VALIDATION_ERROR".to_string(),
-            }),
-        )
-            .into_response();
-    }
-
     match create_user_service(
         &state.db,
         &payload.username,
         &payload.password,
         payload.email,
         payload.is_admin,
+        &PasswordPolicy::from_config(&state.config),
     )
     .await
     {
-        Ok(user) => {
+        Ok(mut user) => {
+            if payload.must_change_password {
+                if let Err(e) = set_must_change_password(&state.db, &user.id, true).await {
+                    tracing::error!("Failed to require a password change: {}", e);
+                    let (status, json) = e.into();
+                    return (status, json).into_response();
+                }
+                user.must_change_password = true;
+            }
+            // The account is usable without its home, which is also created on first use
+            if let Some(settings) = HomesSettings::from_config(&state.config) {
+                if let Err(e) = homes::ensure_home(&settings, &user) {
+                    tracing::warn!("Failed to create home of {}: {}", user.username, e);
+                }
+            }
             let response: UserResponse = user.into();
             (StatusCode::CREATED, Json(response)).into_response()
         }
@@ -229,6 +269,9 @@ async fn update_user(
         UserUpdate {
             email: payload.email.map(Some),
             is_admin: payload.is_admin,
+            system_user: payload
+                .system_user
+                .map(|name| Some(name.trim().to_string()).filter(|n| !n.is_empty())),
             ..Default::default()
         }
     } else {
@@ -256,13 +299,46 @@ async fn delete_user(
     State(state): State<AppState>,
     admin: AdminUser,
     Path(id): Path<String>,
+    Query(query): Query<DeleteUserQuery>,
 ) -> impl IntoResponse {
-    match delete_user_service(&state.db, &id, &admin.id).await {
-        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+    let username = match get_user_by_id(&state.db, &id).await {
+        Ok(Some(user)) => user.username,
+        Ok(None) => {
+            let (status, json) = UserError::NotFound.into();
+            return (status, json).into_response();
+        }
         Err(e) => {
-            tracing::error!("Failed to delete user: {}", e);
             let (status, json) = e.into();
-            (status, json).into_response()
+            return (status, json).into_response();
+        }
+    };
+
+    if let Err(e) = delete_user_service(&state.db, &id, &admin.id).await {
+        tracing::error!("Failed to delete user: {}", e);
+        let (status, json) = e.into();
+        return (status, json).into_response();
+    }
+
+    let Some(settings) = HomesSettings::from_config(&state.config) else {
+        return StatusCode::NO_CONTENT.into_response();
+    };
+    match homes::remove_home(&settings, &username, query.home).await {
+        Ok(archive) => {
+            let note = AuditNote {
+                details: archive.map(|path| format!("Home archived to {}", path.display())),
+                ..Default::default()
+            };
+            (Extension(note), StatusCode::NO_CONTENT).into_response()
+        }
+        // The user is gone at this point, the deletion succeeded with a leftover directory
+        Err(e) => {
+            tracing::error!("Failed to remove home of {}: {}", username, e);
+            let warning = format!("User deleted, but their home directory was left in place: {}", e);
+            let note = AuditNote {
+                details: Some(warning.clone()),
+                ..Default::default()
+            };
+            (Extension(note), Json(DeleteUserResponse { warning: Some(warning) })).into_response()
         }
     }
 }
@@ -274,19 +350,15 @@ async fn change_user_password(
     Path(id): Path<String>,
     Json(payload): Json<ChangePasswordRequest>,
 ) -> impl IntoResponse {
-    if payload.password.len() < 6 {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Password must be at least 6 characters".to_string(),
-                code: "VALIDATION_ERROR".to_string(),
-            }),
-        )
-            .into_response();
-    }
-
-    match change_password(&state.db, &id, &payload.password).await {
-        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+    let policy = PasswordPolicy::from_config(&state.config);
+    match change_password(&state.db, &id, &payload.password, payload.must_change_password, &policy).await {
+        Ok(()) => {
+            // The user has to sign in again everywhere with the new password
+            if let Err(e) = delete_user_sessions(&state.db, &id, None).await {
+                tracing::error!("Failed to revoke sessions after password reset: {}", e);
+            }
+            StatusCode::NO_CONTENT.into_response()
+        }
         Err(e) => {
             tracing::error!("Failed to change password: {}", e);
             let (status, json) = e.into();
@@ -294,3 +366,36 @@ async fn change_user_password(
         }
     }
 }
+
+/// Reset a user's two-factor authentication, e.g. after losing their device (admin only)
+async fn reset_user_mfa(
+    State(state): State<AppState>,
+    admin: AdminUser,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match mfa::disable(&state.db, &id, true).await {
+        Ok(()) => {
+            tracing::info!("Two-factor authentication of user {} reset by {}", id, admin.username);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(MfaError::NotEnabled) => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: MfaError::NotEnabled.to_string(),
+                code: "MFA_NOT_ENABLED".to_string(),
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Failed to reset two-factor authentication: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                    code: "DATABASE_ERROR".to_string(),
+                }),
+            )
+                .into_response()
+        }
+    }
+}
@@ -1,101 +1,285 @@
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
+        Query, State,
     },
     response::IntoResponse,
 };
 use futures_util::{SinkExt, StreamExt};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 use std::time::Duration;
-use sysinfo::System;
-use tokio::time::interval;
+use tokio::sync::broadcast;
 
+use crate::api::middleware::{authenticate_token, AuthUser};
+use crate::services::events::{is_topic_allowed, topic_scope, TOPIC_NOTIFICATIONS, TOPIC_SYSTEM_STATS};
 use crate::AppState;
 
-#[derive(Debug, Serialize)]
-#[serde(tag = "type", content = "data")]
-pub enum WsEvent {
-    #[serde(rename = "system.stats")]
-    SystemStats(SystemStats),
-    #[serde(rename = "notification")]
-    Notification(Notification),
+/// Time allowed for the client to send its auth message
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Interval between checks that the session of a connection is still active
+pub(crate) const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Topics a connection is subscribed to right after authentication
+const DEFAULT_TOPICS: &[&str] = &[TOPIC_SYSTEM_STATS, TOPIC_NOTIFICATIONS];
+
+#[derive(Debug, Deserialize)]
+pub struct WsQuery {
+    /// JWT, for clients that authenticate in the URL
+    pub token: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
-pub struct SystemStats {
-    pub cpu_usage: f32,
-    pub memory_usage: f32,
-    pub memory_used: u64,
-    pub memory_total: u64,
+/// Messages sent by the client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientMessage {
+    Auth { token: String },
+    Subscribe { topics: Vec<String> },
+    Unsubscribe { topics: Vec<String> },
+    Ping,
 }
 
+/// Control messages sent by the server (events use `WsEvent`)
 #[derive(Debug, Serialize)]
-pub struct Notification {
-    pub id: String,
-    pub level: String,
-    pub message: String,
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ServerMessage {
+    Authenticated { username: String, topics: Vec<String> },
+    Subscribed { topics: Vec<String> },
+    Pong,
+    Error { code: String, error: String },
 }
 
 /// WebSocket handler
+/// Authentication is done with `?token=` or a first `{"type": "auth"}` message
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
+    Query(query): Query<WsQuery>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(handle_socket)
+    ws.on_upgrade(move |socket| handle_socket(socket, state, query.token))
 }
 
 /// Handle individual WebSocket connection
-async fn handle_socket(socket: WebSocket) {
+async fn handle_socket(socket: WebSocket, state: AppState, token: Option<String>) {
     let (mut sender, mut receiver) = socket.split();
 
-    // Spawn task to send periodic system stats
-    let send_task = tokio::spawn(async move {
-        let mut interval = interval(Duration::from_secs(2));
-        let mut sys = System::new_all();
-
-        loop {
-            interval.tick().await;
-            sys.refresh_all();
-
-            let cpu_usage = sys.global_cpu_info().cpu_usage();
-            let memory_total = sys.total_memory();
-            let memory_used = sys.used_memory();
-            let memory_usage = (memory_used as f32 / memory_total as f32) * 100.0;
-
-            let event = WsEvent::SystemStats(SystemStats {
-                cpu_usage,
-                memory_usage,
-                memory_used,
-                memory_total,
-            });
-
-            let msg = serde_json::to_string(&event).unwrap();
-            if sender.send(Message::Text(msg)).await.is_err() {
-                break;
+    let auth = match token {
+        Some(token) => authenticate_token(&state, &token).await.ok().map(|user| (user, token)),
+        None => wait_for_auth(&state, &mut receiver).await,
+    };
+    let Some((mut user, token)) = auth else {
+        let _ = send_message(&mut sender, &error_message("UNAUTHORIZED", "Authentication required")).await;
+        let _ = sender.send(Message::Close(None)).await;
+        return;
+    };
+    if user.must_change_password {
+        let _ = send_message(&mut sender, &error_message("PASSWORD_CHANGE_REQUIRED", "Password change required")).await;
+        let _ = sender.send(Message::Close(None)).await;
+        return;
+    }
+
+    tracing::debug!("WebSocket authenticated as {}", user.username);
+
+    let mut topics: BTreeSet<String> = DEFAULT_TOPICS
+        .iter()
+        .filter(|t| can_subscribe(t, &user))
+        .map(|t| t.to_string())
+        .collect();
+    let mut events = state.events.subscribe();
+    let mut session_check = tokio::time::interval(SESSION_CHECK_INTERVAL);
+    session_check.tick().await;
+
+    let welcome = ServerMessage::Authenticated {
+        username: user.username.clone(),
+        topics: topics.iter().cloned().collect(),
+    };
+    if send_message(&mut sender, &welcome).await.is_err() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            msg = receiver.next() => {
+                let reply = match msg {
+                    Some(Ok(Message::Text(text))) => handle_client_message(&text, &user, &mut topics),
+                    Some(Ok(Message::Close(_))) | None => {
+                        tracing::debug!("WebSocket connection closed");
+                        break;
+                    }
+                    Some(Err(e)) => {
+                        tracing::error!("WebSocket error: {}", e);
+                        break;
+                    }
+                    _ => None,
+                };
+
+                if let Some(reply) = reply {
+                    if send_message(&mut sender, &reply).await.is_err() {
+                        break;
+                    }
+                }
             }
-        }
-    });
-
-    // Handle incoming messages
-    while let Some(msg) = receiver.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                tracing::debug!("Received WebSocket message: {}", text);
-                // Handle client messages if needed
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("WebSocket client lagging, {} events dropped", skipped);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                if !topics.contains(event.topic()) || !event.is_visible_to(&user.id, user.is_admin) {
+                    continue;
+                }
+
+                if send_message(&mut sender, &event).await.is_err() {
+                    break;
+                }
             }
-            Ok(Message::Close(_)) => {
-                tracing::debug!("WebSocket connection closed");
-                break;
+            _ = session_check.tick() => {
+                // Logging out, revoking the session or the token ends the connection
+                let Some(current) = recheck_session(&state, &token, &user.id).await else {
+                    tracing::debug!("WebSocket session of {} ended", user.username);
+                    let _ = send_message(&mut sender, &error_message("SESSION_EXPIRED", "Session expired or revoked")).await;
+                    let _ = sender.send(Message::Close(None)).await;
+                    break;
+                };
+                // Rights may have changed since, topics no longer allowed are dropped
+                user = current;
+                let allowed = topics.len();
+                topics.retain(|topic| can_subscribe(topic, &user));
+                if topics.len() != allowed {
+                    let update = ServerMessage::Subscribed { topics: topics.iter().cloned().collect() };
+                    if send_message(&mut sender, &update).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Wait for the first message to be a valid auth message, returns the user and its token
+pub(crate) async fn wait_for_auth(
+    state: &AppState,
+    receiver: &mut futures_util::stream::SplitStream<WebSocket>,
+) -> Option<(AuthUser, String)> {
+    let msg = tokio::time::timeout(AUTH_TIMEOUT, receiver.next()).await.ok()??;
+
+    match msg {
+        Ok(Message::Text(text)) => match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(ClientMessage::Auth { token }) => authenticate_token(state, &token).await.ok().map(|user| (user, token)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Authenticate the token of an open connection again
+/// `None` once its session or API token is gone, or when it now belongs to someone else
+pub(crate) async fn recheck_session(state: &AppState, token: &str, user_id: &str) -> Option<AuthUser> {
+    authenticate_token(state, token).await.ok().filter(|current| current.id == user_id)
+}
+
+/// Apply a client message to the connection and return the reply
+fn handle_client_message(
+    text: &str,
+    user: &AuthUser,
+    topics: &mut BTreeSet<String>,
+) -> Option<ServerMessage> {
+    let message = match serde_json::from_str::<ClientMessage>(text) {
+        Ok(message) => message,
+        Err(e) => return Some(error_message("INVALID_MESSAGE", &e.to_string())),
+    };
+
+    match message {
+        ClientMessage::Auth { .. } => Some(error_message("ALREADY_AUTHENTICATED", "Connection is already authenticated")),
+        ClientMessage::Subscribe { topics: requested } => {
+            if let Some(denied) = requested.iter().find(|t| !can_subscribe(t, user)) {
+                return Some(error_message(
+                    "FORBIDDEN_TOPIC",
+                    &format!("Unknown topic or access denied: {}", denied),
+                ));
             }
-            Err(e) => {
-                tracing::error!("WebSocket error: {}", e);
-                break;
+            topics.extend(requested);
+            Some(ServerMessage::Subscribed { topics: topics.iter().cloned().collect() })
+        }
+        ClientMessage::Unsubscribe { topics: requested } => {
+            for topic in &requested {
+                topics.remove(topic);
             }
-            _ => {}
+            Some(ServerMessage::Subscribed { topics: topics.iter().cloned().collect() })
+        }
+        ClientMessage::Ping => Some(ServerMessage::Pong),
+    }
+}
+
+/// Topic permission, narrowed by the scopes of API tokens
+fn can_subscribe(topic: &str, user: &AuthUser) -> bool {
+    is_topic_allowed(topic, user.is_admin) && user.has_scope(topic_scope(topic), false)
+}
+
+fn error_message(code: &str, error: &str) -> ServerMessage {
+    ServerMessage::Error {
+        code: code.to_string(),
+        error: error.to_string(),
+    }
+}
+
+async fn send_message<T: Serialize>(
+    sender: &mut futures_util::stream::SplitSink<WebSocket, Message>,
+    message: &T,
+) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(message).map_err(axum::Error::new)?;
+    sender.send(Message::Text(text)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(is_admin: bool) -> AuthUser {
+        AuthUser {
+            id: "u1".to_string(),
+            username: "alice".to_string(),
+            is_admin,
+            session_id: Some("s1".to_string()),
+            scopes: None,
+            must_change_password: false,
         }
     }
 
-    // Clean up
-    send_task.abort();
+    #[test]
+    fn test_subscribe_checks_permissions() {
+        let mut topics = BTreeSet::new();
+
+        let reply = handle_client_message(r#"{"type":"subscribe","topics":["docker"]}"#, &user(false), &mut topics);
+        assert!(matches!(reply, Some(ServerMessage::Error { .. })));
+        assert!(topics.is_empty());
+
+        let reply = handle_client_message(r#"{"type":"subscribe","topics":["docker"]}"#, &user(true), &mut topics);
+        assert!(matches!(reply, Some(ServerMessage::Subscribed { .. })));
+        assert!(topics.contains("docker"));
+
+        handle_client_message(r#"{"type":"unsubscribe","topics":["docker"]}"#, &user(true), &mut topics);
+        assert!(topics.is_empty());
+    }
+
+    #[test]
+    fn test_api_token_scopes_limit_topics() {
+        let mut topics = BTreeSet::new();
+        let token_user = AuthUser {
+            scopes: Some(vec!["system:read".to_string()]),
+            ..user(true)
+        };
+
+        let reply = handle_client_message(r#"{"type":"subscribe","topics":["docker"]}"#, &token_user, &mut topics);
+        assert!(matches!(reply, Some(ServerMessage::Error { .. })));
+
+        let reply = handle_client_message(r#"{"type":"subscribe","topics":["system.stats"]}"#, &token_user, &mut topics);
+        assert!(matches!(reply, Some(ServerMessage::Subscribed { .. })));
+        assert!(!can_subscribe(TOPIC_NOTIFICATIONS, &token_user));
+    }
 }
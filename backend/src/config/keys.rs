@@ -0,0 +1,241 @@
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Key id of a secret set through `PINAS_JWT_SECRET`
+const CONFIGURED_KID: &str = "config";
+
+/// Signing key length in bytes
+const KEY_BYTES: usize = 64;
+
+/// A JWT signing key, identified by the `kid` header of the tokens it signs
+#[derive(Clone, Serialize, Deserialize)]
+struct JwtKey {
+    kid: String,
+    /// Hex-encoded secret
+    secret: String,
+    created_at: String,
+    /// Set once a newer key signs tokens, the key then only verifies them
+    retired_at: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct KeyFile {
+    /// Newest first, the first key signs new tokens
+    keys: Vec<JwtKey>,
+}
+
+/// Signing keys shared by every clone of the configuration
+#[derive(Clone, Default)]
+pub struct JwtKeys {
+    keys: Arc<RwLock<KeyFile>>,
+    /// Where generated keys are persisted, None for a configured secret
+    path: Option<PathBuf>,
+}
+
+impl std::fmt::Debug for JwtKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keys = self.keys.read().unwrap();
+        f.debug_struct("JwtKeys")
+            .field("kids", &keys.keys.iter().map(|k| k.kid.as_str()).collect::<Vec<_>>())
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl JwtKeys {
+    /// Single key from a configured secret, it cannot be rotated
+    pub fn from_secret(secret: &str) -> Self {
+        let key = JwtKey {
+            kid: CONFIGURED_KID.to_string(),
+            secret: hex::encode(secret.as_bytes()),
+            created_at: Utc::now().to_rfc3339(),
+            retired_at: None,
+        };
+        Self {
+            keys: Arc::new(RwLock::new(KeyFile { keys: vec![key] })),
+            path: None,
+        }
+    }
+
+    /// Load the key file, generating a first key when it does not exist yet
+    pub fn load_or_generate(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let file = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Invalid JWT key file {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => KeyFile::default(),
+            Err(e) => return Err(anyhow::anyhow!("Cannot read JWT key file {}: {}", path.display(), e)),
+        };
+
+        let keys = Self {
+            keys: Arc::new(RwLock::new(file)),
+            path: Some(path),
+        };
+        if keys.keys.read().unwrap().keys.is_empty() {
+            let kid = keys.rotate(Duration::zero())?;
+            tracing::info!("Generated JWT signing key {}", kid);
+        }
+        Ok(keys)
+    }
+
+    /// Key id and secret signing new tokens
+    pub fn signing_key(&self) -> Option<(String, Vec<u8>)> {
+        let keys = self.keys.read().unwrap();
+        let key = keys.keys.first()?;
+        Some((key.kid.clone(), hex::decode(&key.secret).ok()?))
+    }
+
+    /// Secret verifying tokens signed with a key id
+    pub fn verification_key(&self, kid: &str) -> Option<Vec<u8>> {
+        let keys = self.keys.read().unwrap();
+        let key = keys.keys.iter().find(|k| k.kid == kid)?;
+        hex::decode(&key.secret).ok()
+    }
+
+    /// Whether keys are generated, and can therefore be rotated
+    pub fn is_rotatable(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Age of the signing key
+    pub fn signing_key_age(&self) -> Option<Duration> {
+        let keys = self.keys.read().unwrap();
+        let key = keys.keys.first()?;
+        Some(Utc::now() - parse_time(&key.created_at))
+    }
+
+    /// Start signing with a new key, returns its id
+    /// Retired keys still verify tokens for `retain`, then are dropped
+    pub fn rotate(&self, retain: Duration) -> anyhow::Result<String> {
+        let Some(path) = &self.path else {
+            anyhow::bail!("A configured JWT secret cannot be rotated");
+        };
+
+        let mut secret = [0u8; KEY_BYTES];
+        OsRng.fill_bytes(&mut secret);
+        let now = Utc::now();
+        let key = JwtKey {
+            kid: uuid::Uuid::new_v4().simple().to_string(),
+            secret: hex::encode(secret),
+            created_at: now.to_rfc3339(),
+            retired_at: None,
+        };
+        let kid = key.kid.clone();
+
+        let mut keys = self.keys.write().unwrap();
+        let mut next = Vec::with_capacity(keys.keys.len() + 1);
+        next.push(key);
+        for mut old in keys.keys.iter().cloned() {
+            let retired_at = old.retired_at.get_or_insert_with(|| now.to_rfc3339());
+            if parse_time(retired_at) + retain > now {
+                next.push(old);
+            }
+        }
+
+        let file = KeyFile { keys: next };
+        write_private(path, &serde_json::to_vec_pretty(&file)?)?;
+        *keys = file;
+        Ok(kid)
+    }
+
+    /// Periodically rotate the signing key once it is older than `max_age`
+    pub fn spawn_rotation(self, max_age: Duration, retain: Duration) {
+        if !self.is_rotatable() || max_age <= Duration::zero() {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+            loop {
+                interval.tick().await;
+                if self.signing_key_age().is_some_and(|age| age < max_age) {
+                    continue;
+                }
+                match self.rotate(retain) {
+                    Ok(kid) => tracing::info!("Rotated JWT signing key, now {}", kid),
+                    Err(e) => tracing::error!("Failed to rotate JWT signing key: {}", e),
+                }
+            }
+        });
+    }
+}
+
+/// Write a file only the service user can read, replacing it atomically
+fn write_private(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    let _ = fs::remove_file(&tmp);
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn parse_time(value: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("pinas-keys-{}", uuid::Uuid::new_v4()))
+            .join("jwt_keys.json")
+    }
+
+    #[test]
+    fn test_generated_key_is_persisted_privately() {
+        let path = temp_path();
+        let keys = JwtKeys::load_or_generate(&path).unwrap();
+        let (kid, secret) = keys.signing_key().unwrap();
+        assert_eq!(secret.len(), KEY_BYTES);
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        let reloaded = JwtKeys::load_or_generate(&path).unwrap();
+        assert_eq!(reloaded.signing_key().unwrap(), (kid, secret));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_keeps_retired_keys_for_verification() {
+        let path = temp_path();
+        let keys = JwtKeys::load_or_generate(&path).unwrap();
+        let (old_kid, _) = keys.signing_key().unwrap();
+
+        let new_kid = keys.rotate(Duration::hours(1)).unwrap();
+        assert_eq!(keys.signing_key().unwrap().0, new_kid);
+        assert!(keys.verification_key(&old_kid).is_some());
+
+        keys.rotate(Duration::zero()).unwrap();
+        assert!(keys.verification_key(&old_kid).is_none());
+        assert!(keys.verification_key(&new_kid).is_none());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_configured_secret_cannot_rotate() {
+        let keys = JwtKeys::from_secret("a-configured-secret");
+        assert_eq!(keys.signing_key().unwrap(), ("config".to_string(), b"a-configured-secret".to_vec()));
+        assert!(keys.rotate(Duration::zero()).is_err());
+    }
+}
@@ -1,5 +1,16 @@
+mod keys;
+
+use anyhow::Context;
 use serde::Deserialize;
 
+pub use keys::JwtKeys;
+
+/// Placeholder secret of earlier releases, refused outside dev mode
+const INSECURE_JWT_SECRET: &str = "change-me-in-production";
+
+/// Shortest configured JWT secret accepted outside dev mode
+const MIN_JWT_SECRET_LEN: usize = 32;
+
 /// Application configuration
 #[derive(Debug, Clone, Deserialize)]
 pub struct AppConfig {
@@ -11,18 +22,39 @@ pub struct AppConfig {
     #[serde(default = "default_database_url")]
     pub database_url: String,
 
-    /// JWT secret key
-    #[serde(default = "default_jwt_secret")]
-    pub jwt_secret: String,
+    /// Fixed JWT secret, keys are generated under `data_dir` and rotated when unset
+    #[serde(default)]
+    pub jwt_secret: Option<String>,
+
+    /// Days before the generated JWT signing key is rotated (0 disables)
+    #[serde(default = "default_jwt_key_rotation_days")]
+    pub jwt_key_rotation_days: u64,
+
+    /// Directory for data owned by the service, such as generated keys
+    #[serde(default = "default_data_dir")]
+    pub data_dir: String,
 
-    /// JWT token expiration in hours
+    /// Session lifetime in hours, extended each time the refresh token is used
     #[serde(default = "default_jwt_expiration")]
     pub jwt_expiration_hours: u64,
 
+    /// Access token (JWT) lifetime in minutes
+    #[serde(default = "default_access_token_minutes")]
+    pub access_token_minutes: u64,
+
     /// Root directory for file manager
     #[serde(default = "default_files_root")]
     pub files_root: String,
 
+    /// Directory holding a home directory per user (unset disables home directories)
+    #[serde(default)]
+    pub homes_root: Option<String>,
+
+    /// Samba include written with a private `[homes]` share (empty disables),
+    /// pulled in with `include =` from smb.conf
+    #[serde(default = "default_homes_samba_config")]
+    pub homes_samba_config: String,
+
     /// Directory for static frontend files (optional)
     #[serde(default)]
     pub static_dir: Option<String>,
@@ -30,6 +62,112 @@ pub struct AppConfig {
     /// Development mode - skip actual installations (Docker, downloads, etc.)
     #[serde(default = "default_dev_mode")]
     pub dev_mode: bool,
+
+    /// Shell started by interactive terminal sessions
+    #[serde(default = "default_terminal_shell")]
+    pub terminal_shell: String,
+
+    /// Terminal sessions without input for this long are closed (0 disables)
+    #[serde(default = "default_terminal_idle_timeout")]
+    pub terminal_idle_timeout_secs: u64,
+
+    /// Consecutive failed logins before a username or IP is locked
+    #[serde(default = "default_login_max_failures")]
+    pub login_max_failures: u32,
+
+    /// Lockout duration, also how long failed logins are remembered
+    #[serde(default = "default_login_lockout_minutes")]
+    pub login_lockout_minutes: u64,
+
+    /// Reverse proxies allowed to set X-Forwarded-For (comma-separated IPs or CIDRs)
+    #[serde(default)]
+    pub trusted_proxies: String,
+
+    /// Shortest password accepted
+    #[serde(default = "default_password_min_length")]
+    pub password_min_length: u32,
+
+    /// Character classes (lowercase, uppercase, digits, symbols) a password must mix
+    #[serde(default = "default_password_min_classes")]
+    pub password_min_classes: u32,
+
+    /// Previous passwords a user cannot reuse (0 disables)
+    #[serde(default = "default_password_history")]
+    pub password_history: u32,
+
+    /// Days audit log entries are kept (0 keeps them forever)
+    #[serde(default = "default_audit_retention_days")]
+    pub audit_retention_days: u64,
+
+    /// Hours between integrity verifications of installed packages (0 disables)
+    #[serde(default = "default_package_verify_hours")]
+    pub package_verify_hours: u64,
+
+    /// LDAP server, `ldap://host[:port]` or `ldaps://host[:port]` (unset disables LDAP logins)
+    #[serde(default)]
+    pub ldap_url: Option<String>,
+
+    /// PEM certificate authority trusted for ldaps, next to the public roots
+    #[serde(default)]
+    pub ldap_ca_file: Option<String>,
+
+    /// Service account used to search the directory (anonymous when unset)
+    #[serde(default)]
+    pub ldap_bind_dn: Option<String>,
+
+    #[serde(default)]
+    pub ldap_bind_password: Option<String>,
+
+    /// Base DN searched for users
+    #[serde(default)]
+    pub ldap_user_base: Option<String>,
+
+    /// User search filter, `{username}` is replaced by the escaped login name
+    #[serde(default = "default_ldap_user_filter")]
+    pub ldap_user_filter: String,
+
+    /// Attribute holding the login name (`sAMAccountName` on Active Directory)
+    #[serde(default = "default_ldap_username_attr")]
+    pub ldap_username_attr: String,
+
+    #[serde(default = "default_ldap_email_attr")]
+    pub ldap_email_attr: String,
+
+    /// Base DN searched for groups (unset disables group sync)
+    #[serde(default)]
+    pub ldap_group_base: Option<String>,
+
+    /// Group search filter (`(objectClass=group)` on Active Directory)
+    #[serde(default = "default_ldap_group_filter")]
+    pub ldap_group_filter: String,
+
+    #[serde(default = "default_ldap_group_name_attr")]
+    pub ldap_group_name_attr: String,
+
+    /// Group attribute listing member DNs
+    #[serde(default = "default_ldap_group_member_attr")]
+    pub ldap_group_member_attr: String,
+
+    /// Name of the LDAP group whose members are PiNAS administrators
+    #[serde(default)]
+    pub ldap_admin_group: Option<String>,
+
+    /// Minutes between group syncs (0 disables)
+    #[serde(default = "default_ldap_sync_minutes")]
+    pub ldap_sync_minutes: u64,
+
+    /// Timeout of each LDAP connection and operation
+    #[serde(default = "default_ldap_timeout")]
+    pub ldap_timeout_secs: u64,
+
+    /// External URL of the web interface (e.g. `https://nas.example.org`), used for
+    /// single sign-on redirects; derived from the Host header when unset
+    #[serde(default)]
+    pub public_url: Option<String>,
+
+    /// Signing keys, resolved by `load`
+    #[serde(skip)]
+    pub jwt_keys: JwtKeys,
 }
 
 fn default_bind_address() -> String {
@@ -40,42 +178,182 @@ fn default_database_url() -> String {
     "sqlite:./data/pinas.db?mode=rwc".to_string()
 }
 
-fn default_jwt_secret() -> String {
-    "change-me-in-production".to_string()
+fn default_jwt_key_rotation_days() -> u64 {
+    30
+}
+
+fn default_data_dir() -> String {
+    "./data".to_string()
 }
 
 fn default_jwt_expiration() -> u64 {
     24 // 24 hours
 }
 
+fn default_access_token_minutes() -> u64 {
+    15
+}
+
 fn default_files_root() -> String {
     "./data/files".to_string()
 }
 
+fn default_homes_samba_config() -> String {
+    "/etc/samba/pinas-homes.conf".to_string()
+}
+
 fn default_dev_mode() -> bool {
     false
 }
 
+fn default_terminal_shell() -> String {
+    "/bin/bash".to_string()
+}
+
+fn default_terminal_idle_timeout() -> u64 {
+    1800 // 30 minutes
+}
+
+fn default_login_max_failures() -> u32 {
+    5
+}
+
+fn default_login_lockout_minutes() -> u64 {
+    15
+}
+
+fn default_password_min_length() -> u32 {
+    8
+}
+
+fn default_password_min_classes() -> u32 {
+    3
+}
+
+fn default_password_history() -> u32 {
+    5
+}
+
+fn default_audit_retention_days() -> u64 {
+    365
+}
+
+fn default_package_verify_hours() -> u64 {
+    24
+}
+
+fn default_ldap_user_filter() -> String {
+    "(&(objectClass=person)(uid={username}))".to_string()
+}
+
+fn default_ldap_username_attr() -> String {
+    "uid".to_string()
+}
+
+fn default_ldap_email_attr() -> String {
+    "mail".to_string()
+}
+
+fn default_ldap_group_filter() -> String {
+    "(objectClass=groupOfNames)".to_string()
+}
+
+fn default_ldap_group_name_attr() -> String {
+    "cn".to_string()
+}
+
+fn default_ldap_group_member_attr() -> String {
+    "member".to_string()
+}
+
+fn default_ldap_sync_minutes() -> u64 {
+    60
+}
+
+fn default_ldap_timeout() -> u64 {
+    10
+}
+
 impl AppConfig {
     /// Load configuration from environment variables
     pub fn load() -> anyhow::Result<Self> {
         // Load .env file if present
         dotenvy::dotenv().ok();
 
-        let config = config::Config::builder()
-            .add_source(config::Environment::with_prefix("PINAS"))
-            .build()?;
+        let mut app_config = Self::from_environment(config::Environment::with_prefix("PINAS"))?;
 
-        let app_config: AppConfig = config.try_deserialize().unwrap_or_else(|_| AppConfig {
-            bind_address: default_bind_address(),
-            database_url: default_database_url(),
-            jwt_secret: default_jwt_secret(),
-            jwt_expiration_hours: default_jwt_expiration(),
-            files_root: default_files_root(),
-            static_dir: None,
-            dev_mode: default_dev_mode(),
-        });
+        app_config.jwt_keys = match &app_config.jwt_secret {
+            Some(secret) => {
+                check_jwt_secret(secret, app_config.dev_mode)?;
+                JwtKeys::from_secret(secret)
+            }
+            None => JwtKeys::load_or_generate(app_config.jwt_key_path())?,
+        };
 
         Ok(app_config)
     }
+
+    /// Read the configuration from `PINAS_*` variables. Values are kept as strings and only parsed
+    /// into the fields that are numbers or booleans, so a secret like `007123` stays as written.
+    fn from_environment(environment: config::Environment) -> anyhow::Result<Self> {
+        config::Config::builder()
+            .add_source(environment)
+            .build()?
+            .try_deserialize()
+            .context("Invalid configuration in PINAS_* environment variables")
+    }
+
+    /// File holding generated JWT signing keys
+    pub fn jwt_key_path(&self) -> std::path::PathBuf {
+        std::path::Path::new(&self.data_dir).join("jwt_keys.json")
+    }
+}
+
+/// Refuse a guessable configured secret, unless in dev mode
+fn check_jwt_secret(secret: &str, dev_mode: bool) -> anyhow::Result<()> {
+    let weak = secret == INSECURE_JWT_SECRET || secret.len() < MIN_JWT_SECRET_LEN;
+    if weak && !dev_mode {
+        anyhow::bail!(
+            "PINAS_JWT_SECRET is the default or shorter than {} characters; unset it to use a generated key",
+            MIN_JWT_SECRET_LEN
+        );
+    }
+    if weak {
+        tracing::warn!("Using a weak JWT secret, only acceptable in dev mode");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_environment_values_keep_their_text() {
+        let variables = [
+            ("PINAS_JWT_SECRET", "007123"),
+            ("PINAS_LDAP_BIND_PASSWORD", "1e3"),
+            ("PINAS_LOGIN_MAX_FAILURES", "7"),
+            ("PINAS_DEV_MODE", "true"),
+        ];
+        let environment = config::Environment::with_prefix("PINAS")
+            .source(Some(variables.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()));
+        let config = AppConfig::from_environment(environment).unwrap();
+        assert_eq!(config.jwt_secret.as_deref(), Some("007123"));
+        assert_eq!(config.ldap_bind_password.as_deref(), Some("1e3"));
+        assert_eq!(config.login_max_failures, 7);
+        assert!(config.dev_mode);
+
+        let invalid = config::Environment::with_prefix("PINAS")
+            .source(Some([("PINAS_LOGIN_MAX_FAILURES".to_string(), "many".to_string())].into_iter().collect()));
+        assert!(AppConfig::from_environment(invalid).is_err());
+    }
+
+    #[test]
+    fn test_weak_jwt_secret_is_refused_outside_dev_mode() {
+        assert!(check_jwt_secret(INSECURE_JWT_SECRET, false).is_err());
+        assert!(check_jwt_secret("short", false).is_err());
+        assert!(check_jwt_secret(INSECURE_JWT_SECRET, true).is_ok());
+        assert!(check_jwt_secret(&"x".repeat(MIN_JWT_SECRET_LEN), false).is_ok());
+    }
 }
@@ -20,3 +20,27 @@ pub async fn init_pool(database_url: &str) -> anyhow::Result<SqlitePool> {
 
     Ok(pool)
 }
+
+/// In-memory database with every migration applied, for tests
+/// Limited to one connection, each connection would open its own empty database
+#[cfg(test)]
+pub async fn test_pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+    pool
+}
+
+/// Insert a local user with an unusable password, for tests of rows referencing users
+#[cfg(test)]
+pub async fn test_user(pool: &SqlitePool, id: &str, username: &str) {
+    sqlx::query("INSERT INTO users (id, username, password_hash, created_at, updated_at) VALUES (?, ?, '!', '', '')")
+        .bind(id)
+        .bind(username)
+        .execute(pool)
+        .await
+        .unwrap();
+}
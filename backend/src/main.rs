@@ -6,7 +6,7 @@ use axum::{
     http::StatusCode,
     response::IntoResponse,
     routing::get,
-    Json, Router,
+    Extension, Json, Router,
 };
 use serde::Serialize;
 use tower_http::cors::{Any, CorsLayer};
@@ -20,13 +20,26 @@ mod db;
 mod models;
 mod services;
 
+use crate::api::middleware::ApiScope;
 use crate::config::AppConfig;
+use crate::services::events::EventBus;
+use crate::services::settings::SettingsApplier;
+use crate::services::package_tasks::PackageTasks;
+use crate::services::terminal::TerminalManager;
 
 /// Application state shared across handlers
 #[derive(Clone)]
 pub struct AppState {
     pub config: Arc<AppConfig>,
     pub db: sqlx::SqlitePool,
+    /// Event bus for events pushed to WebSocket clients
+    pub events: EventBus,
+    /// OS hooks for device settings (hostname, timezone, NTP)
+    pub settings_applier: Arc<dyn SettingsApplier>,
+    /// Interactive PTY sessions
+    pub terminals: Arc<TerminalManager>,
+    /// Package installs, updates and removals running in the background
+    pub package_tasks: Arc<PackageTasks>,
 }
 
 #[tokio::main]
@@ -51,9 +64,50 @@ async fn main() -> anyhow::Result<()> {
     sqlx::migrate!("./migrations").run(&db).await?;
 
     // Create app state
+    let events = EventBus::new(256);
+    services::events::spawn_stats_sampler(events.clone());
+    services::docker::spawn_event_forwarder(events.clone());
+    let settings_applier = services::settings::detect_applier(config.dev_mode);
+
+    services::session::spawn_cleanup(db.clone());
+    services::audit::spawn_retention(db.clone(), config.audit_retention_days);
+    services::package::spawn_verification(db.clone(), events.clone(), config.package_verify_hours);
+    if let Some(ldap) = services::ldap::LdapSettings::from_config(&config) {
+        services::ldap::spawn_sync(db.clone(), ldap, config.ldap_sync_minutes);
+    }
+    if let Some(homes) = services::homes::HomesSettings::from_config(&config) {
+        // The Samba configuration is left alone in dev mode
+        if !config.dev_mode {
+            if let Err(e) = services::homes::publish_samba_share(&homes) {
+                tracing::warn!("Failed to write the Samba homes share: {}", e);
+            }
+        }
+    }
+    config.jwt_keys.clone().spawn_rotation(
+        chrono::Duration::days(config.jwt_key_rotation_days as i64),
+        services::auth::retired_key_lifetime(&config),
+    );
+
+    // Sessions cannot survive a restart, close those left open
+    services::terminal::close_stale_sessions(&db).await?;
+    let terminals = Arc::new(TerminalManager::new(
+        db.clone(),
+        config.terminal_shell.clone(),
+        config.terminal_idle_timeout_secs,
+    ));
+    terminals.clone().spawn_reaper();
+
+    // Package tasks are not resumed after a restart, the packages they left halfway are settled
+    services::package_tasks::fail_interrupted(&db).await?;
+    services::package::PackageService::new(db.clone()).await.recover_interrupted().await?;
+
     let state = AppState {
         config: Arc::new(config),
         db,
+        events,
+        settings_applier,
+        terminals,
+        package_tasks: Arc::new(PackageTasks::new()),
     };
 
     // Build router
@@ -64,7 +118,7 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("PiNAS server starting on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
@@ -85,19 +139,27 @@ fn create_router(state: AppState) -> Router {
         // API routes
         .nest("/api/auth", api::auth::router())
         .nest("/api/setup", api::setup::router())
-        .nest("/api/files", api::files::router())
-        .nest("/api/system", api::system::router())
-        .nest("/api/storage", api::storage::router())
-        .nest("/api/shares", api::shares::router())
+        .nest("/api/files", api::files::router().layer(Extension(ApiScope("files"))))
+        .nest("/api/system", api::system::router().layer(Extension(ApiScope("system"))))
+        .nest("/api/storage", api::storage::router().layer(Extension(ApiScope("storage"))))
+        .nest("/api/shares", api::shares::router().layer(Extension(ApiScope("shares"))))
         .nest("/api/users", api::users::router())
         .nest("/api/groups", api::groups::router())
-        .nest("/api/packages", api::packages::router())
-        .nest("/api/docker", api::docker::router())
-        .nest("/api/apps", api::apps::router())
-        .nest("/api/services", api::services::router())
+        .nest("/api/lockouts", api::lockouts::router())
+        .nest("/api/tokens", api::tokens::router())
+        .nest("/api/audit", api::audit::router())
+        .nest("/api/oidc", api::oidc::router())
+        .nest("/api/packages", api::packages::router().layer(Extension(ApiScope("packages"))))
+        .nest("/api/docker", api::docker::router().layer(Extension(ApiScope("docker"))))
+        .nest("/api/apps", api::apps::router().layer(Extension(ApiScope("apps"))))
+        .nest("/api/services", api::services::router().layer(Extension(ApiScope("services"))))
         .nest("/api/terminal", api::terminal::router())
+        .nest("/api/settings", api::settings::router().layer(Extension(ApiScope("settings"))))
+        .nest("/api/notifications", api::notifications::router().layer(Extension(ApiScope("notifications"))))
         // WebSocket
         .route("/api/ws", get(api::ws::ws_handler))
+        // Audit log of mutating requests
+        .layer(axum::middleware::from_fn_with_state(state.clone(), api::audit::record_request))
         // State
         .with_state(state);
 
@@ -111,7 +173,16 @@ fn create_router(state: AppState) -> Router {
     }
 
     // Apply middleware
-    app.layer(TraceLayer::new_for_http()).layer(cors)
+    // Same span as the default one, without tokens passed in the query string
+    let trace = TraceLayer::new_for_http().make_span_with(|request: &axum::http::Request<axum::body::Body>| {
+        tracing::debug_span!(
+            "request",
+            method = %request.method(),
+            uri = %api::middleware::redact_token(request.uri()),
+            version = ?request.version(),
+        )
+    });
+    app.layer(trace).layer(cors)
 }
 
 /// Health check response
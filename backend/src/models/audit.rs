@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Recorded action
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor_id: Option<String>,
+    pub actor: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub ip: Option<String>,
+    pub status: i64,
+    pub outcome: String,
+    pub details: Option<String>,
+    pub created_at: String,
+}
+
+/// Whether a recorded action went through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+    /// Refused for lack of authentication or permission
+    Denied,
+}
+
+impl AuditOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOutcome::Success => "success",
+            AuditOutcome::Failure => "failure",
+            AuditOutcome::Denied => "denied",
+        }
+    }
+
+    /// Outcome of a request from its response status
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            401 | 403 => AuditOutcome::Denied,
+            400.. => AuditOutcome::Failure,
+            _ => AuditOutcome::Success,
+        }
+    }
+}
@@ -8,11 +8,26 @@ pub struct UserGroup {
     pub name: String,
     pub description: Option<String>,
     pub is_system: bool,
+    /// Members must use two-factor authentication
+    #[sqlx(default)]
+    pub require_mfa: bool,
+    /// Directory the group is synced from, None for local groups
+    #[sqlx(default)]
+    pub external_source: Option<String>,
+    /// Directory DN of a synced group
+    #[sqlx(default)]
+    pub external_id: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
 
 impl UserGroup {
+    /// Whether the group is synced from a directory and read-only locally
+    pub fn is_external(&self) -> bool {
+        self.external_source.is_some()
+    }
+
+
     pub fn new(name: String, description: Option<String>, is_system: bool) -> Self {
         let now = chrono::Utc::now().to_rfc3339();
         Self {
@@ -20,6 +35,9 @@ impl UserGroup {
             name,
             description,
             is_system,
+            require_mfa: false,
+            external_source: None,
+            external_id: None,
             created_at: now.clone(),
             updated_at: now,
         }
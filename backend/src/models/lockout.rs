@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Failed login attempt
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct LoginFailure {
+    pub id: i64,
+    pub username: String,
+    pub ip: String,
+    pub reason: String,
+    pub created_at: String,
+}
+
+/// Consecutive failures of a username or client IP
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct LoginLockout {
+    pub id: String,
+    pub kind: String,
+    pub value: String,
+    pub failures: i64,
+    pub last_failure_at: String,
+    pub locked_until: Option<String>,
+}
+
+/// What a failure counter tracks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockoutKind {
+    Username,
+    Ip,
+}
+
+impl LockoutKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LockoutKind::Username => "username",
+            LockoutKind::Ip => "ip",
+        }
+    }
+}
@@ -17,7 +17,7 @@ pub struct CatalogApp {
     pub version: String,
     pub category: String,
     pub icon: Option<String>,
-    pub manifest: String, // URL to manifest.json
+    pub manifest: Option<String>, // URL to manifest.json
 }
 
 /// Full package manifest
@@ -37,6 +37,10 @@ pub struct PackageManifest {
 
     pub install: InstallConfig,
 
+    /// Steps run instead of the install steps when updating an older version
+    #[serde(default)]
+    pub upgrade: Option<UpgradeConfig>,
+
     #[serde(default)]
     pub uninstall: UninstallConfig,
 
@@ -173,6 +177,9 @@ pub enum InstallStep {
     },
     DockerPull {
         image: String,
+        /// SHA-256 of the `docker save` tarball when the image is loaded from a bundle
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
     },
     DockerCreate {
         config: ContainerConfig,
@@ -188,11 +195,21 @@ pub enum InstallStep {
     },
 }
 
+/// Upgrade configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpgradeConfig {
+    #[serde(default)]
+    pub steps: Vec<InstallStep>,
+}
+
 /// Uninstall configuration
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UninstallConfig {
     #[serde(default)]
     pub steps: Vec<InstallStep>,
+    /// Paths holding user data, kept when uninstalling with `keep_data`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data_paths: Vec<String>,
 }
 
 /// Docker container configuration
@@ -1,8 +1,15 @@
+pub mod api_token;
+pub mod audit;
 pub mod group;
+pub mod lockout;
 pub mod manifest;
+pub mod notification;
+pub mod oidc;
 pub mod package;
 pub mod session;
+pub mod settings;
 pub mod share;
+pub mod terminal;
 pub mod user;
 
 pub use group::*;
@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Notification record
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Notification {
+    pub id: String,
+    pub level: String,
+    pub title: String,
+    pub message: String,
+    /// Free-form category used for routing (e.g., "system", "security", "storage")
+    pub category: String,
+    /// Recipient, None means all administrators
+    pub user_id: Option<String>,
+    pub read: bool,
+    pub created_at: String,
+}
+
+impl Notification {
+    pub fn new(
+        level: NotificationLevel,
+        title: String,
+        message: String,
+        category: String,
+        user_id: Option<String>,
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            level: level.to_string(),
+            title,
+            message,
+            category,
+            user_id,
+            read: false,
+            created_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// Notification severity level
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationLevel {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for NotificationLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotificationLevel::Info => write!(f, "info"),
+            NotificationLevel::Success => write!(f, "success"),
+            NotificationLevel::Warning => write!(f, "warning"),
+            NotificationLevel::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl std::str::FromStr for NotificationLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(NotificationLevel::Info),
+            "success" => Ok(NotificationLevel::Success),
+            "warning" => Ok(NotificationLevel::Warning),
+            "error" => Ok(NotificationLevel::Error),
+            _ => Err(format!("Unknown notification level: {}", s)),
+        }
+    }
+}
+
+/// Delivery channel record
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NotificationChannelRecord {
+    pub id: String,
+    pub name: String,
+    pub channel_type: String,
+    /// JSON configuration, see `ChannelConfig`
+    pub config: String,
+    pub enabled: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Typed channel configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ChannelConfig {
+    Smtp {
+        host: String,
+        #[serde(default = "default_smtp_port")]
+        port: u16,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+        /// "none", "starttls" or "tls"
+        #[serde(default = "default_smtp_security")]
+        security: String,
+        from: String,
+        to: Vec<String>,
+    },
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: std::collections::HashMap<String, String>,
+    },
+    Ntfy {
+        /// Server URL (e.g., "https://ntfy.sh")
+        url: String,
+        topic: String,
+        #[serde(default)]
+        token: Option<String>,
+    },
+    Gotify {
+        url: String,
+        token: String,
+    },
+}
+
+impl ChannelConfig {
+    pub fn channel_type(&self) -> &'static str {
+        match self {
+            ChannelConfig::Smtp { .. } => "smtp",
+            ChannelConfig::Webhook { .. } => "webhook",
+            ChannelConfig::Ntfy { .. } => "ntfy",
+            ChannelConfig::Gotify { .. } => "gotify",
+        }
+    }
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_smtp_security() -> String {
+    "starttls".to_string()
+}
+
+/// Routing rule sending a category to a channel
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NotificationRule {
+    pub id: String,
+    pub channel_id: String,
+    /// Category name or "*" for all categories
+    pub category: String,
+    pub min_level: String,
+    pub created_at: String,
+}
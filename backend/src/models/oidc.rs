@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// OpenID Connect provider users can sign in with
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct OidcProvider {
+    pub id: String,
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    #[serde(skip_serializing)]
+    pub client_secret: Option<String>,
+    /// 'client_secret_basic' or 'client_secret_post'
+    pub token_auth_method: String,
+    /// Space-separated scopes
+    pub scopes: String,
+    pub username_claim: String,
+    pub groups_claim: String,
+    /// JSON object mapping a groups claim value to a PiNAS group name
+    pub group_mapping: String,
+    /// JSON array of groups claim values granting admin status
+    pub admin_values: String,
+    /// Create an account on first sign-in when none is linked
+    pub allow_provisioning: bool,
+    /// Link to the existing account with the same verified email
+    pub link_by_email: bool,
+    pub enabled: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl OidcProvider {
+    pub fn group_mapping(&self) -> HashMap<String, String> {
+        serde_json::from_str(&self.group_mapping).unwrap_or_default()
+    }
+
+    pub fn admin_values(&self) -> Vec<String> {
+        serde_json::from_str(&self.admin_values).unwrap_or_default()
+    }
+}
+
+/// Account at a provider linked to a user
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UserIdentity {
+    pub id: String,
+    pub user_id: String,
+    pub provider_id: String,
+    /// `sub` claim, stable for the account at the provider
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: String,
+    pub last_login_at: Option<String>,
+}
+
+impl UserIdentity {
+    pub fn new(user_id: String, provider_id: String, subject: String, email: Option<String>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            user_id,
+            provider_id,
+            subject,
+            email,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            last_login_at: None,
+        }
+    }
+}
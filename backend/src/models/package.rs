@@ -62,16 +62,180 @@ pub struct InstalledPackage {
     pub has_window: bool,
 }
 
-/// Package file record
+/// Newer catalog version of an installed package
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageUpdate {
+    pub package_id: String,
+    pub name: String,
+    pub installed_version: String,
+    pub available_version: String,
+}
+
+/// Outcome of a preflight check
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreflightStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// One requirement checked before installing a package
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreflightCheck {
+    /// "memory", "disk", "arch", "port" or "dependencies"
+    pub check: String,
+    pub status: PreflightStatus,
+    pub required: Option<String>,
+    pub actual: Option<String>,
+    pub message: String,
+}
+
+/// Result of checking a manifest against the device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreflightReport {
+    pub package_id: String,
+    /// False when any check failed, warnings do not block installation
+    pub passed: bool,
+    pub checks: Vec<PreflightCheck>,
+    /// Missing dependencies installed along with the package
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+}
+
+impl PreflightReport {
+    pub fn new(package_id: String, checks: Vec<PreflightCheck>) -> Self {
+        Self {
+            package_id,
+            passed: checks.iter().all(|c| c.status != PreflightStatus::Fail),
+            checks,
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// Add a check, keeping `passed` up to date
+    pub fn push(&mut self, check: PreflightCheck) {
+        self.passed &= check.status != PreflightStatus::Fail;
+        self.checks.push(check);
+    }
+
+    /// Messages of the failed checks
+    pub fn failures(&self) -> Vec<&str> {
+        self.checks
+            .iter()
+            .filter(|c| c.status == PreflightStatus::Fail)
+            .map(|c| c.message.as_str())
+            .collect()
+    }
+}
+
+/// Public key trusted to sign catalogs and manifests
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SigningKey {
+    /// Start of the SHA-256 fingerprint of the key, referenced by signature files
+    pub id: String,
+    pub name: String,
+    /// Base64 raw ed25519 public key
+    pub public_key: String,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+impl SigningKey {
+    pub fn new(name: String, id: String, public_key: String) -> Self {
+        Self {
+            id,
+            name,
+            public_key,
+            created_at: Utc::now().to_rfc3339(),
+            expires_at: None,
+            revoked_at: None,
+        }
+    }
+}
+
+/// Catalog apps are listed from
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CatalogSource {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    /// The highest priority wins when sources list the same app
+    pub priority: i64,
+    pub enabled: bool,
+    #[serde(skip_serializing)]
+    pub etag: Option<String>,
+    #[serde(skip_serializing)]
+    pub last_modified: Option<String>,
+    pub fetched_at: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Package file record
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct PackageFile {
     pub id: i64,
     pub package_id: String,
     pub path: String,
     pub file_type: String,
+    /// Checksum of the file as installed, None for directories and symlinks
+    pub sha256: Option<String>,
+    /// Index of the install step that created it, None for files tracked before steps were recorded
+    pub step: Option<i64>,
     pub created_at: String,
 }
 
+/// What removing a package did with its files
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UninstallReport {
+    pub package_id: String,
+    /// Number of files and directories removed
+    pub removed: usize,
+    /// User data kept as asked, and directories still holding files the package did not create
+    pub kept: Vec<String>,
+    /// Files changed since they were installed, removed anyway
+    pub modified: Vec<String>,
+}
+
+/// Differences between an installed package and what its install created
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VerifyReport {
+    pub package_id: String,
+    /// Installed files and directories no longer on disk
+    pub missing: Vec<String>,
+    /// Files changed since they were installed
+    pub modified: Vec<String>,
+    /// Files in the package's directories it did not create
+    pub extra: Vec<String>,
+    /// Docker images pulled by the install and no longer present
+    pub missing_images: Vec<String>,
+    /// Docker containers created by the install and no longer present
+    pub missing_containers: Vec<String>,
+    pub checked_at: String,
+}
+
+impl VerifyReport {
+    /// Whether the package is as installed, extra files aside
+    pub fn is_intact(&self) -> bool {
+        self.missing.is_empty()
+            && self.modified.is_empty()
+            && self.missing_images.is_empty()
+            && self.missing_containers.is_empty()
+    }
+
+    /// Whether another report found the same differences
+    pub fn same_drift(&self, other: &VerifyReport) -> bool {
+        self.missing == other.missing
+            && self.modified == other.modified
+            && self.extra == other.extra
+            && self.missing_images == other.missing_images
+            && self.missing_containers == other.missing_containers
+    }
+}
+
 /// Task status
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -96,6 +260,27 @@ pub struct PackageTask {
     pub started_at: Option<String>,
     pub completed_at: Option<String>,
     pub created_at: String,
+    /// Install task of the package a dependency is installed for
+    #[sqlx(default)]
+    pub parent_task_id: Option<String>,
+    /// Bytes received by the download step running, if any
+    #[sqlx(default)]
+    pub bytes_done: Option<i64>,
+    #[sqlx(default)]
+    pub bytes_total: Option<i64>,
+}
+
+/// Line of output written by a package task
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PackageTaskLog {
+    pub id: i64,
+    pub task_id: String,
+    /// Index of the step that wrote the line, None for task messages
+    pub step: Option<i32>,
+    /// "stdout", "stderr" or "info"
+    pub stream: String,
+    pub line: String,
+    pub created_at: String,
 }
 
 /// App translation record
@@ -1,23 +1,38 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+/// Login on one device, identified by its rotating refresh token
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Session {
     pub id: String,
     pub user_id: String,
-    pub token: String,
+    #[serde(skip_serializing)]
+    pub refresh_token_hash: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
     pub expires_at: String,
     pub created_at: String,
+    pub last_used_at: String,
 }
 
 impl Session {
-    pub fn new(user_id: String, token: String, expires_at: String) -> Self {
+    pub fn new(
+        user_id: String,
+        refresh_token_hash: String,
+        ip: Option<String>,
+        user_agent: Option<String>,
+        expires_at: String,
+    ) -> Self {
+        let now = chrono::Utc::now().to_rfc3339();
         Self {
             id: uuid::Uuid::new_v4().to_string(),
             user_id,
-            token,
+            refresh_token_hash,
+            ip,
+            user_agent,
             expires_at,
-            created_at: chrono::Utc::now().to_rfc3339(),
+            created_at: now.clone(),
+            last_used_at: now,
         }
     }
 }
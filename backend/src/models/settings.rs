@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Raw row of the key-value settings table
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Setting {
+    pub key: String,
+    pub value: String,
+    pub updated_at: String,
+}
+
+/// Device-level settings stored in the settings table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceSettings {
+    pub hostname: String,
+    pub timezone: String,
+    pub ntp_enabled: bool,
+    pub ntp_servers: Vec<String>,
+    /// Default UI locale (e.g., "en", "fr")
+    pub locale: String,
+    /// Date format shown in the UI (e.g., "DD/MM/YYYY")
+    pub date_format: String,
+}
+
+impl Default for DeviceSettings {
+    fn default() -> Self {
+        Self {
+            hostname: "pinas".to_string(),
+            timezone: "UTC".to_string(),
+            ntp_enabled: true,
+            ntp_servers: vec!["pool.ntp.org".to_string()],
+            locale: "en".to_string(),
+            date_format: "DD/MM/YYYY".to_string(),
+        }
+    }
+}
+
+/// Partial update of device settings, only set fields are changed
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeviceSettingsUpdate {
+    pub hostname: Option<String>,
+    pub timezone: Option<String>,
+    pub ntp_enabled: Option<bool>,
+    pub ntp_servers: Option<Vec<String>>,
+    pub locale: Option<String>,
+    pub date_format: Option<String>,
+}
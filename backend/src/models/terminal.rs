@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Terminal session audit record
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TerminalSessionRecord {
+    pub id: String,
+    pub user_id: String,
+    pub username: String,
+    pub shell: String,
+    pub cwd: String,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    pub end_reason: Option<String>,
+}
+
+/// Line of raw input typed in a terminal session
+/// Editing keys are kept as pressed (`<Tab>`, `<Up>`), so this is not the command the shell ran
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TerminalInput {
+    pub id: i64,
+    pub session_id: String,
+    pub input: String,
+    pub created_at: String,
+}
+
+/// Why a terminal session ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TerminalEndReason {
+    /// The shell exited
+    Exit,
+    /// No input for longer than the idle timeout
+    IdleTimeout,
+    /// No client reattached within the reconnect grace period
+    Disconnected,
+    /// Closed explicitly by the user
+    Closed,
+    /// Server shutting down or shell could not be read anymore
+    Shutdown,
+}
+
+impl std::fmt::Display for TerminalEndReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TerminalEndReason::Exit => write!(f, "exit"),
+            TerminalEndReason::IdleTimeout => write!(f, "idle_timeout"),
+            TerminalEndReason::Disconnected => write!(f, "disconnected"),
+            TerminalEndReason::Closed => write!(f, "closed"),
+            TerminalEndReason::Shutdown => write!(f, "shutdown"),
+        }
+    }
+}
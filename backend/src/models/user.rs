@@ -1,6 +1,15 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+/// Users authenticated by their local password hash
+pub const AUTH_SOURCE_LOCAL: &str = "local";
+
+/// Users authenticated by binding to the LDAP directory
+pub const AUTH_SOURCE_LDAP: &str = "ldap";
+
+/// Users created by an OpenID Connect provider, they only sign in through it
+pub const AUTH_SOURCE_OIDC: &str = "oidc";
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
     pub id: String,
@@ -9,11 +18,34 @@ pub struct User {
     pub password_hash: String,
     pub email: Option<String>,
     pub is_admin: bool,
+    /// System account commands run as, None means same name as `username`
+    #[sqlx(default)]
+    pub system_user: Option<String>,
+    /// Every endpoint but change-password is refused until the password is changed
+    #[sqlx(default)]
+    pub must_change_password: bool,
+    /// 'local' for an Argon2 password, 'ldap' for a directory bind, 'oidc' for single sign-on only
+    #[sqlx(default)]
+    pub auth_source: String,
+    /// Directory DN of an LDAP user
+    #[sqlx(default)]
+    pub external_id: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
 
 impl User {
+    /// Whether the user signs in through the LDAP directory
+    pub fn is_ldap(&self) -> bool {
+        self.auth_source == AUTH_SOURCE_LDAP
+    }
+
+    /// Whether the user has a password hash of its own, which it can sign in with and change
+    pub fn has_local_password(&self) -> bool {
+        self.auth_source.is_empty() || self.auth_source == AUTH_SOURCE_LOCAL
+    }
+
+
     pub fn new(username: String, password_hash: String, email: Option<String>, is_admin: bool) -> Self {
         let now = chrono::Utc::now().to_rfc3339();
         Self {
@@ -22,6 +54,10 @@ impl User {
             password_hash,
             email,
             is_admin,
+            system_user: None,
+            must_change_password: false,
+            auth_source: AUTH_SOURCE_LOCAL.to_string(),
+            external_id: None,
             created_at: now.clone(),
             updated_at: now,
         }
@@ -0,0 +1,274 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use thiserror::Error;
+
+use crate::models::api_token::ApiToken;
+
+/// Prefix telling API tokens apart from JWTs
+pub const TOKEN_PREFIX: &str = "pinas_";
+
+/// Areas a token can be scoped to, as `<area>:read` or `<area>:write`
+pub const SCOPE_AREAS: &[&str] = &[
+    "apps",
+    "docker",
+    "files",
+    "notifications",
+    "packages",
+    "services",
+    "settings",
+    "shares",
+    "storage",
+    "system",
+];
+
+/// Last-used time is only rewritten when older than this, to avoid a write per request
+const LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+#[derive(Debug, Error)]
+pub enum ApiTokenError {
+    #[error("API token not found")]
+    NotFound,
+
+    #[error("Invalid scope: {0}")]
+    InvalidScope(String),
+
+    #[error("Token name is required")]
+    InvalidName,
+
+    #[error("Expiry must be at least one day")]
+    InvalidExpiry,
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// Whether a bearer token is an API token rather than a JWT
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+/// Check scopes are `<area>:read` or `<area>:write` for a known area, returns them normalized
+pub fn validate_scopes(scopes: &[String]) -> Result<Vec<String>, ApiTokenError> {
+    let mut valid = Vec::new();
+    for scope in scopes {
+        let scope = scope.trim().to_lowercase();
+        let known = scope
+            .split_once(':')
+            .is_some_and(|(area, access)| SCOPE_AREAS.contains(&area) && matches!(access, "read" | "write"));
+        if !known {
+            return Err(ApiTokenError::InvalidScope(scope));
+        }
+        if !valid.contains(&scope) {
+            valid.push(scope);
+        }
+    }
+
+    if valid.is_empty() {
+        return Err(ApiTokenError::InvalidScope("at least one scope is required".to_string()));
+    }
+    valid.sort();
+    Ok(valid)
+}
+
+/// Whether scopes grant access to an area, write access implies read
+pub fn scope_allows(scopes: &[String], area: &str, write: bool) -> bool {
+    scopes.iter().any(|scope| match scope.split_once(':') {
+        Some((scope_area, access)) => scope_area == area && (access == "write" || !write),
+        None => false,
+    })
+}
+
+/// Create a token, returns it with the secret to show once
+pub async fn create_token(
+    db: &SqlitePool,
+    user_id: &str,
+    name: &str,
+    scopes: &[String],
+    expires_in_days: Option<i64>,
+) -> Result<(ApiToken, String), ApiTokenError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ApiTokenError::InvalidName);
+    }
+    let scopes = validate_scopes(scopes)?;
+    if expires_in_days.is_some_and(|days| days < 1) {
+        return Err(ApiTokenError::InvalidExpiry);
+    }
+
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let secret = format!("{}{}", TOKEN_PREFIX, hex::encode(bytes));
+
+    let token = ApiToken::new(
+        user_id.to_string(),
+        name.to_string(),
+        hash_token(&secret),
+        secret[..TOKEN_PREFIX.len() + 8].to_string(),
+        scopes.join(" "),
+        expires_in_days.map(|days| (Utc::now() + Duration::days(days)).to_rfc3339()),
+    );
+
+    sqlx::query(
+        r#"
+        INSERT INTO api_tokens (id, user_id, name, token_hash, token_prefix, scopes, expires_at, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&token.id)
+    .bind(&token.user_id)
+    .bind(&token.name)
+    .bind(&token.token_hash)
+    .bind(&token.token_prefix)
+    .bind(&token.scopes)
+    .bind(&token.expires_at)
+    .bind(&token.created_at)
+    .execute(db)
+    .await?;
+
+    Ok((token, secret))
+}
+
+/// List the tokens of a user
+pub async fn list_tokens(db: &SqlitePool, user_id: &str) -> Result<Vec<ApiToken>, ApiTokenError> {
+    let tokens = sqlx::query_as::<_, ApiToken>(
+        "SELECT * FROM api_tokens WHERE user_id = ? ORDER BY created_at DESC",
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(tokens)
+}
+
+/// Revoke a token owned by a user
+pub async fn delete_token(db: &SqlitePool, user_id: &str, id: &str) -> Result<(), ApiTokenError> {
+    let result = sqlx::query("DELETE FROM api_tokens WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiTokenError::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Resolve a presented token, None when unknown or expired; records its use
+pub async fn authenticate(db: &SqlitePool, secret: &str) -> Result<Option<ApiToken>, ApiTokenError> {
+    let token = sqlx::query_as::<_, ApiToken>("SELECT * FROM api_tokens WHERE token_hash = ?")
+        .bind(hash_token(secret))
+        .fetch_optional(db)
+        .await?;
+
+    let Some(token) = token else {
+        return Ok(None);
+    };
+
+    let now = Utc::now();
+    if token.expires_at.as_deref().is_some_and(|at| parse_time(at) <= now) {
+        return Ok(None);
+    }
+
+    let stale_before = (now - Duration::seconds(LAST_USED_RESOLUTION_SECS)).to_rfc3339();
+    sqlx::query(
+        "UPDATE api_tokens SET last_used_at = ? WHERE id = ? AND (last_used_at IS NULL OR last_used_at < ?)",
+    )
+    .bind(now.to_rfc3339())
+    .bind(&token.id)
+    .bind(stale_before)
+    .execute(db)
+    .await?;
+
+    Ok(Some(token))
+}
+
+/// Tokens are random, a fast hash is enough
+fn hash_token(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+fn parse_time(value: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = crate::db::test_pool().await;
+        crate::db::test_user(&pool, "u1", "alice").await;
+        pool
+    }
+
+    fn scopes(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_scope_validation_and_checks() {
+        assert_eq!(
+            validate_scopes(&scopes(&["Files:Read", "docker:write", "files:read"])).unwrap(),
+            scopes(&["docker:write", "files:read"])
+        );
+        assert!(validate_scopes(&scopes(&["users:write"])).is_err());
+        assert!(validate_scopes(&scopes(&["files:admin"])).is_err());
+        assert!(validate_scopes(&[]).is_err());
+
+        let granted = scopes(&["docker:write", "files:read"]);
+        assert!(scope_allows(&granted, "files", false));
+        assert!(!scope_allows(&granted, "files", true));
+        assert!(scope_allows(&granted, "docker", false));
+        assert!(!scope_allows(&granted, "system", false));
+    }
+
+    #[tokio::test]
+    async fn test_create_authenticate_and_revoke() {
+        let pool = setup_test_db().await;
+
+        let (token, secret) = create_token(&pool, "u1", "Home Assistant", &scopes(&["system:read"]), None)
+            .await
+            .unwrap();
+        assert!(is_api_token(&secret));
+        assert!(secret.starts_with(&token.token_prefix));
+
+        let found = authenticate(&pool, &secret).await.unwrap().unwrap();
+        assert_eq!(found.id, token.id);
+        assert_eq!(found.scope_list(), scopes(&["system:read"]));
+        assert!(list_tokens(&pool, "u1").await.unwrap()[0].last_used_at.is_some());
+
+        assert!(authenticate(&pool, "pinas_unknown").await.unwrap().is_none());
+        assert!(matches!(delete_token(&pool, "u2", &token.id).await, Err(ApiTokenError::NotFound)));
+        delete_token(&pool, "u1", &token.id).await.unwrap();
+        assert!(authenticate(&pool, &secret).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_is_rejected() {
+        let pool = setup_test_db().await;
+
+        assert!(matches!(
+            create_token(&pool, "u1", "old", &scopes(&["files:read"]), Some(0)).await,
+            Err(ApiTokenError::InvalidExpiry)
+        ));
+
+        let (token, secret) = create_token(&pool, "u1", "old", &scopes(&["files:read"]), Some(1))
+            .await
+            .unwrap();
+        assert!(authenticate(&pool, &secret).await.unwrap().is_some());
+
+        sqlx::query("UPDATE api_tokens SET expires_at = ? WHERE id = ?")
+            .bind((Utc::now() - Duration::minutes(1)).to_rfc3339())
+            .bind(&token.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(authenticate(&pool, &secret).await.unwrap().is_none());
+    }
+}
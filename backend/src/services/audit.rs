@@ -0,0 +1,378 @@
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use thiserror::Error;
+
+use crate::models::audit::{AuditEntry, AuditOutcome};
+
+/// Most entries returned by an export
+pub const EXPORT_LIMIT: i64 = 100_000;
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("Invalid filter: {0}")]
+    InvalidFilter(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// Action to record
+#[derive(Debug, Clone, Default)]
+pub struct NewAuditEntry {
+    pub actor_id: Option<String>,
+    pub actor: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub ip: Option<String>,
+    pub status: u16,
+    pub details: Option<String>,
+}
+
+/// Filters of the audit log, all optional
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    /// Exact action, or a prefix ending with `.` (e.g. `users.`)
+    pub action: Option<String>,
+    pub outcome: Option<String>,
+    pub ip: Option<String>,
+    /// RFC 3339 lower bound, inclusive
+    pub since: Option<String>,
+    /// RFC 3339 upper bound, exclusive
+    pub until: Option<String>,
+}
+
+impl AuditFilter {
+    fn validate(&self) -> Result<(), AuditError> {
+        if let Some(outcome) = &self.outcome {
+            if !matches!(outcome.as_str(), "success" | "failure" | "denied") {
+                return Err(AuditError::InvalidFilter(format!("unknown outcome '{}'", outcome)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Date bounds converted to UTC and written like the stored dates, so they compare as text
+    fn bounds(&self) -> Result<(Option<String>, Option<String>), AuditError> {
+        let normalize = |name: &str, value: &Option<String>| {
+            value.as_deref()
+                .map(|v| {
+                    chrono::DateTime::parse_from_rfc3339(v)
+                        .map(|date| date.with_timezone(&Utc).to_rfc3339())
+                        .map_err(|_| AuditError::InvalidFilter(format!("{} must be an RFC 3339 date", name)))
+                })
+                .transpose()
+        };
+        Ok((normalize("since", &self.since)?, normalize("until", &self.until)?))
+    }
+
+    /// Pattern matching the action filter with LIKE
+    fn action_pattern(&self) -> Option<String> {
+        self.action.as_ref().map(|action| {
+            let escaped = action.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            if action.ends_with('.') {
+                format!("{}%", escaped)
+            } else {
+                escaped
+            }
+        })
+    }
+}
+
+const FILTER_CLAUSE: &str = r#"
+    WHERE (?1 IS NULL OR actor = ?1)
+      AND (?2 IS NULL OR action LIKE ?2 ESCAPE '\')
+      AND (?3 IS NULL OR outcome = ?3)
+      AND (?4 IS NULL OR ip = ?4)
+      AND (?5 IS NULL OR created_at >= ?5)
+      AND (?6 IS NULL OR created_at < ?6)
+"#;
+
+/// Name an action after the route it went through, e.g. `DELETE /api/users/:id` is `users.delete`
+/// Returns the action and the route parameters as target
+pub fn describe(method: &str, route: &str, path: &str) -> (String, Option<String>) {
+    let route: Vec<&str> = route.trim_start_matches("/api/").split('/').filter(|s| !s.is_empty()).collect();
+    let path: Vec<&str> = path.trim_start_matches("/api/").split('/').filter(|s| !s.is_empty()).collect();
+
+    let mut names = Vec::new();
+    let mut params = Vec::new();
+    for (i, segment) in route.iter().enumerate() {
+        if segment.starts_with(':') || segment.starts_with('*') {
+            params.push(path.get(i).copied().unwrap_or(segment));
+        } else {
+            names.push(*segment);
+        }
+    }
+
+    // A trailing word on a POST route names the operation, e.g. /containers/:id/stop
+    let named = method == "POST" && names.len() > 1 && route.last().is_some_and(|s| !s.starts_with(':'));
+    if !named {
+        names.push(match method {
+            "POST" => "create",
+            "PUT" | "PATCH" => "update",
+            "DELETE" => "delete",
+            _ => "request",
+        });
+    }
+
+    let target = (!params.is_empty()).then(|| params.join("/"));
+    (names.join("."), target)
+}
+
+/// Append an entry to the audit log
+pub async fn record(db: &SqlitePool, entry: &NewAuditEntry) -> Result<(), AuditError> {
+    sqlx::query(
+        r#"
+        INSERT INTO audit_log (actor_id, actor, action, target, method, path, ip, status, outcome, details, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&entry.actor_id)
+    .bind(&entry.actor)
+    .bind(&entry.action)
+    .bind(&entry.target)
+    .bind(&entry.method)
+    .bind(&entry.path)
+    .bind(&entry.ip)
+    .bind(entry.status as i64)
+    .bind(AuditOutcome::from_status(entry.status).as_str())
+    .bind(&entry.details)
+    .bind(Utc::now().to_rfc3339())
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Matching entries, newest first, with the total count
+pub async fn list_entries(
+    db: &SqlitePool,
+    filter: &AuditFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<AuditEntry>, i64), AuditError> {
+    filter.validate()?;
+    let (since, until) = filter.bounds()?;
+    let pattern = filter.action_pattern();
+
+    let entries = sqlx::query_as::<_, AuditEntry>(&format!(
+        "SELECT * FROM audit_log {} ORDER BY id DESC LIMIT ?7 OFFSET ?8",
+        FILTER_CLAUSE
+    ))
+    .bind(&filter.actor)
+    .bind(&pattern)
+    .bind(&filter.outcome)
+    .bind(&filter.ip)
+    .bind(&since)
+    .bind(&until)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db)
+    .await?;
+
+    let total: (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM audit_log {}", FILTER_CLAUSE))
+        .bind(&filter.actor)
+        .bind(&pattern)
+        .bind(&filter.outcome)
+        .bind(&filter.ip)
+        .bind(&since)
+        .bind(&until)
+        .fetch_one(db)
+        .await?;
+
+    Ok((entries, total.0))
+}
+
+/// Render entries as CSV, with a header row
+pub fn to_csv(entries: &[AuditEntry]) -> String {
+    let mut csv = String::from("id,created_at,actor_id,actor,action,target,method,path,ip,status,outcome,details\n");
+    for e in entries {
+        let fields = [
+            e.id.to_string(),
+            e.created_at.clone(),
+            e.actor_id.clone().unwrap_or_default(),
+            e.actor.clone().unwrap_or_default(),
+            e.action.clone(),
+            e.target.clone().unwrap_or_default(),
+            e.method.clone(),
+            e.path.clone(),
+            e.ip.clone().unwrap_or_default(),
+            e.status.to_string(),
+            e.outcome.clone(),
+            e.details.clone().unwrap_or_default(),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Quote a CSV field when needed, and defuse spreadsheet formulas
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// Delete entries older than the retention period
+pub async fn prune(db: &SqlitePool, retention_days: u64) -> Result<u64, AuditError> {
+    let cutoff = (Utc::now() - Duration::days(retention_days as i64)).to_rfc3339();
+    let result = sqlx::query("DELETE FROM audit_log WHERE created_at < ?")
+        .bind(cutoff)
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Periodically apply the retention policy, 0 days keeps entries forever
+pub fn spawn_retention(db: SqlitePool, retention_days: u64) {
+    if retention_days == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(24 * 3600));
+        loop {
+            interval.tick().await;
+            match prune(&db, retention_days).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Removed {} audit log entries past retention", count),
+                Err(e) => tracing::error!("Failed to apply audit log retention: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(actor: &str, action: &str, status: u16) -> NewAuditEntry {
+        NewAuditEntry {
+            actor: Some(actor.to_string()),
+            action: action.to_string(),
+            method: "POST".to_string(),
+            path: "/api/test".to_string(),
+            ip: Some("10.0.0.1".to_string()),
+            status,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_describe_routes() {
+        assert_eq!(describe("POST", "/api/users", "/api/users"), ("users.create".to_string(), None));
+        assert_eq!(
+            describe("DELETE", "/api/groups/:id/members/:user_id", "/api/groups/g1/members/u1"),
+            ("groups.members.delete".to_string(), Some("g1/u1".to_string()))
+        );
+        assert_eq!(
+            describe("POST", "/api/docker/containers/:id/stop", "/api/docker/containers/abc/stop"),
+            ("docker.containers.stop".to_string(), Some("abc".to_string()))
+        );
+        assert_eq!(describe("POST", "/api/auth/login", "/api/auth/login"), ("auth.login".to_string(), None));
+        assert_eq!(describe("PUT", "/api/settings", "/api/settings"), ("settings.update".to_string(), None));
+    }
+
+    #[tokio::test]
+    async fn test_record_filter_and_paginate() {
+        let pool = crate::db::test_pool().await;
+        record(&pool, &entry("admin", "users.create", 201)).await.unwrap();
+        record(&pool, &entry("admin", "users.delete", 403)).await.unwrap();
+        record(&pool, &entry("bob", "docker.containers.stop", 500)).await.unwrap();
+
+        let (all, total) = list_entries(&pool, &AuditFilter::default(), 2, 0).await.unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].action, "docker.containers.stop");
+        assert_eq!(all[0].outcome, "failure");
+
+        let filter = AuditFilter {
+            action: Some("users.".to_string()),
+            ..Default::default()
+        };
+        let (users, total) = list_entries(&pool, &filter, 10, 0).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(users[0].outcome, "denied");
+
+        let filter = AuditFilter {
+            actor: Some("bob".to_string()),
+            outcome: Some("success".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(list_entries(&pool, &filter, 10, 0).await.unwrap().1, 0);
+
+        let filter = AuditFilter {
+            outcome: Some("maybe".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(list_entries(&pool, &filter, 10, 0).await, Err(AuditError::InvalidFilter(_))));
+    }
+
+    #[tokio::test]
+    async fn test_date_bounds_with_offsets() {
+        let pool = crate::db::test_pool().await;
+        record(&pool, &entry("admin", "users.create", 201)).await.unwrap();
+        sqlx::query("DROP TRIGGER audit_log_no_update").execute(&pool).await.unwrap();
+        sqlx::query("UPDATE audit_log SET created_at = '2026-03-01T10:00:00+00:00'")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // 11:30+02:00 is 09:30 UTC, before the entry, although it sorts after it as text
+        let filter = AuditFilter {
+            since: Some("2026-03-01T11:30:00+02:00".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(list_entries(&pool, &filter, 10, 0).await.unwrap().1, 1);
+
+        let filter = AuditFilter {
+            until: Some("2026-03-01T11:30:00+02:00".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(list_entries(&pool, &filter, 10, 0).await.unwrap().1, 0);
+
+        let filter = AuditFilter {
+            since: Some("yesterday".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(list_entries(&pool, &filter, 10, 0).await, Err(AuditError::InvalidFilter(_))));
+    }
+
+    #[tokio::test]
+    async fn test_entries_are_append_only() {
+        let pool = crate::db::test_pool().await;
+        record(&pool, &entry("admin", "users.create", 201)).await.unwrap();
+
+        assert!(sqlx::query("UPDATE audit_log SET actor = 'someone'").execute(&pool).await.is_err());
+        assert!(sqlx::query("DELETE FROM audit_log").execute(&pool).await.is_err());
+        assert_eq!(prune(&pool, 30).await.unwrap(), 0);
+
+        sqlx::query("DROP TRIGGER audit_log_no_update").execute(&pool).await.unwrap();
+        sqlx::query("UPDATE audit_log SET created_at = ?")
+            .bind((Utc::now() - Duration::days(40)).to_rfc3339())
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(prune(&pool, 30).await.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_csv_escaping() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("=cmd()"), "'=cmd()");
+    }
+}
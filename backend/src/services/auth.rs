@@ -3,8 +3,8 @@ use argon2::{
     Argon2,
 };
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
-use serde::{Deserialize, Serialize};
+use jsonwebtoken::{decode, decode_header, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::config::AppConfig;
@@ -19,12 +19,40 @@ pub struct Claims {
     pub username: String,
     /// Admin status
     pub is_admin: bool,
+    /// Session the token was issued for, checked on every request
+    pub sid: String,
     /// Expiration time (Unix timestamp)
     pub exp: usize,
     /// Issued at (Unix timestamp)
     pub iat: usize,
 }
 
+/// Audience of tokens only valid for the second login step
+const MFA_AUDIENCE: &str = "pinas-mfa";
+
+/// Lifetime of a pending two-factor token in minutes
+const MFA_PENDING_MINUTES: i64 = 5;
+
+/// What a pending two-factor token allows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MfaPurpose {
+    /// Submit a TOTP or recovery code
+    Verify,
+    /// Enrol a TOTP secret required by a group
+    Enroll,
+}
+
+/// Claims of a token issued after the password check, before the second factor
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MfaPendingClaims {
+    pub sub: String,
+    pub purpose: MfaPurpose,
+    pub aud: String,
+    pub exp: usize,
+    pub iat: usize,
+}
+
 /// Authentication errors
 #[derive(Debug, Error)]
 pub enum AuthError {
@@ -42,6 +70,12 @@ pub enum AuthError {
 
     #[error("Token generation failed: {0}")]
     TokenGenerationError(String),
+
+    #[error("Session revoked")]
+    SessionRevoked,
+
+    #[error("Session storage failed: {0}")]
+    SessionError(String),
 }
 
 /// Hash a password using Argon2id
@@ -65,41 +99,98 @@ pub fn verify_password(password: &str, hash: &str) -> Result<bool, AuthError> {
         .is_ok())
 }
 
-/// Generate a JWT token for a user
-pub fn generate_jwt(user: &User, config: &AppConfig) -> Result<String, AuthError> {
+/// Generate a short-lived access token for a user session
+pub fn generate_jwt(user: &User, session_id: &str, config: &AppConfig) -> Result<String, AuthError> {
     let now = Utc::now();
-    let expiration = now + Duration::hours(config.jwt_expiration_hours as i64);
+    let expiration = now + Duration::minutes(config.access_token_minutes as i64);
 
     let claims = Claims {
         sub: user.id.clone(),
         username: user.username.clone(),
         is_admin: user.is_admin,
+        sid: session_id.to_string(),
         exp: expiration.timestamp() as usize,
         iat: now.timestamp() as usize,
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
-    )
-    .map_err(|e| AuthError::TokenGenerationError(e.to_string()))
+    sign(&claims, config)
 }
 
 /// Validate a JWT token and return the claims
 pub fn validate_jwt(token: &str, config: &AppConfig) -> Result<Claims, AuthError> {
-    let validation = Validation::default();
-
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(config.jwt_secret.as_bytes()),
-        &validation,
-    )
-    .map(|data| data.claims)
-    .map_err(|e| match e.kind() {
-        jsonwebtoken::errors::ErrorKind::ExpiredSignature => AuthError::TokenExpired,
-        _ => AuthError::InvalidToken,
-    })
+    verify(token, &Validation::default(), config)
+}
+
+/// Generate a short-lived token exchanged for a JWT once the second factor is checked
+/// Its audience makes it unusable as a regular JWT
+pub fn generate_mfa_pending_token(
+    user: &User,
+    purpose: MfaPurpose,
+    config: &AppConfig,
+) -> Result<String, AuthError> {
+    let now = Utc::now();
+    let claims = MfaPendingClaims {
+        sub: user.id.clone(),
+        purpose,
+        aud: MFA_AUDIENCE.to_string(),
+        exp: (now + Duration::minutes(MFA_PENDING_MINUTES)).timestamp() as usize,
+        iat: now.timestamp() as usize,
+    };
+
+    sign(&claims, config)
+}
+
+/// Validate a pending two-factor token for the expected purpose
+pub fn validate_mfa_pending_token(
+    token: &str,
+    purpose: MfaPurpose,
+    config: &AppConfig,
+) -> Result<MfaPendingClaims, AuthError> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[MFA_AUDIENCE]);
+
+    let claims: MfaPendingClaims = verify(token, &validation, config)?;
+
+    if claims.purpose != purpose {
+        return Err(AuthError::InvalidToken);
+    }
+    Ok(claims)
+}
+
+/// How long a retired signing key must keep verifying, the longest token lifetime
+pub fn retired_key_lifetime(config: &AppConfig) -> Duration {
+    Duration::minutes((config.access_token_minutes as i64).max(MFA_PENDING_MINUTES))
+}
+
+/// Sign claims with the current key, its id goes in the `kid` header
+fn sign<T: Serialize>(claims: &T, config: &AppConfig) -> Result<String, AuthError> {
+    let (kid, secret) = config
+        .jwt_keys
+        .signing_key()
+        .ok_or_else(|| AuthError::TokenGenerationError("No signing key".to_string()))?;
+    let header = Header {
+        kid: Some(kid),
+        ..Header::default()
+    };
+
+    encode(&header, claims, &EncodingKey::from_secret(&secret))
+        .map_err(|e| AuthError::TokenGenerationError(e.to_string()))
+}
+
+/// Verify a token with the key named by its `kid` header, which may be retired
+fn verify<T: DeserializeOwned>(token: &str, validation: &Validation, config: &AppConfig) -> Result<T, AuthError> {
+    let kid = decode_header(token)
+        .ok()
+        .and_then(|header| header.kid)
+        .ok_or(AuthError::InvalidToken)?;
+    let secret = config.jwt_keys.verification_key(&kid).ok_or(AuthError::InvalidToken)?;
+
+    decode::<T>(token, &DecodingKey::from_secret(&secret), validation)
+        .map(|data| data.claims)
+        .map_err(|e| match e.kind() {
+            jsonwebtoken::errors::ErrorKind::ExpiredSignature => AuthError::TokenExpired,
+            _ => AuthError::InvalidToken,
+        })
 }
 
 /// Extract bearer token from Authorization header
@@ -112,6 +203,7 @@ pub fn extract_bearer_token(header: &str) -> Option<&str> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::JwtKeys;
 
     #[test]
     fn test_password_hashing() {
@@ -123,17 +215,53 @@ mod tests {
         assert!(!verify_password("wrong_password", &hash).expect("Verification should succeed"));
     }
 
-    #[test]
-    fn test_jwt_generation_and_validation() {
-        let config = AppConfig {
+    fn test_config() -> AppConfig {
+        AppConfig {
             bind_address: "0.0.0.0:3000".to_string(),
             database_url: "sqlite::memory:".to_string(),
-            jwt_secret: "test-secret-key".to_string(),
+            jwt_secret: Some("test-secret-key".to_string()),
+            jwt_key_rotation_days: 0,
+            data_dir: "./data".to_string(),
             jwt_expiration_hours: 24,
+            access_token_minutes: 15,
             files_root: "./data/files".to_string(),
+            homes_root: None,
+            homes_samba_config: String::new(),
             static_dir: None,
             dev_mode: false,
-        };
+            terminal_shell: "/bin/sh".to_string(),
+            terminal_idle_timeout_secs: 0,
+            login_max_failures: 5,
+            login_lockout_minutes: 15,
+            trusted_proxies: String::new(),
+            password_min_length: 8,
+            password_min_classes: 3,
+            password_history: 5,
+            audit_retention_days: 365,
+            package_verify_hours: 24,
+            ldap_url: None,
+            ldap_ca_file: None,
+            ldap_bind_dn: None,
+            ldap_bind_password: None,
+            ldap_user_base: None,
+            ldap_user_filter: "(&(objectClass=person)(uid={username}))".to_string(),
+            ldap_username_attr: "uid".to_string(),
+            ldap_email_attr: "mail".to_string(),
+            ldap_group_base: None,
+            ldap_group_filter: "(objectClass=groupOfNames)".to_string(),
+            ldap_group_name_attr: "cn".to_string(),
+            ldap_group_member_attr: "member".to_string(),
+            ldap_admin_group: None,
+            ldap_sync_minutes: 60,
+            ldap_timeout_secs: 10,
+            public_url: None,
+            jwt_keys: JwtKeys::from_secret("test-secret-key"),
+        }
+    }
+
+    #[test]
+    fn test_jwt_generation_and_validation() {
+        let config = test_config();
 
         let user = User::new(
             "testuser".to_string(),
@@ -142,12 +270,13 @@ mod tests {
             true,
         );
 
-        let token = generate_jwt(&user, &config).expect("Token generation should succeed");
+        let token = generate_jwt(&user, "session-1", &config).expect("Token generation should succeed");
         let claims = validate_jwt(&token, &config).expect("Token validation should succeed");
 
         assert_eq!(claims.sub, user.id);
         assert_eq!(claims.username, "testuser");
         assert!(claims.is_admin);
+        assert_eq!(claims.sid, "session-1");
     }
 
     #[test]
@@ -163,4 +292,40 @@ mod tests {
         assert_eq!(extract_bearer_token("Basic abc123"), None);
         assert_eq!(extract_bearer_token("abc123"), None);
     }
+
+    #[test]
+    fn test_mfa_pending_token_is_not_a_session_token() {
+        let config = test_config();
+        let user = User::new("testuser".to_string(), "hash".to_string(), None, false);
+
+        let token = generate_mfa_pending_token(&user, MfaPurpose::Verify, &config).unwrap();
+        assert!(validate_jwt(&token, &config).is_err());
+        assert!(validate_mfa_pending_token(&token, MfaPurpose::Enroll, &config).is_err());
+
+        let claims = validate_mfa_pending_token(&token, MfaPurpose::Verify, &config).unwrap();
+        assert_eq!(claims.sub, user.id);
+
+        // A session token cannot be used as a pending token either
+        let jwt = generate_jwt(&user, "session-1", &config).unwrap();
+        assert!(validate_mfa_pending_token(&jwt, MfaPurpose::Verify, &config).is_err());
+    }
+
+    #[test]
+    fn test_tokens_survive_key_rotation() {
+        let dir = std::env::temp_dir().join(format!("pinas-auth-{}", uuid::Uuid::new_v4()));
+        let mut config = test_config();
+        config.jwt_keys = JwtKeys::load_or_generate(dir.join("jwt_keys.json")).unwrap();
+        let user = User::new("testuser".to_string(), "hash".to_string(), None, false);
+
+        let token = generate_jwt(&user, "session-1", &config).unwrap();
+        config.jwt_keys.rotate(retired_key_lifetime(&config)).unwrap();
+        assert!(validate_jwt(&token, &config).is_ok());
+        assert!(validate_jwt(&generate_jwt(&user, "session-1", &config).unwrap(), &config).is_ok());
+
+        // Once dropped, the retired key no longer verifies
+        config.jwt_keys.rotate(Duration::zero()).unwrap();
+        assert!(matches!(validate_jwt(&token, &config), Err(AuthError::InvalidToken)));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
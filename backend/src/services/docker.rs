@@ -6,16 +6,23 @@ use bollard::container::{
 };
 use bollard::image::{ListImagesOptions, RemoveImageOptions};
 use bollard::models::{ContainerSummary, HostConfig, ImageSummary, PortBinding};
+use bollard::system::EventsOptions;
 use bollard::Docker;
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 use crate::models::manifest::ContainerConfig;
+use crate::services::events::{DockerEvent, EventBus, WsEvent};
+
+/// Delay before reconnecting to the Docker event stream
+const EVENTS_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
 
 /// Docker service for managing containers and images
 pub struct DockerService {
     client: Option<Docker>,
+    /// Path of the socket the client is connected to
+    socket: Option<String>,
 }
 
 /// Docker system stats
@@ -74,12 +81,14 @@ pub struct ContainerStats {
 impl DockerService {
     /// Create a new Docker service
     pub async fn new() -> Self {
-        let client = Self::connect().await.ok();
-        Self { client }
+        match Self::connect().await {
+            Ok((client, socket)) => Self { client: Some(client), socket: Some(socket) },
+            Err(_) => Self { client: None, socket: None },
+        }
     }
 
     /// Try to connect to Docker daemon
-    async fn connect() -> Result<Docker> {
+    async fn connect() -> Result<(Docker, String)> {
         // Try socket path first (common on Linux)
         let socket_path = std::env::var("DOCKER_HOST")
             .unwrap_or_else(|_| "unix:///var/run/docker.sock".to_string());
@@ -92,11 +101,12 @@ impl DockerService {
         ];
 
         for path in paths {
-            if let Ok(docker) = Docker::connect_with_socket(&path.replace("unix://", ""), 120, bollard::API_DEFAULT_VERSION) {
+            let socket = path.replace("unix://", "");
+            if let Ok(docker) = Docker::connect_with_socket(&socket, 120, bollard::API_DEFAULT_VERSION) {
                 // Verify connection
                 if docker.ping().await.is_ok() {
                     tracing::info!("Connected to Docker at {}", path);
-                    return Ok(docker);
+                    return Ok((docker, socket));
                 }
             }
         }
@@ -345,6 +355,48 @@ impl DockerService {
         Ok(())
     }
 
+    /// Load images from a `docker save` tarball
+    /// The tarball is streamed to the socket, bollard only sends bodies held in memory
+    pub async fn load_image(&self, tarball: &std::path::Path) -> Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let socket = self.socket.as_deref().ok_or_else(|| anyhow!("Docker is not available"))?;
+        let mut file = tokio::fs::File::open(tarball).await?;
+        let length = file.metadata().await?.len();
+
+        // HTTP/1.0, so the response is a plain body ended by the daemon closing the connection
+        let mut connection = tokio::net::UnixStream::connect(socket).await?;
+        let head = format!(
+            "POST /images/load?quiet=1 HTTP/1.0\r\nHost: docker\r\nContent-Type: application/x-tar\r\nContent-Length: {}\r\n\r\n",
+            length
+        );
+        connection.write_all(head.as_bytes()).await?;
+        tokio::io::copy(&mut file, &mut connection).await?;
+
+        let mut response = Vec::new();
+        connection.read_to_end(&mut response).await?;
+        let response = String::from_utf8_lossy(&response);
+        let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+        let status = head.split_whitespace().nth(1).unwrap_or_default();
+        if !status.starts_with('2') {
+            return Err(anyhow!("Docker returned HTTP {}: {}", status, body.trim()));
+        }
+
+        for line in body.lines() {
+            let Ok(info) = serde_json::from_str::<bollard::models::BuildInfo>(line) else {
+                continue;
+            };
+            if let Some(error) = info.error {
+                return Err(anyhow!("Failed to load {}: {}", tarball.display(), error));
+            }
+            if let Some(stream) = info.stream {
+                tracing::info!("Load {}: {}", tarball.display(), stream.trim());
+            }
+        }
+
+        Ok(())
+    }
+
     /// Remove an image
     pub async fn remove_image(&self, image: &str, force: bool) -> Result<()> {
         let client = self.client()?;
@@ -451,6 +503,41 @@ impl DockerService {
         let response = client.create_container(Some(options), container_config).await?;
         Ok(response.id)
     }
+
+    /// Forward Docker daemon events to the event bus until the stream ends
+    pub async fn forward_events(&self, bus: &EventBus) -> Result<()> {
+        let client = self.client()?;
+        let mut stream = client.events(None::<EventsOptions<String>>);
+
+        while let Some(message) = stream.next().await {
+            let message = message?;
+            let actor = message.actor.unwrap_or_default();
+            bus.publish(WsEvent::DockerEvent(DockerEvent {
+                kind: message.typ.map(|t| t.to_string()).unwrap_or_default(),
+                action: message.action.unwrap_or_default(),
+                id: actor.id.unwrap_or_default(),
+                name: actor.attributes.and_then(|mut a| a.remove("name")),
+                time: message.time.unwrap_or(0),
+            }));
+        }
+
+        Ok(())
+    }
+}
+
+/// Spawn a background task forwarding Docker events, reconnecting when the daemon restarts
+pub fn spawn_event_forwarder(bus: EventBus) {
+    tokio::spawn(async move {
+        loop {
+            let docker = DockerService::new().await;
+            if docker.is_available() {
+                if let Err(e) = docker.forward_events(&bus).await {
+                    tracing::warn!("Docker event stream interrupted: {}", e);
+                }
+            }
+            tokio::time::sleep(EVENTS_RETRY_DELAY).await;
+        }
+    });
 }
 
 /// Calculate CPU percentage from stats
@@ -469,3 +556,64 @@ fn calculate_cpu_percent(stats: &Stats) -> f64 {
         0.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixListener;
+
+    /// Accept one request on a socket, reply with `response` and return the body received
+    fn spawn_socket_mock(path: &std::path::Path, response: &'static str) -> tokio::task::JoinHandle<Vec<u8>> {
+        let listener = UnixListener::bind(path).unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0u8; length];
+            reader.read_exact(&mut body).await.unwrap();
+            reader.get_mut().write_all(response.as_bytes()).await.unwrap();
+            body
+        })
+    }
+
+    #[tokio::test]
+    async fn test_load_image_streams_the_tarball() {
+        let dir = std::env::temp_dir().join(format!("pinas-docker-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let tarball = dir.join("image.tar");
+        std::fs::write(&tarball, vec![7u8; 200_000]).unwrap();
+        let service = DockerService {
+            client: None,
+            socket: Some(dir.join("docker.sock").to_string_lossy().into_owned()),
+        };
+
+        let server = spawn_socket_mock(
+            &dir.join("docker.sock"),
+            "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{\"stream\":\"Loaded image: app:1\\n\"}\n",
+        );
+        service.load_image(&tarball).await.unwrap();
+        assert_eq!(server.await.unwrap(), vec![7u8; 200_000]);
+
+        std::fs::remove_file(dir.join("docker.sock")).unwrap();
+        let server = spawn_socket_mock(
+            &dir.join("docker.sock"),
+            "HTTP/1.0 200 OK\r\n\r\n{\"errorDetail\":{\"message\":\"bad tar\"},\"error\":\"bad tar\"}\n",
+        );
+        let error = service.load_image(&tarball).await.unwrap_err();
+        assert!(error.to_string().contains("bad tar"));
+        server.await.unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
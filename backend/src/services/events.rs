@@ -0,0 +1,208 @@
+use serde::Serialize;
+use std::time::Duration;
+use sysinfo::System;
+use tokio::sync::broadcast;
+
+use crate::models::notification::Notification;
+use crate::models::package::{PackageTask, PackageTaskLog};
+use crate::models::settings::DeviceSettings;
+
+/// Interval between two system stats samples
+const STATS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Topics clients can subscribe to
+pub const TOPIC_SYSTEM_STATS: &str = "system.stats";
+pub const TOPIC_NOTIFICATIONS: &str = "notifications";
+pub const TOPIC_SETTINGS: &str = "settings";
+pub const TOPIC_PACKAGES: &str = "packages";
+pub const TOPIC_DOCKER: &str = "docker";
+
+/// All topics, with whether they are restricted to administrators
+pub const TOPICS: &[(&str, bool)] = &[
+    (TOPIC_SYSTEM_STATS, false),
+    (TOPIC_NOTIFICATIONS, false),
+    (TOPIC_SETTINGS, false),
+    (TOPIC_PACKAGES, true),
+    (TOPIC_DOCKER, true),
+];
+
+/// Event published on the bus and pushed to WebSocket clients
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data")]
+pub enum WsEvent {
+    #[serde(rename = "system.stats")]
+    SystemStats(SystemStats),
+    #[serde(rename = "notification")]
+    Notification(Notification),
+    #[serde(rename = "settings.changed")]
+    SettingsChanged(DeviceSettings),
+    #[serde(rename = "package.task")]
+    PackageTask(PackageTask),
+    #[serde(rename = "package.task_log")]
+    PackageTaskLog(PackageTaskLog),
+    #[serde(rename = "docker.event")]
+    DockerEvent(DockerEvent),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemStats {
+    pub cpu_usage: f32,
+    pub memory_usage: f32,
+    pub memory_used: u64,
+    pub memory_total: u64,
+}
+
+/// Docker daemon event (container started, image pulled, ...)
+#[derive(Debug, Clone, Serialize)]
+pub struct DockerEvent {
+    /// Object type ("container", "image", "network", ...)
+    pub kind: String,
+    /// Action ("start", "die", "pull", ...)
+    pub action: String,
+    pub id: String,
+    pub name: Option<String>,
+    pub time: i64,
+}
+
+impl WsEvent {
+    /// Topic the event is published on
+    pub fn topic(&self) -> &'static str {
+        match self {
+            WsEvent::SystemStats(_) => TOPIC_SYSTEM_STATS,
+            WsEvent::Notification(_) => TOPIC_NOTIFICATIONS,
+            WsEvent::SettingsChanged(_) => TOPIC_SETTINGS,
+            WsEvent::PackageTask(_) | WsEvent::PackageTaskLog(_) => TOPIC_PACKAGES,
+            WsEvent::DockerEvent(_) => TOPIC_DOCKER,
+        }
+    }
+
+    /// Whether a user may receive this event
+    pub fn is_visible_to(&self, user_id: &str, is_admin: bool) -> bool {
+        match self {
+            WsEvent::Notification(n) => match &n.user_id {
+                Some(recipient) => recipient == user_id,
+                None => is_admin,
+            },
+            _ => is_topic_allowed(self.topic(), is_admin),
+        }
+    }
+}
+
+/// API token scope area granting read access to a topic
+pub fn topic_scope(topic: &str) -> &str {
+    match topic {
+        TOPIC_SYSTEM_STATS => "system",
+        other => other,
+    }
+}
+
+/// Check a topic exists and the user may subscribe to it
+pub fn is_topic_allowed(topic: &str, is_admin: bool) -> bool {
+    TOPICS
+        .iter()
+        .any(|(name, admin_only)| *name == topic && (is_admin || !admin_only))
+}
+
+/// Central broadcast bus any service can publish events to
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<WsEvent>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Publish an event to all current subscribers
+    pub fn publish(&self, event: WsEvent) {
+        // No subscribers is not an error
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<WsEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+/// Spawn the shared system stats sampler, idle while nobody is connected
+pub fn spawn_stats_sampler(bus: EventBus) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(STATS_INTERVAL);
+        let mut sys = System::new();
+
+        loop {
+            interval.tick().await;
+            if bus.subscriber_count() == 0 {
+                continue;
+            }
+
+            sys.refresh_cpu();
+            sys.refresh_memory();
+
+            let memory_total = sys.total_memory();
+            let memory_used = sys.used_memory();
+            let memory_usage = if memory_total > 0 {
+                (memory_used as f32 / memory_total as f32) * 100.0
+            } else {
+                0.0
+            };
+
+            bus.publish(WsEvent::SystemStats(SystemStats {
+                cpu_usage: sys.global_cpu_info().cpu_usage(),
+                memory_usage,
+                memory_used,
+                memory_total,
+            }));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::notification::NotificationLevel;
+
+    fn notification_for(user_id: Option<&str>) -> WsEvent {
+        WsEvent::Notification(Notification::new(
+            NotificationLevel::Info,
+            "t".to_string(),
+            "m".to_string(),
+            "system".to_string(),
+            user_id.map(|u| u.to_string()),
+        ))
+    }
+
+    #[test]
+    fn test_topic_permissions() {
+        assert!(is_topic_allowed(TOPIC_SYSTEM_STATS, false));
+        assert!(!is_topic_allowed(TOPIC_DOCKER, false));
+        assert!(is_topic_allowed(TOPIC_DOCKER, true));
+        assert!(!is_topic_allowed("unknown", true));
+    }
+
+    #[test]
+    fn test_notification_visibility() {
+        assert!(notification_for(Some("u1")).is_visible_to("u1", false));
+        assert!(!notification_for(Some("u1")).is_visible_to("u2", true));
+        assert!(notification_for(None).is_visible_to("u2", true));
+        assert!(!notification_for(None).is_visible_to("u2", false));
+    }
+
+    #[tokio::test]
+    async fn test_bus_delivers_to_subscribers() {
+        let bus = EventBus::new(4);
+        // Publishing without subscribers is a no-op
+        bus.publish(notification_for(None));
+
+        let mut rx = bus.subscribe();
+        assert_eq!(bus.subscriber_count(), 1);
+        bus.publish(notification_for(Some("u1")));
+        assert_eq!(rx.recv().await.unwrap().topic(), TOPIC_NOTIFICATIONS);
+    }
+}
@@ -22,6 +22,9 @@ pub enum GroupError {
     #[error("User is already a member of this group")]
     AlreadyMember,
 
+    #[error("Group is synced from the LDAP directory and cannot be edited")]
+    ExternalGroup,
+
     #[error("Database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
 }
@@ -97,12 +100,19 @@ pub async fn update_group(
     id: &str,
     name: Option<String>,
     description: Option<Option<String>>,
+    require_mfa: Option<bool>,
 ) -> Result<UserGroup, GroupError> {
     let existing = get_group_by_id(db, id).await?.ok_or(GroupError::NotFound)?;
 
+    // Directory data is read-only, requiring two-factor authentication stays a local choice
+    if existing.is_external() && (name.is_some() || description.is_some()) {
+        return Err(GroupError::ExternalGroup);
+    }
+
     let now = chrono::Utc::now().to_rfc3339();
     let new_name = name.unwrap_or(existing.name.clone());
     let new_description = description.unwrap_or(existing.description.clone());
+    let new_require_mfa = require_mfa.unwrap_or(existing.require_mfa);
 
     // Check for duplicate name if changing
     if new_name != existing.name {
@@ -114,12 +124,13 @@ pub async fn update_group(
     sqlx::query(
         r#"
         UPDATE user_groups
-        SET name = ?, description = ?, updated_at = ?
+        SET name = ?, description = ?, require_mfa = ?, updated_at = ?
         WHERE id = ?
         "#,
     )
     .bind(&new_name)
     .bind(&new_description)
+    .bind(new_require_mfa)
     .bind(&now)
     .bind(id)
     .execute(db)
@@ -135,6 +146,9 @@ pub async fn delete_group(db: &SqlitePool, id: &str) -> Result<(), GroupError> {
     if group.is_system {
         return Err(GroupError::CannotDeleteSystemGroup);
     }
+    if group.is_external() {
+        return Err(GroupError::ExternalGroup);
+    }
 
     sqlx::query("DELETE FROM user_groups WHERE id = ?")
         .bind(id)
@@ -151,9 +165,12 @@ pub async fn add_member(
     user_id: &str,
 ) -> Result<(), GroupError> {
     // Check if group exists
-    let _ = get_group_by_id(db, group_id)
+    let group = get_group_by_id(db, group_id)
         .await?
         .ok_or(GroupError::NotFound)?;
+    if group.is_external() {
+        return Err(GroupError::ExternalGroup);
+    }
 
     // Check if already a member
     let existing = sqlx::query_as::<_, GroupMember>(
@@ -192,6 +209,13 @@ pub async fn remove_member(
     group_id: &str,
     user_id: &str,
 ) -> Result<(), GroupError> {
+    let group = get_group_by_id(db, group_id)
+        .await?
+        .ok_or(GroupError::NotFound)?;
+    if group.is_external() {
+        return Err(GroupError::ExternalGroup);
+    }
+
     let result = sqlx::query("DELETE FROM user_group_members WHERE group_id = ? AND user_id = ?")
         .bind(group_id)
         .bind(user_id)
@@ -0,0 +1,255 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::config::AppConfig;
+use crate::models::user::User;
+use crate::services::system_user;
+
+/// Hidden folder of the homes root keeping archives of deleted users' homes
+const ARCHIVE_DIR: &str = ".archive";
+
+/// Home directories are private to their owner
+const HOME_MODE: u32 = 0o700;
+
+/// Home directory errors
+#[derive(Debug, Error)]
+pub enum HomeError {
+    #[error("Username cannot be used as a directory name")]
+    InvalidUsername,
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to archive home directory: {0}")]
+    Archive(String),
+}
+
+/// What happens to a home directory when its user is deleted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HomeRemoval {
+    /// Keep a compressed copy under the homes root, then remove the directory
+    #[default]
+    Archive,
+    Delete,
+    Keep,
+}
+
+/// Home directory settings, None when the feature is disabled
+#[derive(Debug, Clone)]
+pub struct HomesSettings {
+    pub root: PathBuf,
+    /// Samba configuration include holding the `[homes]` share
+    pub samba_config: Option<PathBuf>,
+}
+
+impl HomesSettings {
+    pub fn from_config(config: &AppConfig) -> Option<Self> {
+        let root = config.homes_root.as_deref().filter(|r| !r.is_empty())?;
+        Some(Self {
+            root: PathBuf::from(root),
+            samba_config: Some(&config.homes_samba_config)
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from),
+        })
+    }
+
+    /// Home directory of a username
+    pub fn home_path(&self, username: &str) -> Result<PathBuf, HomeError> {
+        let valid = !username.is_empty()
+            && !username.starts_with('.')
+            && !username.contains(['/', '\\', '\0']);
+        if !valid {
+            return Err(HomeError::InvalidUsername);
+        }
+        Ok(self.root.join(username))
+    }
+}
+
+/// Create the user's home directory when missing, owned by their system account
+pub fn ensure_home(settings: &HomesSettings, user: &User) -> Result<PathBuf, HomeError> {
+    let home = settings.home_path(&user.username)?;
+    if home.is_dir() {
+        return Ok(home);
+    }
+
+    fs::create_dir_all(&settings.root)?;
+    fs::create_dir(&home)?;
+    fs::set_permissions(&home, fs::Permissions::from_mode(HOME_MODE))?;
+
+    // Users without a system account (or mapped to root) keep a home owned by the daemon
+    let account = user.system_user.as_deref().unwrap_or(&user.username);
+    match system_user::lookup(account) {
+        Ok(identity) if !identity.uid.is_root() => {
+            if let Err(e) = nix::unistd::chown(&home, Some(identity.uid), Some(identity.gid)) {
+                tracing::warn!("Failed to give {} to {}: {}", home.display(), account, e);
+            }
+        }
+        Ok(_) => tracing::warn!("Not giving home of {} to root", user.username),
+        Err(e) => tracing::debug!("Home of {} stays owned by the daemon: {}", user.username, e),
+    }
+
+    tracing::info!("Created home directory {}", home.display());
+    Ok(home)
+}
+
+/// Archive, delete or keep a deleted user's home, returns the archive path
+pub async fn remove_home(
+    settings: &HomesSettings,
+    username: &str,
+    removal: HomeRemoval,
+) -> Result<Option<PathBuf>, HomeError> {
+    let home = settings.home_path(username)?;
+    if removal == HomeRemoval::Keep || !home.exists() {
+        return Ok(None);
+    }
+
+    let archive = match removal {
+        HomeRemoval::Archive => {
+            let dir = settings.root.join(ARCHIVE_DIR);
+            fs::create_dir_all(&dir)?;
+            fs::set_permissions(&dir, fs::Permissions::from_mode(HOME_MODE))?;
+            let name = format!("{}-{}.tar.gz", username, chrono::Utc::now().format("%Y%m%d%H%M%S"));
+            let path = dir.join(name);
+
+            let (source, target) = (home.clone(), path.clone());
+            tokio::task::spawn_blocking(move || archive_directory(&source, &target))
+                .await
+                .map_err(|e| HomeError::Archive(e.to_string()))??;
+            Some(path)
+        }
+        _ => None,
+    };
+
+    tokio::fs::remove_dir_all(&home).await?;
+    tracing::info!("Removed home directory {}", home.display());
+    Ok(archive)
+}
+
+/// Write a tar.gz of a directory, its entries under the directory name
+fn archive_directory(source: &Path, target: &Path) -> Result<(), HomeError> {
+    let name = source.file_name().ok_or(HomeError::InvalidUsername)?;
+    let file = fs::File::create(target)?;
+    fs::set_permissions(target, fs::Permissions::from_mode(0o600))?;
+
+    let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    builder.follow_symlinks(false);
+    let result = builder
+        .append_dir_all(name, source)
+        .and_then(|_| builder.into_inner())
+        .and_then(|encoder| encoder.finish());
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(target);
+        return Err(HomeError::Archive(e.to_string()));
+    }
+    Ok(())
+}
+
+/// Samba share giving each user their home, `%U` being the connected user
+pub fn samba_share(settings: &HomesSettings) -> String {
+    format!(
+        "# Generated by PiNAS, changes are overwritten\n\
+         [homes]\n\
+         \x20  comment = Home directory\n\
+         \x20  path = {}/%U\n\
+         \x20  browseable = no\n\
+         \x20  read only = no\n\
+         \x20  valid users = %S\n\
+         \x20  create mask = 0600\n\
+         \x20  directory mask = 0700\n",
+        settings.root.display()
+    )
+}
+
+/// Write the `[homes]` share include, Samba reloads changed configuration on its own
+pub fn publish_samba_share(settings: &HomesSettings) -> Result<(), HomeError> {
+    let Some(path) = &settings.samba_config else {
+        return Ok(());
+    };
+    let content = samba_share(settings);
+    if fs::read_to_string(path).ok().as_deref() == Some(content.as_str()) {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)?;
+    tracing::info!("Wrote Samba homes share to {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> HomesSettings {
+        let root = std::env::temp_dir().join(format!("pinas-homes-{}", uuid::Uuid::new_v4()));
+        HomesSettings {
+            samba_config: Some(root.join("samba/pinas-homes.conf")),
+            root,
+        }
+    }
+
+    fn user(username: &str) -> User {
+        let mut user = User::new(username.to_string(), "x".to_string(), None, false);
+        user.system_user = Some("pinas-no-such-user".to_string());
+        user
+    }
+
+    #[test]
+    fn test_home_path_rejects_unsafe_names() {
+        let settings = settings();
+        assert_eq!(settings.home_path("alice").unwrap(), settings.root.join("alice"));
+        for name in ["", ".", "..", ".archive", "a/b", "a\\b"] {
+            assert!(matches!(settings.home_path(name), Err(HomeError::InvalidUsername)), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provision_archive_and_delete() {
+        let settings = settings();
+
+        let home = ensure_home(&settings, &user("alice")).unwrap();
+        assert_eq!(fs::metadata(&home).unwrap().permissions().mode() & 0o777, HOME_MODE);
+        fs::write(home.join("notes.txt"), "hello").unwrap();
+        // Existing homes are left alone
+        assert_eq!(ensure_home(&settings, &user("alice")).unwrap(), home);
+        assert!(home.join("notes.txt").exists());
+
+        let archive = remove_home(&settings, "alice", HomeRemoval::Archive).await.unwrap().unwrap();
+        assert!(!home.exists());
+        let mut entries = tar::Archive::new(flate2::read::GzDecoder::new(fs::File::open(&archive).unwrap()));
+        let names: Vec<String> = entries
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert!(names.contains(&"alice/notes.txt".to_string()));
+
+        let home = ensure_home(&settings, &user("bob")).unwrap();
+        assert_eq!(remove_home(&settings, "bob", HomeRemoval::Keep).await.unwrap(), None);
+        assert!(home.exists());
+        assert_eq!(remove_home(&settings, "bob", HomeRemoval::Delete).await.unwrap(), None);
+        assert!(!home.exists());
+
+        fs::remove_dir_all(&settings.root).unwrap();
+    }
+
+    #[test]
+    fn test_publish_samba_share() {
+        let settings = settings();
+        publish_samba_share(&settings).unwrap();
+        let written = fs::read_to_string(settings.samba_config.as_ref().unwrap()).unwrap();
+        assert!(written.contains("[homes]"));
+        assert!(written.contains(&format!("path = {}/%U", settings.root.display())));
+        assert!(written.contains("valid users = %S"));
+
+        fs::remove_dir_all(&settings.root).unwrap();
+    }
+}
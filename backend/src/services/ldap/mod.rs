@@ -0,0 +1,909 @@
+mod protocol;
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use sqlx::SqlitePool;
+use thiserror::Error;
+
+use crate::config::AppConfig;
+use crate::models::group::{GroupMember, UserGroup};
+use crate::models::user::{User, AUTH_SOURCE_LDAP};
+
+pub use protocol::{escape_filter_value, LdapConnection, LdapResult, SearchEntry};
+use protocol::{RESULT_INVALID_CREDENTIALS, RESULT_SUCCESS};
+
+/// `external_source` of groups synced from the directory
+pub const EXTERNAL_SOURCE_LDAP: &str = "ldap";
+
+/// Stored as the password hash of directory users, no password ever verifies against it
+const UNUSABLE_PASSWORD_HASH: &str = "!ldap";
+
+/// LDAP errors
+#[derive(Debug, Error)]
+pub enum LdapError {
+    #[error("Invalid LDAP URL: {0}")]
+    InvalidUrl(String),
+
+    #[error("Invalid LDAP filter: {0}")]
+    InvalidFilter(String),
+
+    #[error("LDAP server did not answer in time")]
+    Timeout,
+
+    #[error("LDAP connection error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("LDAP TLS error: {0}")]
+    Tls(String),
+
+    #[error("LDAP protocol error: {0}")]
+    Protocol(String),
+
+    #[error("LDAP server returned error {}: {}", .0.code, .0.message)]
+    Operation(LdapResult),
+
+    #[error("Username {0} is already used by a local account")]
+    UsernameTaken(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// Directory connection and attribute mapping
+#[derive(Debug, Clone)]
+pub struct LdapSettings {
+    pub url: String,
+    pub ca_file: Option<String>,
+    pub bind_dn: Option<String>,
+    pub bind_password: Option<String>,
+    pub user_base: String,
+    pub user_filter: String,
+    pub username_attr: String,
+    pub email_attr: String,
+    pub group_base: Option<String>,
+    pub group_filter: String,
+    pub group_name_attr: String,
+    pub group_member_attr: String,
+    pub admin_group: Option<String>,
+    pub timeout: Duration,
+}
+
+impl LdapSettings {
+    /// None unless both the server URL and the user base DN are configured
+    pub fn from_config(config: &AppConfig) -> Option<Self> {
+        let url = config.ldap_url.clone().filter(|u| !u.trim().is_empty())?;
+        let user_base = config.ldap_user_base.clone().filter(|b| !b.trim().is_empty())?;
+        Some(Self {
+            url,
+            ca_file: config.ldap_ca_file.clone(),
+            bind_dn: config.ldap_bind_dn.clone().filter(|d| !d.is_empty()),
+            bind_password: config.ldap_bind_password.clone(),
+            user_base,
+            user_filter: config.ldap_user_filter.clone(),
+            username_attr: config.ldap_username_attr.clone(),
+            email_attr: config.ldap_email_attr.clone(),
+            group_base: config.ldap_group_base.clone().filter(|b| !b.trim().is_empty()),
+            group_filter: config.ldap_group_filter.clone(),
+            group_name_attr: config.ldap_group_name_attr.clone(),
+            group_member_attr: config.ldap_group_member_attr.clone(),
+            admin_group: config.ldap_admin_group.clone().filter(|g| !g.is_empty()),
+            timeout: Duration::from_secs(config.ldap_timeout_secs.max(1)),
+        })
+    }
+
+    /// Whether a group, by name or DN, is the administrators group
+    fn is_admin_group(&self, group: &LdapGroup) -> bool {
+        self.admin_group.as_deref().is_some_and(|admin| {
+            admin.eq_ignore_ascii_case(&group.name) || admin.eq_ignore_ascii_case(&group.dn)
+        })
+    }
+}
+
+/// A directory group
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LdapGroup {
+    pub dn: String,
+    pub name: String,
+}
+
+/// A user whose password the directory accepted
+#[derive(Debug, Clone)]
+pub struct LdapUser {
+    pub dn: String,
+    pub username: String,
+    pub email: Option<String>,
+    pub groups: Vec<LdapGroup>,
+}
+
+/// Outcome of a group sync
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncReport {
+    pub groups: usize,
+    pub memberships: usize,
+    pub removed: usize,
+}
+
+/// Open a connection, bound as the service account when one is configured
+async fn connect(settings: &LdapSettings) -> Result<LdapConnection, LdapError> {
+    let mut conn =
+        LdapConnection::connect(&settings.url, settings.ca_file.as_deref(), settings.timeout).await?;
+    bind_service(&mut conn, settings).await?;
+    Ok(conn)
+}
+
+async fn bind_service(conn: &mut LdapConnection, settings: &LdapSettings) -> Result<(), LdapError> {
+    let Some(dn) = &settings.bind_dn else {
+        return Ok(());
+    };
+    let result = conn
+        .simple_bind(dn, settings.bind_password.as_deref().unwrap_or_default())
+        .await?;
+    if result.code != RESULT_SUCCESS {
+        return Err(LdapError::Operation(result));
+    }
+    Ok(())
+}
+
+/// Check a username and password against the directory
+/// Returns None when the user does not exist or the password is wrong
+pub async fn authenticate(
+    settings: &LdapSettings,
+    username: &str,
+    password: &str,
+) -> Result<Option<LdapUser>, LdapError> {
+    // An empty password is an unauthenticated bind, which servers accept for any DN
+    if username.trim().is_empty() || password.is_empty() {
+        return Ok(None);
+    }
+
+    let mut conn = connect(settings).await?;
+    let filter = settings
+        .user_filter
+        .replace("{username}", &escape_filter_value(username));
+    let mut entries = conn
+        .search(
+            &settings.user_base,
+            &filter,
+            &[&settings.username_attr, &settings.email_attr],
+            2,
+        )
+        .await?;
+    if entries.len() != 1 {
+        if entries.len() > 1 {
+            tracing::warn!("LDAP user filter matches several entries for {}", username);
+        }
+        conn.unbind().await;
+        return Ok(None);
+    }
+    let entry = entries.remove(0);
+
+    let result = conn.simple_bind(&entry.dn, password).await?;
+    match result.code {
+        RESULT_SUCCESS => {}
+        RESULT_INVALID_CREDENTIALS => {
+            conn.unbind().await;
+            return Ok(None);
+        }
+        _ => return Err(LdapError::Operation(result)),
+    }
+
+    // Group lookups run as the service account, users may not read groups
+    let groups = match &settings.group_base {
+        Some(base) => {
+            bind_service(&mut conn, settings).await?;
+            let filter = format!(
+                "(&{}({}={}))",
+                wrap_filter(&settings.group_filter),
+                settings.group_member_attr,
+                escape_filter_value(&entry.dn)
+            );
+            conn.search(base, &filter, &[&settings.group_name_attr], 0)
+                .await?
+                .iter()
+                .filter_map(|group| to_group(settings, group))
+                .collect()
+        }
+        None => Vec::new(),
+    };
+    conn.unbind().await;
+
+    Ok(Some(LdapUser {
+        username: entry
+            .first(&settings.username_attr)
+            .unwrap_or(username)
+            .to_string(),
+        email: entry.first(&settings.email_attr).map(str::to_string),
+        dn: entry.dn,
+        groups,
+    }))
+}
+
+/// Create or refresh the local account of a directory user, and its group memberships
+pub async fn provision_user(
+    db: &SqlitePool,
+    settings: &LdapSettings,
+    ldap_user: &LdapUser,
+) -> Result<User, LdapError> {
+    let is_admin = settings
+        .admin_group
+        .as_ref()
+        .map(|_| ldap_user.groups.iter().any(|g| settings.is_admin_group(g)));
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let user = match find_user_by_dn(db, &ldap_user.dn).await? {
+        Some(existing) => {
+            sqlx::query("UPDATE users SET username = ?, email = ?, is_admin = ?, updated_at = ? WHERE id = ?")
+                .bind(&ldap_user.username)
+                .bind(&ldap_user.email)
+                .bind(is_admin.unwrap_or(existing.is_admin))
+                .bind(&now)
+                .bind(&existing.id)
+                .execute(db)
+                .await?;
+            find_user_by_dn(db, &ldap_user.dn)
+                .await?
+                .ok_or(sqlx::Error::RowNotFound)?
+        }
+        None => {
+            let taken: Option<(String,)> = sqlx::query_as("SELECT id FROM users WHERE username = ?")
+                .bind(&ldap_user.username)
+                .fetch_optional(db)
+                .await?;
+            if taken.is_some() {
+                return Err(LdapError::UsernameTaken(ldap_user.username.clone()));
+            }
+
+            let mut user = User::new(
+                ldap_user.username.clone(),
+                UNUSABLE_PASSWORD_HASH.to_string(),
+                ldap_user.email.clone(),
+                is_admin.unwrap_or(false),
+            );
+            user.auth_source = AUTH_SOURCE_LDAP.to_string();
+            user.external_id = Some(ldap_user.dn.clone());
+
+            sqlx::query(
+                r#"
+                INSERT INTO users (id, username, password_hash, email, is_admin, auth_source, external_id, created_at, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&user.id)
+            .bind(&user.username)
+            .bind(&user.password_hash)
+            .bind(&user.email)
+            .bind(user.is_admin)
+            .bind(&user.auth_source)
+            .bind(&user.external_id)
+            .bind(&user.created_at)
+            .bind(&user.updated_at)
+            .execute(db)
+            .await?;
+
+            tracing::info!("Provisioned LDAP user {} ({})", user.username, ldap_user.dn);
+            user
+        }
+    };
+
+    if settings.group_base.is_some() {
+        let mut group_ids = Vec::new();
+        for group in &ldap_user.groups {
+            if let Some(id) = upsert_group(db, group).await? {
+                group_ids.push(id);
+            }
+        }
+        set_user_groups(db, &user.id, &group_ids).await?;
+    }
+
+    Ok(user)
+}
+
+/// Mirror directory groups and their members into `user_groups`
+/// Only users who already signed in are added as members
+pub async fn sync_groups(db: &SqlitePool, settings: &LdapSettings) -> Result<SyncReport, LdapError> {
+    let Some(base) = &settings.group_base else {
+        return Ok(SyncReport::default());
+    };
+
+    let mut conn = connect(settings).await?;
+    let entries = conn
+        .search(
+            base,
+            &settings.group_filter,
+            &[&settings.group_name_attr, &settings.group_member_attr],
+            0,
+        )
+        .await?;
+    conn.unbind().await;
+
+    let users: Vec<(String, String)> =
+        sqlx::query_as("SELECT id, external_id FROM users WHERE auth_source = ? AND external_id IS NOT NULL")
+            .bind(AUTH_SOURCE_LDAP)
+            .fetch_all(db)
+            .await?;
+    let users: HashMap<String, String> = users
+        .into_iter()
+        .map(|(id, dn)| (normalize_dn(&dn), id))
+        .collect();
+
+    let mut report = SyncReport::default();
+    let mut seen = HashSet::new();
+    let mut admins = HashSet::new();
+    for entry in &entries {
+        let Some(group) = to_group(settings, entry) else {
+            continue;
+        };
+        let Some(group_id) = upsert_group(db, &group).await? else {
+            continue;
+        };
+        seen.insert(group_id.clone());
+        report.groups += 1;
+
+        let members: Vec<String> = entry
+            .values(&settings.group_member_attr)
+            .iter()
+            .filter_map(|dn| users.get(&normalize_dn(dn)).cloned())
+            .collect();
+        if settings.is_admin_group(&group) {
+            admins.extend(members.iter().cloned());
+        }
+        report.memberships += members.len();
+        set_group_members(db, &group_id, &members).await?;
+    }
+
+    // Groups removed from the directory, or no longer matching the filter
+    let existing: Vec<(String,)> = sqlx::query_as("SELECT id FROM user_groups WHERE external_source = ?")
+        .bind(EXTERNAL_SOURCE_LDAP)
+        .fetch_all(db)
+        .await?;
+    for (id,) in existing {
+        if !seen.contains(&id) {
+            sqlx::query("DELETE FROM user_groups WHERE id = ?")
+                .bind(&id)
+                .execute(db)
+                .await?;
+            report.removed += 1;
+        }
+    }
+
+    if settings.admin_group.is_some() {
+        let now = chrono::Utc::now().to_rfc3339();
+        for id in users.values() {
+            sqlx::query("UPDATE users SET is_admin = ?, updated_at = ? WHERE id = ? AND is_admin != ?")
+                .bind(admins.contains(id))
+                .bind(&now)
+                .bind(id)
+                .bind(admins.contains(id))
+                .execute(db)
+                .await?;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Periodically sync directory groups
+pub fn spawn_sync(db: SqlitePool, settings: LdapSettings, minutes: u64) {
+    if minutes == 0 || settings.group_base.is_none() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(minutes * 60));
+        loop {
+            interval.tick().await;
+            match sync_groups(&db, &settings).await {
+                Ok(report) => tracing::info!(
+                    "Synced {} LDAP groups ({} memberships, {} removed)",
+                    report.groups,
+                    report.memberships,
+                    report.removed
+                ),
+                Err(e) => tracing::error!("Failed to sync LDAP groups: {}", e),
+            }
+        }
+    });
+}
+
+async fn find_user_by_dn(db: &SqlitePool, dn: &str) -> Result<Option<User>, sqlx::Error> {
+    let users = sqlx::query_as::<_, User>("SELECT * FROM users WHERE auth_source = ? AND external_id IS NOT NULL")
+        .bind(AUTH_SOURCE_LDAP)
+        .fetch_all(db)
+        .await?;
+    let dn = normalize_dn(dn);
+    Ok(users
+        .into_iter()
+        .find(|u| u.external_id.as_deref().map(normalize_dn).as_deref() == Some(dn.as_str())))
+}
+
+/// Create or rename the local copy of a directory group, returns its id
+/// None when a local group already uses the name
+async fn upsert_group(db: &SqlitePool, group: &LdapGroup) -> Result<Option<String>, sqlx::Error> {
+    let existing = sqlx::query_as::<_, UserGroup>(
+        "SELECT * FROM user_groups WHERE external_source = ? AND external_id = ?",
+    )
+    .bind(EXTERNAL_SOURCE_LDAP)
+    .bind(&group.dn)
+    .fetch_optional(db)
+    .await?;
+
+    let conflict = sqlx::query_as::<_, UserGroup>("SELECT * FROM user_groups WHERE name = ?")
+        .bind(&group.name)
+        .fetch_optional(db)
+        .await?
+        .filter(|g| existing.as_ref().map(|e| &e.id) != Some(&g.id));
+    if conflict.is_some() {
+        tracing::warn!("Skipping LDAP group {}, a group with that name exists", group.dn);
+        return Ok(existing.map(|g| g.id));
+    }
+
+    if let Some(existing) = existing {
+        if existing.name != group.name {
+            sqlx::query("UPDATE user_groups SET name = ?, updated_at = ? WHERE id = ?")
+                .bind(&group.name)
+                .bind(chrono::Utc::now().to_rfc3339())
+                .bind(&existing.id)
+                .execute(db)
+                .await?;
+        }
+        return Ok(Some(existing.id));
+    }
+
+    let mut created = UserGroup::new(
+        group.name.clone(),
+        Some("Synced from LDAP".to_string()),
+        false,
+    );
+    created.external_source = Some(EXTERNAL_SOURCE_LDAP.to_string());
+    created.external_id = Some(group.dn.clone());
+    sqlx::query(
+        r#"
+        INSERT INTO user_groups (id, name, description, is_system, external_source, external_id, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&created.id)
+    .bind(&created.name)
+    .bind(&created.description)
+    .bind(created.is_system)
+    .bind(&created.external_source)
+    .bind(&created.external_id)
+    .bind(&created.created_at)
+    .bind(&created.updated_at)
+    .execute(db)
+    .await?;
+
+    Ok(Some(created.id))
+}
+
+/// Make a user a member of exactly these directory groups
+async fn set_user_groups(db: &SqlitePool, user_id: &str, group_ids: &[String]) -> Result<(), sqlx::Error> {
+    let current: Vec<(String,)> = sqlx::query_as(
+        r#"
+        SELECT m.group_id FROM user_group_members m
+        INNER JOIN user_groups g ON g.id = m.group_id
+        WHERE m.user_id = ? AND g.external_source = ?
+        "#,
+    )
+    .bind(user_id)
+    .bind(EXTERNAL_SOURCE_LDAP)
+    .fetch_all(db)
+    .await?;
+    let current: HashSet<String> = current.into_iter().map(|(id,)| id).collect();
+
+    for group_id in current.iter().filter(|id| !group_ids.contains(id)) {
+        remove_membership(db, group_id, user_id).await?;
+    }
+    for group_id in group_ids.iter().filter(|id| !current.contains(*id)) {
+        add_membership(db, group_id, user_id).await?;
+    }
+    Ok(())
+}
+
+/// Make these users the exact members of a directory group
+async fn set_group_members(db: &SqlitePool, group_id: &str, user_ids: &[String]) -> Result<(), sqlx::Error> {
+    let current: Vec<(String,)> = sqlx::query_as("SELECT user_id FROM user_group_members WHERE group_id = ?")
+        .bind(group_id)
+        .fetch_all(db)
+        .await?;
+    let current: HashSet<String> = current.into_iter().map(|(id,)| id).collect();
+
+    for user_id in current.iter().filter(|id| !user_ids.contains(id)) {
+        remove_membership(db, group_id, user_id).await?;
+    }
+    for user_id in user_ids.iter().filter(|id| !current.contains(*id)) {
+        add_membership(db, group_id, user_id).await?;
+    }
+    Ok(())
+}
+
+async fn add_membership(db: &SqlitePool, group_id: &str, user_id: &str) -> Result<(), sqlx::Error> {
+    let member = GroupMember::new(user_id.to_string(), group_id.to_string());
+    sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO user_group_members (id, user_id, group_id, created_at)
+        VALUES (?, ?, ?, ?)
+        "#,
+    )
+    .bind(&member.id)
+    .bind(&member.user_id)
+    .bind(&member.group_id)
+    .bind(&member.created_at)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+async fn remove_membership(db: &SqlitePool, group_id: &str, user_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM user_group_members WHERE group_id = ? AND user_id = ?")
+        .bind(group_id)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+fn to_group(settings: &LdapSettings, entry: &SearchEntry) -> Option<LdapGroup> {
+    let name = entry.first(&settings.group_name_attr)?.trim();
+    if name.is_empty() {
+        return None;
+    }
+    Some(LdapGroup {
+        dn: entry.dn.clone(),
+        name: name.to_string(),
+    })
+}
+
+/// DNs compare case-insensitively and without spaces around separators
+fn normalize_dn(dn: &str) -> String {
+    dn.split(',')
+        .map(|rdn| {
+            rdn.split('=')
+                .map(str::trim)
+                .collect::<Vec<_>>()
+                .join("=")
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+        .to_lowercase()
+}
+
+/// Parenthesize a configured filter given without parentheses
+fn wrap_filter(filter: &str) -> String {
+    let filter = filter.trim();
+    if filter.starts_with('(') {
+        filter.to_string()
+    } else {
+        format!("({})", filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::protocol::{
+        constructed, enumerated, message, octets, read_tlv, Tlv, OP_BIND_REQUEST, OP_BIND_RESPONSE,
+        OP_SEARCH_DONE, OP_SEARCH_ENTRY, OP_SEARCH_REQUEST, OP_UNBIND_REQUEST, TAG_SEQUENCE,
+    };
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const TAG_SET: u8 = 0x31;
+    const SERVICE_DN: &str = "cn=pinas,dc=example,dc=org";
+
+    /// An entry of the mock directory, with its password when it can bind
+    #[derive(Clone)]
+    struct MockEntry {
+        dn: String,
+        password: Option<String>,
+        attrs: Vec<(String, Vec<String>)>,
+    }
+
+    impl MockEntry {
+        fn values(&self, attr: &str) -> Vec<String> {
+            self.attrs
+                .iter()
+                .filter(|(name, _)| name.eq_ignore_ascii_case(attr))
+                .flat_map(|(_, values)| values.clone())
+                .collect()
+        }
+    }
+
+    type Directory = Arc<Mutex<Vec<MockEntry>>>;
+
+    fn person(uid: &str, password: &str) -> MockEntry {
+        MockEntry {
+            dn: format!("uid={},ou=people,dc=example,dc=org", uid),
+            password: Some(password.to_string()),
+            attrs: vec![
+                ("objectClass".into(), vec!["person".into()]),
+                ("uid".into(), vec![uid.into()]),
+                ("mail".into(), vec![format!("{}@example.org", uid)]),
+            ],
+        }
+    }
+
+    fn group(cn: &str, members: &[&str]) -> MockEntry {
+        MockEntry {
+            dn: format!("cn={},ou=groups,dc=example,dc=org", cn),
+            password: None,
+            attrs: vec![
+                ("objectClass".into(), vec!["groupOfNames".into()]),
+                ("cn".into(), vec![cn.into()]),
+                (
+                    "member".into(),
+                    members
+                        .iter()
+                        .map(|uid| format!("uid={}, ou=people,dc=example,dc=org", uid))
+                        .collect(),
+                ),
+            ],
+        }
+    }
+
+    fn directory() -> Directory {
+        let mut service = person("pinas", "service-secret");
+        service.dn = SERVICE_DN.to_string();
+        service.attrs.clear();
+        Arc::new(Mutex::new(vec![
+            service,
+            person("alice", "alice-pw"),
+            person("bob", "bob-pw"),
+            group("staff", &["alice", "bob"]),
+            group("nas-admins", &["alice"]),
+        ]))
+    }
+
+    /// Evaluate an encoded filter: and, or, not, equality, substrings and presence
+    fn matches(filter: &Tlv<'_>, entry: &MockEntry) -> bool {
+        let children = filter.children().unwrap_or_default();
+        match filter.tag {
+            0xa0 => children.iter().all(|f| matches(f, entry)),
+            0xa1 => children.iter().any(|f| matches(f, entry)),
+            0xa2 => !matches(&children[0], entry),
+            0xa3 => {
+                let (attr, value) = (children[0].string(), children[1].string());
+                let normalized = normalize_dn(&value);
+                entry
+                    .values(&attr)
+                    .iter()
+                    .any(|v| v.eq_ignore_ascii_case(&value) || normalize_dn(v) == normalized)
+            }
+            0xa4 => {
+                let attr = children[0].string();
+                let parts = children[1].children().unwrap();
+                entry.values(&attr).iter().any(|v| {
+                    let v = v.to_lowercase();
+                    let mut rest = v.as_str();
+                    for part in &parts {
+                        let needle = part.string().to_lowercase();
+                        match part.tag {
+                            0x80 if !rest.starts_with(&needle) => return false,
+                            0x82 => return rest.ends_with(&needle),
+                            _ => match rest.find(&needle) {
+                                Some(i) => rest = &rest[i + needle.len()..],
+                                None => return false,
+                            },
+                        }
+                    }
+                    true
+                })
+            }
+            0x87 => !entry.values(&filter.string()).is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Answer one client connection: binds against the directory, searches with subtree scope
+    async fn serve(mut socket: tokio::net::TcpStream, directory: Directory) {
+        let mut buffer = Vec::new();
+        loop {
+            let (id, op_tag, op) = loop {
+                if let Some((envelope, used)) = read_tlv(&buffer).unwrap() {
+                    let fields = envelope.children().unwrap();
+                    let parsed = (fields[0].integer().unwrap(), fields[1].tag, fields[1].content.to_vec());
+                    buffer.drain(..used);
+                    break parsed;
+                }
+                let mut chunk = [0u8; 4096];
+                match socket.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                }
+            };
+            let op = Tlv { tag: op_tag, content: &op };
+            let fields = op.children().unwrap_or_default();
+
+            let mut replies = Vec::new();
+            match op_tag {
+                OP_BIND_REQUEST => {
+                    let (dn, password) = (fields[1].string(), fields[2].string());
+                    let accepted = directory.lock().unwrap().iter().any(|e| {
+                        normalize_dn(&e.dn) == normalize_dn(&dn) && e.password.as_deref() == Some(&password)
+                    });
+                    let code = if accepted { RESULT_SUCCESS } else { RESULT_INVALID_CREDENTIALS };
+                    replies.push(result(OP_BIND_RESPONSE, code));
+                }
+                OP_SEARCH_REQUEST => {
+                    let base = normalize_dn(&fields[0].string());
+                    let size_limit = fields[3].integer().unwrap() as usize;
+                    let requested: Vec<String> =
+                        fields[7].children().unwrap().iter().map(Tlv::string).collect();
+                    let found: Vec<MockEntry> = directory
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .filter(|e| normalize_dn(&e.dn).ends_with(&base) && matches(&fields[6], e))
+                        .cloned()
+                        .collect();
+                    let limited = size_limit > 0 && found.len() > size_limit;
+                    for entry in found.iter().take(if limited { size_limit } else { found.len() }) {
+                        let attrs: Vec<Vec<u8>> = entry
+                            .attrs
+                            .iter()
+                            .filter(|(name, _)| requested.iter().any(|r| r.eq_ignore_ascii_case(name)))
+                            .map(|(name, values)| {
+                                let values: Vec<Vec<u8>> = values.iter().map(|v| octets(v.as_bytes())).collect();
+                                constructed(TAG_SEQUENCE, &[octets(name.as_bytes()), constructed(TAG_SET, &values)])
+                            })
+                            .collect();
+                        replies.push(constructed(
+                            OP_SEARCH_ENTRY,
+                            &[octets(entry.dn.as_bytes()), constructed(TAG_SEQUENCE, &attrs)],
+                        ));
+                    }
+                    let code = if limited { 4 } else { RESULT_SUCCESS };
+                    replies.push(result(OP_SEARCH_DONE, code));
+                }
+                OP_UNBIND_REQUEST => return,
+                _ => return,
+            }
+
+            for reply in replies {
+                if socket.write_all(&message(id, reply)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn result(op: u8, code: u32) -> Vec<u8> {
+        constructed(op, &[enumerated(code), octets(b""), octets(b"")])
+    }
+
+    /// Start the mock directory, returns its ldap:// URL
+    async fn mock_server(directory: Directory) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(serve(socket, directory.clone()));
+            }
+        });
+        format!("ldap://{}", addr)
+    }
+
+    fn settings(url: String) -> LdapSettings {
+        LdapSettings {
+            url,
+            ca_file: None,
+            bind_dn: Some(SERVICE_DN.to_string()),
+            bind_password: Some("service-secret".to_string()),
+            user_base: "ou=people,dc=example,dc=org".to_string(),
+            user_filter: "(&(objectClass=person)(uid={username}))".to_string(),
+            username_attr: "uid".to_string(),
+            email_attr: "mail".to_string(),
+            group_base: Some("ou=groups,dc=example,dc=org".to_string()),
+            group_filter: "(objectClass=groupOfNames)".to_string(),
+            group_name_attr: "cn".to_string(),
+            group_member_attr: "member".to_string(),
+            admin_group: Some("nas-admins".to_string()),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    async fn group_names(db: &SqlitePool, user_id: &str) -> Vec<String> {
+        crate::services::group::get_user_groups(db, user_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|g| g.name)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_binds_as_the_user() {
+        let settings = settings(mock_server(directory()).await);
+
+        let user = authenticate(&settings, "alice", "alice-pw").await.unwrap().unwrap();
+        assert_eq!(user.dn, "uid=alice,ou=people,dc=example,dc=org");
+        assert_eq!(user.email.as_deref(), Some("alice@example.org"));
+        let mut groups: Vec<_> = user.groups.iter().map(|g| g.name.as_str()).collect();
+        groups.sort();
+        assert_eq!(groups, vec!["nas-admins", "staff"]);
+
+        assert!(authenticate(&settings, "alice", "wrong").await.unwrap().is_none());
+        assert!(authenticate(&settings, "alice", "").await.unwrap().is_none());
+        assert!(authenticate(&settings, "nobody", "alice-pw").await.unwrap().is_none());
+        // Filter metacharacters in the username cannot widen the search
+        assert!(authenticate(&settings, "*", "alice-pw").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_wrong_service_password_is_an_error() {
+        let mut settings = settings(mock_server(directory()).await);
+        settings.bind_password = Some("nope".to_string());
+
+        let err = authenticate(&settings, "alice", "alice-pw").await.unwrap_err();
+        assert!(matches!(err, LdapError::Operation(LdapResult { code: 49, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_provision_maps_attributes_and_groups() {
+        let db = crate::db::test_pool().await;
+        let settings = settings(mock_server(directory()).await);
+
+        let ldap_user = authenticate(&settings, "alice", "alice-pw").await.unwrap().unwrap();
+        let user = provision_user(&db, &settings, &ldap_user).await.unwrap();
+        assert!(user.is_ldap());
+        assert!(user.is_admin);
+        assert_eq!(user.email.as_deref(), Some("alice@example.org"));
+        assert_eq!(group_names(&db, &user.id).await, vec!["nas-admins", "staff"]);
+
+        // A second login reuses the account
+        let again = provision_user(&db, &settings, &ldap_user).await.unwrap();
+        assert_eq!(again.id, user.id);
+
+        // Synced groups are read-only locally
+        let staff = crate::services::group::get_group_by_name(&db, "staff").await.unwrap().unwrap();
+        assert!(staff.is_external());
+        let err = crate::services::group::remove_member(&db, &staff.id, &user.id).await.unwrap_err();
+        assert!(matches!(err, crate::services::group::GroupError::ExternalGroup));
+    }
+
+    #[tokio::test]
+    async fn test_provision_refuses_local_username() {
+        let db = crate::db::test_pool().await;
+        let settings = settings(mock_server(directory()).await);
+        sqlx::query("INSERT INTO users (id, username, password_hash, created_at, updated_at) VALUES ('l', 'bob', 'x', '', '')")
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let ldap_user = authenticate(&settings, "bob", "bob-pw").await.unwrap().unwrap();
+        let err = provision_user(&db, &settings, &ldap_user).await.unwrap_err();
+        assert!(matches!(err, LdapError::UsernameTaken(_)));
+    }
+
+    #[tokio::test]
+    async fn test_sync_follows_directory_changes() {
+        let db = crate::db::test_pool().await;
+        let directory = directory();
+        let settings = settings(mock_server(directory.clone()).await);
+
+        let alice = authenticate(&settings, "alice", "alice-pw").await.unwrap().unwrap();
+        let alice = provision_user(&db, &settings, &alice).await.unwrap();
+        let bob = authenticate(&settings, "bob", "bob-pw").await.unwrap().unwrap();
+        let bob = provision_user(&db, &settings, &bob).await.unwrap();
+        assert!(!bob.is_admin);
+
+        // Bob becomes an admin, alice leaves staff, and staff is later deleted
+        {
+            let mut entries = directory.lock().unwrap();
+            entries.retain(|e| !e.dn.ends_with("ou=groups,dc=example,dc=org"));
+            entries.push(group("staff", &["bob"]));
+            entries.push(group("nas-admins", &["alice", "bob"]));
+        }
+        let report = sync_groups(&db, &settings).await.unwrap();
+        assert_eq!(report, SyncReport { groups: 2, memberships: 3, removed: 0 });
+        assert_eq!(group_names(&db, &alice.id).await, vec!["nas-admins"]);
+        let bob = crate::services::user::get_user_by_id(&db, &bob.id).await.unwrap().unwrap();
+        assert!(bob.is_admin);
+
+        directory.lock().unwrap().retain(|e| !e.dn.starts_with("cn=staff"));
+        let report = sync_groups(&db, &settings).await.unwrap();
+        assert_eq!(report.removed, 1);
+        assert!(crate::services::group::get_group_by_name(&db, "staff").await.unwrap().is_none());
+    }
+}
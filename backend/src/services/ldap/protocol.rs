@@ -0,0 +1,694 @@
+//! Minimal LDAPv3 client: simple bind and search over plain TCP or TLS (RFC 4511)
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls;
+
+use super::LdapError;
+
+// BER universal tags
+pub(super) const TAG_BOOLEAN: u8 = 0x01;
+pub(super) const TAG_INTEGER: u8 = 0x02;
+pub(super) const TAG_OCTET_STRING: u8 = 0x04;
+pub(super) const TAG_ENUMERATED: u8 = 0x0a;
+pub(super) const TAG_SEQUENCE: u8 = 0x30;
+
+// Protocol operations, [APPLICATION n]
+pub(super) const OP_BIND_REQUEST: u8 = 0x60;
+pub(super) const OP_BIND_RESPONSE: u8 = 0x61;
+pub(super) const OP_UNBIND_REQUEST: u8 = 0x42;
+pub(super) const OP_SEARCH_REQUEST: u8 = 0x63;
+pub(super) const OP_SEARCH_ENTRY: u8 = 0x64;
+pub(super) const OP_SEARCH_DONE: u8 = 0x65;
+pub(super) const OP_SEARCH_REFERENCE: u8 = 0x73;
+
+/// Result code of a successful operation
+pub const RESULT_SUCCESS: u32 = 0;
+/// Result code of a search cut short by a size limit, its entries are still valid
+pub const RESULT_SIZE_LIMIT_EXCEEDED: u32 = 4;
+/// Result code of a bind with a wrong DN or password
+pub const RESULT_INVALID_CREDENTIALS: u32 = 49;
+
+/// Largest message accepted from a server
+const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// Searches cover the whole subtree under their base
+const SCOPE_SUBTREE: u32 = 2;
+
+/// Outcome of an operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LdapResult {
+    pub code: u32,
+    pub message: String,
+}
+
+/// An entry returned by a search, attribute names lowercased
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchEntry {
+    pub dn: String,
+    pub attrs: HashMap<String, Vec<String>>,
+}
+
+impl SearchEntry {
+    /// First value of an attribute
+    pub fn first(&self, attr: &str) -> Option<&str> {
+        self.values(attr).first().map(String::as_str)
+    }
+
+    /// All values of an attribute, empty when missing
+    pub fn values(&self, attr: &str) -> &[String] {
+        self.attrs
+            .get(&attr.to_ascii_lowercase())
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+}
+
+// ============================================================================
+// BER encoding
+// ============================================================================
+
+/// Encode a tag, its definite length and its content
+pub(super) fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(content.len() + 6);
+    out.push(tag);
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = (len as u32).to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (4 - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Encode a constructed value from already encoded parts
+pub(super) fn constructed(tag: u8, parts: &[Vec<u8>]) -> Vec<u8> {
+    tlv(tag, &parts.concat())
+}
+
+pub(super) fn integer(tag: u8, value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    // Drop leading bytes that only repeat the sign
+    let mut start = 0;
+    while start < 7 {
+        let (b, next) = (bytes[start], bytes[start + 1]);
+        if (b == 0x00 && next & 0x80 == 0) || (b == 0xff && next & 0x80 != 0) {
+            start += 1;
+        } else {
+            break;
+        }
+    }
+    tlv(tag, &bytes[start..])
+}
+
+pub(super) fn octets(value: &[u8]) -> Vec<u8> {
+    tlv(TAG_OCTET_STRING, value)
+}
+
+pub(super) fn boolean(value: bool) -> Vec<u8> {
+    tlv(TAG_BOOLEAN, &[if value { 0xff } else { 0x00 }])
+}
+
+pub(super) fn enumerated(value: u32) -> Vec<u8> {
+    integer(TAG_ENUMERATED, value as i64)
+}
+
+/// Wrap a protocol operation in an LDAPMessage
+pub(super) fn message(id: i64, op: Vec<u8>) -> Vec<u8> {
+    constructed(TAG_SEQUENCE, &[integer(TAG_INTEGER, id), op])
+}
+
+// ============================================================================
+// BER decoding
+// ============================================================================
+
+/// A decoded tag and its content
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Tlv<'a> {
+    pub tag: u8,
+    pub content: &'a [u8],
+}
+
+impl<'a> Tlv<'a> {
+    pub fn children(&self) -> Result<Vec<Tlv<'a>>, LdapError> {
+        let mut rest = self.content;
+        let mut children = Vec::new();
+        while !rest.is_empty() {
+            let (child, used) = read_tlv(rest)?.ok_or_else(|| protocol_error("truncated value"))?;
+            children.push(child);
+            rest = &rest[used..];
+        }
+        Ok(children)
+    }
+
+    pub fn integer(&self) -> Result<i64, LdapError> {
+        if self.content.is_empty() || self.content.len() > 8 {
+            return Err(protocol_error("invalid integer"));
+        }
+        let negative = self.content[0] & 0x80 != 0;
+        let mut value: i64 = if negative { -1 } else { 0 };
+        for b in self.content {
+            value = (value << 8) | *b as i64;
+        }
+        Ok(value)
+    }
+
+    pub fn string(&self) -> String {
+        String::from_utf8_lossy(self.content).into_owned()
+    }
+}
+
+/// Read one value, None when more bytes are needed
+/// Returns the value and the number of bytes it spans
+pub(super) fn read_tlv(buf: &[u8]) -> Result<Option<(Tlv<'_>, usize)>, LdapError> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let tag = buf[0];
+    if tag & 0x1f == 0x1f {
+        return Err(protocol_error("multi-byte tags are not supported"));
+    }
+
+    let (len, header) = if buf[1] & 0x80 == 0 {
+        (buf[1] as usize, 2)
+    } else {
+        let count = (buf[1] & 0x7f) as usize;
+        if count == 0 || count > 4 {
+            return Err(protocol_error("unsupported length encoding"));
+        }
+        if buf.len() < 2 + count {
+            return Ok(None);
+        }
+        let len = buf[2..2 + count]
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (len, 2 + count)
+    };
+
+    if len > MAX_MESSAGE_LEN {
+        return Err(protocol_error("message too large"));
+    }
+    if buf.len() < header + len {
+        return Ok(None);
+    }
+    Ok(Some((
+        Tlv {
+            tag,
+            content: &buf[header..header + len],
+        },
+        header + len,
+    )))
+}
+
+fn protocol_error(reason: &str) -> LdapError {
+    LdapError::Protocol(reason.to_string())
+}
+
+/// Decode the (code, matched DN, message) prefix of an LDAPResult
+fn parse_result(op: &Tlv<'_>) -> Result<LdapResult, LdapError> {
+    let fields = op.children()?;
+    if fields.len() < 3 {
+        return Err(protocol_error("short result"));
+    }
+    Ok(LdapResult {
+        code: fields[0].integer()? as u32,
+        message: fields[2].string(),
+    })
+}
+
+/// Decode a SearchResultEntry
+fn parse_entry(op: &Tlv<'_>) -> Result<SearchEntry, LdapError> {
+    let fields = op.children()?;
+    let dn = fields.first().ok_or_else(|| protocol_error("entry without DN"))?.string();
+    let mut attrs: HashMap<String, Vec<String>> = HashMap::new();
+    if let Some(list) = fields.get(1) {
+        for attr in list.children()? {
+            let parts = attr.children()?;
+            let (Some(name), Some(values)) = (parts.first(), parts.get(1)) else {
+                return Err(protocol_error("invalid attribute"));
+            };
+            let values = values.children()?.iter().map(Tlv::string).collect::<Vec<_>>();
+            attrs
+                .entry(name.string().to_ascii_lowercase())
+                .or_default()
+                .extend(values);
+        }
+    }
+    Ok(SearchEntry { dn, attrs })
+}
+
+// ============================================================================
+// Search filters (RFC 4515)
+// ============================================================================
+
+/// Escape a value substituted into a filter, so it always matches literally
+pub fn escape_filter_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '*' => out.push_str("\\2a"),
+            '(' => out.push_str("\\28"),
+            ')' => out.push_str("\\29"),
+            '\\' => out.push_str("\\5c"),
+            '\0' => out.push_str("\\00"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Encode a string filter such as `(&(objectClass=person)(uid=jdoe))`
+pub fn encode_filter(filter: &str) -> Result<Vec<u8>, LdapError> {
+    let filter = filter.trim();
+    // A bare item without parentheses is accepted as well
+    let filter = if filter.starts_with('(') {
+        filter.to_string()
+    } else {
+        format!("({})", filter)
+    };
+    let mut parser = FilterParser {
+        input: filter.as_bytes(),
+        pos: 0,
+    };
+    let encoded = parser.filter()?;
+    if parser.pos != parser.input.len() {
+        return Err(LdapError::InvalidFilter(filter.clone()));
+    }
+    Ok(encoded)
+}
+
+struct FilterParser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl FilterParser<'_> {
+    fn error(&self) -> LdapError {
+        LdapError::InvalidFilter(String::from_utf8_lossy(self.input).into_owned())
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), LdapError> {
+        if self.input.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn filter(&mut self) -> Result<Vec<u8>, LdapError> {
+        self.expect(b'(')?;
+        let encoded = match self.input.get(self.pos) {
+            Some(b'&') => {
+                self.pos += 1;
+                constructed(0xa0, &self.filter_list()?)
+            }
+            Some(b'|') => {
+                self.pos += 1;
+                constructed(0xa1, &self.filter_list()?)
+            }
+            Some(b'!') => {
+                self.pos += 1;
+                constructed(0xa2, &[self.filter()?])
+            }
+            Some(_) => self.item()?,
+            None => return Err(self.error()),
+        };
+        self.expect(b')')?;
+        Ok(encoded)
+    }
+
+    fn filter_list(&mut self) -> Result<Vec<Vec<u8>>, LdapError> {
+        let mut list = Vec::new();
+        while self.input.get(self.pos) == Some(&b'(') {
+            list.push(self.filter()?);
+        }
+        if list.is_empty() {
+            return Err(self.error());
+        }
+        Ok(list)
+    }
+
+    fn item(&mut self) -> Result<Vec<u8>, LdapError> {
+        let start = self.pos;
+        while let Some(b) = self.input.get(self.pos) {
+            if matches!(b, b'=' | b'~' | b'>' | b'<' | b'(' | b')') {
+                break;
+            }
+            self.pos += 1;
+        }
+        let attr = std::str::from_utf8(&self.input[start..self.pos])
+            .map_err(|_| self.error())?
+            .trim()
+            .to_string();
+        if attr.is_empty() {
+            return Err(self.error());
+        }
+
+        let op = match self.input.get(self.pos) {
+            Some(b'=') => {
+                self.pos += 1;
+                b'='
+            }
+            Some(b @ (b'~' | b'>' | b'<')) => {
+                let b = *b;
+                self.pos += 1;
+                self.expect(b'=')?;
+                b
+            }
+            _ => return Err(self.error()),
+        };
+
+        let start = self.pos;
+        while let Some(b) = self.input.get(self.pos) {
+            if matches!(b, b'(' | b')') {
+                break;
+            }
+            self.pos += 1;
+        }
+        let raw = &self.input[start..self.pos];
+        let name = attr.as_bytes();
+        let attr = octets(name);
+
+        Ok(match op {
+            b'~' => constructed(0xa8, &[attr, octets(&self.unescape(raw)?)]),
+            b'>' => constructed(0xa5, &[attr, octets(&self.unescape(raw)?)]),
+            b'<' => constructed(0xa6, &[attr, octets(&self.unescape(raw)?)]),
+            _ if raw == b"*" => tlv(0x87, name),
+            _ if raw.contains(&b'*') => {
+                // Unescaped stars split the value into initial, any and final parts
+                let parts: Vec<&[u8]> = raw.split(|b| *b == b'*').collect();
+                let last = parts.len() - 1;
+                let mut substrings = Vec::new();
+                for (i, part) in parts.iter().enumerate() {
+                    if part.is_empty() {
+                        continue;
+                    }
+                    let tag = match i {
+                        0 => 0x80,
+                        i if i == last => 0x82,
+                        _ => 0x81,
+                    };
+                    substrings.push(tlv(tag, &self.unescape(part)?));
+                }
+                constructed(0xa4, &[attr, constructed(TAG_SEQUENCE, &substrings)])
+            }
+            _ => constructed(0xa3, &[attr, octets(&self.unescape(raw)?)]),
+        })
+    }
+
+    /// Resolve `\XX` hex escapes
+    fn unescape(&self, raw: &[u8]) -> Result<Vec<u8>, LdapError> {
+        let mut out = Vec::with_capacity(raw.len());
+        let mut i = 0;
+        while i < raw.len() {
+            if raw[i] == b'\\' {
+                let hex = raw.get(i + 1..i + 3).ok_or_else(|| self.error())?;
+                let hex = std::str::from_utf8(hex).map_err(|_| self.error())?;
+                out.push(u8::from_str_radix(hex, 16).map_err(|_| self.error())?);
+                i += 3;
+            } else {
+                out.push(raw[i]);
+                i += 1;
+            }
+        }
+        Ok(out)
+    }
+}
+
+// ============================================================================
+// Connection
+// ============================================================================
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// An open connection to a directory server
+pub struct LdapConnection {
+    stream: Box<dyn Stream>,
+    buffer: Vec<u8>,
+    next_id: i64,
+    timeout: Duration,
+}
+
+impl LdapConnection {
+    /// Connect to an `ldap://host[:port]` or `ldaps://host[:port]` URL
+    /// `ca_file` adds a PEM certificate authority trusted for ldaps next to the system roots
+    pub async fn connect(
+        url: &str,
+        ca_file: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Self, LdapError> {
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("ldaps://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("ldap://") {
+            (false, rest)
+        } else {
+            return Err(LdapError::InvalidUrl(url.to_string()));
+        };
+        let authority = rest.split('/').next().unwrap_or_default();
+        if authority.is_empty() {
+            return Err(LdapError::InvalidUrl(url.to_string()));
+        }
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(bracketed) => {
+                let (host, rest) = bracketed
+                    .split_once(']')
+                    .ok_or_else(|| LdapError::InvalidUrl(url.to_string()))?;
+                (host, rest.strip_prefix(':'))
+            }
+            None => match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let port = match port {
+            Some(port) => port
+                .parse::<u16>()
+                .map_err(|_| LdapError::InvalidUrl(url.to_string()))?,
+            None if tls => 636,
+            None => 389,
+        };
+
+        let tcp = tokio::time::timeout(timeout, TcpStream::connect((host, port)))
+            .await
+            .map_err(|_| LdapError::Timeout)??;
+        let _ = tcp.set_nodelay(true);
+
+        let stream: Box<dyn Stream> = if tls {
+            let connector = tls_connector(ca_file)?;
+            let name = rustls::ServerName::try_from(host)
+                .map_err(|_| LdapError::InvalidUrl(url.to_string()))?;
+            let stream = tokio::time::timeout(timeout, connector.connect(name, tcp))
+                .await
+                .map_err(|_| LdapError::Timeout)??;
+            Box::new(stream)
+        } else {
+            Box::new(tcp)
+        };
+
+        Ok(Self {
+            stream,
+            buffer: Vec::new(),
+            next_id: 1,
+            timeout,
+        })
+    }
+
+    /// Simple bind, the result code tells whether the credentials were accepted
+    pub async fn simple_bind(&mut self, dn: &str, password: &str) -> Result<LdapResult, LdapError> {
+        let op = constructed(
+            OP_BIND_REQUEST,
+            &[
+                integer(TAG_INTEGER, 3),
+                octets(dn.as_bytes()),
+                tlv(0x80, password.as_bytes()),
+            ],
+        );
+        let id = self.send(op).await?;
+        loop {
+            let (msg_id, tag, content) = self.receive().await?;
+            if msg_id != id {
+                continue;
+            }
+            if tag != OP_BIND_RESPONSE {
+                return Err(protocol_error("unexpected response to bind"));
+            }
+            return parse_result(&Tlv { tag, content: &content });
+        }
+    }
+
+    /// Search the subtree under `base`, failing unless the search completes or only hits a size limit
+    pub async fn search(
+        &mut self,
+        base: &str,
+        filter: &str,
+        attrs: &[&str],
+        size_limit: i64,
+    ) -> Result<Vec<SearchEntry>, LdapError> {
+        let attrs = attrs.iter().map(|a| octets(a.as_bytes())).collect::<Vec<_>>();
+        let op = constructed(
+            OP_SEARCH_REQUEST,
+            &[
+                octets(base.as_bytes()),
+                enumerated(SCOPE_SUBTREE),
+                enumerated(0), // never dereference aliases
+                integer(TAG_INTEGER, size_limit),
+                integer(TAG_INTEGER, self.timeout.as_secs() as i64),
+                boolean(false),
+                encode_filter(filter)?,
+                constructed(TAG_SEQUENCE, &attrs),
+            ],
+        );
+        let id = self.send(op).await?;
+
+        let mut entries = Vec::new();
+        loop {
+            let (msg_id, tag, content) = self.receive().await?;
+            if msg_id != id {
+                continue;
+            }
+            let op = Tlv { tag, content: &content };
+            match tag {
+                OP_SEARCH_ENTRY => entries.push(parse_entry(&op)?),
+                OP_SEARCH_REFERENCE => {}
+                OP_SEARCH_DONE => {
+                    let result = parse_result(&op)?;
+                    return match result.code {
+                        RESULT_SUCCESS | RESULT_SIZE_LIMIT_EXCEEDED => Ok(entries),
+                        _ => Err(LdapError::Operation(result)),
+                    };
+                }
+                _ => return Err(protocol_error("unexpected response to search")),
+            }
+        }
+    }
+
+    /// Tell the server the connection is done, errors are irrelevant at that point
+    pub async fn unbind(mut self) {
+        let _ = self.send(tlv(OP_UNBIND_REQUEST, &[])).await;
+        let _ = self.stream.shutdown().await;
+    }
+
+    async fn send(&mut self, op: Vec<u8>) -> Result<i64, LdapError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let bytes = message(id, op);
+        tokio::time::timeout(self.timeout, self.stream.write_all(&bytes))
+            .await
+            .map_err(|_| LdapError::Timeout)??;
+        Ok(id)
+    }
+
+    /// Next message as (message id, operation tag, operation content)
+    async fn receive(&mut self) -> Result<(i64, u8, Vec<u8>), LdapError> {
+        loop {
+            if let Some((envelope, used)) = read_tlv(&self.buffer)? {
+                let fields = envelope.children()?;
+                let (Some(id), Some(op)) = (fields.first(), fields.get(1)) else {
+                    return Err(protocol_error("invalid message"));
+                };
+                let parsed = (id.integer()?, op.tag, op.content.to_vec());
+                self.buffer.drain(..used);
+                return Ok(parsed);
+            }
+
+            let mut chunk = [0u8; 8192];
+            let n = tokio::time::timeout(self.timeout, self.stream.read(&mut chunk))
+                .await
+                .map_err(|_| LdapError::Timeout)??;
+            if n == 0 {
+                return Err(protocol_error("connection closed by server"));
+            }
+            self.buffer.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+/// TLS connector trusting the bundled web roots and an optional local CA
+fn tls_connector(ca_file: Option<&str>) -> Result<tokio_rustls::TlsConnector, LdapError> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+
+    if let Some(path) = ca_file {
+        let pem = std::fs::read(path)?;
+        let certs = rustls_pemfile::certs(&mut pem.as_slice())?;
+        if certs.is_empty() {
+            return Err(LdapError::Tls(format!("no certificate in {}", path)));
+        }
+        for cert in certs {
+            roots
+                .add(&rustls::Certificate(cert))
+                .map_err(|e| LdapError::Tls(format!("{}: {}", path, e)))?;
+        }
+    }
+
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(tokio_rustls::TlsConnector::from(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lengths_and_integers_round_trip() {
+        let long = vec![7u8; 300];
+        let encoded = octets(&long);
+        assert_eq!(&encoded[..4], &[0x04, 0x82, 0x01, 0x2c]);
+        let (value, used) = read_tlv(&encoded).unwrap().unwrap();
+        assert_eq!((value.content, used), (long.as_slice(), encoded.len()));
+        assert!(read_tlv(&encoded[..100]).unwrap().is_none());
+
+        for n in [0i64, 1, 127, 128, 255, 256, -1, -129, 2_147_483_647] {
+            let encoded = integer(TAG_INTEGER, n);
+            assert_eq!(read_tlv(&encoded).unwrap().unwrap().0.integer().unwrap(), n);
+        }
+        assert_eq!(integer(TAG_INTEGER, 128), vec![0x02, 0x02, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn test_filter_encoding() {
+        assert_eq!(
+            encode_filter("(uid=jdoe)").unwrap(),
+            vec![0xa3, 0x0b, 0x04, 0x03, b'u', b'i', b'd', 0x04, 0x04, b'j', b'd', b'o', b'e']
+        );
+        assert_eq!(
+            encode_filter("objectClass=*").unwrap(),
+            [&[0x87, 0x0b][..], b"objectClass"].concat()
+        );
+        let substrings = encode_filter("(cn=ad*mi*n)").unwrap();
+        assert_eq!(substrings[0], 0xa4);
+        assert!(encode_filter("(&(objectClass=person)(|(uid=a)(!(mail=b*))))").is_ok());
+        assert!(encode_filter("(&(uid=a)").is_err());
+        assert!(encode_filter("(uid=a\\zz)").is_err());
+    }
+
+    #[test]
+    fn test_escaped_values_stay_literal() {
+        let escaped = escape_filter_value("*)(uid=*");
+        assert_eq!(escaped, "\\2a\\29\\28uid=\\2a");
+        let encoded = encode_filter(&format!("(uid={})", escaped)).unwrap();
+        // Equality match on the literal value, not a substring or injected filter
+        assert_eq!(encoded[0], 0xa3);
+        assert!(encoded.ends_with(b"*)(uid=*"));
+    }
+}
@@ -0,0 +1,375 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::SqlitePool;
+use thiserror::Error;
+
+use crate::config::AppConfig;
+use crate::models::lockout::{LockoutKind, LoginFailure, LoginLockout};
+
+/// Upper bound of the delay imposed between two failed attempts
+const BACKOFF_MAX_SECS: i64 = 60;
+
+/// Failed attempts older than this are pruned
+const FAILURE_RETENTION_DAYS: i64 = 7;
+
+#[derive(Debug, Error)]
+pub enum LockoutError {
+    #[error("Too many failed attempts, retry in {0} seconds")]
+    Throttled(i64),
+
+    #[error("Login temporarily locked after too many failed attempts, retry in {0} seconds")]
+    Locked(i64),
+
+    #[error("Lockout not found")]
+    NotFound,
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+impl LockoutError {
+    /// Seconds the client should wait, for the Retry-After header
+    pub fn retry_after(&self) -> Option<i64> {
+        match self {
+            LockoutError::Throttled(secs) | LockoutError::Locked(secs) => Some(*secs),
+            _ => None,
+        }
+    }
+}
+
+/// Thresholds applied to failed logins
+#[derive(Debug, Clone, Copy)]
+pub struct LockoutPolicy {
+    /// Consecutive failures before a lockout
+    pub max_failures: i64,
+    /// Lockout duration, also the window after which failures are forgotten
+    pub lockout: Duration,
+}
+
+impl LockoutPolicy {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            max_failures: config.login_max_failures.max(1) as i64,
+            lockout: Duration::minutes(config.login_lockout_minutes as i64),
+        }
+    }
+
+    /// Delay before the next attempt after `failures` consecutive failures (1s, 2s, 4s, ...)
+    fn backoff(&self, failures: i64) -> Duration {
+        if failures <= 0 {
+            return Duration::zero();
+        }
+        let secs = 1i64 << (failures - 1).min(6);
+        Duration::seconds(secs.min(BACKOFF_MAX_SECS))
+    }
+}
+
+/// Refuse the attempt if the username or the client IP is locked or backing off
+pub async fn check(
+    db: &SqlitePool,
+    policy: &LockoutPolicy,
+    username: &str,
+    ip: &str,
+) -> Result<(), LockoutError> {
+    check_at(db, policy, username, ip, Utc::now()).await
+}
+
+/// Count a failed attempt, returns the counters that just got locked
+pub async fn record_failure(
+    db: &SqlitePool,
+    policy: &LockoutPolicy,
+    username: &str,
+    ip: &str,
+    reason: &str,
+) -> Result<Vec<LoginLockout>, LockoutError> {
+    record_failure_at(db, policy, username, ip, reason, Utc::now()).await
+}
+
+/// Forget the failures of a username after a successful login
+pub async fn record_success(db: &SqlitePool, username: &str) -> Result<(), LockoutError> {
+    sqlx::query("DELETE FROM login_lockouts WHERE kind = ? AND value = ?")
+        .bind(LockoutKind::Username.as_str())
+        .bind(username.to_lowercase())
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Usernames and IPs currently locked
+pub async fn list_locked(db: &SqlitePool) -> Result<Vec<LoginLockout>, LockoutError> {
+    let now = Utc::now();
+    let lockouts: Vec<LoginLockout> = sqlx::query_as(
+        "SELECT * FROM login_lockouts WHERE locked_until IS NOT NULL ORDER BY last_failure_at DESC",
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(lockouts
+        .into_iter()
+        .filter(|l| locked_for(l, now).is_some())
+        .collect())
+}
+
+/// Most recent failed attempts
+pub async fn list_failures(db: &SqlitePool, limit: i64) -> Result<Vec<LoginFailure>, LockoutError> {
+    let failures = sqlx::query_as("SELECT * FROM login_failures ORDER BY id DESC LIMIT ?")
+        .bind(limit)
+        .fetch_all(db)
+        .await?;
+    Ok(failures)
+}
+
+/// Lift a lockout and reset its failure counter
+pub async fn unlock(db: &SqlitePool, id: &str) -> Result<LoginLockout, LockoutError> {
+    let lockout: LoginLockout = sqlx::query_as("SELECT * FROM login_lockouts WHERE id = ?")
+        .bind(id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(LockoutError::NotFound)?;
+
+    sqlx::query("DELETE FROM login_lockouts WHERE id = ?")
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    Ok(lockout)
+}
+
+async fn check_at(
+    db: &SqlitePool,
+    policy: &LockoutPolicy,
+    username: &str,
+    ip: &str,
+    now: DateTime<Utc>,
+) -> Result<(), LockoutError> {
+    for (kind, value) in keys(username, ip) {
+        let Some(counter) = get_counter(db, kind, &value).await? else {
+            continue;
+        };
+
+        if let Some(remaining) = locked_for(&counter, now) {
+            return Err(LockoutError::Locked(remaining));
+        }
+
+        let failures = active_failures(&counter, policy, now);
+        let allowed_at = parse_time(&counter.last_failure_at) + policy.backoff(failures);
+        if failures > 0 && now < allowed_at {
+            return Err(LockoutError::Throttled(seconds_until(allowed_at, now)));
+        }
+    }
+
+    Ok(())
+}
+
+async fn record_failure_at(
+    db: &SqlitePool,
+    policy: &LockoutPolicy,
+    username: &str,
+    ip: &str,
+    reason: &str,
+    now: DateTime<Utc>,
+) -> Result<Vec<LoginLockout>, LockoutError> {
+    let timestamp = now.to_rfc3339();
+
+    sqlx::query("INSERT INTO login_failures (username, ip, reason, created_at) VALUES (?, ?, ?, ?)")
+        .bind(username)
+        .bind(ip)
+        .bind(reason)
+        .bind(&timestamp)
+        .execute(db)
+        .await?;
+
+    let mut newly_locked = Vec::new();
+    for (kind, value) in keys(username, ip) {
+        let existing = get_counter(db, kind, &value).await?;
+        let failures = existing
+            .as_ref()
+            .map(|c| active_failures(c, policy, now))
+            .unwrap_or(0)
+            + 1;
+        let already_locked = existing.as_ref().and_then(|c| locked_for(c, now)).is_some();
+
+        let locked_until = if failures >= policy.max_failures && !already_locked {
+            Some((now + policy.lockout).to_rfc3339())
+        } else {
+            existing.as_ref().and_then(|c| c.locked_until.clone())
+        };
+
+        let counter = LoginLockout {
+            id: existing
+                .map(|c| c.id)
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            kind: kind.as_str().to_string(),
+            value,
+            failures,
+            last_failure_at: timestamp.clone(),
+            locked_until,
+        };
+
+        sqlx::query(
+            r#"INSERT INTO login_lockouts (id, kind, value, failures, last_failure_at, locked_until)
+               VALUES (?, ?, ?, ?, ?, ?)
+               ON CONFLICT(kind, value) DO UPDATE SET
+                   failures = excluded.failures,
+                   last_failure_at = excluded.last_failure_at,
+                   locked_until = excluded.locked_until"#,
+        )
+        .bind(&counter.id)
+        .bind(&counter.kind)
+        .bind(&counter.value)
+        .bind(counter.failures)
+        .bind(&counter.last_failure_at)
+        .bind(&counter.locked_until)
+        .execute(db)
+        .await?;
+
+        if failures >= policy.max_failures && !already_locked {
+            newly_locked.push(counter);
+        }
+    }
+
+    prune(db, now).await?;
+
+    Ok(newly_locked)
+}
+
+/// Drop old failed attempts and counters that are neither recent nor locked
+async fn prune(db: &SqlitePool, now: DateTime<Utc>) -> Result<(), LockoutError> {
+    let cutoff = (now - Duration::days(FAILURE_RETENTION_DAYS)).to_rfc3339();
+    sqlx::query("DELETE FROM login_failures WHERE created_at < ?")
+        .bind(&cutoff)
+        .execute(db)
+        .await?;
+    sqlx::query("DELETE FROM login_lockouts WHERE last_failure_at < ?")
+        .bind(&cutoff)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+async fn get_counter(
+    db: &SqlitePool,
+    kind: LockoutKind,
+    value: &str,
+) -> Result<Option<LoginLockout>, LockoutError> {
+    let counter = sqlx::query_as("SELECT * FROM login_lockouts WHERE kind = ? AND value = ?")
+        .bind(kind.as_str())
+        .bind(value)
+        .fetch_optional(db)
+        .await?;
+    Ok(counter)
+}
+
+/// Usernames are matched case-insensitively so variants share one counter
+fn keys(username: &str, ip: &str) -> [(LockoutKind, String); 2] {
+    [
+        (LockoutKind::Username, username.to_lowercase()),
+        (LockoutKind::Ip, ip.to_string()),
+    ]
+}
+
+/// Seconds left on an active lock
+fn locked_for(counter: &LoginLockout, now: DateTime<Utc>) -> Option<i64> {
+    let until = parse_time(counter.locked_until.as_deref()?);
+    (until > now).then(|| seconds_until(until, now))
+}
+
+/// Failures still counting: forgotten once the window passed since the last one
+fn active_failures(counter: &LoginLockout, policy: &LockoutPolicy, now: DateTime<Utc>) -> i64 {
+    if parse_time(&counter.last_failure_at) + policy.lockout < now {
+        0
+    } else {
+        counter.failures
+    }
+}
+
+fn parse_time(value: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_default()
+}
+
+fn seconds_until(at: DateTime<Utc>, now: DateTime<Utc>) -> i64 {
+    // Round up so clients never retry a moment too early
+    ((at - now).num_milliseconds() + 999) / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> LockoutPolicy {
+        LockoutPolicy {
+            max_failures: 3,
+            lockout: Duration::minutes(15),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backoff_then_lockout() {
+        let pool = crate::db::test_pool().await;
+        let policy = policy();
+        let start = Utc::now();
+
+        check_at(&pool, &policy, "alice", "10.0.0.1", start).await.unwrap();
+        let locked = record_failure_at(&pool, &policy, "alice", "10.0.0.1", "password", start)
+            .await
+            .unwrap();
+        assert!(locked.is_empty());
+
+        // One failure imposes a one second delay
+        assert!(matches!(
+            check_at(&pool, &policy, "Alice", "10.0.0.2", start).await,
+            Err(LockoutError::Throttled(1))
+        ));
+        let later = start + Duration::seconds(1);
+        check_at(&pool, &policy, "bob", "10.0.0.2", later).await.unwrap();
+
+        record_failure_at(&pool, &policy, "alice", "10.0.0.2", "password", later)
+            .await
+            .unwrap();
+        let later = later + Duration::seconds(2);
+        let locked = record_failure_at(&pool, &policy, "alice", "10.0.0.3", "mfa", later)
+            .await
+            .unwrap();
+        assert_eq!(locked.len(), 1);
+        assert_eq!(locked[0].kind, "username");
+
+        assert!(matches!(
+            check_at(&pool, &policy, "alice", "10.0.0.9", later).await,
+            Err(LockoutError::Locked(900))
+        ));
+        assert_eq!(list_failures(&pool, 10).await.unwrap().len(), 3);
+
+        // Admin unlock resets the counter
+        unlock(&pool, &locked[0].id).await.unwrap();
+        check_at(&pool, &policy, "alice", "10.0.0.9", later).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ip_lockout_and_expiry() {
+        let pool = crate::db::test_pool().await;
+        let policy = policy();
+        let start = Utc::now() - Duration::minutes(30);
+
+        for (i, user) in ["a", "b", "c"].iter().enumerate() {
+            let at = start + Duration::seconds(i as i64 * 10);
+            record_failure_at(&pool, &policy, user, "10.0.0.1", "password", at)
+                .await
+                .unwrap();
+        }
+
+        let during = start + Duration::minutes(5);
+        assert!(matches!(
+            check_at(&pool, &policy, "d", "10.0.0.1", during).await,
+            Err(LockoutError::Locked(_))
+        ));
+
+        // Lockout and failures expire after the window
+        let after = start + Duration::minutes(20);
+        check_at(&pool, &policy, "d", "10.0.0.1", after).await.unwrap();
+        let locked = record_failure_at(&pool, &policy, "d", "10.0.0.1", "password", after)
+            .await
+            .unwrap();
+        assert!(locked.is_empty());
+    }
+}
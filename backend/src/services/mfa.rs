@@ -0,0 +1,429 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use thiserror::Error;
+
+/// Issuer shown in authenticator apps
+const ISSUER: &str = "PiNAS";
+
+/// RFC 6238 parameters (the defaults every authenticator app supports)
+const TOTP_STEP_SECS: u64 = 30;
+const TOTP_DIGITS: u32 = 6;
+const SECRET_BYTES: usize = 20;
+
+/// Accepted clock drift, in steps on each side
+const TOTP_WINDOW: i64 = 1;
+
+/// Number of recovery codes generated at once
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+#[derive(Debug, Error)]
+pub enum MfaError {
+    #[error("Two-factor authentication is not enabled")]
+    NotEnabled,
+
+    #[error("Two-factor authentication is already enabled")]
+    AlreadyEnabled,
+
+    #[error("No enrolment in progress")]
+    NoEnrolment,
+
+    #[error("Invalid verification code")]
+    InvalidCode,
+
+    #[error("Two-factor authentication is required by one of your groups")]
+    Required,
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// Secret returned when starting an enrolment
+#[derive(Debug, Serialize)]
+pub struct MfaEnrolment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// Two-factor state of a user
+#[derive(Debug, Serialize)]
+pub struct MfaStatus {
+    pub enabled: bool,
+    pub required: bool,
+    pub recovery_codes_remaining: i64,
+}
+
+/// Generate a random base32 TOTP secret
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+/// Build the otpauth:// URI rendered as a QR code by the client
+pub fn otpauth_uri(account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = ISSUER,
+        account = urlencode(account),
+        secret = secret,
+        digits = TOTP_DIGITS,
+        period = TOTP_STEP_SECS,
+    )
+}
+
+/// Compute the TOTP code of a time step (RFC 4226 dynamic truncation)
+pub fn totp_code(secret: &[u8], step: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]])
+        & 0x7fff_ffff;
+    binary % 10u32.pow(TOTP_DIGITS)
+}
+
+/// Check a code against the steps around `unix_time`, returns the matching step
+pub fn verify_totp(secret: &str, code: &str, unix_time: u64) -> Option<u64> {
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+
+    let current = (unix_time / TOTP_STEP_SECS) as i64;
+    (-TOTP_WINDOW..=TOTP_WINDOW)
+        .map(|delta| current + delta)
+        .filter(|step| *step >= 0)
+        .map(|step| step as u64)
+        .find(|step| totp_code(&secret, *step) == code)
+}
+
+/// Start (or restart) an enrolment, the secret is only active once confirmed
+pub async fn begin_enrolment(
+    db: &SqlitePool,
+    user_id: &str,
+    username: &str,
+) -> Result<MfaEnrolment, MfaError> {
+    if is_enabled(db, user_id).await? {
+        return Err(MfaError::AlreadyEnabled);
+    }
+
+    let secret = generate_secret();
+    sqlx::query(
+        r#"INSERT INTO user_totp (user_id, secret, enabled, last_used_step, created_at)
+           VALUES (?, ?, FALSE, 0, ?)
+           ON CONFLICT(user_id) DO UPDATE SET secret = excluded.secret, created_at = excluded.created_at"#,
+    )
+    .bind(user_id)
+    .bind(&secret)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(db)
+    .await?;
+
+    Ok(MfaEnrolment {
+        otpauth_uri: otpauth_uri(username, &secret),
+        secret,
+    })
+}
+
+/// Confirm an enrolment with a first code, returns the recovery codes
+pub async fn confirm_enrolment(
+    db: &SqlitePool,
+    user_id: &str,
+    code: &str,
+) -> Result<Vec<String>, MfaError> {
+    let (secret, enabled) = get_totp(db, user_id).await?.ok_or(MfaError::NoEnrolment)?;
+    if enabled {
+        return Err(MfaError::AlreadyEnabled);
+    }
+
+    let step = verify_totp(&secret, code, unix_now()).ok_or(MfaError::InvalidCode)?;
+
+    sqlx::query(
+        "UPDATE user_totp SET enabled = TRUE, last_used_step = ?, confirmed_at = ? WHERE user_id = ?",
+    )
+    .bind(step as i64)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
+    replace_recovery_codes(db, user_id).await
+}
+
+/// Check a second factor: a TOTP code, or an unused recovery code
+pub async fn verify_second_factor(db: &SqlitePool, user_id: &str, code: &str) -> Result<(), MfaError> {
+    let (secret, enabled) = get_totp(db, user_id).await?.ok_or(MfaError::NotEnabled)?;
+    if !enabled {
+        return Err(MfaError::NotEnabled);
+    }
+
+    if let Some(step) = verify_totp(&secret, code, unix_now()) {
+        // Only accept steps after the last used one so a code cannot be replayed
+        let result = sqlx::query(
+            "UPDATE user_totp SET last_used_step = ? WHERE user_id = ? AND last_used_step < ?",
+        )
+        .bind(step as i64)
+        .bind(user_id)
+        .bind(step as i64)
+        .execute(db)
+        .await?;
+
+        return if result.rows_affected() == 1 {
+            Ok(())
+        } else {
+            Err(MfaError::InvalidCode)
+        };
+    }
+
+    let result = sqlx::query(
+        "UPDATE user_recovery_codes SET used_at = ? WHERE user_id = ? AND code_hash = ? AND used_at IS NULL",
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(user_id)
+    .bind(hash_recovery_code(code))
+    .execute(db)
+    .await?;
+
+    if result.rows_affected() == 1 {
+        tracing::info!("Recovery code used by user {}", user_id);
+        Ok(())
+    } else {
+        Err(MfaError::InvalidCode)
+    }
+}
+
+/// Generate new recovery codes, invalidating the previous ones
+pub async fn regenerate_recovery_codes(db: &SqlitePool, user_id: &str) -> Result<Vec<String>, MfaError> {
+    if !is_enabled(db, user_id).await? {
+        return Err(MfaError::NotEnabled);
+    }
+    replace_recovery_codes(db, user_id).await
+}
+
+/// Turn two-factor authentication off for a user
+/// `force` lets administrators reset users who lost their device
+pub async fn disable(db: &SqlitePool, user_id: &str, force: bool) -> Result<(), MfaError> {
+    if !force && is_required(db, user_id).await? {
+        return Err(MfaError::Required);
+    }
+
+    let mut tx = db.begin().await?;
+    let result = sqlx::query("DELETE FROM user_totp WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM user_recovery_codes WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    if result.rows_affected() == 0 {
+        return Err(MfaError::NotEnabled);
+    }
+    Ok(())
+}
+
+/// Whether the user has a confirmed TOTP secret
+pub async fn is_enabled(db: &SqlitePool, user_id: &str) -> Result<bool, MfaError> {
+    Ok(matches!(get_totp(db, user_id).await?, Some((_, true))))
+}
+
+/// Whether one of the user's groups requires two-factor authentication
+pub async fn is_required(db: &SqlitePool, user_id: &str) -> Result<bool, MfaError> {
+    let count: (i64,) = sqlx::query_as(
+        r#"SELECT COUNT(*) FROM user_group_members m
+           JOIN user_groups g ON g.id = m.group_id
+           WHERE m.user_id = ? AND g.require_mfa = TRUE"#,
+    )
+    .bind(user_id)
+    .fetch_one(db)
+    .await?;
+
+    Ok(count.0 > 0)
+}
+
+/// Two-factor state of a user
+pub async fn get_status(db: &SqlitePool, user_id: &str) -> Result<MfaStatus, MfaError> {
+    let remaining: (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM user_recovery_codes WHERE user_id = ? AND used_at IS NULL",
+    )
+    .bind(user_id)
+    .fetch_one(db)
+    .await?;
+
+    Ok(MfaStatus {
+        enabled: is_enabled(db, user_id).await?,
+        required: is_required(db, user_id).await?,
+        recovery_codes_remaining: remaining.0,
+    })
+}
+
+async fn get_totp(db: &SqlitePool, user_id: &str) -> Result<Option<(String, bool)>, MfaError> {
+    let row: Option<(String, bool)> =
+        sqlx::query_as("SELECT secret, enabled FROM user_totp WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(db)
+            .await?;
+    Ok(row)
+}
+
+async fn replace_recovery_codes(db: &SqlitePool, user_id: &str) -> Result<Vec<String>, MfaError> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| generate_recovery_code()).collect();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let mut tx = db.begin().await?;
+    sqlx::query("DELETE FROM user_recovery_codes WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    for code in &codes {
+        sqlx::query(
+            "INSERT INTO user_recovery_codes (id, user_id, code_hash, created_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(user_id)
+        .bind(hash_recovery_code(code))
+        .bind(&now)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(codes)
+}
+
+/// Random code formatted as xxxxx-xxxxx
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 7];
+    OsRng.fill_bytes(&mut bytes);
+    let encoded = BASE32_NOPAD.encode(&bytes).to_lowercase();
+    format!("{}-{}", &encoded[..5], &encoded[5..10])
+}
+
+/// Recovery codes are random, a fast hash is enough; input formatting is ignored
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+fn urlencode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn unix_now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn current_code(secret: &str) -> String {
+        let secret = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+        format!("{:06}", totp_code(&secret, unix_now() / TOTP_STEP_SECS))
+    }
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        // RFC 6238 appendix B, SHA1 secret, truncated to 6 digits
+        let secret = b"12345678901234567890";
+        assert_eq!(totp_code(secret, 59 / 30), 287082);
+        assert_eq!(totp_code(secret, 1111111109 / 30), 81804);
+        assert_eq!(totp_code(secret, 1234567890 / 30), 5924);
+        assert_eq!(totp_code(secret, 2000000000 / 30), 279037);
+
+        let encoded = BASE32_NOPAD.encode(secret);
+        assert_eq!(verify_totp(&encoded, "287082", 59), Some(1));
+        // One step of drift is accepted, two are not
+        assert_eq!(verify_totp(&encoded, "287082", 89), Some(1));
+        assert_eq!(verify_totp(&encoded, "287082", 150), None);
+        assert_eq!(verify_totp(&encoded, "28708", 59), None);
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        let uri = otpauth_uri("jane doe", "ABCDEF");
+        assert_eq!(
+            uri,
+            "otpauth://totp/PiNAS:jane%20doe?secret=ABCDEF&issuer=PiNAS&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_enrolment_and_verification() {
+        let pool = crate::db::test_pool().await;
+        crate::db::test_user(&pool, "u1", "alice").await;
+
+        let enrolment = begin_enrolment(&pool, "u1", "alice").await.unwrap();
+        assert!(!is_enabled(&pool, "u1").await.unwrap());
+        let wrong = format!("{:06}", (current_code(&enrolment.secret).parse::<u32>().unwrap() + 1) % 1_000_000);
+        assert!(matches!(
+            confirm_enrolment(&pool, "u1", &wrong).await,
+            Err(MfaError::InvalidCode)
+        ));
+
+        let codes = confirm_enrolment(&pool, "u1", &current_code(&enrolment.secret))
+            .await
+            .unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(is_enabled(&pool, "u1").await.unwrap());
+
+        // The code used for enrolment cannot be replayed
+        assert!(matches!(
+            verify_second_factor(&pool, "u1", &current_code(&enrolment.secret)).await,
+            Err(MfaError::InvalidCode)
+        ));
+
+        // Recovery codes are single use and formatting is ignored
+        let upper = codes[0].to_uppercase().replace('-', " ");
+        verify_second_factor(&pool, "u1", &upper).await.unwrap();
+        assert!(verify_second_factor(&pool, "u1", &codes[0]).await.is_err());
+        assert_eq!(get_status(&pool, "u1").await.unwrap().recovery_codes_remaining, 9);
+    }
+
+    #[tokio::test]
+    async fn test_group_requirement_blocks_disable() {
+        let pool = crate::db::test_pool().await;
+        crate::db::test_user(&pool, "u1", "admin").await;
+        // The built-in administrators group
+        sqlx::query("UPDATE user_groups SET require_mfa = TRUE WHERE id = '00000000-0000-0000-0000-000000000001'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO user_group_members (id, user_id, group_id, created_at) VALUES ('m1', 'u1', '00000000-0000-0000-0000-000000000001', '')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let enrolment = begin_enrolment(&pool, "u1", "admin").await.unwrap();
+        confirm_enrolment(&pool, "u1", &current_code(&enrolment.secret))
+            .await
+            .unwrap();
+
+        assert!(is_required(&pool, "u1").await.unwrap());
+        assert!(matches!(disable(&pool, "u1", false).await, Err(MfaError::Required)));
+        disable(&pool, "u1", true).await.unwrap();
+        assert!(!is_enabled(&pool, "u1").await.unwrap());
+    }
+}
@@ -1,10 +1,30 @@
+pub mod api_token;
+pub mod audit;
 pub mod auth;
 pub mod docker;
+pub mod events;
 pub mod group;
+pub mod homes;
+pub mod ldap;
+pub mod lockout;
+pub mod mfa;
+pub mod notification;
+pub mod notification_channel;
+pub mod oidc;
+pub mod password_policy;
 pub mod package;
+pub mod package_bundle;
+pub mod package_catalog;
+pub mod package_deps;
+pub mod package_preflight;
+pub mod package_signing;
+pub mod package_tasks;
 pub mod service;
 pub mod session;
+pub mod settings;
 pub mod share;
 pub mod storage;
 pub mod system;
+pub mod system_user;
+pub mod terminal;
 pub mod user;
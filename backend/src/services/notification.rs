@@ -0,0 +1,630 @@
+use sqlx::SqlitePool;
+use thiserror::Error;
+
+use crate::services::events::{EventBus, WsEvent};
+use crate::models::notification::{
+    ChannelConfig, Notification, NotificationChannelRecord, NotificationLevel, NotificationRule,
+};
+use crate::services::notification_channel::channel_from_config;
+
+/// Placeholder returned by the API instead of channel secrets
+pub const MASKED_SECRET: &str = "********";
+
+/// Notification service errors
+#[derive(Debug, Error)]
+pub enum NotificationError {
+    #[error("Notification not found")]
+    NotFound,
+
+    #[error("Channel not found")]
+    ChannelNotFound,
+
+    #[error("Channel name already exists")]
+    DuplicateChannelName,
+
+    #[error("Invalid channel configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Invalid notification level: {0}")]
+    InvalidLevel(String),
+
+    #[error("Delivery failed: {0}")]
+    DeliveryFailed(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// Notify administrators: persist, push over WebSocket and fan out to channels
+pub async fn notify(
+    db: &SqlitePool,
+    events: &EventBus,
+    level: NotificationLevel,
+    title: &str,
+    message: &str,
+    category: &str,
+) -> Result<Notification, NotificationError> {
+    let notification = Notification::new(
+        level,
+        title.to_string(),
+        message.to_string(),
+        category.to_string(),
+        None,
+    );
+    publish(db, events, notification).await
+}
+
+/// Notify a single user: persist, push over WebSocket and fan out to channels
+pub async fn notify_user(
+    db: &SqlitePool,
+    events: &EventBus,
+    user_id: &str,
+    level: NotificationLevel,
+    title: &str,
+    message: &str,
+    category: &str,
+) -> Result<Notification, NotificationError> {
+    let notification = Notification::new(
+        level,
+        title.to_string(),
+        message.to_string(),
+        category.to_string(),
+        Some(user_id.to_string()),
+    );
+    publish(db, events, notification).await
+}
+
+async fn publish(
+    db: &SqlitePool,
+    events: &EventBus,
+    notification: Notification,
+) -> Result<Notification, NotificationError> {
+    sqlx::query(
+        r#"
+        INSERT INTO notifications (id, level, title, message, category, user_id, read, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&notification.id)
+    .bind(&notification.level)
+    .bind(&notification.title)
+    .bind(&notification.message)
+    .bind(&notification.category)
+    .bind(&notification.user_id)
+    .bind(notification.read)
+    .bind(&notification.created_at)
+    .execute(db)
+    .await?;
+
+    // No subscribers is not an error
+    events.publish(WsEvent::Notification(notification.clone()));
+
+    // Deliver to external channels in the background so callers never wait on SMTP/HTTP
+    let db = db.clone();
+    let to_dispatch = notification.clone();
+    tokio::spawn(async move {
+        if let Err(e) = dispatch(&db, &to_dispatch).await {
+            tracing::warn!("Failed to dispatch notification {}: {}", to_dispatch.id, e);
+        }
+    });
+
+    Ok(notification)
+}
+
+/// Deliver a notification to every enabled channel whose rules match it
+pub async fn dispatch(db: &SqlitePool, notification: &Notification) -> Result<usize, NotificationError> {
+    let level: NotificationLevel = notification
+        .level
+        .parse()
+        .map_err(NotificationError::InvalidLevel)?;
+
+    let candidates = sqlx::query_as::<_, (String, String, String)>(
+        r#"
+        SELECT c.id, c.config, r.min_level FROM notification_channels c
+        INNER JOIN notification_rules r ON r.channel_id = c.id
+        WHERE c.enabled = TRUE AND (r.category = ? OR r.category = '*')
+        "#,
+    )
+    .bind(&notification.category)
+    .fetch_all(db)
+    .await?;
+
+    let mut delivered = Vec::new();
+    for (channel_id, config, min_level) in candidates {
+        // A channel can match both a specific and a wildcard rule; deliver once
+        if delivered.contains(&channel_id) {
+            continue;
+        }
+        let min_level: NotificationLevel = min_level.parse().unwrap_or(NotificationLevel::Info);
+        if level < min_level {
+            continue;
+        }
+
+        let config: ChannelConfig = match serde_json::from_str(&config) {
+            Ok(config) => config,
+            Err(e) => {
+                tracing::warn!("Invalid config for notification channel {}: {}", channel_id, e);
+                continue;
+            }
+        };
+
+        match channel_from_config(config).send(notification).await {
+            Ok(()) => delivered.push(channel_id),
+            Err(e) => tracing::warn!("Notification channel {} failed: {}", channel_id, e),
+        }
+    }
+
+    Ok(delivered.len())
+}
+
+/// List notifications visible to a user, newest first
+pub async fn list_notifications(
+    db: &SqlitePool,
+    user_id: &str,
+    is_admin: bool,
+    unread_only: bool,
+    limit: i64,
+) -> Result<Vec<Notification>, NotificationError> {
+    let notifications = sqlx::query_as::<_, Notification>(
+        r#"
+        SELECT * FROM notifications
+        WHERE (user_id = ? OR (user_id IS NULL AND ?))
+          AND (read = FALSE OR NOT ?)
+        ORDER BY created_at DESC
+        LIMIT ?
+        "#,
+    )
+    .bind(user_id)
+    .bind(is_admin)
+    .bind(unread_only)
+    .bind(limit)
+    .fetch_all(db)
+    .await?;
+
+    Ok(notifications)
+}
+
+/// Count unread notifications visible to a user
+pub async fn count_unread(
+    db: &SqlitePool,
+    user_id: &str,
+    is_admin: bool,
+) -> Result<i64, NotificationError> {
+    let count: (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM notifications WHERE read = FALSE AND (user_id = ? OR (user_id IS NULL AND ?))",
+    )
+    .bind(user_id)
+    .bind(is_admin)
+    .fetch_one(db)
+    .await?;
+
+    Ok(count.0)
+}
+
+/// Mark a notification as read
+pub async fn mark_read(
+    db: &SqlitePool,
+    id: &str,
+    user_id: &str,
+    is_admin: bool,
+) -> Result<(), NotificationError> {
+    let result = sqlx::query(
+        "UPDATE notifications SET read = TRUE WHERE id = ? AND (user_id = ? OR (user_id IS NULL AND ?))",
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(is_admin)
+    .execute(db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(NotificationError::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Mark all notifications visible to a user as read
+pub async fn mark_all_read(
+    db: &SqlitePool,
+    user_id: &str,
+    is_admin: bool,
+) -> Result<u64, NotificationError> {
+    let result = sqlx::query(
+        "UPDATE notifications SET read = TRUE WHERE read = FALSE AND (user_id = ? OR (user_id IS NULL AND ?))",
+    )
+    .bind(user_id)
+    .bind(is_admin)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Delete a notification
+pub async fn delete_notification(
+    db: &SqlitePool,
+    id: &str,
+    user_id: &str,
+    is_admin: bool,
+) -> Result<(), NotificationError> {
+    let result = sqlx::query(
+        "DELETE FROM notifications WHERE id = ? AND (user_id = ? OR (user_id IS NULL AND ?))",
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(is_admin)
+    .execute(db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(NotificationError::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Create a delivery channel
+pub async fn create_channel(
+    db: &SqlitePool,
+    name: &str,
+    config: &ChannelConfig,
+    enabled: bool,
+) -> Result<NotificationChannelRecord, NotificationError> {
+    if get_channel_by_name(db, name).await?.is_some() {
+        return Err(NotificationError::DuplicateChannelName);
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let channel = NotificationChannelRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        channel_type: config.channel_type().to_string(),
+        config: serde_json::to_string(config).map_err(|e| NotificationError::InvalidConfig(e.to_string()))?,
+        enabled,
+        created_at: now.clone(),
+        updated_at: now,
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO notification_channels (id, name, channel_type, config, enabled, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&channel.id)
+    .bind(&channel.name)
+    .bind(&channel.channel_type)
+    .bind(&channel.config)
+    .bind(channel.enabled)
+    .bind(&channel.created_at)
+    .bind(&channel.updated_at)
+    .execute(db)
+    .await?;
+
+    Ok(channel)
+}
+
+/// Get a channel by ID
+pub async fn get_channel(
+    db: &SqlitePool,
+    id: &str,
+) -> Result<Option<NotificationChannelRecord>, NotificationError> {
+    let channel = sqlx::query_as::<_, NotificationChannelRecord>(
+        "SELECT * FROM notification_channels WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(channel)
+}
+
+/// Get a channel by name
+pub async fn get_channel_by_name(
+    db: &SqlitePool,
+    name: &str,
+) -> Result<Option<NotificationChannelRecord>, NotificationError> {
+    let channel = sqlx::query_as::<_, NotificationChannelRecord>(
+        "SELECT * FROM notification_channels WHERE name = ?",
+    )
+    .bind(name)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(channel)
+}
+
+/// List all channels
+pub async fn list_channels(db: &SqlitePool) -> Result<Vec<NotificationChannelRecord>, NotificationError> {
+    let channels = sqlx::query_as::<_, NotificationChannelRecord>(
+        "SELECT * FROM notification_channels ORDER BY name",
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(channels)
+}
+
+/// Update a channel
+pub async fn update_channel(
+    db: &SqlitePool,
+    id: &str,
+    name: Option<String>,
+    config: Option<ChannelConfig>,
+    enabled: Option<bool>,
+) -> Result<NotificationChannelRecord, NotificationError> {
+    let existing = get_channel(db, id).await?.ok_or(NotificationError::ChannelNotFound)?;
+
+    let new_name = name.unwrap_or(existing.name.clone());
+    if new_name != existing.name && get_channel_by_name(db, &new_name).await?.is_some() {
+        return Err(NotificationError::DuplicateChannelName);
+    }
+
+    let (channel_type, config_json) = match config {
+        Some(config) => {
+            let mut value = serde_json::to_value(&config)
+                .map_err(|e| NotificationError::InvalidConfig(e.to_string()))?;
+            // Secrets are masked in API responses; a masked value means "unchanged"
+            let previous: serde_json::Value =
+                serde_json::from_str(&existing.config).unwrap_or(serde_json::Value::Null);
+            for secret in ["password", "token"] {
+                if value.get(secret).and_then(|v| v.as_str()) == Some(MASKED_SECRET) {
+                    value[secret] = previous.get(secret).cloned().unwrap_or(serde_json::Value::Null);
+                }
+            }
+            if let Some(headers) = value.get_mut("headers").and_then(|h| h.as_object_mut()) {
+                for (name, header) in headers.iter_mut() {
+                    if header.as_str() == Some(MASKED_SECRET) {
+                        if let Some(kept) = previous.get("headers").and_then(|h| h.get(name)) {
+                            *header = kept.clone();
+                        }
+                    }
+                }
+            }
+            (config.channel_type().to_string(), value.to_string())
+        }
+        None => (existing.channel_type, existing.config),
+    };
+    let enabled = enabled.unwrap_or(existing.enabled);
+    let now = chrono::Utc::now().to_rfc3339();
+
+    sqlx::query(
+        r#"
+        UPDATE notification_channels
+        SET name = ?, channel_type = ?, config = ?, enabled = ?, updated_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(&new_name)
+    .bind(&channel_type)
+    .bind(&config_json)
+    .bind(enabled)
+    .bind(&now)
+    .bind(id)
+    .execute(db)
+    .await?;
+
+    get_channel(db, id).await?.ok_or(NotificationError::ChannelNotFound)
+}
+
+/// Delete a channel and its rules
+pub async fn delete_channel(db: &SqlitePool, id: &str) -> Result<(), NotificationError> {
+    sqlx::query("DELETE FROM notification_rules WHERE channel_id = ?")
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    let result = sqlx::query("DELETE FROM notification_channels WHERE id = ?")
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(NotificationError::ChannelNotFound);
+    }
+
+    Ok(())
+}
+
+/// Send a test notification through a channel, reporting delivery errors
+pub async fn test_channel(db: &SqlitePool, id: &str) -> Result<(), NotificationError> {
+    let channel = get_channel(db, id).await?.ok_or(NotificationError::ChannelNotFound)?;
+    let config: ChannelConfig = serde_json::from_str(&channel.config)
+        .map_err(|e| NotificationError::InvalidConfig(e.to_string()))?;
+
+    let notification = Notification::new(
+        NotificationLevel::Info,
+        "Test notification".to_string(),
+        format!("This is a test notification for channel '{}'", channel.name),
+        "system".to_string(),
+        None,
+    );
+
+    channel_from_config(config)
+        .send(&notification)
+        .await
+        .map_err(|e| NotificationError::DeliveryFailed(e.to_string()))
+}
+
+/// Create or replace a routing rule
+pub async fn set_rule(
+    db: &SqlitePool,
+    channel_id: &str,
+    category: &str,
+    min_level: NotificationLevel,
+) -> Result<NotificationRule, NotificationError> {
+    let _ = get_channel(db, channel_id)
+        .await?
+        .ok_or(NotificationError::ChannelNotFound)?;
+
+    let rule = NotificationRule {
+        id: uuid::Uuid::new_v4().to_string(),
+        channel_id: channel_id.to_string(),
+        category: category.to_string(),
+        min_level: min_level.to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO notification_rules (id, channel_id, category, min_level, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(channel_id, category) DO UPDATE SET min_level = excluded.min_level
+        "#,
+    )
+    .bind(&rule.id)
+    .bind(&rule.channel_id)
+    .bind(&rule.category)
+    .bind(&rule.min_level)
+    .bind(&rule.created_at)
+    .execute(db)
+    .await?;
+
+    let rule = sqlx::query_as::<_, NotificationRule>(
+        "SELECT * FROM notification_rules WHERE channel_id = ? AND category = ?",
+    )
+    .bind(channel_id)
+    .bind(category)
+    .fetch_one(db)
+    .await?;
+
+    Ok(rule)
+}
+
+/// List all routing rules
+pub async fn list_rules(db: &SqlitePool) -> Result<Vec<NotificationRule>, NotificationError> {
+    let rules = sqlx::query_as::<_, NotificationRule>(
+        "SELECT * FROM notification_rules ORDER BY category, channel_id",
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rules)
+}
+
+/// Delete a routing rule
+pub async fn delete_rule(db: &SqlitePool, id: &str) -> Result<(), NotificationError> {
+    let result = sqlx::query("DELETE FROM notification_rules WHERE id = ?")
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(NotificationError::NotFound);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_notify_persists_and_broadcasts() {
+        let pool = crate::db::test_pool().await;
+        let events = EventBus::new(8);
+        let mut rx = events.subscribe();
+
+        let created = notify(&pool, &events, NotificationLevel::Error, "RAID degraded", "Disk sdb failed", "storage")
+            .await
+            .unwrap();
+
+        match rx.recv().await.unwrap() {
+            WsEvent::Notification(n) => assert_eq!(n.id, created.id),
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        let admin_view = list_notifications(&pool, "admin-1", true, false, 50).await.unwrap();
+        assert_eq!(admin_view.len(), 1);
+        assert_eq!(admin_view[0].category, "storage");
+
+        // Admin-wide notifications are not shown to regular users
+        let user_view = list_notifications(&pool, "user-1", false, false, 50).await.unwrap();
+        assert!(user_view.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_read_and_delete_are_scoped_to_recipient() {
+        let pool = crate::db::test_pool().await;
+        crate::db::test_user(&pool, "user-1", "alice").await;
+        let events = EventBus::new(8);
+
+        let n = notify_user(&pool, &events, "user-1", NotificationLevel::Info, "Hello", "Welcome", "system")
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            mark_read(&pool, &n.id, "user-2", false).await,
+            Err(NotificationError::NotFound)
+        ));
+        assert_eq!(count_unread(&pool, "user-1", false).await.unwrap(), 1);
+
+        mark_read(&pool, &n.id, "user-1", false).await.unwrap();
+        assert_eq!(count_unread(&pool, "user-1", false).await.unwrap(), 0);
+        assert!(list_notifications(&pool, "user-1", false, true, 50).await.unwrap().is_empty());
+
+        delete_notification(&pool, &n.id, "user-1", false).await.unwrap();
+        assert!(list_notifications(&pool, "user-1", false, false, 50).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_respects_rules() {
+        let pool = crate::db::test_pool().await;
+
+        // Unroutable URL: delivery fails, so only rule matching is exercised here
+        let channel = create_channel(
+            &pool,
+            "hook",
+            &ChannelConfig::Webhook {
+                url: "http://127.0.0.1:1/hook".to_string(),
+                headers: Default::default(),
+            },
+            true,
+        )
+        .await
+        .unwrap();
+        set_rule(&pool, &channel.id, "storage", NotificationLevel::Warning).await.unwrap();
+
+        // Replacing the rule for the same category keeps a single row
+        set_rule(&pool, &channel.id, "storage", NotificationLevel::Error).await.unwrap();
+        let rules = list_rules(&pool).await.unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].min_level, "error");
+
+        let info = Notification::new(NotificationLevel::Info, "t".into(), "m".into(), "storage".into(), None);
+        assert_eq!(dispatch(&pool, &info).await.unwrap(), 0);
+
+        delete_channel(&pool, &channel.id).await.unwrap();
+        assert!(list_rules(&pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_masked_headers_are_kept_on_update() {
+        let pool = crate::db::test_pool().await;
+        let headers = |value: &str| {
+            [("Authorization".to_string(), value.to_string())].into_iter().collect()
+        };
+        let channel = create_channel(
+            &pool,
+            "hook",
+            &ChannelConfig::Webhook {
+                url: "https://example.com/hook".to_string(),
+                headers: headers("Bearer secret"),
+            },
+            true,
+        )
+        .await
+        .unwrap();
+
+        let config = ChannelConfig::Webhook {
+            url: "https://example.com/other".to_string(),
+            headers: headers(MASKED_SECRET),
+        };
+        let updated = update_channel(&pool, &channel.id, None, Some(config), None).await.unwrap();
+        let stored: serde_json::Value = serde_json::from_str(&updated.config).unwrap();
+        assert_eq!(stored["url"], "https://example.com/other");
+        assert_eq!(stored["headers"]["Authorization"], "Bearer secret");
+    }
+}
@@ -0,0 +1,344 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::models::notification::{ChannelConfig, Notification};
+
+/// Timeout for a single delivery attempt
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A destination notifications can be delivered to
+#[async_trait]
+pub trait NotificationChannel: Send + Sync {
+    async fn send(&self, notification: &Notification) -> Result<()>;
+}
+
+/// Build the channel implementation for a configuration
+pub fn channel_from_config(config: ChannelConfig) -> Box<dyn NotificationChannel> {
+    match config {
+        ChannelConfig::Smtp {
+            host,
+            port,
+            username,
+            password,
+            security,
+            from,
+            to,
+        } => Box::new(SmtpChannel {
+            host,
+            port,
+            credentials: username.map(|u| (u, password.unwrap_or_default())),
+            security,
+            from,
+            to,
+        }),
+        ChannelConfig::Webhook { url, headers } => Box::new(WebhookChannel { url, headers }),
+        ChannelConfig::Ntfy { url, topic, token } => Box::new(NtfyChannel { url, topic, token }),
+        ChannelConfig::Gotify { url, token } => Box::new(GotifyChannel { url, token }),
+    }
+}
+
+/// Email delivery over SMTP
+pub struct SmtpChannel {
+    host: String,
+    port: u16,
+    credentials: Option<(String, String)>,
+    security: String,
+    from: String,
+    to: Vec<String>,
+}
+
+#[async_trait]
+impl NotificationChannel for SmtpChannel {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let mut builder = Message::builder()
+            .from(self.from.parse::<Mailbox>()?)
+            .subject(format!("[PiNAS] {}", notification.title));
+        for recipient in &self.to {
+            builder = builder.to(recipient.parse::<Mailbox>()?);
+        }
+        let email = builder.body(format!(
+            "{}\n\nLevel: {}\nCategory: {}\nDate: {}\n",
+            notification.message, notification.level, notification.category, notification.created_at
+        ))?;
+
+        let mut transport = match self.security.as_str() {
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)?,
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)?,
+            "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.host),
+            other => return Err(anyhow!("Unknown SMTP security mode: {}", other)),
+        }
+        .port(self.port)
+        .timeout(Some(DELIVERY_TIMEOUT));
+
+        if let Some((username, password)) = &self.credentials {
+            transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        transport.build().send(email).await?;
+        Ok(())
+    }
+}
+
+/// Generic webhook receiving the notification as JSON
+pub struct WebhookChannel {
+    url: String,
+    headers: HashMap<String, String>,
+}
+
+#[async_trait]
+impl NotificationChannel for WebhookChannel {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let mut request = http_client()?.post(&self.url).json(notification);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Webhook returned HTTP {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Push delivery through an ntfy server
+pub struct NtfyChannel {
+    url: String,
+    topic: String,
+    token: Option<String>,
+}
+
+#[async_trait]
+impl NotificationChannel for NtfyChannel {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let priority = match notification.level.as_str() {
+            "error" => 5,
+            "warning" => 4,
+            _ => 3,
+        };
+
+        // Published as JSON rather than with headers, which cannot carry non-ASCII titles
+        let mut request = http_client()?
+            .post(format!("{}/", self.url.trim_end_matches('/')))
+            .json(&serde_json::json!({
+                "topic": self.topic,
+                "title": notification.title,
+                "message": notification.message,
+                "priority": priority,
+                "tags": [notification.category],
+            }));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("ntfy returned HTTP {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Push delivery through a Gotify server
+pub struct GotifyChannel {
+    url: String,
+    token: String,
+}
+
+#[async_trait]
+impl NotificationChannel for GotifyChannel {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let priority = match notification.level.as_str() {
+            "error" => 8,
+            "warning" => 5,
+            _ => 2,
+        };
+
+        let response = http_client()?
+            .post(format!("{}/message", self.url.trim_end_matches('/')))
+            .header("X-Gotify-Key", &self.token)
+            .json(&serde_json::json!({
+                "title": notification.title,
+                "message": notification.message,
+                "priority": priority,
+            }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Gotify returned HTTP {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+fn http_client() -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder().timeout(DELIVERY_TIMEOUT).build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::notification::NotificationLevel;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+
+    fn sample_notification() -> Notification {
+        Notification::new(
+            NotificationLevel::Warning,
+            "Disk almost full".to_string(),
+            "Volume /storage is 95% full".to_string(),
+            "storage".to_string(),
+            None,
+        )
+    }
+
+    /// Accept one HTTP request and return its head and body
+    async fn spawn_http_mock() -> (String, oneshot::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                head.push_str(&line);
+            }
+            let length = head
+                .lines()
+                .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(0);
+            let mut body = vec![0u8; length];
+            reader.read_exact(&mut body).await.unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            let _ = tx.send((head, String::from_utf8_lossy(&body).to_string()));
+        });
+
+        (format!("http://{}", addr), rx)
+    }
+
+    /// Minimal SMTP server accepting one message and returning its DATA section
+    async fn spawn_smtp_mock() -> (u16, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            reader.get_mut().write_all(b"220 mock ESMTP\r\n").await.unwrap();
+
+            let mut data = String::new();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                if in_data {
+                    if line == ".\r\n" {
+                        in_data = false;
+                        reader.get_mut().write_all(b"250 OK\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                    }
+                    continue;
+                }
+                let command = line.to_uppercase();
+                let reply: &[u8] = if command.starts_with("EHLO") {
+                    b"250 mock\r\n"
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if command.starts_with("QUIT") {
+                    reader.get_mut().write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 OK\r\n"
+                };
+                reader.get_mut().write_all(reply).await.unwrap();
+            }
+            let _ = tx.send(data);
+        });
+
+        (port, rx)
+    }
+
+    #[tokio::test]
+    async fn test_webhook_posts_notification_json() {
+        let (url, rx) = spawn_http_mock().await;
+        let mut headers = HashMap::new();
+        headers.insert("X-Token".to_string(), "secret".to_string());
+        let channel = channel_from_config(ChannelConfig::Webhook { url, headers });
+
+        let notification = sample_notification();
+        channel.send(&notification).await.unwrap();
+
+        let (head, body) = rx.await.unwrap();
+        assert!(head.starts_with("POST / HTTP/1.1"));
+        assert!(head.to_lowercase().contains("x-token: secret"));
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["id"], notification.id);
+        assert_eq!(json["level"], "warning");
+    }
+
+    #[tokio::test]
+    async fn test_ntfy_sets_title_and_priority() {
+        let (url, rx) = spawn_http_mock().await;
+        let channel = channel_from_config(ChannelConfig::Ntfy {
+            url,
+            topic: "pinas".to_string(),
+            token: None,
+        });
+
+        let mut notification = sample_notification();
+        notification.title = "Disque presque plein – 95 %".to_string();
+        channel.send(&notification).await.unwrap();
+
+        let (head, body) = rx.await.unwrap();
+        assert!(head.starts_with("POST / HTTP/1.1"));
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["topic"], "pinas");
+        assert_eq!(body["title"], "Disque presque plein – 95 %");
+        assert_eq!(body["priority"], 4);
+        assert_eq!(body["message"], "Volume /storage is 95% full");
+    }
+
+    #[tokio::test]
+    async fn test_smtp_delivers_email() {
+        let (port, rx) = spawn_smtp_mock().await;
+        let channel = channel_from_config(ChannelConfig::Smtp {
+            host: "127.0.0.1".to_string(),
+            port,
+            username: None,
+            password: None,
+            security: "none".to_string(),
+            from: "nas@example.com".to_string(),
+            to: vec!["admin@example.com".to_string()],
+        });
+
+        channel.send(&sample_notification()).await.unwrap();
+
+        let data = rx.await.unwrap();
+        assert!(data.contains("Subject: [PiNAS] Disk almost full"));
+        assert!(data.contains("To: admin@example.com"));
+        assert!(data.contains("Volume /storage is 95% full"));
+    }
+}
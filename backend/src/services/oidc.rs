@@ -0,0 +1,1102 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use thiserror::Error;
+
+use crate::models::group::{GroupMember, UserGroup};
+use crate::models::oidc::{OidcProvider, UserIdentity};
+use crate::models::user::{User, AUTH_SOURCE_OIDC};
+
+/// Client authentication at the token endpoint, HTTP Basic or form fields
+pub const TOKEN_AUTH_BASIC: &str = "client_secret_basic";
+pub const TOKEN_AUTH_POST: &str = "client_secret_post";
+
+/// Time allowed between the redirect to the provider and its callback
+const LOGIN_TIMEOUT_MINUTES: i64 = 10;
+
+/// Time allowed for the web interface to redeem a sign-in ticket
+const TICKET_TIMEOUT_SECONDS: i64 = 60;
+
+/// Timeout of requests to a provider
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Clock skew tolerated on ID token timestamps, in seconds
+const CLOCK_LEEWAY: u64 = 60;
+
+/// Stored as the password hash of provisioned users, no password ever verifies against it
+const UNUSABLE_PASSWORD_HASH: &str = "!oidc";
+
+/// OpenID Connect errors
+#[derive(Debug, Error)]
+pub enum OidcError {
+    #[error("Identity provider not found")]
+    NotFound,
+
+    #[error("Identity provider is disabled")]
+    Disabled,
+
+    #[error("Identity provider name already exists")]
+    DuplicateName,
+
+    #[error("Invalid identity provider settings: {0}")]
+    InvalidSettings(String),
+
+    #[error("Identity provider error: {0}")]
+    Provider(String),
+
+    #[error("Invalid ID token: {0}")]
+    InvalidIdToken(String),
+
+    #[error("Sign-in request expired or unknown")]
+    InvalidState,
+
+    #[error("Sign-in ticket expired or unknown")]
+    InvalidTicket,
+
+    #[error("No account is linked to this identity")]
+    NoAccount,
+
+    #[error("Username {0} already exists, sign in with it and link the identity by email instead")]
+    UsernameTaken(String),
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// Provider settings, as created or replaced by an admin
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderInput {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    /// Kept unchanged on update when omitted
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    #[serde(default = "default_token_auth_method")]
+    pub token_auth_method: String,
+    #[serde(default = "default_scopes")]
+    pub scopes: String,
+    #[serde(default = "default_username_claim")]
+    pub username_claim: String,
+    #[serde(default = "default_groups_claim")]
+    pub groups_claim: String,
+    #[serde(default)]
+    pub group_mapping: HashMap<String, String>,
+    #[serde(default)]
+    pub admin_values: Vec<String>,
+    #[serde(default = "default_true")]
+    pub allow_provisioning: bool,
+    #[serde(default)]
+    pub link_by_email: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_token_auth_method() -> String {
+    TOKEN_AUTH_BASIC.to_string()
+}
+
+fn default_scopes() -> String {
+    "openid profile email".to_string()
+}
+
+fn default_username_claim() -> String {
+    "preferred_username".to_string()
+}
+
+fn default_groups_claim() -> String {
+    "groups".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl ProviderInput {
+    fn validate(&self) -> Result<(), OidcError> {
+        let invalid = |reason: &str| Err(OidcError::InvalidSettings(reason.to_string()));
+        if self.name.trim().is_empty() {
+            return invalid("name is required");
+        }
+        if self.client_id.trim().is_empty() {
+            return invalid("client_id is required");
+        }
+        match reqwest::Url::parse(&self.issuer) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            _ => return invalid("issuer must be an http(s) URL"),
+        }
+        if ![TOKEN_AUTH_BASIC, TOKEN_AUTH_POST].contains(&self.token_auth_method.as_str()) {
+            return invalid("token_auth_method must be client_secret_basic or client_secret_post");
+        }
+        if !self.scopes.split_whitespace().any(|s| s == "openid") {
+            return invalid("scopes must include openid");
+        }
+        if self.username_claim.trim().is_empty() || self.groups_claim.trim().is_empty() {
+            return invalid("claim names cannot be empty");
+        }
+        Ok(())
+    }
+}
+
+/// Endpoints published by a provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Discovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+    #[serde(default)]
+    pub userinfo_endpoint: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    #[serde(default)]
+    id_token: Option<String>,
+    #[serde(default)]
+    access_token: Option<String>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct LoginState {
+    provider_id: String,
+    code_verifier: String,
+    nonce: String,
+    redirect_uri: String,
+    expires_at: String,
+}
+
+// ============================================================================
+// Providers
+// ============================================================================
+
+/// List providers, by name
+pub async fn list_providers(db: &SqlitePool) -> Result<Vec<OidcProvider>, OidcError> {
+    let providers = sqlx::query_as::<_, OidcProvider>("SELECT * FROM oidc_providers ORDER BY name")
+        .fetch_all(db)
+        .await?;
+    Ok(providers)
+}
+
+pub async fn get_provider(db: &SqlitePool, id: &str) -> Result<OidcProvider, OidcError> {
+    sqlx::query_as::<_, OidcProvider>("SELECT * FROM oidc_providers WHERE id = ?")
+        .bind(id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(OidcError::NotFound)
+}
+
+pub async fn create_provider(db: &SqlitePool, input: &ProviderInput) -> Result<OidcProvider, OidcError> {
+    input.validate()?;
+    if name_taken(db, &input.name, None).await? {
+        return Err(OidcError::DuplicateName);
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        r#"
+        INSERT INTO oidc_providers (id, name, issuer, client_id, client_secret, token_auth_method, scopes,
+            username_claim, groups_claim, group_mapping, admin_values, allow_provisioning, link_by_email,
+            enabled, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&id)
+    .bind(input.name.trim())
+    .bind(input.issuer.trim_end_matches('/'))
+    .bind(input.client_id.trim())
+    .bind(input.client_secret.as_deref().filter(|s| !s.is_empty()))
+    .bind(&input.token_auth_method)
+    .bind(&input.scopes)
+    .bind(&input.username_claim)
+    .bind(&input.groups_claim)
+    .bind(serde_json::to_string(&input.group_mapping).unwrap_or_default())
+    .bind(serde_json::to_string(&input.admin_values).unwrap_or_default())
+    .bind(input.allow_provisioning)
+    .bind(input.link_by_email)
+    .bind(input.enabled)
+    .bind(&now)
+    .bind(&now)
+    .execute(db)
+    .await?;
+
+    get_provider(db, &id).await
+}
+
+/// Replace the settings of a provider, keeping its client secret when none is given
+pub async fn update_provider(
+    db: &SqlitePool,
+    id: &str,
+    input: &ProviderInput,
+) -> Result<OidcProvider, OidcError> {
+    let existing = get_provider(db, id).await?;
+    input.validate()?;
+    if name_taken(db, &input.name, Some(id)).await? {
+        return Err(OidcError::DuplicateName);
+    }
+
+    let client_secret = match &input.client_secret {
+        Some(secret) if secret.is_empty() => None,
+        Some(secret) => Some(secret.clone()),
+        None => existing.client_secret,
+    };
+
+    sqlx::query(
+        r#"
+        UPDATE oidc_providers
+        SET name = ?, issuer = ?, client_id = ?, client_secret = ?, token_auth_method = ?, scopes = ?,
+            username_claim = ?, groups_claim = ?, group_mapping = ?, admin_values = ?,
+            allow_provisioning = ?, link_by_email = ?, enabled = ?, updated_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(input.name.trim())
+    .bind(input.issuer.trim_end_matches('/'))
+    .bind(input.client_id.trim())
+    .bind(client_secret)
+    .bind(&input.token_auth_method)
+    .bind(&input.scopes)
+    .bind(&input.username_claim)
+    .bind(&input.groups_claim)
+    .bind(serde_json::to_string(&input.group_mapping).unwrap_or_default())
+    .bind(serde_json::to_string(&input.admin_values).unwrap_or_default())
+    .bind(input.allow_provisioning)
+    .bind(input.link_by_email)
+    .bind(input.enabled)
+    .bind(Utc::now().to_rfc3339())
+    .bind(id)
+    .execute(db)
+    .await?;
+
+    get_provider(db, id).await
+}
+
+/// Delete a provider, unlinking the identities of its users
+pub async fn delete_provider(db: &SqlitePool, id: &str) -> Result<(), OidcError> {
+    let result = sqlx::query("DELETE FROM oidc_providers WHERE id = ?")
+        .bind(id)
+        .execute(db)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(OidcError::NotFound);
+    }
+    Ok(())
+}
+
+async fn name_taken(db: &SqlitePool, name: &str, except: Option<&str>) -> Result<bool, sqlx::Error> {
+    let existing: Option<(String,)> = sqlx::query_as("SELECT id FROM oidc_providers WHERE name = ?")
+        .bind(name.trim())
+        .fetch_optional(db)
+        .await?;
+    Ok(existing.is_some_and(|(id,)| Some(id.as_str()) != except))
+}
+
+/// Fetch the discovery document of an issuer
+pub async fn discover(issuer: &str) -> Result<Discovery, OidcError> {
+    fetch_discovery(&http_client()?, issuer).await
+}
+
+async fn fetch_discovery(client: &reqwest::Client, issuer: &str) -> Result<Discovery, OidcError> {
+    let issuer = issuer.trim_end_matches('/');
+    let url = format!("{}/.well-known/openid-configuration", issuer);
+    let discovery: Discovery = client.get(&url).send().await?.error_for_status()?.json().await?;
+    if discovery.issuer.trim_end_matches('/') != issuer {
+        return Err(OidcError::Provider(format!(
+            "discovery document is for issuer {}",
+            discovery.issuer
+        )));
+    }
+    Ok(discovery)
+}
+
+fn http_client() -> Result<reqwest::Client, OidcError> {
+    Ok(reqwest::Client::builder().timeout(HTTP_TIMEOUT).build()?)
+}
+
+// ============================================================================
+// Sign-in flow
+// ============================================================================
+
+/// Start an authorization code flow with PKCE, returns the URL to send the browser to
+pub async fn begin_login(
+    db: &SqlitePool,
+    provider: &OidcProvider,
+    redirect_uri: &str,
+) -> Result<String, OidcError> {
+    if !provider.enabled {
+        return Err(OidcError::Disabled);
+    }
+    let discovery = discover(&provider.issuer).await?;
+    prune_expired(db).await?;
+
+    let state = random_token();
+    let code_verifier = random_token();
+    let nonce = random_token();
+    let expires_at = Utc::now() + chrono::Duration::minutes(LOGIN_TIMEOUT_MINUTES);
+    sqlx::query(
+        r#"
+        INSERT INTO oidc_login_states (state, provider_id, code_verifier, nonce, redirect_uri, expires_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&state)
+    .bind(&provider.id)
+    .bind(&code_verifier)
+    .bind(&nonce)
+    .bind(redirect_uri)
+    .bind(expires_at.to_rfc3339())
+    .execute(db)
+    .await?;
+
+    let mut url = reqwest::Url::parse(&discovery.authorization_endpoint)
+        .map_err(|_| OidcError::Provider("invalid authorization endpoint".to_string()))?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("scope", &provider.scopes)
+        .append_pair("state", &state)
+        .append_pair("nonce", &nonce)
+        .append_pair("code_challenge", &pkce_challenge(&code_verifier))
+        .append_pair("code_challenge_method", "S256");
+    Ok(url.to_string())
+}
+
+/// Complete a sign-in from the provider callback, returns the linked or provisioned user
+pub async fn finish_login(
+    db: &SqlitePool,
+    provider_id: &str,
+    code: &str,
+    state: &str,
+) -> Result<User, OidcError> {
+    // A state is used once, whatever the outcome
+    let login = sqlx::query_as::<_, LoginState>("SELECT * FROM oidc_login_states WHERE state = ?")
+        .bind(state)
+        .fetch_optional(db)
+        .await?;
+    sqlx::query("DELETE FROM oidc_login_states WHERE state = ?")
+        .bind(state)
+        .execute(db)
+        .await?;
+    let login = login
+        .filter(|l| l.provider_id == provider_id && l.expires_at > Utc::now().to_rfc3339())
+        .ok_or(OidcError::InvalidState)?;
+
+    let provider = get_provider(db, provider_id).await?;
+    if !provider.enabled {
+        return Err(OidcError::Disabled);
+    }
+
+    let client = http_client()?;
+    let discovery = fetch_discovery(&client, &provider.issuer).await?;
+    let tokens = exchange_code(&client, &provider, &discovery, code, &login).await?;
+    let id_token = tokens
+        .id_token
+        .ok_or_else(|| OidcError::Provider("token response has no id_token".to_string()))?;
+    let mut claims = verify_id_token(&client, &provider, &discovery, &id_token, &login.nonce).await?;
+
+    // Some providers only return profile and group claims from the userinfo endpoint
+    let missing = [&provider.username_claim, &provider.groups_claim]
+        .iter()
+        .any(|claim| !claims.contains_key(claim.as_str()));
+    if let (true, Some(endpoint), Some(access_token)) =
+        (missing, &discovery.userinfo_endpoint, &tokens.access_token)
+    {
+        let userinfo: Map<String, Value> = client
+            .get(endpoint)
+            .bearer_auth(access_token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if userinfo.get("sub") == claims.get("sub") {
+            for (key, value) in userinfo {
+                claims.entry(key).or_insert(value);
+            }
+        }
+    }
+
+    resolve_user(db, &provider, &claims).await
+}
+
+async fn exchange_code(
+    client: &reqwest::Client,
+    provider: &OidcProvider,
+    discovery: &Discovery,
+    code: &str,
+    login: &LoginState,
+) -> Result<TokenResponse, OidcError> {
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", login.redirect_uri.as_str()),
+        ("code_verifier", login.code_verifier.as_str()),
+    ];
+    let mut request = client.post(&discovery.token_endpoint);
+    match provider.client_secret.as_deref() {
+        Some(secret) if provider.token_auth_method == TOKEN_AUTH_POST => {
+            form.push(("client_id", &provider.client_id));
+            form.push(("client_secret", secret));
+        }
+        Some(secret) => request = request.basic_auth(&provider.client_id, Some(secret)),
+        None => form.push(("client_id", &provider.client_id)),
+    }
+
+    let response = request.form(&form).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(OidcError::Provider(format!(
+            "token endpoint returned {}: {}",
+            status,
+            body.chars().take(200).collect::<String>()
+        )));
+    }
+    Ok(response.json().await?)
+}
+
+/// Check the signature, issuer, audience, expiry and nonce of an ID token, returns its claims
+async fn verify_id_token(
+    client: &reqwest::Client,
+    provider: &OidcProvider,
+    discovery: &Discovery,
+    id_token: &str,
+    nonce: &str,
+) -> Result<Map<String, Value>, OidcError> {
+    let invalid = |e: jsonwebtoken::errors::Error| OidcError::InvalidIdToken(e.to_string());
+    let header = decode_header(id_token).map_err(invalid)?;
+
+    let key = match header.alg {
+        // Symmetric tokens are signed with the client secret
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+            let secret = provider.client_secret.as_deref().ok_or_else(|| {
+                OidcError::InvalidIdToken("HMAC signed token without a client secret".to_string())
+            })?;
+            DecodingKey::from_secret(secret.as_bytes())
+        }
+        _ => {
+            let jwks: JwkSet = client
+                .get(&discovery.jwks_uri)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            let jwk = match &header.kid {
+                Some(kid) => jwks.find(kid),
+                None if jwks.keys.len() == 1 => jwks.keys.first(),
+                None => None,
+            }
+            .ok_or_else(|| OidcError::InvalidIdToken("signing key not found".to_string()))?;
+            DecodingKey::from_jwk(jwk).map_err(invalid)?
+        }
+    };
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&discovery.issuer]);
+    validation.set_audience(&[&provider.client_id]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+    validation.leeway = CLOCK_LEEWAY;
+    let claims = decode::<Map<String, Value>>(id_token, &key, &validation)
+        .map_err(invalid)?
+        .claims;
+
+    if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+        return Err(OidcError::InvalidIdToken("nonce mismatch".to_string()));
+    }
+    Ok(claims)
+}
+
+// ============================================================================
+// Accounts
+// ============================================================================
+
+/// Find the user linked to the identity, linking by verified email or provisioning one
+/// when allowed, then apply the admin and group claims
+pub async fn resolve_user(
+    db: &SqlitePool,
+    provider: &OidcProvider,
+    claims: &Map<String, Value>,
+) -> Result<User, OidcError> {
+    let subject = claim_string(claims, "sub")
+        .ok_or_else(|| OidcError::InvalidIdToken("missing sub".to_string()))?;
+    let email = claim_string(claims, "email");
+    let email_verified = claims.get("email_verified").and_then(Value::as_bool) == Some(true);
+
+    let identity = sqlx::query_as::<_, UserIdentity>(
+        "SELECT * FROM user_identities WHERE provider_id = ? AND subject = ?",
+    )
+    .bind(&provider.id)
+    .bind(&subject)
+    .fetch_optional(db)
+    .await?;
+
+    let user_id = match identity {
+        Some(identity) => identity.user_id,
+        None => {
+            // Only a verified address proves ownership of the local account
+            let linked = match (&email, provider.link_by_email && email_verified) {
+                (Some(email), true) => {
+                    let matches = sqlx::query_as::<_, User>(
+                        "SELECT * FROM users WHERE email = ? COLLATE NOCASE",
+                    )
+                    .bind(email)
+                    .fetch_all(db)
+                    .await?;
+                    if matches.len() > 1 {
+                        tracing::warn!("Several users have the email {}, not linking", email);
+                    }
+                    (matches.len() == 1).then(|| matches[0].id.clone())
+                }
+                _ => None,
+            };
+
+            let user_id = match linked {
+                Some(user_id) => user_id,
+                None if provider.allow_provisioning => {
+                    provision_user(db, provider, claims, email.clone()).await?.id
+                }
+                None => return Err(OidcError::NoAccount),
+            };
+
+            let identity = UserIdentity::new(user_id.clone(), provider.id.clone(), subject.clone(), email.clone());
+            sqlx::query(
+                r#"
+                INSERT INTO user_identities (id, user_id, provider_id, subject, email, created_at)
+                VALUES (?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&identity.id)
+            .bind(&identity.user_id)
+            .bind(&identity.provider_id)
+            .bind(&identity.subject)
+            .bind(&identity.email)
+            .bind(&identity.created_at)
+            .execute(db)
+            .await?;
+            tracing::info!("Linked {} identity {} to user {}", provider.name, subject, user_id);
+            user_id
+        }
+    };
+
+    sqlx::query("UPDATE user_identities SET email = ?, last_login_at = ? WHERE provider_id = ? AND subject = ?")
+        .bind(&email)
+        .bind(Utc::now().to_rfc3339())
+        .bind(&provider.id)
+        .bind(&subject)
+        .execute(db)
+        .await?;
+
+    let values = claim_values(claims, &provider.groups_claim);
+    apply_claims(db, provider, &user_id, &values).await?;
+
+    sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(OidcError::NoAccount)
+}
+
+async fn provision_user(
+    db: &SqlitePool,
+    provider: &OidcProvider,
+    claims: &Map<String, Value>,
+    email: Option<String>,
+) -> Result<User, OidcError> {
+    let username = claim_string(claims, &provider.username_claim)
+        .or_else(|| email.as_deref().and_then(|e| e.split('@').next()).map(str::to_string))
+        .map(|name| sanitize_username(&name))
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| {
+            OidcError::InvalidIdToken(format!("missing {} claim", provider.username_claim))
+        })?;
+
+    let taken: Option<(String,)> = sqlx::query_as("SELECT id FROM users WHERE username = ?")
+        .bind(&username)
+        .fetch_optional(db)
+        .await?;
+    if taken.is_some() {
+        return Err(OidcError::UsernameTaken(username));
+    }
+
+    let mut user = User::new(username, UNUSABLE_PASSWORD_HASH.to_string(), email, false);
+    user.auth_source = AUTH_SOURCE_OIDC.to_string();
+    sqlx::query(
+        r#"
+        INSERT INTO users (id, username, password_hash, email, is_admin, auth_source, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&user.id)
+    .bind(&user.username)
+    .bind(&user.password_hash)
+    .bind(&user.email)
+    .bind(user.is_admin)
+    .bind(&user.auth_source)
+    .bind(&user.created_at)
+    .bind(&user.updated_at)
+    .execute(db)
+    .await?;
+
+    tracing::info!("Provisioned user {} from {}", user.username, provider.name);
+    Ok(user)
+}
+
+/// Set admin status and mapped group memberships from the groups claim values
+async fn apply_claims(
+    db: &SqlitePool,
+    provider: &OidcProvider,
+    user_id: &str,
+    values: &[String],
+) -> Result<(), OidcError> {
+    let admin_values = provider.admin_values();
+    if !admin_values.is_empty() {
+        let is_admin = values.iter().any(|v| admin_values.contains(v));
+        let (was_admin,): (bool,) = sqlx::query_as("SELECT is_admin FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_one(db)
+            .await?;
+        let (admins,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users WHERE is_admin = TRUE")
+            .fetch_one(db)
+            .await?;
+        if was_admin && !is_admin && admins <= 1 {
+            tracing::warn!("Not removing admin status from the last admin {}", user_id);
+        } else if was_admin != is_admin {
+            sqlx::query("UPDATE users SET is_admin = ?, updated_at = ? WHERE id = ?")
+                .bind(is_admin)
+                .bind(Utc::now().to_rfc3339())
+                .bind(user_id)
+                .execute(db)
+                .await?;
+        }
+    }
+
+    // A group is wanted when any of the values mapped to it is present
+    let mut wanted: HashMap<String, bool> = HashMap::new();
+    for (value, group) in provider.group_mapping() {
+        *wanted.entry(group).or_default() |= values.contains(&value);
+    }
+    for (name, member) in wanted {
+        let group = sqlx::query_as::<_, UserGroup>("SELECT * FROM user_groups WHERE name = ?")
+            .bind(&name)
+            .fetch_optional(db)
+            .await?;
+        let Some(group) = group.filter(|g| !g.is_external()) else {
+            tracing::warn!("{} maps a claim to {}, which is not a local group", provider.name, name);
+            continue;
+        };
+
+        if member {
+            let membership = GroupMember::new(user_id.to_string(), group.id);
+            sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO user_group_members (id, user_id, group_id, created_at)
+                VALUES (?, ?, ?, ?)
+                "#,
+            )
+            .bind(&membership.id)
+            .bind(&membership.user_id)
+            .bind(&membership.group_id)
+            .bind(&membership.created_at)
+            .execute(db)
+            .await?;
+        } else {
+            sqlx::query("DELETE FROM user_group_members WHERE user_id = ? AND group_id = ?")
+                .bind(user_id)
+                .bind(&group.id)
+                .execute(db)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Tickets
+// ============================================================================
+
+/// One-time ticket the web interface exchanges for a session after the callback
+pub async fn create_ticket(db: &SqlitePool, user_id: &str) -> Result<String, OidcError> {
+    prune_expired(db).await?;
+    let ticket = random_token();
+    let expires_at = Utc::now() + chrono::Duration::seconds(TICKET_TIMEOUT_SECONDS);
+    sqlx::query("INSERT INTO oidc_login_tickets (ticket_hash, user_id, expires_at) VALUES (?, ?, ?)")
+        .bind(hash_ticket(&ticket))
+        .bind(user_id)
+        .bind(expires_at.to_rfc3339())
+        .execute(db)
+        .await?;
+    Ok(ticket)
+}
+
+/// Redeem a ticket, returns the user it was issued for
+pub async fn redeem_ticket(db: &SqlitePool, ticket: &str) -> Result<User, OidcError> {
+    let hash = hash_ticket(ticket);
+    let row: Option<(String, String)> =
+        sqlx::query_as("SELECT user_id, expires_at FROM oidc_login_tickets WHERE ticket_hash = ?")
+            .bind(&hash)
+            .fetch_optional(db)
+            .await?;
+    sqlx::query("DELETE FROM oidc_login_tickets WHERE ticket_hash = ?")
+        .bind(&hash)
+        .execute(db)
+        .await?;
+
+    let (user_id, _) = row
+        .filter(|(_, expires_at)| *expires_at > Utc::now().to_rfc3339())
+        .ok_or(OidcError::InvalidTicket)?;
+    sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(OidcError::InvalidTicket)
+}
+
+async fn prune_expired(db: &SqlitePool) -> Result<(), sqlx::Error> {
+    let now = Utc::now().to_rfc3339();
+    sqlx::query("DELETE FROM oidc_login_states WHERE expires_at <= ?")
+        .bind(&now)
+        .execute(db)
+        .await?;
+    sqlx::query("DELETE FROM oidc_login_tickets WHERE expires_at <= ?")
+        .bind(&now)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// S256 code challenge of a PKCE verifier (RFC 7636)
+fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+fn hash_ticket(ticket: &str) -> String {
+    hex::encode(Sha256::digest(ticket.as_bytes()))
+}
+
+fn claim_string(claims: &Map<String, Value>, name: &str) -> Option<String> {
+    claims
+        .get(name)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+/// Values of a claim holding a string or an array of strings
+fn claim_values(claims: &Map<String, Value>, name: &str) -> Vec<String> {
+    match claims.get(name) {
+        Some(Value::String(value)) => vec![value.clone()],
+        Some(Value::Array(values)) => values
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Keep the characters usable in a system account name
+fn sanitize_username(name: &str) -> String {
+    name.trim()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{Form, State};
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use std::sync::{Arc, Mutex};
+
+    const SIGNING_KEY: &str = include_str!("oidc_test_key.pem");
+    const SIGNING_KEY_N: &str = "n5grrJR4gVVUbbbwxfSVyibvM2VMfmsZGPN_-_Q9ilSuvCiEFXW4apk6vfrRkgNhTYE8JLx40BUj1kMrevSaAdYx9oX-sXzWGT2YrM8WvwHd21QKnqrNhs0m3bYaiY4cP42RxY12agpAZ_Kdv_haS6WTNgAA9-vb-885dR5wi3Sqt_GdcpV2YmAyzYYWZcgLDoM6XiV5gtunUIQswEwu_HpNNKcspv6sq5tlfyNITomHZfvJniGWK6Uo_bdX3sCMW1GSe_i-unS07juRex4o6CormVO4GUKqiS37Y5r2guvazk7P6DP-inUkNL5k8qSK4Tg_qWi49u25BXSiMooTRQ";
+
+    /// Authorization granted by the mock issuer, waiting for the token request
+    struct Grant {
+        nonce: String,
+        code_challenge: String,
+        claims: Value,
+    }
+
+    #[derive(Default)]
+    struct MockIssuer {
+        issuer: String,
+        grants: HashMap<String, Grant>,
+        /// Returned by the userinfo endpoint, keyed by access token
+        userinfo: HashMap<String, Value>,
+    }
+
+    type Issuer = Arc<Mutex<MockIssuer>>;
+
+    async fn discovery_document(State(mock): State<Issuer>) -> Json<Value> {
+        let issuer = mock.lock().unwrap().issuer.clone();
+        Json(serde_json::json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{}/authorize", issuer),
+            "token_endpoint": format!("{}/token", issuer),
+            "jwks_uri": format!("{}/jwks", issuer),
+            "userinfo_endpoint": format!("{}/userinfo", issuer),
+        }))
+    }
+
+    async fn jwks() -> Json<Value> {
+        Json(serde_json::json!({
+            "keys": [{"kty": "RSA", "kid": "test-key", "use": "sig", "alg": "RS256", "n": SIGNING_KEY_N, "e": "AQAB"}]
+        }))
+    }
+
+    async fn token(
+        State(mock): State<Issuer>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Result<Json<Value>, axum::http::StatusCode> {
+        let mut mock = mock.lock().unwrap();
+        let grant = mock
+            .grants
+            .remove(form.get("code").map(String::as_str).unwrap_or_default())
+            .ok_or(axum::http::StatusCode::BAD_REQUEST)?;
+        let verifier = form.get("code_verifier").cloned().unwrap_or_default();
+        if pkce_challenge(&verifier) != grant.code_challenge {
+            return Err(axum::http::StatusCode::BAD_REQUEST);
+        }
+
+        let mut claims = grant.claims;
+        claims["iss"] = Value::String(mock.issuer.clone());
+        claims["aud"] = Value::String("pinas".to_string());
+        claims["exp"] = Value::from(Utc::now().timestamp() + 300);
+        claims["iat"] = Value::from(Utc::now().timestamp());
+        claims["nonce"] = Value::String(grant.nonce);
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("test-key".to_string());
+        let id_token = encode(
+            &header,
+            &claims,
+            &EncodingKey::from_rsa_pem(SIGNING_KEY.as_bytes()).unwrap(),
+        )
+        .unwrap();
+
+        let access_token = random_token();
+        let mut userinfo = serde_json::json!({"sub": claims["sub"]});
+        if let Some(groups) = claims.get("x-userinfo-groups") {
+            userinfo["groups"] = groups.clone();
+        }
+        mock.userinfo.insert(access_token.clone(), userinfo);
+        Ok(Json(serde_json::json!({
+            "access_token": access_token,
+            "token_type": "Bearer",
+            "id_token": id_token,
+        })))
+    }
+
+    async fn userinfo(State(mock): State<Issuer>, headers: axum::http::HeaderMap) -> Json<Value> {
+        let token = headers
+            .get("authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        Json(mock.lock().unwrap().userinfo.get(token).cloned().unwrap_or_default())
+    }
+
+    async fn mock_issuer() -> Issuer {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mock: Issuer = Arc::new(Mutex::new(MockIssuer {
+            issuer: format!("http://{}", listener.local_addr().unwrap()),
+            ..Default::default()
+        }));
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery_document))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .route("/userinfo", get(userinfo))
+            .with_state(mock.clone());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        mock
+    }
+
+    /// What the provider does between the redirect and the callback, returns (code, state)
+    fn authorize(mock: &Issuer, url: &str, claims: Value) -> (String, String) {
+        let url = reqwest::Url::parse(url).unwrap();
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["code_challenge_method"], "S256");
+        let code = random_token();
+        mock.lock().unwrap().grants.insert(
+            code.clone(),
+            Grant {
+                nonce: params["nonce"].clone(),
+                code_challenge: params["code_challenge"].clone(),
+                claims,
+            },
+        );
+        (code, params["state"].clone())
+    }
+
+    async fn setup_db() -> SqlitePool {
+        let pool = crate::db::test_pool().await;
+        sqlx::raw_sql(
+            r#"
+            INSERT INTO user_groups (id, name, created_at, updated_at) VALUES ('g-media', 'media', '', '');
+            INSERT INTO users (id, username, password_hash, email, is_admin, created_at, updated_at)
+                VALUES ('u-admin', 'admin', 'x', 'admin@example.org', TRUE, '', '');
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool
+    }
+
+    async fn provider(db: &SqlitePool, mock: &Issuer, link_by_email: bool, allow_provisioning: bool) -> OidcProvider {
+        let issuer = mock.lock().unwrap().issuer.clone();
+        let input: ProviderInput = serde_json::from_value(serde_json::json!({
+            "name": "Authelia",
+            "issuer": issuer,
+            "client_id": "pinas",
+            "client_secret": "client-secret",
+            "group_mapping": {"nas-media": "media"},
+            "admin_values": ["nas-admins"],
+            "link_by_email": link_by_email,
+            "allow_provisioning": allow_provisioning,
+        }))
+        .unwrap();
+        create_provider(db, &input).await.unwrap()
+    }
+
+    async fn sign_in(db: &SqlitePool, mock: &Issuer, provider: &OidcProvider, claims: Value) -> Result<User, OidcError> {
+        let url = begin_login(db, provider, "http://nas.local/api/auth/oidc/callback").await?;
+        let (code, state) = authorize(mock, &url, claims);
+        finish_login(db, &provider.id, &code, &state).await
+    }
+
+    #[test]
+    fn test_pkce_challenge() {
+        // RFC 7636, appendix B
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_provisioning_maps_claims() {
+        let db = setup_db().await;
+        let mock = mock_issuer().await;
+        let provider = provider(&db, &mock, false, true).await;
+
+        let claims = serde_json::json!({
+            "sub": "abc-123",
+            "preferred_username": "jdoe",
+            "email": "jdoe@example.org",
+            "x-userinfo-groups": ["nas-admins", "nas-media"],
+        });
+        let user = sign_in(&db, &mock, &provider, claims.clone()).await.unwrap();
+        assert_eq!(user.username, "jdoe");
+        assert_eq!(user.auth_source, AUTH_SOURCE_OIDC);
+        assert!(!user.has_local_password());
+        // Groups only came from the userinfo endpoint
+        assert!(user.is_admin);
+        let (members,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM user_group_members WHERE group_id = 'g-media'")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(members, 1);
+
+        // The next sign-in finds the identity by sub, and follows claim changes
+        let mut claims = claims;
+        claims["x-userinfo-groups"] = serde_json::json!([]);
+        let again = sign_in(&db, &mock, &provider, claims).await.unwrap();
+        assert_eq!(again.id, user.id);
+        assert!(!again.is_admin);
+        let (members,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM user_group_members WHERE group_id = 'g-media'")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(members, 0);
+    }
+
+    #[tokio::test]
+    async fn test_linking_by_verified_email() {
+        let db = setup_db().await;
+        let mock = mock_issuer().await;
+        let provider = provider(&db, &mock, true, false).await;
+
+        let unverified = serde_json::json!({"sub": "s1", "email": "ADMIN@example.org", "email_verified": false});
+        let err = sign_in(&db, &mock, &provider, unverified).await.unwrap_err();
+        assert!(matches!(err, OidcError::NoAccount), "{:?}", err);
+
+        let verified = serde_json::json!({"sub": "s1", "email": "ADMIN@example.org", "email_verified": true});
+        let user = sign_in(&db, &mock, &provider, verified).await.unwrap();
+        assert_eq!(user.id, "u-admin");
+        assert!(user.has_local_password());
+        // The last admin keeps admin status without the admin claim
+        assert!(user.is_admin);
+    }
+
+    #[tokio::test]
+    async fn test_state_is_single_use_and_tickets_expire() {
+        let db = setup_db().await;
+        let mock = mock_issuer().await;
+        let provider = provider(&db, &mock, false, true).await;
+
+        let url = begin_login(&db, &provider, "http://nas.local/callback").await.unwrap();
+        let claims = serde_json::json!({"sub": "s2", "preferred_username": "amy"});
+        let (code, state) = authorize(&mock, &url, claims);
+        assert!(matches!(
+            finish_login(&db, "other-provider", &code, &state).await.unwrap_err(),
+            OidcError::InvalidState
+        ));
+        assert!(matches!(
+            finish_login(&db, &provider.id, &code, &state).await.unwrap_err(),
+            OidcError::InvalidState
+        ));
+
+        let ticket = create_ticket(&db, "u-admin").await.unwrap();
+        assert_eq!(redeem_ticket(&db, &ticket).await.unwrap().id, "u-admin");
+        assert!(matches!(redeem_ticket(&db, &ticket).await.unwrap_err(), OidcError::InvalidTicket));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_tokens_for_another_client() {
+        let db = setup_db().await;
+        let mock = mock_issuer().await;
+        let provider = provider(&db, &mock, false, true).await;
+        sqlx::query("UPDATE oidc_providers SET client_id = 'someone-else'")
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let claims = serde_json::json!({"sub": "s3", "preferred_username": "eve"});
+        let err = sign_in(&db, &mock, &provider, claims).await.unwrap_err();
+        assert!(matches!(err, OidcError::InvalidIdToken(_)), "{:?}", err);
+    }
+}
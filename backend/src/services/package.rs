@@ -1,16 +1,61 @@
 use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use sqlx::SqlitePool;
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
 use uuid::Uuid;
 
-use crate::models::manifest::{InstallStep, PackageManifest};
-use crate::models::package::{InstalledPackage, PackageTask};
+use crate::models::manifest::{Catalog, InstallStep, PackageManifest};
+use crate::models::notification::NotificationLevel;
+use crate::models::package::{
+    InstalledPackage, PackageFile, PackageStatus, PackageTask, PackageTaskLog, PackageUpdate, PreflightReport,
+    UninstallReport, VerifyReport,
+};
 use crate::services::docker::DockerService;
+use crate::services::events::{EventBus, WsEvent};
+use crate::services::notification;
+use crate::services::package_bundle::Bundle;
+use crate::services::package_deps::{self, PlannedPackage};
+use crate::services::package_preflight::{self, HostFacts};
+use crate::services::package_tasks::{is_cancelled, CancelToken, TaskCancelled};
+
+/// Directory of the packages directory holding the copies of packages being updated
+const SNAPSHOTS_DIR: &str = ".snapshots";
+
+/// Suffix of files being downloaded, kept to resume interrupted downloads
+const PART_SUFFIX: &str = ".part";
+
+/// Attempts of a download before giving up
+const DOWNLOAD_ATTEMPTS: u32 = 5;
+
+/// Delay before retrying a download, doubled after each failure
+const DOWNLOAD_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// A download receiving nothing for this long is retried
+const DOWNLOAD_STALL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Minimum interval between two progress updates of a download
+const DOWNLOAD_PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Kinds of paths recorded in `package_files`
+const FILE_BINARY: &str = "binary";
+const FILE_CONFIG: &str = "config";
+const FILE_DATA: &str = "data";
+const FILE_SYMLINK: &str = "symlink";
+const FILE_DIRECTORY: &str = "directory";
+
+/// Longest line of command output kept in a task log
+const MAX_LOG_LINE: usize = 4096;
+
+/// Directory holding installed packages, downloads and package state
+pub fn data_dir() -> String {
+    std::env::var("PINAS_DATA_DIR").unwrap_or_else(|_| "/storage/.pinas".to_string())
+}
 
 /// Package service handles installation, updates, and removal of packages
 pub struct PackageService {
@@ -22,12 +67,17 @@ pub struct PackageService {
     bin_dir: String,
     docker_service: DockerService,
     dev_mode: bool,
+    /// Bus receiving task progress, if any
+    events: Option<EventBus>,
+    /// Sideloaded bundle providing downloads and images, if any
+    bundle: Option<Bundle>,
+    /// Signal interrupting the task being run
+    cancel: CancelToken,
 }
 
 impl PackageService {
     pub async fn new(db: SqlitePool) -> Self {
-        let data_dir = std::env::var("PINAS_DATA_DIR")
-            .unwrap_or_else(|_| "/storage/.pinas".to_string());
+        let data_dir = data_dir();
 
         let dev_mode = std::env::var("PINAS_DEV_MODE")
             .map(|v| v.to_lowercase() == "true" || v == "1")
@@ -50,6 +100,132 @@ impl PackageService {
                 .unwrap_or_else(|_| format!("{}/bin", data_dir)),
             docker_service: DockerService::new().await,
             dev_mode,
+            events: None,
+            bundle: None,
+            cancel: CancelToken::never(),
+        }
+    }
+
+    /// Publish task progress to the event bus
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Resolve downloads and images against an unpacked bundle instead of the network
+    pub fn with_bundle(mut self, bundle: Bundle) -> Self {
+        self.bundle = Some(bundle);
+        self
+    }
+
+    /// Stop the task being run at the next step once the token is cancelled
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Take back the bundle, to remove it once installed
+    pub fn take_bundle(&mut self) -> Option<Bundle> {
+        self.bundle.take()
+    }
+
+    /// Directory uploaded bundles are unpacked in
+    pub fn sideload_dir(&self) -> PathBuf {
+        Path::new(&self.data_dir).join("sideload")
+    }
+
+    /// Send the current state of a task to the event bus
+    async fn publish_task(&self, task_id: &str) {
+        let Some(events) = &self.events else {
+            return;
+        };
+        match self.get_task(task_id).await {
+            Ok(Some(task)) => events.publish(WsEvent::PackageTask(task)),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to load task {} for publishing: {}", task_id, e),
+        }
+    }
+
+    /// Record a new running task and publish it
+    async fn create_task(
+        &self,
+        package_id: &str,
+        task_type: &str,
+        total_steps: usize,
+        parent_task_id: Option<&str>,
+    ) -> Result<String> {
+        let task_id = Uuid::new_v4().to_string();
+        let now = chrono::Utc::now().to_rfc3339();
+        sqlx::query(
+            r#"INSERT INTO package_tasks (id, package_id, task_type, status, progress, total_steps, created_at, started_at, parent_task_id)
+               VALUES (?, ?, ?, 'running', 0, ?, ?, ?, ?)"#
+        )
+        .bind(&task_id)
+        .bind(package_id)
+        .bind(task_type)
+        .bind(total_steps as i32)
+        .bind(&now)
+        .bind(&now)
+        .bind(parent_task_id)
+        .execute(&self.db)
+        .await?;
+        self.publish_task(&task_id).await;
+
+        Ok(task_id)
+    }
+
+    /// Move a task to its next step and publish it
+    async fn set_task_step(&self, task_id: &str, progress: usize, current_step: &str) -> Result<()> {
+        sqlx::query("UPDATE package_tasks SET progress = ?, current_step = ?, bytes_done = NULL, bytes_total = NULL WHERE id = ?")
+            .bind(progress as i32)
+            .bind(current_step)
+            .bind(task_id)
+            .execute(&self.db)
+            .await?;
+        self.publish_task(task_id).await;
+        Ok(())
+    }
+
+    /// Mark a task as completed and publish it
+    async fn complete_task(&self, task_id: &str) -> Result<()> {
+        sqlx::query("UPDATE package_tasks SET status = 'completed', progress = total_steps, completed_at = ? WHERE id = ?")
+            .bind(chrono::Utc::now().to_rfc3339())
+            .bind(task_id)
+            .execute(&self.db)
+            .await?;
+        self.publish_task(task_id).await;
+        Ok(())
+    }
+
+    /// Append a line to a task's log and publish it
+    async fn log_task(&self, task_id: &str, step: Option<usize>, stream: &str, line: &str) {
+        let mut line = line.trim_end().to_string();
+        if line.len() > MAX_LOG_LINE {
+            let mut end = MAX_LOG_LINE;
+            while !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            line.truncate(end);
+        }
+
+        let result = sqlx::query_as::<_, PackageTaskLog>(
+            r#"INSERT INTO package_task_logs (task_id, step, stream, line, created_at) VALUES (?, ?, ?, ?, ?)
+               RETURNING id, task_id, step, stream, line, created_at"#
+        )
+        .bind(task_id)
+        .bind(step.map(|s| s as i32))
+        .bind(stream)
+        .bind(&line)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .fetch_one(&self.db)
+        .await;
+        match result {
+            Ok(log) => {
+                if let Some(events) = &self.events {
+                    events.publish(WsEvent::PackageTaskLog(log));
+                }
+            }
+            Err(e) => tracing::warn!("Failed to log output of task {}: {}", task_id, e),
         }
     }
 
@@ -122,8 +298,9 @@ impl PackageService {
             InstallStep::Delete { path } => InstallStep::Delete {
                 path: self.substitute_vars(path),
             },
-            InstallStep::DockerPull { image } => InstallStep::DockerPull {
+            InstallStep::DockerPull { image, sha256 } => InstallStep::DockerPull {
                 image: self.substitute_vars(image),
+                sha256: sha256.clone(),
             },
             InstallStep::DockerCreate { config } => {
                 let mut new_config = config.clone();
@@ -195,36 +372,115 @@ impl PackageService {
         Ok(count > 0)
     }
 
+    /// Check that the dependencies of a manifest are installed in a matching version
+    async fn check_dependencies(&self, manifest: &PackageManifest) -> Result<()> {
+        for dep in package_deps::dependencies(manifest)? {
+            let version = self.get_installed(&dep.id).await?
+                .filter(|p| p.status == PackageStatus::Installed.to_string())
+                .map(|p| p.version);
+            match version {
+                None => return Err(anyhow!("Missing dependency: {}", dep.id)),
+                Some(version) if !dep.accepts(&version) => {
+                    return Err(anyhow!("Dependency {} {} does not match the required version", dep.id, version));
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the requirements of a manifest against this device
+    pub async fn preflight(&self, manifest: &PackageManifest) -> PreflightReport {
+        let facts = HostFacts::gather(Path::new(&self.packages_dir), &self.docker_service).await;
+        package_preflight::evaluate(manifest, &facts)
+    }
+
+    /// Versions of installed packages, by package ID
+    pub async fn installed_versions(&self) -> Result<HashMap<String, String>> {
+        Ok(self.list_installed().await?
+            .into_iter()
+            .filter(|p| p.status == PackageStatus::Installed.to_string())
+            .map(|p| (p.id, p.version))
+            .collect())
+    }
+
     /// Install a package from manifest
     pub async fn install(&self, manifest: &PackageManifest, manifest_url: Option<&str>) -> Result<String> {
+        let plan = [PlannedPackage {
+            manifest: manifest.clone(),
+            manifest_url: manifest_url.map(str::to_string),
+        }];
+        self.install_plan(&plan).await
+    }
+
+    /// Install a resolved plan, dependencies first, as child tasks of the last package's task
+    pub async fn install_plan(&self, plan: &[PlannedPackage]) -> Result<String> {
+        let task_id = self.prepare_install(plan).await?;
+        self.run_install(&task_id, plan).await?;
+        Ok(task_id)
+    }
+
+    /// Check a plan can be installed and create its task, to be run by `run_install`
+    pub async fn prepare_install(&self, plan: &[PlannedPackage]) -> Result<String> {
+        let Some((root, dependencies)) = plan.split_last() else {
+            return Err(anyhow!("Nothing to install"));
+        };
+        if dependencies.is_empty() {
+            self.check_installable(&root.manifest).await?;
+            self.create_task(&root.manifest.id, "install", root.manifest.install.steps.len(), None).await
+        } else {
+            self.create_task(&root.manifest.id, "install", plan.len(), None).await
+        }
+    }
+
+    /// Run the task of a prepared plan. A single package is installed by the task itself,
+    /// a plan with dependencies gets a child task per package.
+    pub async fn run_install(&self, task_id: &str, plan: &[PlannedPackage]) -> Result<()> {
+        if let [package] = plan {
+            return self.install_package(&package.manifest, package.manifest_url.as_deref(), task_id).await;
+        }
+
+        for (i, package) in plan.iter().enumerate() {
+            // Dependencies installed before a failure stay installed, they are complete packages
+            let result = async {
+                self.cancel.check()?;
+                self.set_task_step(task_id, i, &format!("Installing {} {}", package.manifest.id, package.manifest.version)).await?;
+                self.check_installable(&package.manifest).await?;
+                let child_id = self.create_task(&package.manifest.id, "install", package.manifest.install.steps.len(), Some(task_id)).await?;
+                self.install_package(&package.manifest, package.manifest_url.as_deref(), &child_id).await
+            }
+            .await
+            .with_context(|| format!("Failed to install {}", package.manifest.id));
+            if let Err(e) = result {
+                self.fail_task(task_id, &e).await?;
+                return Err(e);
+            }
+        }
+
+        self.complete_task(task_id).await
+    }
+
+    /// Check a package is not installed yet and its dependencies and requirements are met
+    async fn check_installable(&self, manifest: &PackageManifest) -> Result<()> {
         // Check if already installed
         if self.is_installed(&manifest.id).await? {
             return Err(anyhow!("Package {} is already installed", manifest.id));
         }
 
-        // Check dependencies
-        for dep in &manifest.requirements.dependencies {
-            if !self.is_installed(dep).await? {
-                return Err(anyhow!("Missing dependency: {}", dep));
+        self.check_dependencies(manifest).await?;
+
+        // Requirements are not enforced in dev mode, nothing gets installed
+        if !self.dev_mode {
+            let report = self.preflight(manifest).await;
+            if !report.passed {
+                return Err(anyhow!("Requirements of {} not met: {}", manifest.id, report.failures().join("; ")));
             }
         }
+        Ok(())
+    }
 
-        // Create task for progress tracking
-        let task_id = Uuid::new_v4().to_string();
+    async fn install_package(&self, manifest: &PackageManifest, manifest_url: Option<&str>, task_id: &str) -> Result<()> {
         let now = chrono::Utc::now().to_rfc3339();
-        let total_steps = manifest.install.steps.len() as i32;
-
-        sqlx::query(
-            r#"INSERT INTO package_tasks (id, package_id, task_type, status, progress, total_steps, created_at, started_at)
-               VALUES (?, ?, 'install', 'running', 0, ?, ?, ?)"#
-        )
-        .bind(&task_id)
-        .bind(&manifest.id)
-        .bind(total_steps)
-        .bind(&now)
-        .bind(&now)
-        .execute(&self.db)
-        .await?;
 
         // Prepare frontend config
         let frontend_config_json = manifest.frontend.as_ref()
@@ -254,9 +510,9 @@ impl PackageService {
         // Execute installation steps (skip in dev mode)
         let result = if self.dev_mode {
             tracing::info!("Dev mode: skipping installation steps for {}", manifest.id);
-            Ok(())
+            self.cancel.check().map_err(Into::into)
         } else {
-            self.execute_install_steps(manifest, &task_id).await
+            self.execute_install_steps(manifest, &manifest.install.steps, task_id).await
         };
 
         // Update status based on result
@@ -269,12 +525,6 @@ impl PackageService {
                     .execute(&self.db)
                     .await?;
 
-                sqlx::query("UPDATE package_tasks SET status = 'completed', completed_at = ? WHERE id = ?")
-                    .bind(&now)
-                    .bind(&task_id)
-                    .execute(&self.db)
-                    .await?;
-
                 // Store translations if frontend config has i18n
                 if let Some(frontend) = &manifest.frontend {
                     for (locale, translations) in &frontend.i18n {
@@ -292,6 +542,12 @@ impl PackageService {
                         .await?;
                     }
                 }
+
+                self.complete_task(task_id).await?;
+            }
+            Err(ref e) if is_cancelled(e) => {
+                self.discard_install(manifest, task_id).await;
+                self.fail_task(task_id, e).await?;
             }
             Err(ref e) => {
                 let now = chrono::Utc::now().to_rfc3339();
@@ -303,53 +559,132 @@ impl PackageService {
                     .execute(&self.db)
                     .await?;
 
-                sqlx::query("UPDATE package_tasks SET status = 'failed', error_message = ?, completed_at = ? WHERE id = ?")
-                    .bind(&error_msg)
-                    .bind(&now)
-                    .bind(&task_id)
-                    .execute(&self.db)
-                    .await?;
+                self.fail_task(task_id, e).await?;
             }
         }
 
-        result?;
-        Ok(task_id)
+        result
+    }
+
+    /// Undo the steps a cancelled install has run, up to the interrupted one, and forget the package
+    async fn discard_install(&self, manifest: &PackageManifest, task_id: &str) {
+        let reached = match self.get_task(task_id).await {
+            Ok(Some(task)) => task.progress as usize,
+            _ => manifest.install.steps.len(),
+        };
+        self.log_task(task_id, None, "info", "Removing what the install left behind").await;
+
+        // Files the steps created are tracked, files they overwrote are left alone
+        if let Err(e) = self.remove_tracked_files(&manifest.id, false).await {
+            tracing::warn!("Failed to remove the files of cancelled install of {}: {}", manifest.id, e);
+        }
+        for step in manifest.install.steps.iter().take(reached + 1).rev() {
+            let result: Result<()> = match self.substitute_step(step) {
+                InstallStep::Download { dest, .. } => {
+                    fs::remove_file(format!("{}{}", dest, PART_SUFFIX)).await.or_else(|e| match e.kind() {
+                        std::io::ErrorKind::NotFound => Ok(()),
+                        _ => Err(e.into()),
+                    })
+                }
+                InstallStep::DockerCreate { config } => self.docker_service.remove_container(&config.name, true).await,
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                tracing::warn!("Failed to undo step of cancelled install of {}: {}", manifest.id, e);
+            }
+        }
+
+        if let Err(e) = remove_if_exists(&self.package_dir(&manifest.id)).await {
+            tracing::warn!("Failed to remove the directory of {}: {}", manifest.id, e);
+        }
+        for table in ["package_files", "app_translations"] {
+            let result = sqlx::query(&format!("DELETE FROM {} WHERE package_id = ?", table))
+                .bind(&manifest.id)
+                .execute(&self.db)
+                .await;
+            if let Err(e) = result {
+                tracing::warn!("Failed to clear {} of {}: {}", table, manifest.id, e);
+            }
+        }
+        if let Err(e) = sqlx::query("DELETE FROM installed_packages WHERE id = ?").bind(&manifest.id).execute(&self.db).await {
+            tracing::warn!("Failed to remove the record of {}: {}", manifest.id, e);
+        }
+    }
+
+    /// Execute installation steps, stopping at the next step or download chunk once cancelled
+    async fn execute_install_steps(&self, manifest: &PackageManifest, steps: &[InstallStep], task_id: &str) -> Result<()> {
+        let selected: Vec<usize> = (0..steps.len()).collect();
+        self.execute_selected_steps(manifest, steps, &selected, task_id).await
     }
 
-    /// Execute installation steps
-    async fn execute_install_steps(&self, manifest: &PackageManifest, task_id: &str) -> Result<()> {
-        for (i, step) in manifest.install.steps.iter().enumerate() {
+    /// Execute some of the installation steps in the given order, the task progressing by one per step
+    async fn execute_selected_steps(&self, manifest: &PackageManifest, steps: &[InstallStep], selected: &[usize], task_id: &str) -> Result<()> {
+        for (n, &i) in selected.iter().enumerate() {
+            self.cancel.check()?;
+
             // Apply variable substitution
-            let substituted_step = self.substitute_step(step);
+            let substituted_step = self.substitute_step(&steps[i]);
             let step_desc = format!("{:?}", substituted_step);
-            tracing::info!("Executing step {}/{}: {}", i + 1, manifest.install.steps.len(), step_desc);
+            tracing::info!("Executing step {}/{}: {}", i + 1, steps.len(), step_desc);
 
             // Update progress
-            sqlx::query("UPDATE package_tasks SET progress = ?, current_step = ? WHERE id = ?")
-                .bind(i as i32)
-                .bind(&step_desc)
-                .bind(task_id)
-                .execute(&self.db)
-                .await?;
-
-            self.execute_step(&substituted_step, manifest).await
-                .with_context(|| format!("Failed at step {}: {:?}", i + 1, substituted_step))?;
+            self.set_task_step(task_id, n, &step_desc).await?;
+
+            let context = StepContext { task_id, step: i, track: true };
+            let result = tokio::select! {
+                result = self.execute_step(&substituted_step, manifest, Some(context)) => result,
+                _ = self.cancel.cancelled() => Err(TaskCancelled.into()),
+            };
+            if let Err(e) = result {
+                if is_cancelled(&e) {
+                    // A cancelled download is not resumed, its part file goes
+                    if let InstallStep::Download { dest, .. } = &substituted_step {
+                        let _ = fs::remove_file(format!("{}{}", dest, PART_SUFFIX)).await;
+                    }
+                    self.log_task(task_id, Some(i), "info", &format!("Cancelled at step {}", i + 1)).await;
+                    return Err(e);
+                }
+                self.log_task(task_id, Some(i), "info", &format!("Step {} failed: {:#}", i + 1, e)).await;
+                return Err(e.context(format!("Failed at step {}: {:?}", i + 1, substituted_step)));
+            }
         }
 
         Ok(())
     }
 
-    /// Execute a single installation step
-    async fn execute_step(&self, step: &InstallStep, manifest: &PackageManifest) -> Result<()> {
+    /// Execute a single installation step, its download progress and output going to the task if any.
+    /// Files and directories it creates are recorded for the package when the context tracks them.
+    async fn execute_step(&self, step: &InstallStep, manifest: &PackageManifest, context: Option<StepContext<'_>>) -> Result<()> {
+        let task_id = context.map(|c| c.task_id);
+        let track = context.filter(|c| c.track).map(|c| c.step);
+        let mut created: Vec<(PathBuf, &str)> = Vec::new();
+        // Files the step overwrites rather than creates
+        let mut preexisting: HashSet<PathBuf> = HashSet::new();
+        if let Some(dest) = step_output(step).filter(|_| !matches!(step, InstallStep::Extract { .. } | InstallStep::Mkdir { .. })) {
+            if fs::symlink_metadata(dest).await.is_ok() {
+                preexisting.insert(PathBuf::from(dest));
+            }
+        }
         match step {
             InstallStep::Download { url, sha256, dest } => {
-                self.download_file(url, dest, sha256.as_deref()).await?;
+                created.extend(missing_dirs(Path::new(dest).parent()).into_iter().map(|d| (d, FILE_DIRECTORY)));
+                match self.bundle.as_ref().and_then(|b| b.artifact(url)) {
+                    Some(artifact) => self.copy_artifact(&artifact, dest, sha256.as_deref()).await?,
+                    None if Bundle::is_bundle_url(url) => {
+                        return Err(anyhow!("{} is not in the package bundle", url));
+                    }
+                    None => self.download_file(url, dest, sha256.as_deref(), task_id).await?,
+                }
+                created.push((PathBuf::from(dest), FILE_BINARY));
             }
             InstallStep::Extract { src, dest } => {
-                self.extract_archive(src, dest).await?;
+                let (extracted, overwritten) = self.extract_archive(src, dest).await?;
+                created.extend(extracted);
+                preexisting.extend(overwritten);
             }
             InstallStep::Copy { src, dest } => {
                 fs::copy(src, dest).await?;
+                created.push((PathBuf::from(dest), FILE_BINARY));
             }
             InstallStep::Symlink { src, dest } => {
                 // Remove existing symlink if present
@@ -357,6 +692,7 @@ impl PackageService {
                     fs::remove_file(dest).await?;
                 }
                 tokio::fs::symlink(src, dest).await?;
+                created.push((PathBuf::from(dest), FILE_SYMLINK));
             }
             InstallStep::Chmod { path, mode } => {
                 let mode_val = u32::from_str_radix(mode, 8)?;
@@ -365,16 +701,19 @@ impl PackageService {
                 fs::set_permissions(path, perms).await?;
             }
             InstallStep::Mkdir { path } => {
+                created.extend(missing_dirs(Some(Path::new(path))).into_iter().map(|d| (d, FILE_DIRECTORY)));
                 fs::create_dir_all(path).await?;
             }
             InstallStep::Template { src, dest } => {
                 if let Some(content) = manifest.files.get(src) {
                     let decoded = base64_decode(content)?;
                     let parent = Path::new(dest).parent();
+                    created.extend(missing_dirs(parent).into_iter().map(|d| (d, FILE_DIRECTORY)));
                     if let Some(p) = parent {
                         fs::create_dir_all(p).await?;
                     }
                     fs::write(dest, decoded).await?;
+                    created.push((PathBuf::from(dest), FILE_CONFIG));
                 } else {
                     return Err(anyhow!("Template file not found in manifest: {}", src));
                 }
@@ -382,20 +721,18 @@ impl PackageService {
             InstallStep::WriteFile { dest, content } => {
                 let decoded = base64_decode(content)?;
                 let parent = Path::new(dest).parent();
+                created.extend(missing_dirs(parent).into_iter().map(|d| (d, FILE_DIRECTORY)));
                 if let Some(p) = parent {
                     fs::create_dir_all(p).await?;
                 }
                 fs::write(dest, decoded).await?;
+                created.push((PathBuf::from(dest), FILE_CONFIG));
             }
             InstallStep::Exec { command, ignore_error } => {
-                let status = Command::new("sh")
-                    .arg("-c")
-                    .arg(command)
-                    .status()
-                    .await?;
+                let status = self.run_command(command, context).await?;
 
                 if !status.success() && !ignore_error {
-                    return Err(anyhow!("Command failed: {}", command));
+                    return Err(anyhow!("Command failed ({}): {}", status, command));
                 }
             }
             InstallStep::Delete { path } => {
@@ -406,11 +743,36 @@ impl PackageService {
                         fs::remove_file(path).await?;
                     }
                 }
+                // What an earlier step created and this one deleted is not part of the package
+                if track.is_some() {
+                    self.untrack_path(&manifest.id, path).await?;
+                }
             }
             // Docker steps
-            InstallStep::DockerPull { image } => {
-                tracing::info!("Pulling Docker image: {}", image);
-                self.docker_service.pull_image(image).await?;
+            InstallStep::DockerPull { image, sha256 } => {
+                match self.bundle.as_ref().and_then(|b| b.image(image).map(|tarball| (b, tarball))) {
+                    Some((bundle, tarball)) => {
+                        // The tarball names its own tags, only the signed manifest vouches for its content
+                        match sha256 {
+                            Some(expected) => check_sha256(tarball, expected).await?,
+                            None if bundle.allow_unsigned => {
+                                tracing::warn!("Loading bundled image {} without a checksum", image);
+                            }
+                            None => {
+                                return Err(anyhow!(
+                                    "Bundled image {} has no sha256 in the manifest and unsigned packages are not allowed",
+                                    image
+                                ));
+                            }
+                        }
+                        tracing::info!("Loading Docker image {} from bundle", image);
+                        self.docker_service.load_image(tarball).await?;
+                    }
+                    None => {
+                        tracing::info!("Pulling Docker image: {}", image);
+                        self.docker_service.pull_image(image).await?;
+                    }
+                }
             }
             InstallStep::DockerCreate { config } => {
                 tracing::info!("Creating Docker container: {}", config.name);
@@ -434,11 +796,62 @@ impl PackageService {
             }
         }
 
+        if let Some(step) = track {
+            for (path, file_type) in created {
+                // A file the package did not create is not its own to remove, unless an earlier install created it
+                if preexisting.contains(&path) && !self.is_tracked(&manifest.id, &path).await? {
+                    continue;
+                }
+                self.track_path(manifest, &path, file_type, step).await?;
+            }
+        }
         Ok(())
     }
 
-    /// Download a file with optional SHA256 verification
-    async fn download_file(&self, url: &str, dest: &str, sha256: Option<&str>) -> Result<()> {
+    /// Run a shell command, its output going to the task log line by line
+    async fn run_command(&self, command: &str, context: Option<StepContext<'_>>) -> Result<std::process::ExitStatus> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true)
+            .spawn()?;
+        // Processes the command starts are killed along with it when the step is interrupted
+        let group = child.id().map(ProcessGroup::new);
+
+        let mut stdout = BufReader::new(child.stdout.take().context("No stdout")?).split(b'\n');
+        let mut stderr = BufReader::new(child.stderr.take().context("No stderr")?).split(b'\n');
+        let (mut stdout_open, mut stderr_open) = (true, true);
+        while stdout_open || stderr_open {
+            let (stream, line) = tokio::select! {
+                line = stdout.next_segment(), if stdout_open => ("stdout", line?),
+                line = stderr.next_segment(), if stderr_open => ("stderr", line?),
+            };
+            match line {
+                Some(line) => {
+                    let line = String::from_utf8_lossy(&line);
+                    match context {
+                        Some(context) => self.log_task(context.task_id, Some(context.step), stream, &line).await,
+                        None => tracing::debug!("{}: {}", stream, line.trim_end()),
+                    }
+                }
+                None if stream == "stdout" => stdout_open = false,
+                None => stderr_open = false,
+            }
+        }
+
+        let status = child.wait().await?;
+        if let Some(group) = group {
+            group.release();
+        }
+        Ok(status)
+    }
+
+    /// Download a file, streaming it to `<dest>.part` and resuming that part file after interruptions
+    async fn download_file(&self, url: &str, dest: &str, sha256: Option<&str>, task_id: Option<&str>) -> Result<()> {
         tracing::info!("Downloading {} to {}", url, dest);
 
         // Ensure parent directory exists
@@ -446,49 +859,205 @@ impl PackageService {
             fs::create_dir_all(parent).await?;
         }
 
-        // Download file
-        let response = reqwest::get(url).await?;
-        if !response.status().is_success() {
-            return Err(anyhow!("Download failed: HTTP {}", response.status()));
-        }
+        let part = PathBuf::from(format!("{}{}", dest, PART_SUFFIX));
+        let client = reqwest::Client::builder()
+            .connect_timeout(DOWNLOAD_STALL_TIMEOUT)
+            .build()?;
+
+        let mut delay = DOWNLOAD_RETRY_DELAY;
+        let mut attempt = 1;
+        let size = loop {
+            match self.download_attempt(&client, url, &part, sha256, task_id).await {
+                Ok(size) => break size,
+                Err(DownloadFailure::Fatal(e)) => return Err(e),
+                Err(DownloadFailure::Retry(e)) if attempt < DOWNLOAD_ATTEMPTS => {
+                    tracing::warn!("Download of {} failed (attempt {}/{}), retrying in {:?}: {:#}", url, attempt, DOWNLOAD_ATTEMPTS, delay, e);
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(DownloadFailure::Retry(e)) => {
+                    return Err(e.context(format!("Download failed after {} attempts", attempt)));
+                }
+            }
+        };
 
-        let bytes = response.bytes().await?;
+        fs::rename(&part, dest).await?;
+        tracing::info!("Downloaded {} bytes to {}", size, dest);
+        Ok(())
+    }
 
-        // Verify SHA256 if provided
-        if let Some(expected_hash) = sha256 {
-            let mut hasher = Sha256::new();
-            hasher.update(&bytes);
-            let actual_hash = hex::encode(hasher.finalize());
+    /// One request of a download, continuing the part file when the server supports ranges.
+    /// Returns the size of the complete file.
+    async fn download_attempt(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        part: &Path,
+        sha256: Option<&str>,
+        task_id: Option<&str>,
+    ) -> std::result::Result<u64, DownloadFailure> {
+        use reqwest::header::{CONTENT_RANGE, RANGE};
+        use reqwest::StatusCode;
+
+        let retry = |e: anyhow::Error| DownloadFailure::Retry(e);
+        let offset = fs::metadata(part).await.map(|m| m.len()).unwrap_or(0);
+
+        let mut request = client.get(url);
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        }
+        let mut response = request.send().await.map_err(|e| retry(e.into()))?;
+
+        let status = response.status();
+        let content_range = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .map(parse_content_range);
+        let resumed = match status {
+            StatusCode::PARTIAL_CONTENT if offset > 0 && content_range.is_some_and(|(start, _)| start == Some(offset)) => true,
+            StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+                // The part file is complete when it is exactly as long as the file
+                if content_range.is_some_and(|(_, total)| total == Some(offset)) {
+                    self.verify_download(part, sha256, None, true).await?;
+                    self.record_download_progress(task_id, offset, Some(offset)).await;
+                    return Ok(offset);
+                }
+                let _ = fs::remove_file(part).await;
+                return Err(retry(anyhow!("Partial download is longer than the file, restarting")));
+            }
+            StatusCode::PARTIAL_CONTENT => {
+                let _ = fs::remove_file(part).await;
+                return Err(retry(anyhow!("Unexpected range in response, restarting")));
+            }
+            s if s.is_success() => false,
+            s if s.is_server_error() || s == StatusCode::REQUEST_TIMEOUT || s == StatusCode::TOO_MANY_REQUESTS => {
+                return Err(retry(anyhow!("Download failed: HTTP {}", s)));
+            }
+            s => return Err(DownloadFailure::Fatal(anyhow!("Download failed: HTTP {}", s))),
+        };
 
-            if actual_hash != expected_hash.to_lowercase() {
-                return Err(anyhow!(
-                    "SHA256 mismatch: expected {}, got {}",
-                    expected_hash,
-                    actual_hash
-                ));
+        let (mut file, mut hasher, mut done) = if resumed {
+            tracing::info!("Resuming download of {} at byte {}", url, offset);
+            let hasher = hash_file(part).await.map_err(retry)?;
+            let file = fs::OpenOptions::new().append(true).open(part).await.map_err(|e| retry(e.into()))?;
+            (file, hasher, offset)
+        } else {
+            (fs::File::create(part).await.map_err(|e| retry(e.into()))?, Sha256::new(), 0)
+        };
+        let total = match content_range.and_then(|(_, total)| total) {
+            Some(total) if resumed => Some(total),
+            _ => response.content_length().map(|length| done + length),
+        };
+        self.record_download_progress(task_id, done, total).await;
+
+        let mut last_progress = std::time::Instant::now();
+        let streamed = async {
+            loop {
+                let chunk = tokio::time::timeout(DOWNLOAD_STALL_TIMEOUT, response.chunk())
+                    .await
+                    .map_err(|_| anyhow!("Download stalled"))??;
+                let Some(chunk) = chunk else {
+                    return Ok::<_, anyhow::Error>(());
+                };
+                hasher.update(&chunk);
+                file.write_all(&chunk).await?;
+                done += chunk.len() as u64;
+                if last_progress.elapsed() >= DOWNLOAD_PROGRESS_INTERVAL {
+                    self.record_download_progress(task_id, done, total).await;
+                    last_progress = std::time::Instant::now();
+                }
             }
+        }
+        .await;
+        // Keep what was received for the next attempt
+        let flushed = file.flush().await;
+        streamed.map_err(retry)?;
+        flushed.map_err(|e| retry(e.into()))?;
+        self.record_download_progress(task_id, done, total).await;
+
+        if let Some(total) = total {
+            if done != total {
+                return Err(retry(anyhow!("Connection closed after {} of {} bytes", done, total)));
+            }
+        }
+        self.verify_download(part, sha256, Some(hasher), resumed).await?;
+        Ok(done)
+    }
+
+    /// Check the SHA256 of a complete part file, removing it when it does not match.
+    /// A resumed part may come from another file, it is downloaded again from scratch.
+    async fn verify_download(
+        &self,
+        part: &Path,
+        sha256: Option<&str>,
+        hasher: Option<Sha256>,
+        resumed: bool,
+    ) -> std::result::Result<(), DownloadFailure> {
+        let Some(expected_hash) = sha256 else {
+            return Ok(());
+        };
+        let hasher = match hasher {
+            Some(hasher) => hasher,
+            None => hash_file(part).await.map_err(DownloadFailure::Retry)?,
+        };
+        let actual_hash = hex::encode(hasher.finalize());
+        if actual_hash == expected_hash.to_lowercase() {
             tracing::info!("SHA256 verified: {}", actual_hash);
+            return Ok(());
+        }
+
+        let _ = fs::remove_file(part).await;
+        let mismatch = anyhow!("SHA256 mismatch: expected {}, got {}", expected_hash, actual_hash);
+        Err(if resumed { DownloadFailure::Retry(mismatch) } else { DownloadFailure::Fatal(mismatch) })
+    }
+
+    /// Store and publish the byte progress of a download
+    async fn record_download_progress(&self, task_id: Option<&str>, done: u64, total: Option<u64>) {
+        let Some(task_id) = task_id else {
+            return;
+        };
+        let result = sqlx::query("UPDATE package_tasks SET bytes_done = ?, bytes_total = ? WHERE id = ?")
+            .bind(done as i64)
+            .bind(total.map(|t| t as i64))
+            .bind(task_id)
+            .execute(&self.db)
+            .await;
+        match result {
+            Ok(_) => self.publish_task(task_id).await,
+            Err(e) => tracing::warn!("Failed to record download progress of task {}: {}", task_id, e),
         }
+    }
+
+    /// Copy a bundled file where a download would have been written
+    async fn copy_artifact(&self, src: &Path, dest: &str, sha256: Option<&str>) -> Result<()> {
+        tracing::info!("Copying bundled {} to {}", src.display(), dest);
 
-        // Write to file
-        let mut file = fs::File::create(dest).await?;
-        file.write_all(&bytes).await?;
+        if let Some(parent) = Path::new(dest).parent() {
+            fs::create_dir_all(parent).await?;
+        }
 
-        tracing::info!("Downloaded {} bytes to {}", bytes.len(), dest);
+        if let Some(expected_hash) = sha256 {
+            check_sha256(src, expected_hash).await?;
+        }
+        fs::copy(src, dest).await?;
         Ok(())
     }
 
-    /// Extract a tar.gz archive
-    async fn extract_archive(&self, src: &str, dest: &str) -> Result<()> {
+    /// Extract a gzipped tarball, returning the files it wrote and the directories it created,
+    /// along with the files that were there before and got overwritten
+    async fn extract_archive(&self, src: &str, dest: &str) -> Result<(Vec<(PathBuf, &'static str)>, Vec<PathBuf>)> {
         tracing::info!("Extracting {} to {}", src, dest);
 
+        let mut created: Vec<_> = missing_dirs(Some(Path::new(dest))).into_iter().map(|d| (d, FILE_DIRECTORY)).collect();
         fs::create_dir_all(dest).await?;
 
         let src_path = src.to_string();
-        let dest_path = dest.to_string();
+        let dest_path = PathBuf::from(dest);
 
         // Run extraction in blocking task
-        tokio::task::spawn_blocking(move || {
+        let extracted = tokio::task::spawn_blocking(move || {
             use flate2::read::GzDecoder;
             use std::fs::File;
             use tar::Archive;
@@ -496,95 +1065,1176 @@ impl PackageService {
             let file = File::open(&src_path)?;
             let decoder = GzDecoder::new(file);
             let mut archive = Archive::new(decoder);
-            archive.unpack(&dest_path)?;
+            let mut extracted = Vec::new();
+            let mut overwritten = Vec::new();
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let target = dest_path.join(entry.path()?);
+                let is_dir = entry.header().entry_type().is_dir();
+                let existed = !is_dir && target.symlink_metadata().is_ok();
+                // Directories existing before are shared, only new ones belong to the package
+                let new_dirs = missing_dirs(if is_dir { Some(target.as_path()) } else { target.parent() });
+                // Entries escaping the destination are skipped
+                if !entry.unpack_in(&dest_path)? {
+                    continue;
+                }
+                extracted.extend(new_dirs.into_iter().map(|d| (d, FILE_DIRECTORY)));
+                if !is_dir {
+                    let file_type = if entry.header().entry_type().is_symlink() { FILE_SYMLINK } else { FILE_BINARY };
+                    if existed {
+                        overwritten.push(target.clone());
+                    }
+                    extracted.push((target, file_type));
+                }
+            }
 
-            Ok::<_, anyhow::Error>(())
+            Ok::<_, anyhow::Error>((extracted, overwritten))
         })
         .await??;
 
         tracing::info!("Extraction complete");
-        Ok(())
+        let (extracted, overwritten) = extracted;
+        created.extend(extracted);
+        Ok((created, overwritten))
     }
 
-    /// Uninstall a package
-    pub async fn uninstall(&self, package_id: &str) -> Result<()> {
-        let package = self.get_installed(package_id).await?
-            .ok_or_else(|| anyhow!("Package not found: {}", package_id))?;
+    /// Installed packages depending directly on a package
+    pub async fn dependents(&self, package_id: &str) -> Result<Vec<String>> {
+        let mut dependents = Vec::new();
+        for package in self.list_installed().await? {
+            let Some(manifest_data) = &package.manifest_data else {
+                continue;
+            };
+            let manifest: PackageManifest = match serde_json::from_str(manifest_data) {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    tracing::warn!("Ignoring unreadable manifest of {}: {}", package.id, e);
+                    continue;
+                }
+            };
+            let depends = manifest.requirements.dependencies.iter()
+                .filter_map(|spec| package_deps::Dependency::parse(spec).ok())
+                .any(|dep| dep.id == package_id);
+            if depends && package.id != package_id {
+                dependents.push(package.id);
+            }
+        }
+        Ok(dependents)
+    }
 
-        // Parse manifest to get uninstall steps
-        if let Some(manifest_data) = &package.manifest_data {
-            let manifest: PackageManifest = serde_json::from_str(manifest_data)?;
+    /// Uninstall a package along with every package depending on it, dependents first.
+    /// Returns the removed package IDs in removal order.
+    pub async fn uninstall_cascade(&self, package_id: &str) -> Result<Vec<String>> {
+        let pending = self.prepare_uninstall(package_id, true).await?;
+        let order = pending.order.clone();
+        self.run_uninstall(pending, false).await?;
+        Ok(order)
+    }
 
-            // Execute uninstall steps
-            for step in &manifest.uninstall.steps {
-                if let Err(e) = self.execute_step(step, &manifest).await {
-                    tracing::warn!("Uninstall step failed (continuing): {}", e);
-                }
+    /// Check a package can be removed, claim it and create its removal task, to be run by `run_uninstall`.
+    /// With `cascade` the packages depending on it are removed first, otherwise they are an error.
+    pub async fn prepare_uninstall(&self, package_id: &str, cascade: bool) -> Result<PendingUninstall> {
+        if self.get_installed(package_id).await?.is_none() {
+            return Err(anyhow!("Package not found: {}", package_id));
+        }
+
+        let mut order = Vec::new();
+        if cascade {
+            self.collect_dependents(package_id, &mut HashSet::new(), &mut order).await?;
+        } else {
+            let dependents = self.dependents(package_id).await?;
+            if !dependents.is_empty() {
+                return Err(anyhow!("Package {} is required by {}", package_id, dependents.join(", ")));
             }
+            order.push(package_id.to_string());
         }
 
-        // Delete tracked files
-        let files: Vec<String> = sqlx::query_scalar(
-            "SELECT path FROM package_files WHERE package_id = ? ORDER BY id DESC"
-        )
-        .bind(package_id)
-        .fetch_all(&self.db)
-        .await?;
+        // Claim the packages, an update, repair or removal in progress leaves them in another status
+        let mut statuses: Vec<String> = Vec::with_capacity(order.len());
+        for id in &order {
+            let status = self.get_installed(id).await?.map(|p| p.status).unwrap_or_else(|| "removed".to_string());
+            let removable = [PackageStatus::Installed.to_string(), PackageStatus::Error.to_string()].contains(&status);
+            let claimed = removable
+                && sqlx::query("UPDATE installed_packages SET status = ?, updated_at = ? WHERE id = ? AND status = ?")
+                    .bind(PackageStatus::Removing.to_string())
+                    .bind(chrono::Utc::now().to_rfc3339())
+                    .bind(id)
+                    .bind(&status)
+                    .execute(&self.db)
+                    .await?
+                    .rows_affected()
+                    > 0;
+            if !claimed {
+                let claims: Vec<_> = order.iter().cloned().zip(statuses).collect();
+                self.release_removal(&claims).await?;
+                return Err(PackageBusy(id.clone(), status).into());
+            }
+            statuses.push(status);
+        }
+
+        let task_id = self.create_task(package_id, "uninstall", order.len(), None).await?;
+        Ok(PendingUninstall { task_id, order, statuses })
+    }
+
+    /// Run the task of a prepared removal. Cancelling stops before the next package,
+    /// a package being removed is removed completely. With `keep_data` the user data
+    /// of the packages stays on disk. Returns what was done with the files of each package.
+    pub async fn run_uninstall(&self, pending: PendingUninstall, keep_data: bool) -> Result<Vec<UninstallReport>> {
+        let PendingUninstall { task_id, order, statuses } = pending;
+        let mut reports = Vec::with_capacity(order.len());
+        for (i, id) in order.iter().enumerate() {
+            let checked = async {
+                self.cancel.check()?;
+                self.set_task_step(&task_id, i, &format!("Removing {}", id)).await?;
+                self.check_removable(id).await
+            }
+            .await;
+            let (result, started) = match checked {
+                Ok(package) => (self.remove_package(package, &task_id, keep_data).await, true),
+                Err(e) => (Err(e), false),
+            };
+            let e = match result {
+                Ok(report) => {
+                    reports.push(report);
+                    continue;
+                }
+                Err(e) => e.context(format!("Failed to uninstall {}", id)),
+            };
 
-        for file_path in files {
-            if let Err(e) = fs::remove_file(&file_path).await {
-                tracing::warn!("Failed to remove file {}: {}", file_path, e);
+            // Packages not reached get their claim back, one removed halfway is left in error
+            if started {
+                self.mark_failed(id, &e).await?;
             }
+            let first_unclaimed = if started { i + 1 } else { i };
+            let claims: Vec<_> = order.iter().cloned().zip(statuses.iter().cloned()).skip(first_unclaimed).collect();
+            self.release_removal(&claims).await?;
+            self.fail_task(&task_id, &e).await?;
+            return Err(e);
         }
 
-        // Remove from database
-        sqlx::query("DELETE FROM package_files WHERE package_id = ?")
-            .bind(package_id)
-            .execute(&self.db)
-            .await?;
+        self.complete_task(&task_id).await?;
+        Ok(reports)
+    }
 
-        sqlx::query("DELETE FROM app_translations WHERE package_id = ?")
-            .bind(package_id)
-            .execute(&self.db)
-            .await?;
+    /// Give packages claimed for removal their previous status back
+    async fn release_removal(&self, claims: &[(String, String)]) -> Result<()> {
+        for (id, status) in claims {
+            sqlx::query("UPDATE installed_packages SET status = ?, updated_at = ? WHERE id = ? AND status = ?")
+                .bind(status)
+                .bind(chrono::Utc::now().to_rfc3339())
+                .bind(id)
+                .bind(PackageStatus::Removing.to_string())
+                .execute(&self.db)
+                .await?;
+        }
+        Ok(())
+    }
 
-        sqlx::query("DELETE FROM installed_packages WHERE id = ?")
+    /// Leave a package in error after a task failed on it
+    async fn mark_failed(&self, package_id: &str, e: &anyhow::Error) -> Result<()> {
+        sqlx::query("UPDATE installed_packages SET status = ?, error_message = ?, updated_at = ? WHERE id = ?")
+            .bind(PackageStatus::Error.to_string())
+            .bind(format!("{:#}", e))
+            .bind(chrono::Utc::now().to_rfc3339())
             .bind(package_id)
             .execute(&self.db)
             .await?;
-
         Ok(())
     }
 
-    /// Get task status
-    pub async fn get_task(&self, task_id: &str) -> Result<Option<PackageTask>> {
-        let task = sqlx::query_as::<_, PackageTask>(
-            r#"SELECT id, package_id, task_type, status, progress, total_steps,
-                      current_step, error_message, started_at, completed_at, created_at
-               FROM package_tasks WHERE id = ?"#
-        )
-        .bind(task_id)
-        .fetch_optional(&self.db)
-        .await?;
+    /// Post-order walk of the dependents graph, so a package comes after all its dependents
+    fn collect_dependents<'a>(
+        &'a self,
+        package_id: &'a str,
+        visited: &'a mut HashSet<String>,
+        order: &'a mut Vec<String>,
+    ) -> futures_util::future::BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if !visited.insert(package_id.to_string()) {
+                return Ok(());
+            }
+            for dependent in self.dependents(package_id).await? {
+                self.collect_dependents(&dependent, visited, order).await?;
+            }
+            order.push(package_id.to_string());
+            Ok(())
+        })
+    }
+
+    /// Uninstall a package, refused while other packages depend on it
+    pub async fn uninstall(&self, package_id: &str, keep_data: bool) -> Result<UninstallReport> {
+        let pending = self.prepare_uninstall(package_id, false).await?;
+        let mut reports = self.run_uninstall(pending, keep_data).await?;
+        Ok(reports.pop().unwrap_or_default())
+    }
+
+    /// Check nothing depends on a package about to be removed, returning its record
+    async fn check_removable(&self, package_id: &str) -> Result<InstalledPackage> {
+        let package = self.get_installed(package_id).await?
+            .ok_or_else(|| anyhow!("Package not found: {}", package_id))?;
+
+        let dependents = self.dependents(package_id).await?;
+        if !dependents.is_empty() {
+            return Err(anyhow!("Package {} is required by {}", package_id, dependents.join(", ")));
+        }
+        Ok(package)
+    }
+
+    /// Remove a claimed package and the files its install created, its uninstall steps writing to the task log
+    async fn remove_package(&self, package: InstalledPackage, task_id: &str, keep_data: bool) -> Result<UninstallReport> {
+        let package_id = package.id.as_str();
+
+        // Parse manifest to get uninstall steps
+        if let Some(manifest_data) = &package.manifest_data {
+            let manifest: PackageManifest = serde_json::from_str(manifest_data)?;
+
+            // Execute uninstall steps
+            for (i, step) in manifest.uninstall.steps.iter().enumerate() {
+                let context = StepContext { task_id, step: i, track: false };
+                if let Err(e) = self.execute_step(&self.substitute_step(step), &manifest, Some(context)).await {
+                    tracing::warn!("Uninstall step failed (continuing): {}", e);
+                    self.log_task(task_id, Some(i), "info", &format!("Step {} failed (continuing): {:#}", i + 1, e)).await;
+                }
+            }
+        }
+
+        let report = self.remove_tracked_files(package_id, keep_data).await?;
+        self.log_task(task_id, None, "info", &format!("Removed {} files of {}", report.removed, package_id)).await;
+        for path in &report.modified {
+            self.log_task(task_id, None, "info", &format!("Removed {}, modified since install", path)).await;
+        }
+        for path in &report.kept {
+            self.log_task(task_id, None, "info", &format!("Kept {}", path)).await;
+        }
+
+        // Remove from database
+        sqlx::query("DELETE FROM app_translations WHERE package_id = ?")
+            .bind(package_id)
+            .execute(&self.db)
+            .await?;
+
+        sqlx::query("DELETE FROM installed_packages WHERE id = ?")
+            .bind(package_id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(report)
+    }
+
+    /// Compare a package with what its install created: files missing or changed since, files
+    /// its directories gained, and Docker images and containers gone. User data is not checked.
+    /// The report is kept as the package's last verification.
+    pub async fn verify(&self, package_id: &str) -> Result<VerifyReport> {
+        let package = self.get_installed(package_id).await?
+            .ok_or_else(|| anyhow!("Package not found: {}", package_id))?;
+        let files = self.tracked_files(package_id).await?;
+
+        let mut report = VerifyReport {
+            package_id: package_id.to_string(),
+            checked_at: chrono::Utc::now().to_rfc3339(),
+            ..Default::default()
+        };
+        for file in files.iter().filter(|f| f.file_type != FILE_DATA) {
+            let metadata = match fs::symlink_metadata(&file.path).await {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    report.missing.push(file.path.clone());
+                    continue;
+                }
+                Err(e) => return Err(anyhow!("Failed to inspect {}: {}", file.path, e)),
+            };
+            if let (Some(expected), true) = (&file.sha256, metadata.is_file()) {
+                if hex::encode(hash_file(Path::new(&file.path)).await?.finalize()) != *expected {
+                    report.modified.push(file.path.clone());
+                }
+            }
+        }
+
+        // Only the directories the package created are its own to check
+        let tracked: HashSet<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        for dir in files.iter().filter(|f| f.file_type == FILE_DIRECTORY) {
+            let Ok(mut entries) = fs::read_dir(&dir.path).await else {
+                continue;
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path().to_string_lossy().to_string();
+                if !tracked.contains(path.as_str()) {
+                    report.extra.push(path);
+                }
+            }
+        }
+
+        let manifest = package.manifest_data.as_deref()
+            .map(serde_json::from_str::<PackageManifest>)
+            .transpose()?;
+        if let (Some(manifest), true) = (&manifest, self.docker_service.is_available()) {
+            let images: HashSet<String> = self.docker_service.list_images().await?
+                .into_iter()
+                .flat_map(|image| image.repo_tags)
+                .collect();
+            let containers: HashSet<String> = self.docker_service.list_containers(true).await?
+                .into_iter()
+                .map(|container| container.name)
+                .collect();
+            for step in &manifest.install.steps {
+                match self.substitute_step(step) {
+                    InstallStep::DockerPull { image, .. } if !images.contains(&image_reference(&image)) => {
+                        report.missing_images.push(image);
+                    }
+                    InstallStep::DockerCreate { config } if !containers.contains(&config.name) => {
+                        report.missing_containers.push(config.name);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        sqlx::query("UPDATE installed_packages SET verify_report = ?, verified_at = ? WHERE id = ?")
+            .bind(serde_json::to_string(&report)?)
+            .bind(&report.checked_at)
+            .bind(package_id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(report)
+    }
+
+    /// Verify every installed package, notifying about those whose files drifted since their last verification.
+    /// Returns the reports of the packages that are not intact.
+    pub async fn verify_installed(&self, events: &EventBus) -> Result<Vec<VerifyReport>> {
+        let mut drifted = Vec::new();
+        for package in self.list_installed().await? {
+            if package.status != PackageStatus::Installed.to_string() {
+                continue;
+            }
+            let previous: Option<String> = sqlx::query_scalar("SELECT verify_report FROM installed_packages WHERE id = ?")
+                .bind(&package.id)
+                .fetch_optional(&self.db)
+                .await?
+                .flatten();
+            let previous: Option<VerifyReport> = previous.and_then(|report| serde_json::from_str(&report).ok());
+
+            let report = match self.verify(&package.id).await {
+                Ok(report) => report,
+                Err(e) => {
+                    tracing::warn!("Failed to verify {}: {:#}", package.id, e);
+                    continue;
+                }
+            };
+            if report.is_intact() {
+                continue;
+            }
+            if !previous.is_some_and(|previous| previous.same_drift(&report)) {
+                let message = format!(
+                    "{} has {} missing and {} modified files, {} missing Docker images and {} missing containers. Repair it from the App Center.",
+                    package.name,
+                    report.missing.len(),
+                    report.modified.len(),
+                    report.missing_images.len(),
+                    report.missing_containers.len(),
+                );
+                if let Err(e) = notification::notify(
+                    &self.db,
+                    events,
+                    NotificationLevel::Warning,
+                    "Package files changed",
+                    &message,
+                    "packages",
+                )
+                .await
+                {
+                    tracing::error!("Failed to send package drift notification: {}", e);
+                }
+            }
+            drifted.push(report);
+        }
+
+        Ok(drifted)
+    }
+
+    /// Verify a package and, when something can be restored, claim it and create the repair task,
+    /// to be run by `run_repair`. Repairs hold the package in the updating status.
+    pub async fn prepare_repair(&self, package_id: &str) -> Result<(VerifyReport, Option<PendingRepair>)> {
+        let report = self.verify(package_id).await?;
+        if report.is_intact() {
+            return Ok((report, None));
+        }
+
+        let package = self.get_installed(package_id).await?
+            .ok_or_else(|| anyhow!("Package not found: {}", package_id))?;
+        let manifest: PackageManifest = serde_json::from_str(
+            package.manifest_data.as_deref().ok_or_else(|| anyhow!("Package {} has no manifest", package_id))?,
+        )?;
+        let substituted: Vec<InstallStep> = manifest.install.steps.iter().map(|step| self.substitute_step(step)).collect();
+        let steps = repair_steps(&substituted, &self.tracked_files(package_id).await?, &report);
+        if steps.is_empty() {
+            return Err(anyhow!("Package {} cannot be repaired, reinstall it", package_id));
+        }
+
+        let claimed = sqlx::query("UPDATE installed_packages SET status = ?, updated_at = ? WHERE id = ? AND status = ?")
+            .bind(PackageStatus::Updating.to_string())
+            .bind(chrono::Utc::now().to_rfc3339())
+            .bind(package_id)
+            .bind(PackageStatus::Installed.to_string())
+            .execute(&self.db)
+            .await?
+            .rows_affected();
+        if claimed == 0 {
+            return Err(PackageBusy(package_id.to_string(), package.status).into());
+        }
+
+        let task_id = self.create_task(package_id, "repair", steps.len(), None).await?;
+        Ok((report, Some(PendingRepair { task_id, manifest, steps })))
+    }
+
+    /// Run the task of a prepared repair, then verify the package again. Install commands are not run again.
+    pub async fn run_repair(&self, pending: PendingRepair) -> Result<()> {
+        let PendingRepair { task_id, manifest, steps } = pending;
+
+        let result = self.execute_selected_steps(&manifest, &manifest.install.steps, &steps, &task_id).await;
+        sqlx::query("UPDATE installed_packages SET status = ?, updated_at = ? WHERE id = ?")
+            .bind(PackageStatus::Installed.to_string())
+            .bind(chrono::Utc::now().to_rfc3339())
+            .bind(&manifest.id)
+            .execute(&self.db)
+            .await?;
+        if let Err(e) = result {
+            let e = e.context(format!("Failed to repair {}", manifest.id));
+            self.fail_task(&task_id, &e).await?;
+            return Err(e);
+        }
+
+        let report = self.verify(&manifest.id).await?;
+        if !report.is_intact() {
+            self.log_task(&task_id, None, "info", &format!(
+                "Still {} missing and {} modified files after the repair",
+                report.missing.len() + report.missing_images.len() + report.missing_containers.len(),
+                report.modified.len(),
+            )).await;
+        }
+        self.complete_task(&task_id).await
+    }
+
+    /// Compare installed packages against the catalog, listing those with a newer version
+    pub async fn check_updates(&self, catalog: &Catalog) -> Result<Vec<PackageUpdate>> {
+        let updates = self.list_installed().await?
+            .into_iter()
+            .filter_map(|package| {
+                let app = catalog.apps.iter().find(|app| app.id == package.id)?;
+                is_newer_version(&app.version, &package.version).then(|| PackageUpdate {
+                    package_id: package.id,
+                    name: package.name,
+                    installed_version: package.version,
+                    available_version: app.version.clone(),
+                })
+            })
+            .collect();
+
+        Ok(updates)
+    }
+
+    /// Update an installed package to a newer manifest version, rolling back if any step fails
+    pub async fn update(&self, manifest: &PackageManifest, manifest_url: Option<&str>) -> Result<String> {
+        let pending = self.prepare_update(manifest).await?;
+        let task_id = pending.task_id.clone();
+        self.run_update(pending, manifest, manifest_url).await?;
+        Ok(task_id)
+    }
+
+    /// Check a manifest updates an installed package, claim the package and create the update task,
+    /// to be run by `run_update`
+    pub async fn prepare_update(&self, manifest: &PackageManifest) -> Result<PendingUpdate> {
+        let package = self.get_installed(&manifest.id).await?
+            .ok_or_else(|| anyhow!("Package not found: {}", manifest.id))?;
+
+        if !is_newer_version(&manifest.version, &package.version) {
+            return Err(anyhow!(
+                "Package {} {} is not newer than the installed version {}",
+                manifest.id, manifest.version, package.version
+            ));
+        }
+
+        self.check_dependencies(manifest).await?;
+
+        // Claim the package, a concurrent update or removal leaves it in another status
+        let now = chrono::Utc::now().to_rfc3339();
+        let claimed = sqlx::query("UPDATE installed_packages SET status = ?, updated_at = ? WHERE id = ? AND status = ?")
+            .bind(PackageStatus::Updating.to_string())
+            .bind(&now)
+            .bind(&manifest.id)
+            .bind(PackageStatus::Installed.to_string())
+            .execute(&self.db)
+            .await?
+            .rows_affected();
+        if claimed == 0 {
+            return Err(PackageBusy(manifest.id.clone(), package.status.clone()).into());
+        }
+
+        let task_id = self.create_task(&manifest.id, "update", update_steps(manifest).len(), None).await?;
+        Ok(PendingUpdate { task_id, package })
+    }
+
+    /// Run the task of a prepared update, a cancelled update being rolled back like a failed one
+    pub async fn run_update(&self, pending: PendingUpdate, manifest: &PackageManifest, manifest_url: Option<&str>) -> Result<()> {
+        let PendingUpdate { task_id, package } = pending;
+        let steps = update_steps(manifest);
+
+        let snapshot = match self.take_snapshot(&package).await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                // Nothing was changed yet, only the claim has to be released
+                let e = e.context(format!("Failed to snapshot {}", manifest.id));
+                sqlx::query("UPDATE installed_packages SET status = ?, updated_at = ? WHERE id = ?")
+                    .bind(&package.status)
+                    .bind(&package.updated_at)
+                    .bind(&manifest.id)
+                    .execute(&self.db)
+                    .await?;
+                self.fail_task(&task_id, &e).await?;
+                return Err(e);
+            }
+        };
+
+        let result = if self.dev_mode {
+            tracing::info!("Dev mode: skipping update steps for {}", manifest.id);
+            match self.cancel.check() {
+                Ok(()) => self.store_update(manifest, manifest_url).await,
+                Err(e) => Err(e.into()),
+            }
+        } else {
+            match self.execute_install_steps(manifest, steps, &task_id).await {
+                Ok(()) => self.store_update(manifest, manifest_url).await,
+                Err(e) => Err(e),
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                // The update is stored, a restart must no longer roll it back
+                self.discard_snapshot(&snapshot).await;
+                self.complete_task(&task_id).await?;
+                tracing::info!("Updated {} from {} to {}", manifest.id, package.version, manifest.version);
+            }
+            Err(e) => {
+                let e = match self.restore_snapshot(&snapshot).await {
+                    Ok(()) => {
+                        self.discard_snapshot(&snapshot).await;
+                        e.context(format!("Update failed, rolled back to {}", package.version))
+                    }
+                    Err(restore_err) => {
+                        tracing::error!("Failed to roll back {}: {}", manifest.id, restore_err);
+                        let error_msg = format!("{}; rollback failed: {}", e, restore_err);
+                        sqlx::query("UPDATE installed_packages SET status = 'error', error_message = ?, updated_at = ? WHERE id = ?")
+                            .bind(&error_msg)
+                            .bind(chrono::Utc::now().to_rfc3339())
+                            .bind(&manifest.id)
+                            .execute(&self.db)
+                            .await?;
+                        e.context(format!("Update failed and could not be rolled back: {}", restore_err))
+                    }
+                };
+                self.fail_task(&task_id, &e).await?;
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Mark a task as failed, or cancelled when the error comes from a cancellation, and publish it
+    async fn fail_task(&self, task_id: &str, error: &anyhow::Error) -> Result<()> {
+        let status = if is_cancelled(error) { "cancelled" } else { "failed" };
+        sqlx::query("UPDATE package_tasks SET status = ?, error_message = ?, completed_at = ? WHERE id = ?")
+            .bind(status)
+            .bind(format!("{:#}", error))
+            .bind(chrono::Utc::now().to_rfc3339())
+            .bind(task_id)
+            .execute(&self.db)
+            .await?;
+        self.publish_task(task_id).await;
+        Ok(())
+    }
+
+    /// Directory holding a package's files
+    fn package_dir(&self, package_id: &str) -> PathBuf {
+        Path::new(&self.packages_dir).join(package_id)
+    }
+
+    /// Copy the package directory aside and keep its record, before an update touches them
+    async fn take_snapshot(&self, package: &InstalledPackage) -> Result<UpdateSnapshot> {
+        let dir = self.package_dir(&package.id);
+        let dir_copy = if fs::metadata(&dir).await.map(|m| m.is_dir()).unwrap_or(false) {
+            // Next to the package, on the same filesystem, so restoring it is a rename
+            let copy = Path::new(&self.packages_dir).join(SNAPSHOTS_DIR).join(&package.id);
+            if fs::metadata(&copy).await.is_ok() {
+                fs::remove_dir_all(&copy).await?;
+            }
+            let target = copy.clone();
+            tokio::task::spawn_blocking(move || copy_dir_all(&dir, &target)).await??;
+            Some(copy)
+        } else {
+            None
+        };
+
+        fs::create_dir_all(Path::new(&self.data_dir).join("snapshots")).await?;
+        let snapshot = UpdateSnapshot {
+            package: package.clone(),
+            dir_copy,
+            files: self.tracked_files(&package.id).await?,
+        };
+        fs::write(self.snapshot_record(&package.id), serde_json::to_vec(&snapshot)?).await?;
+        Ok(snapshot)
+    }
+
+    /// File holding the record of a package's update snapshot
+    fn snapshot_record(&self, package_id: &str) -> PathBuf {
+        Path::new(&self.data_dir).join("snapshots").join(format!("{}.json", package_id))
+    }
+
+    /// Forget an update snapshot once the update is over
+    async fn discard_snapshot(&self, snapshot: &UpdateSnapshot) {
+        if let Some(copy) = &snapshot.dir_copy {
+            if let Err(e) = remove_if_exists(copy).await {
+                tracing::warn!("Failed to remove snapshot {}: {}", copy.display(), e);
+            }
+        }
+        if let Err(e) = remove_if_exists(&self.snapshot_record(&snapshot.package.id)).await {
+            tracing::warn!("Failed to remove the snapshot record of {}: {}", snapshot.package.id, e);
+        }
+    }
+
+    /// Settle packages a restart caught in the middle of a task: interrupted updates are rolled back,
+    /// repairs released, and interrupted installs and removals left in error to be uninstalled
+    pub async fn recover_interrupted(&self) -> Result<()> {
+        let snapshots = Path::new(&self.data_dir).join("snapshots");
+        if let Ok(mut entries) = fs::read_dir(&snapshots).await {
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.extension().is_none_or(|ext| ext != "json") {
+                    continue;
+                }
+                let snapshot: UpdateSnapshot = match fs::read(&path).await.map_err(anyhow::Error::from)
+                    .and_then(|data| Ok(serde_json::from_slice(&data)?))
+                {
+                    Ok(snapshot) => snapshot,
+                    Err(e) => {
+                        tracing::warn!("Ignoring unreadable snapshot {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+                // A snapshot that could not be restored is kept for the next attempt
+                match self.restore_snapshot(&snapshot).await {
+                    Ok(()) => {
+                        tracing::warn!("Rolled back the update of {} a restart interrupted", snapshot.package.id);
+                        self.discard_snapshot(&snapshot).await;
+                    }
+                    Err(e) => tracing::error!("Failed to roll back the interrupted update of {}: {:#}", snapshot.package.id, e),
+                }
+            }
+        }
+
+        let now = chrono::Utc::now().to_rfc3339();
+        sqlx::query("UPDATE installed_packages SET status = ?, updated_at = ? WHERE status = ?")
+            .bind(PackageStatus::Installed.to_string())
+            .bind(&now)
+            .bind(PackageStatus::Updating.to_string())
+            .execute(&self.db)
+            .await?;
+        sqlx::query("UPDATE installed_packages SET status = ?, error_message = 'Interrupted by a restart', updated_at = ? WHERE status IN (?, ?)")
+            .bind(PackageStatus::Error.to_string())
+            .bind(&now)
+            .bind(PackageStatus::Installing.to_string())
+            .bind(PackageStatus::Removing.to_string())
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Put back the package directory, record and tracked files saved before a failed update.
+    /// Files the update created outside the package directory are removed.
+    async fn restore_snapshot(&self, snapshot: &UpdateSnapshot) -> Result<()> {
+        let package = &snapshot.package;
+        let dir = self.package_dir(&package.id);
+
+        let saved: HashSet<&str> = snapshot.files.iter().map(|f| f.path.as_str()).collect();
+        for file in self.tracked_files(&package.id).await?.iter().rev() {
+            let path = Path::new(&file.path);
+            if saved.contains(file.path.as_str()) || path.starts_with(&dir) {
+                continue;
+            }
+            let result = match fs::symlink_metadata(path).await {
+                Ok(metadata) if metadata.is_dir() => fs::remove_dir(path).await,
+                Ok(_) => fs::remove_file(path).await,
+                Err(_) => Ok(()),
+            };
+            if let Err(e) = result {
+                tracing::warn!("Failed to remove {} created by the failed update of {}: {}", file.path, package.id, e);
+            }
+        }
+        // The failed version is moved aside, and only deleted once the copy is back in place
+        let failed = Path::new(&self.packages_dir).join(SNAPSHOTS_DIR).join(format!("{}.failed", package.id));
+        remove_if_exists(&failed).await?;
+        let had_dir = fs::symlink_metadata(&dir).await.is_ok();
+        if had_dir {
+            fs::create_dir_all(Path::new(&self.packages_dir).join(SNAPSHOTS_DIR)).await?;
+            fs::rename(&dir, &failed).await
+                .with_context(|| format!("Failed to move {} aside", dir.display()))?;
+        }
+        if let Some(copy) = &snapshot.dir_copy {
+            if let Err(e) = restore_dir(copy, &dir).await {
+                let _ = remove_if_exists(&dir).await;
+                if had_dir {
+                    let _ = fs::rename(&failed, &dir).await;
+                }
+                return Err(e.context(format!("Failed to restore {}", dir.display())));
+            }
+        }
+        if let Err(e) = remove_if_exists(&failed).await {
+            tracing::warn!("Failed to remove {}: {}", failed.display(), e);
+        }
+
+        sqlx::query(
+            r#"UPDATE installed_packages
+               SET name = ?, version = ?, package_type = ?, manifest_url = ?, manifest_data = ?,
+                   status = ?, error_message = ?, updated_at = ?, frontend_config = ?, has_window = ?
+               WHERE id = ?"#
+        )
+        .bind(&package.name)
+        .bind(&package.version)
+        .bind(&package.package_type)
+        .bind(&package.manifest_url)
+        .bind(&package.manifest_data)
+        .bind(&package.status)
+        .bind(&package.error_message)
+        .bind(&package.updated_at)
+        .bind(&package.frontend_config)
+        .bind(package.has_window)
+        .bind(&package.id)
+        .execute(&self.db)
+        .await?;
+
+        sqlx::query("DELETE FROM package_files WHERE package_id = ?")
+            .bind(&package.id)
+            .execute(&self.db)
+            .await?;
+        for file in &snapshot.files {
+            sqlx::query(
+                "INSERT INTO package_files (id, package_id, path, file_type, sha256, step, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+            )
+            .bind(file.id)
+            .bind(&file.package_id)
+            .bind(&file.path)
+            .bind(&file.file_type)
+            .bind(&file.sha256)
+            .bind(file.step)
+            .bind(&file.created_at)
+            .execute(&self.db)
+            .await?;
+        }
+
+        tracing::info!("Rolled back {} to {}", package.id, package.version);
+        Ok(())
+    }
+
+    /// Record the new version of an updated package along with its translations
+    async fn store_update(&self, manifest: &PackageManifest, manifest_url: Option<&str>) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let frontend_config_json = manifest.frontend.as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let manifest_json = serde_json::to_string(manifest)?;
+
+        let mut tx = self.db.begin().await?;
+        sqlx::query(
+            r#"UPDATE installed_packages
+               SET name = ?, version = ?, package_type = ?, manifest_url = ?, manifest_data = ?,
+                   status = 'installed', error_message = NULL, updated_at = ?, frontend_config = ?, has_window = ?
+               WHERE id = ?"#
+        )
+        .bind(&manifest.name)
+        .bind(&manifest.version)
+        .bind(&manifest.install.install_type)
+        .bind(manifest_url)
+        .bind(&manifest_json)
+        .bind(&now)
+        .bind(&frontend_config_json)
+        .bind(manifest.frontend.is_some())
+        .bind(&manifest.id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM app_translations WHERE package_id = ?")
+            .bind(&manifest.id)
+            .execute(&mut *tx)
+            .await?;
+        if let Some(frontend) = &manifest.frontend {
+            for (locale, translations) in &frontend.i18n {
+                sqlx::query(
+                    r#"INSERT INTO app_translations (package_id, locale, translations, created_at, updated_at)
+                       VALUES (?, ?, ?, ?, ?)"#
+                )
+                .bind(&manifest.id)
+                .bind(locale)
+                .bind(serde_json::to_string(translations)?)
+                .bind(&now)
+                .bind(&now)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Get task status
+    pub async fn get_task(&self, task_id: &str) -> Result<Option<PackageTask>> {
+        let task = sqlx::query_as::<_, PackageTask>(
+            r#"SELECT id, package_id, task_type, status, progress, total_steps,
+                      current_step, error_message, started_at, completed_at, created_at, parent_task_id,
+                      bytes_done, bytes_total
+               FROM package_tasks WHERE id = ?"#
+        )
+        .bind(task_id)
+        .fetch_optional(&self.db)
+        .await?;
 
         Ok(task)
     }
 
-    /// Track a file created during installation
-    pub async fn track_file(&self, package_id: &str, path: &str, file_type: &str) -> Result<()> {
+    /// Track a file created during installation, a file written again keeping its place in the removal order
+    pub async fn track_file(&self, package_id: &str, path: &str, file_type: &str, sha256: Option<&str>, step: usize) -> Result<()> {
         let now = chrono::Utc::now().to_rfc3339();
         sqlx::query(
-            "INSERT INTO package_files (package_id, path, file_type, created_at) VALUES (?, ?, ?, ?)"
+            r#"INSERT INTO package_files (package_id, path, file_type, sha256, step, created_at) VALUES (?, ?, ?, ?, ?, ?)
+               ON CONFLICT(package_id, path) DO UPDATE SET file_type = excluded.file_type, sha256 = excluded.sha256, step = excluded.step"#
         )
         .bind(package_id)
         .bind(path)
         .bind(file_type)
+        .bind(sha256)
+        .bind(step as i64)
         .bind(&now)
         .execute(&self.db)
         .await?;
 
         Ok(())
     }
+
+    /// Track a path a step created, with the checksum of files. Paths under the manifest's
+    /// data paths are tracked as user data.
+    async fn track_path(&self, manifest: &PackageManifest, path: &Path, file_type: &str, step: usize) -> Result<()> {
+        let metadata = fs::symlink_metadata(path).await
+            .with_context(|| format!("Created path {} is missing", path.display()))?;
+        let sha256 = if metadata.is_file() {
+            Some(hex::encode(hash_file(path).await?.finalize()))
+        } else {
+            None
+        };
+        let file_type = if self.data_paths(manifest).iter().any(|data| path.starts_with(data)) {
+            FILE_DATA
+        } else {
+            file_type
+        };
+        self.track_file(&manifest.id, &path.to_string_lossy(), file_type, sha256.as_deref(), step).await
+    }
+
+    /// Whether a path is tracked as a file of a package
+    async fn is_tracked(&self, package_id: &str, path: &Path) -> Result<bool> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM package_files WHERE package_id = ? AND path = ?")
+            .bind(package_id)
+            .bind(path.to_string_lossy())
+            .fetch_one(&self.db)
+            .await?;
+
+        Ok(count > 0)
+    }
+
+    /// Forget a tracked path and everything tracked under it
+    async fn untrack_path(&self, package_id: &str, path: &str) -> Result<()> {
+        let path = path.trim_end_matches('/');
+        sqlx::query("DELETE FROM package_files WHERE package_id = ? AND (path = ? OR substr(path, 1, length(?) + 1) = ? || '/')")
+            .bind(package_id)
+            .bind(path)
+            .bind(path)
+            .bind(path)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Files tracked for a package, in creation order
+    async fn tracked_files(&self, package_id: &str) -> Result<Vec<PackageFile>> {
+        let files = sqlx::query_as::<_, PackageFile>(
+            "SELECT id, package_id, path, file_type, sha256, step, created_at FROM package_files WHERE package_id = ? ORDER BY id"
+        )
+        .bind(package_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(files)
+    }
+
+    /// User data paths of a manifest, variables substituted
+    fn data_paths(&self, manifest: &PackageManifest) -> Vec<PathBuf> {
+        manifest.uninstall.data_paths.iter()
+            .map(|path| PathBuf::from(self.substitute_vars(path)))
+            .collect()
+    }
+
+    /// Remove the tracked files of a package, newest first, and forget them.
+    /// Directories are only removed once empty, and user data is left alone with `keep_data`.
+    async fn remove_tracked_files(&self, package_id: &str, keep_data: bool) -> Result<UninstallReport> {
+        let mut files = self.tracked_files(package_id).await?;
+        files.reverse();
+
+        let mut report = UninstallReport {
+            package_id: package_id.to_string(),
+            ..Default::default()
+        };
+        for file in files {
+            let path = Path::new(&file.path);
+            let is_data = file.file_type == FILE_DATA;
+            if is_data && keep_data {
+                report.kept.push(file.path);
+                continue;
+            }
+            let metadata = match fs::symlink_metadata(path).await {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    tracing::warn!("Failed to inspect {}: {}", file.path, e);
+                    report.kept.push(file.path);
+                    continue;
+                }
+            };
+
+            let result = if metadata.is_dir() && is_data {
+                // What the app stored in its data directories goes with them
+                fs::remove_dir_all(path).await
+            } else if metadata.is_dir() {
+                fs::remove_dir(path).await
+            } else {
+                if let (Some(expected), false) = (&file.sha256, is_data) {
+                    match hash_file(path).await {
+                        Ok(hasher) => {
+                            if hex::encode(hasher.finalize()) != *expected {
+                                report.modified.push(file.path.clone());
+                            }
+                        }
+                        Err(e) => tracing::warn!("Failed to hash {}: {}", file.path, e),
+                    }
+                }
+                fs::remove_file(path).await
+            };
+            match result {
+                Ok(()) => report.removed += 1,
+                Err(e) => {
+                    // A directory still holding files the package did not create stays
+                    if !metadata.is_dir() {
+                        tracing::warn!("Failed to remove {}: {}", file.path, e);
+                    }
+                    report.kept.push(file.path);
+                }
+            }
+        }
+
+        sqlx::query("DELETE FROM package_files WHERE package_id = ?")
+            .bind(package_id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(report)
+    }
+}
+
+/// Error of a package another task is working on
+#[derive(Debug, thiserror::Error)]
+#[error("Package {0} is busy ({1})")]
+pub struct PackageBusy(pub String, pub String);
+
+/// Removal whose packages are claimed and task created, waiting to be run
+pub struct PendingUninstall {
+    pub task_id: String,
+    /// Package IDs in removal order
+    pub order: Vec<String>,
+    /// Status of each package before it was claimed
+    statuses: Vec<String>,
+}
+
+/// Update whose package is claimed and task created, waiting to be run
+pub struct PendingUpdate {
+    pub task_id: String,
+    /// Record of the package before it was claimed
+    package: InstalledPackage,
+}
+
+/// Periodically verify installed packages, notifying about drifted ones (0 hours disables)
+pub fn spawn_verification(db: SqlitePool, events: EventBus, interval_hours: u64) {
+    if interval_hours == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let period = std::time::Duration::from_secs(interval_hours * 3600);
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            match PackageService::new(db.clone()).await.verify_installed(&events).await {
+                Ok(drifted) if drifted.is_empty() => {}
+                Ok(drifted) => tracing::warn!("{} installed packages drifted from their install", drifted.len()),
+                Err(e) => tracing::error!("Failed to verify installed packages: {:#}", e),
+            }
+        }
+    });
+}
+
+/// Repair whose package is claimed and task created, waiting to be run
+pub struct PendingRepair {
+    pub task_id: String,
+    manifest: PackageManifest,
+    /// Indexes of the install steps to run again, in order
+    steps: Vec<usize>,
+}
+
+/// Path an install step creates, directories and archives included
+fn step_output(step: &InstallStep) -> Option<&str> {
+    match step {
+        InstallStep::Download { dest, .. }
+        | InstallStep::Extract { dest, .. }
+        | InstallStep::Copy { dest, .. }
+        | InstallStep::Symlink { dest, .. }
+        | InstallStep::Template { dest, .. }
+        | InstallStep::WriteFile { dest, .. } => Some(dest),
+        InstallStep::Mkdir { path } => Some(path),
+        _ => None,
+    }
+}
+
+/// Install steps, variables substituted, to run again to restore what a verification found
+/// missing or modified. Steps producing the missing inputs of those are added, with the
+/// deletions cleaning up after them, and so are the chmods and container starts that follow.
+fn repair_steps(steps: &[InstallStep], files: &[PackageFile], report: &VerifyReport) -> Vec<usize> {
+    let mut selected: std::collections::BTreeSet<usize> = files.iter()
+        .filter(|file| report.missing.contains(&file.path) || report.modified.contains(&file.path))
+        .filter_map(|file| file.step)
+        .map(|step| step as usize)
+        .filter(|&step| step < steps.len())
+        .collect();
+    for (i, step) in steps.iter().enumerate() {
+        match step {
+            InstallStep::DockerPull { image, .. } if report.missing_images.contains(image) => {
+                selected.insert(i);
+            }
+            InstallStep::DockerCreate { config } if report.missing_containers.contains(&config.name) => {
+                selected.insert(i);
+                selected.extend(steps.iter().enumerate().skip(i).filter_map(|(j, later)| {
+                    matches!(later, InstallStep::DockerStart { container } if *container == config.name).then_some(j)
+                }));
+            }
+            _ => {}
+        }
+    }
+
+    // An archive or source file deleted once used has to be produced again
+    let mut pending: Vec<usize> = selected.iter().copied().collect();
+    while let Some(i) = pending.pop() {
+        let input = match &steps[i] {
+            InstallStep::Extract { src, .. } | InstallStep::Copy { src, .. } => src,
+            _ => continue,
+        };
+        if Path::new(input).exists() {
+            continue;
+        }
+        let Some(producer) = (0..i).rev().find(|&j| step_output(&steps[j]) == Some(input.as_str())) else {
+            continue;
+        };
+        if selected.insert(producer) {
+            pending.push(producer);
+        }
+        selected.extend((producer + 1..steps.len()).filter(|&k| {
+            matches!(&steps[k], InstallStep::Delete { path } if path == input)
+        }));
+    }
+
+    let outputs: Vec<(usize, &str)> = selected.iter().filter_map(|&i| Some((i, step_output(&steps[i])?))).collect();
+    for (k, step) in steps.iter().enumerate() {
+        if let InstallStep::Chmod { path, .. } = step {
+            if outputs.iter().any(|&(i, output)| i < k && Path::new(path).starts_with(output)) {
+                selected.insert(k);
+            }
+        }
+    }
+
+    selected.into_iter().collect()
+}
+
+/// Image reference as Docker lists it, with the default tag and registry left implicit
+fn image_reference(image: &str) -> String {
+    let image = image.strip_prefix("docker.io/").unwrap_or(image);
+    let image = image.strip_prefix("library/").unwrap_or(image);
+    let name = image.rsplit('/').next().unwrap_or(image);
+    if name.contains(':') || name.contains('@') {
+        image.to_string()
+    } else {
+        format!("{}:latest", image)
+    }
+}
+
+/// Steps of an update, the upgrade steps replacing the install steps when the manifest has them
+fn update_steps(manifest: &PackageManifest) -> &[InstallStep] {
+    manifest.upgrade.as_ref()
+        .map(|upgrade| &upgrade.steps)
+        .unwrap_or(&manifest.install.steps)
+}
+
+/// Package state saved before an update, also written to disk to roll back an update a restart interrupted
+#[derive(Serialize, Deserialize)]
+struct UpdateSnapshot {
+    package: InstalledPackage,
+    /// Copy of the package directory, None when the package has none
+    dir_copy: Option<PathBuf>,
+    /// Files tracked for the package, with their checksums as installed
+    files: Vec<PackageFile>,
+}
+
+/// Parse a package version, accepting a leading `v` and missing minor or patch numbers
+pub fn parse_version(version: &str) -> Option<semver::Version> {
+    let version = version.trim().trim_start_matches('v');
+    let (core, suffix) = version.split_at(version.find(['-', '+']).unwrap_or(version.len()));
+    let padding = ".0".repeat(2usize.saturating_sub(core.matches('.').count()));
+    semver::Version::parse(&format!("{}{}{}", core, padding, suffix)).ok()
+}
+
+/// Whether a candidate version is newer than the installed one, unparsable versions never are
+pub fn is_newer_version(candidate: &str, installed: &str) -> bool {
+    match (parse_version(candidate), parse_version(installed)) {
+        (Some(candidate), Some(installed)) => candidate > installed,
+        _ => {
+            tracing::debug!("Cannot compare versions {} and {}", candidate, installed);
+            false
+        }
+    }
+}
+
+/// Recursively copy a directory, keeping symlinks as links
+fn copy_dir_all(src: &Path, dest: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dest)?;
+    std::fs::set_permissions(dest, std::fs::metadata(src)?.permissions())?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            std::os::unix::fs::symlink(std::fs::read_link(entry.path())?, &target)?;
+        } else if file_type.is_dir() {
+            copy_dir_all(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
 }
 
 /// Decode base64 string
@@ -592,3 +2242,777 @@ fn base64_decode(input: &str) -> Result<Vec<u8>> {
     use base64::{engine::general_purpose::STANDARD, Engine};
     STANDARD.decode(input).map_err(|e| anyhow!("Base64 decode error: {}", e))
 }
+
+/// Task a step runs for, receiving its download progress and output
+#[derive(Clone, Copy)]
+struct StepContext<'a> {
+    task_id: &'a str,
+    /// Index of the step among the manifest's steps
+    step: usize,
+    /// Whether what the step creates is recorded as files of the package
+    track: bool,
+}
+
+/// Process group of a running command, killed when dropped unless released
+struct ProcessGroup(Option<nix::unistd::Pid>);
+
+impl ProcessGroup {
+    fn new(pid: u32) -> Self {
+        Self(Some(nix::unistd::Pid::from_raw(pid as i32)))
+    }
+
+    /// Leave the processes the command started running, once it has exited normally
+    fn release(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if let Some(pgid) = self.0 {
+            let _ = nix::sys::signal::killpg(pgid, nix::sys::signal::Signal::SIGKILL);
+        }
+    }
+}
+
+/// Directories that would have to be created for a path to exist, outermost first
+fn missing_dirs(path: Option<&Path>) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = path
+        .into_iter()
+        .flat_map(Path::ancestors)
+        .take_while(|dir| !dir.as_os_str().is_empty() && std::fs::symlink_metadata(dir).is_err())
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    missing
+}
+
+/// Remove a file, symlink or directory tree, nothing at the path being fine
+/// Put a snapshot copy back in place, copying it when it is on another filesystem
+async fn restore_dir(copy: &Path, dir: &Path) -> Result<()> {
+    if fs::rename(copy, dir).await.is_ok() {
+        return Ok(());
+    }
+    let (source, target) = (copy.to_path_buf(), dir.to_path_buf());
+    tokio::task::spawn_blocking(move || copy_dir_all(&source, &target)).await??;
+    remove_if_exists(copy).await?;
+    Ok(())
+}
+
+async fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match fs::symlink_metadata(path).await {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path).await,
+        Ok(_) => fs::remove_file(path).await,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Why a download attempt failed, only some failures are worth retrying
+enum DownloadFailure {
+    Retry(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+/// Start offset and total size of a Content-Range header, e.g. `bytes 100-199/200` or `bytes */200`
+fn parse_content_range(value: &str) -> (Option<u64>, Option<u64>) {
+    let Some(range) = value.trim().strip_prefix("bytes ") else {
+        return (None, None);
+    };
+    let (span, total) = range.split_once('/').unwrap_or((range, "*"));
+    let start = span.split_once('-').and_then(|(start, _)| start.trim().parse().ok());
+    (start, total.trim().parse().ok())
+}
+
+/// SHA256 state of a file read so far, to continue hashing a resumed download
+/// Fail unless a file hashes to the expected SHA-256
+async fn check_sha256(path: &Path, expected_hash: &str) -> Result<()> {
+    let actual_hash = hex::encode(hash_file(path).await?.finalize());
+    if actual_hash != expected_hash.to_lowercase() {
+        return Err(anyhow!(
+            "SHA256 mismatch: expected {}, got {}",
+            expected_hash,
+            actual_hash
+        ));
+    }
+    Ok(())
+}
+
+async fn hash_file(path: &Path) -> Result<Sha256> {
+    use tokio::io::AsyncReadExt;
+
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(hasher);
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::manifest::{InstallConfig, UninstallConfig, UpgradeConfig};
+    use crate::services::package_tasks::{self, PackageTasks};
+    use std::sync::Arc;
+
+    async fn service(dir: &Path) -> PackageService {
+        let db = crate::db::test_pool().await;
+        sqlx::query(
+            "INSERT INTO installed_packages (id, name, version, package_type, status, installed_at, updated_at)
+             VALUES ('notes', 'Notes', '1.2.0', 'binary', 'installed', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z')",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let data_dir = dir.to_string_lossy().to_string();
+        PackageService {
+            db,
+            catalog_url: String::new(),
+            packages_dir: format!("{}/apps", data_dir),
+            downloads_dir: format!("{}/downloads", data_dir),
+            bin_dir: format!("{}/bin", data_dir),
+            data_dir,
+            docker_service: DockerService::new().await,
+            dev_mode: false,
+            events: None,
+            bundle: None,
+            cancel: CancelToken::never(),
+        }
+    }
+
+    fn exec(command: &str) -> InstallStep {
+        InstallStep::Exec { command: command.to_string(), ignore_error: false }
+    }
+
+    fn manifest(version: &str, upgrade: Vec<InstallStep>) -> PackageManifest {
+        PackageManifest {
+            id: "notes".to_string(),
+            name: "Notes".to_string(),
+            version: version.to_string(),
+            description: HashMap::new(),
+            author: None,
+            license: None,
+            website: None,
+            icon: None,
+            requirements: Default::default(),
+            install: InstallConfig {
+                install_type: "binary".to_string(),
+                steps: vec![],
+                image: None,
+                container: None,
+            },
+            upgrade: Some(UpgradeConfig { steps: upgrade }),
+            uninstall: UninstallConfig::default(),
+            files: HashMap::new(),
+            config: HashMap::new(),
+            frontend: None,
+        }
+    }
+
+    fn write_config(content: &str) -> InstallStep {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        InstallStep::WriteFile {
+            dest: "${PACKAGES_DIR}/notes/config".to_string(),
+            content: STANDARD.encode(content),
+        }
+    }
+
+    fn app(id: &str, deps: &[&str]) -> PlannedPackage {
+        let mut manifest = manifest("1.0.0", vec![]);
+        manifest.id = id.to_string();
+        manifest.requirements.dependencies = deps.iter().map(|d| d.to_string()).collect();
+        PlannedPackage { manifest, manifest_url: None }
+    }
+
+    #[test]
+    fn test_is_newer_version() {
+        assert!(is_newer_version("1.10.0", "1.9.0"));
+        assert!(is_newer_version("v2.0", "1.9.9"));
+        assert!(is_newer_version("1.0.0", "1.0.0-rc.1"));
+        assert!(!is_newer_version("1.0.0", "1.0.0"));
+        assert!(!is_newer_version("1.0.0", "1.2"));
+        assert!(!is_newer_version("latest", "1.0.0"));
+    }
+
+    #[tokio::test]
+    async fn test_check_updates() {
+        let dir = std::env::temp_dir().join(format!("pinas-packages-{}", Uuid::new_v4()));
+        let service = service(&dir).await;
+        let catalog: Catalog = serde_json::from_value(serde_json::json!({
+            "version": "1.0.0",
+            "updated": "2024-01-01T00:00:00Z",
+            "apps": [
+                { "id": "notes", "name": "Notes", "version": "1.10.0", "category": "utilities", "manifest": null },
+                { "id": "other", "name": "Other", "version": "9.0.0", "category": "utilities", "manifest": null }
+            ]
+        }))
+        .unwrap();
+
+        let updates = service.check_updates(&catalog).await.unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].package_id, "notes");
+        assert_eq!(updates[0].installed_version, "1.2.0");
+        assert_eq!(updates[0].available_version, "1.10.0");
+    }
+
+    #[tokio::test]
+    async fn test_update_rolls_back_on_failure() {
+        let dir = std::env::temp_dir().join(format!("pinas-packages-{}", Uuid::new_v4()));
+        let service = service(&dir).await;
+        let app_dir = service.package_dir("notes");
+        std::fs::create_dir_all(&app_dir).unwrap();
+        std::fs::write(app_dir.join("config"), "v1").unwrap();
+        let config = app_dir.join("config").to_string_lossy().to_string();
+        let v1 = hex::encode(Sha256::digest(b"v1"));
+        service.track_file("notes", &config, FILE_CONFIG, Some(&v1), 0).await.unwrap();
+
+        let failing = manifest("1.3.0", vec![
+            write_config("v2"),
+            InstallStep::WriteFile { dest: "${DATA_DIR}/notes.conf".to_string(), content: "aGVsbG8=".to_string() },
+            InstallStep::Exec { command: "false".to_string(), ignore_error: false },
+        ]);
+        let err = service.update(&failing, None).await.unwrap_err();
+        assert!(err.to_string().contains("rolled back to 1.2.0"), "{}", err);
+
+        assert_eq!(std::fs::read_to_string(app_dir.join("config")).unwrap(), "v1");
+        let package = service.get_installed("notes").await.unwrap().unwrap();
+        assert_eq!(package.version, "1.2.0");
+        assert_eq!(package.status, "installed");
+        assert_eq!(package.updated_at, "2024-01-01T00:00:00Z");
+        // Tracked files are back to what the installed version created
+        assert!(!dir.join("notes.conf").exists());
+        let files: Vec<_> = service.tracked_files("notes").await.unwrap().into_iter().map(|f| (f.path, f.sha256)).collect();
+        assert_eq!(files, vec![(config.clone(), Some(v1))]);
+        assert!(service.verify("notes").await.unwrap().is_intact());
+        // Neither the copy nor the failed version are left behind
+        assert_eq!(std::fs::read_dir(dir.join("apps/.snapshots")).unwrap().count(), 0);
+        let status: String = sqlx::query_scalar("SELECT status FROM package_tasks WHERE task_type = 'update'")
+            .fetch_one(&service.db)
+            .await
+            .unwrap();
+        assert_eq!(status, "failed");
+
+        let task_id = service.update(&manifest("1.3.0", vec![write_config("v2")]), None).await.unwrap();
+        assert_eq!(service.get_task(&task_id).await.unwrap().unwrap().status, "completed");
+        assert_eq!(std::fs::read_to_string(app_dir.join("config")).unwrap(), "v2");
+        let package = service.get_installed("notes").await.unwrap().unwrap();
+        assert_eq!(package.version, "1.3.0");
+        assert!(package.manifest_data.unwrap().contains("1.3.0"));
+        assert!(!dir.join("apps/.snapshots/notes").exists());
+        assert!(!dir.join("snapshots/notes.json").exists());
+
+        // Same or older versions are refused
+        assert!(service.update(&manifest("1.3.0", vec![]), None).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_install_plan_and_cascade_uninstall() {
+        let dir = std::env::temp_dir().join(format!("pinas-packages-{}", Uuid::new_v4()));
+        let service = service(&dir).await;
+
+        // Dependencies must be installed first
+        let web = app("web", &["runtime@^1", "notes"]);
+        assert!(service.install_plan(std::slice::from_ref(&web)).await.is_err());
+
+        let task_id = service.install_plan(&[app("runtime", &[]), web]).await.unwrap();
+        let task = service.get_task(&task_id).await.unwrap().unwrap();
+        assert_eq!((task.package_id.as_str(), task.status.as_str()), ("web", "completed"));
+        let children: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM package_tasks WHERE parent_task_id = ?")
+            .bind(&task_id)
+            .fetch_one(&service.db)
+            .await
+            .unwrap();
+        assert_eq!(children, 2);
+
+        assert_eq!(service.dependents("notes").await.unwrap(), vec!["web"]);
+        let err = service.uninstall("runtime", false).await.unwrap_err();
+        assert!(err.to_string().contains("required by web"), "{}", err);
+
+        let removed = service.uninstall_cascade("runtime").await.unwrap();
+        assert_eq!(removed, vec!["web", "runtime"]);
+        assert!(service.get_installed("runtime").await.unwrap().is_none());
+        assert!(service.dependents("notes").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_install_from_bundle() {
+        use crate::services::package_signing::{self, TrustPolicy};
+        use flate2::{write::GzEncoder, Compression};
+
+        let dir = std::env::temp_dir().join(format!("pinas-packages-{}", Uuid::new_v4()));
+        let service = service(&dir).await;
+        package_signing::set_allow_unsigned(&service.db, true).await.unwrap();
+
+        let mut app = app("tool", &[]).manifest;
+        app.install.steps = vec![
+            InstallStep::Download {
+                url: "https://example.invalid/releases/tool.bin".to_string(),
+                sha256: Some(hex::encode(Sha256::digest(b"tool v1"))),
+                dest: "${PACKAGES_DIR}/tool/tool.bin".to_string(),
+            },
+            InstallStep::Download {
+                url: "bundle:artifacts/missing.bin".to_string(),
+                sha256: None,
+                dest: "${PACKAGES_DIR}/tool/missing.bin".to_string(),
+            },
+        ];
+        let manifest = serde_json::to_vec(&app).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        let image = br#"[{"Config":"c.json","RepoTags":["tool:1.0"],"Layers":[]}]"#;
+        let mut image_tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(image.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        image_tar.append_data(&mut header, "manifest.json", image.as_slice()).unwrap();
+        let image_tar = image_tar.into_inner().unwrap();
+        for (path, content) in [
+            ("manifest.json", manifest.as_slice()),
+            ("artifacts/tool.bin", b"tool v1".as_slice()),
+            ("images/tool.tar", image_tar.as_slice()),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, content).unwrap();
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("tool.pinaspkg");
+        std::fs::write(&archive, builder.into_inner().unwrap().finish().unwrap()).unwrap();
+
+        let policy = TrustPolicy::load(&service.db).await.unwrap();
+        let bundle = Bundle::open(&archive, &service.sideload_dir().join("tool"), &policy).await.unwrap();
+        let manifest = bundle.manifest.clone();
+        let mut service = service.with_bundle(bundle);
+
+        // The first download comes from the bundle, the second is not bundled and never fetched
+        let err = service.install(&manifest, None).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("bundle:artifacts/missing.bin is not in the package bundle"), "{:#}", err);
+        assert_eq!(std::fs::read(service.package_dir("tool").join("tool.bin")).unwrap(), b"tool v1");
+
+        // Bundled images only load when the manifest vouches for the tarball
+        let mut pull = app.clone();
+        pull.id = "tool-image".to_string();
+        pull.install.steps = vec![InstallStep::DockerPull {
+            image: "docker.io/tool:1.0".to_string(),
+            sha256: Some(hex::encode(Sha256::digest(b"another image"))),
+        }];
+        let err = service.install(&pull, None).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("SHA256 mismatch"), "{:#}", err);
+
+        let mut bundle = service.take_bundle().unwrap();
+        bundle.allow_unsigned = false;
+        let mut service = service.with_bundle(bundle);
+        pull.id = "tool-unchecked".to_string();
+        pull.install.steps = vec![InstallStep::DockerPull { image: "tool:1.0".to_string(), sha256: None }];
+        let err = service.install(&pull, None).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("has no sha256 in the manifest"), "{:#}", err);
+
+        service.take_bundle().unwrap().remove().await;
+        assert!(!service.sideload_dir().join("tool").exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+    #[tokio::test]
+    async fn test_download_resumes_interrupted_transfer() {
+        use axum::{body::Body, http::HeaderMap, http::StatusCode, response::IntoResponse, routing::get, Router};
+        use std::sync::{Arc, Mutex};
+
+        const CONTENT: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let seen = ranges.clone();
+        let file = move |headers: HeaderMap| async move {
+            let range = headers.get("range").map(|v| v.to_str().unwrap().to_string());
+            seen.lock().unwrap().push(range.clone());
+            match range.and_then(|r| r.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok()) {
+                Some(start) => (
+                    StatusCode::PARTIAL_CONTENT,
+                    [("content-range", format!("bytes {}-{}/{}", start, CONTENT.len() - 1, CONTENT.len()))],
+                    Body::from(&CONTENT[start..]),
+                )
+                .into_response(),
+                // The first response is cut after 10 bytes
+                None => {
+                    use futures_util::StreamExt;
+                    let chunks = futures_util::stream::iter([Ok(&CONTENT[..10]), Err(std::io::Error::other("connection reset"))])
+                        .then(|chunk| async move {
+                            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                            chunk
+                        });
+                    Body::from_stream(chunks).into_response()
+                }
+            }
+        };
+        let app = Router::new().route("/tool.bin", get(file));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let dir = std::env::temp_dir().join(format!("pinas-packages-{}", Uuid::new_v4()));
+        let service = service(&dir).await;
+        let task_id = Uuid::new_v4().to_string();
+        sqlx::query("INSERT INTO package_tasks (id, package_id, task_type, status, created_at) VALUES (?, 'notes', 'install', 'running', ?)")
+            .bind(&task_id)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(&service.db)
+            .await
+            .unwrap();
+        let dest = dir.join("tool.bin").to_string_lossy().to_string();
+        let sha256 = hex::encode(Sha256::digest(CONTENT));
+
+        let url = format!("http://{}/tool.bin", addr);
+        service.download_file(&url, &dest, Some(&sha256), Some(&task_id)).await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), CONTENT);
+        assert!(!Path::new(&format!("{}{}", dest, PART_SUFFIX)).exists());
+        assert_eq!(*ranges.lock().unwrap(), vec![None, Some("bytes=10-".to_string())]);
+
+        let task = service.get_task(&task_id).await.unwrap().unwrap();
+        assert_eq!(task.bytes_done, Some(CONTENT.len() as i64));
+        assert_eq!(task.bytes_total, Some(CONTENT.len() as i64));
+
+        // Client errors are not retried
+        let missing = format!("http://{}/missing.bin", addr);
+        let err = service.download_file(&missing, &dest, None, None).await.unwrap_err();
+        assert!(err.to_string().contains("404"), "{:#}", err);
+
+        server.abort();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_download_retries_server_errors_and_rejects_bad_checksums() {
+        use axum::{http::StatusCode, response::IntoResponse, routing::get, Router};
+        use std::sync::{Arc, Mutex};
+        use std::time::Instant;
+
+        const CONTENT: &[u8] = b"package contents";
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        // Unavailable on the first request only
+        let flaky = move || async move {
+            let mut seen = seen.lock().unwrap();
+            seen.push(Instant::now());
+            if seen.len() == 1 {
+                StatusCode::SERVICE_UNAVAILABLE.into_response()
+            } else {
+                CONTENT.into_response()
+            }
+        };
+        let corrupt_requests = Arc::new(Mutex::new(0));
+        let counted = corrupt_requests.clone();
+        let corrupt = move || async move {
+            *counted.lock().unwrap() += 1;
+            b"tampered contents".as_slice()
+        };
+        let app = Router::new().route("/flaky.bin", get(flaky)).route("/corrupt.bin", get(corrupt));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let dir = std::env::temp_dir().join(format!("pinas-packages-{}", Uuid::new_v4()));
+        let service = service(&dir).await;
+        let sha256 = hex::encode(Sha256::digest(CONTENT));
+
+        let dest = dir.join("flaky.bin").to_string_lossy().to_string();
+        service.download_file(&format!("http://{}/flaky.bin", addr), &dest, Some(&sha256), None).await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), CONTENT);
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1] - requests[0] >= DOWNLOAD_RETRY_DELAY);
+
+        // A complete download with the wrong checksum is not retried and leaves nothing behind
+        let dest = dir.join("corrupt.bin").to_string_lossy().to_string();
+        let err = service
+            .download_file(&format!("http://{}/corrupt.bin", addr), &dest, Some(&sha256), None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("SHA256 mismatch"), "{:#}", err);
+        assert_eq!(*corrupt_requests.lock().unwrap(), 1);
+        assert!(!Path::new(&dest).exists());
+        assert!(!Path::new(&format!("{}{}", dest, PART_SUFFIX)).exists());
+
+        server.abort();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_exec_output_is_logged() {
+        let dir = std::env::temp_dir().join(format!("pinas-packages-{}", Uuid::new_v4()));
+        let service = service(&dir).await;
+        let mut app = app("tool", &[]).manifest;
+        app.install.steps = vec![exec("echo configured; echo 'no config' >&2"), exec("printf 'last line'")];
+
+        let task_id = service.install(&app, None).await.unwrap();
+        let logs = package_tasks::list_logs(&service.db, &task_id, 0).await.unwrap();
+        let lines: Vec<_> = logs.iter().map(|l| (l.step, l.stream.as_str(), l.line.as_str())).collect();
+        assert!(lines.contains(&(Some(0), "stdout", "configured")), "{:?}", lines);
+        assert!(lines.contains(&(Some(0), "stderr", "no config")), "{:?}", lines);
+        assert_eq!(lines.last(), Some(&(Some(1), "stdout", "last line")));
+
+        let after = logs[0].id;
+        assert_eq!(package_tasks::list_logs(&service.db, &task_id, after).await.unwrap().len(), logs.len() - 1);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_cancel_install_cleans_up() {
+        let dir = std::env::temp_dir().join(format!("pinas-packages-{}", Uuid::new_v4()));
+        let tasks = Arc::new(PackageTasks::new());
+        let service = service(&dir).await;
+        let db = service.db.clone();
+        let mut app = app("tool", &[]).manifest;
+        app.install.steps = vec![
+            InstallStep::Mkdir { path: "${PACKAGES_DIR}/tool".to_string() },
+            InstallStep::WriteFile { dest: "${DOWNLOADS_DIR}/tool.tar".to_string(), content: String::new() },
+            // The background process must not outlive the cancelled step
+            exec("sleep 30 & echo $! > ${DOWNLOADS_DIR}/sleep.pid; wait"),
+            exec("touch ${PACKAGES_DIR}/tool/never"),
+        ];
+        let plan = vec![PlannedPackage { manifest: app, manifest_url: None }];
+
+        let task_id = service.prepare_install(&plan).await.unwrap();
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+        let id = task_id.clone();
+        tasks.spawn(&task_id, move |cancel| async move {
+            let result = service.with_cancel(cancel).run_install(&id, &plan).await;
+            let _ = done_tx.send(result);
+        });
+
+        // Wait for the command to run
+        let pid_file = dir.join("downloads/sleep.pid");
+        for _ in 0..100 {
+            if std::fs::read_to_string(&pid_file).is_ok_and(|pid| pid.ends_with('\n')) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        let pid = std::fs::read_to_string(&pid_file).unwrap().trim().to_string();
+        assert!(tasks.cancel(&task_id));
+
+        let err = tokio::time::timeout(std::time::Duration::from_secs(5), done_rx).await.unwrap().unwrap().unwrap_err();
+        assert!(is_cancelled(&err), "{:#}", err);
+        assert!(!tasks.cancel(&task_id));
+
+        let task: (String, Option<String>) = sqlx::query_as("SELECT status, error_message FROM package_tasks WHERE id = ?")
+            .bind(&task_id)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(task, ("cancelled".to_string(), Some("Task cancelled".to_string())));
+        // Killed processes reparented to an init that does not reap them stay zombies
+        let state = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+        assert!(state.is_empty() || state.contains(") Z "), "sleep still running: {}", state);
+        assert!(!dir.join("apps/tool").exists());
+        assert!(!dir.join("downloads/tool.tar").exists());
+        let records: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM installed_packages WHERE id = 'tool'")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(records, 0);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_uninstall_claims_the_package() {
+        let dir = std::env::temp_dir().join(format!("pinas-packages-{}", Uuid::new_v4()));
+        let service = service(&dir).await;
+        let db = service.db.clone();
+        let status = || async {
+            sqlx::query_scalar::<_, String>("SELECT status FROM installed_packages WHERE id = 'notes'")
+                .fetch_one(&db)
+                .await
+                .unwrap()
+        };
+
+        // A package an update is working on is not removed under it
+        sqlx::query("UPDATE installed_packages SET status = 'updating' WHERE id = 'notes'").execute(&db).await.unwrap();
+        let err = service.prepare_uninstall("notes", false).await.err().unwrap();
+        assert!(err.is::<PackageBusy>(), "{:#}", err);
+        assert_eq!(status().await, "updating");
+
+        sqlx::query("UPDATE installed_packages SET status = 'error' WHERE id = 'notes'").execute(&db).await.unwrap();
+        let pending = service.prepare_uninstall("notes", false).await.unwrap();
+        assert_eq!(status().await, "removing");
+        assert!(service.prepare_uninstall("notes", false).await.err().unwrap().is::<PackageBusy>());
+
+        // Cancelled before the removal started, the package gets its status back
+        let tasks = Arc::new(PackageTasks::new());
+        let (start_tx, start_rx) = tokio::sync::oneshot::channel::<()>();
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+        let task_id = pending.task_id.clone();
+        tasks.spawn(&task_id, move |cancel| async move {
+            let _ = start_rx.await;
+            let _ = done_tx.send(service.with_cancel(cancel).run_uninstall(pending, false).await);
+        });
+        assert!(tasks.cancel(&task_id));
+        start_tx.send(()).unwrap();
+        assert!(package_tasks::is_cancelled(&done_rx.await.unwrap().unwrap_err()));
+        assert_eq!(status().await, "error");
+    }
+
+    #[tokio::test]
+    async fn test_uninstall_removes_tracked_files() {
+        let dir = std::env::temp_dir().join(format!("pinas-packages-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("system.conf"), "system").unwrap();
+        let service = service(&dir).await;
+        let mut app = app("tool", &[]).manifest;
+        app.install.steps = vec![
+            // Overwritten, not created: the file stays when the package goes
+            InstallStep::WriteFile { dest: "${DATA_DIR}/system.conf".to_string(), content: "aGVsbG8=".to_string() },
+            InstallStep::WriteFile { dest: "${PACKAGES_DIR}/tool/etc/tool.conf".to_string(), content: "aGVsbG8=".to_string() },
+            InstallStep::Mkdir { path: "${DATA_DIR}/tool/db".to_string() },
+            InstallStep::WriteFile { dest: "${DATA_DIR}/tool/db/state".to_string(), content: "aGVsbG8=".to_string() },
+        ];
+        app.uninstall.data_paths = vec!["${DATA_DIR}/tool".to_string()];
+        service.install(&app, None).await.unwrap();
+
+        let files: Vec<(String, String, Option<String>)> = sqlx::query_as(
+            "SELECT path, file_type, sha256 FROM package_files WHERE package_id = 'tool' ORDER BY id"
+        )
+        .fetch_all(&service.db)
+        .await
+        .unwrap();
+        let conf = dir.join("apps/tool/etc/tool.conf").to_string_lossy().to_string();
+        let paths: Vec<_> = files.iter().map(|(path, file_type, _)| (path.strip_prefix(&*dir.to_string_lossy()).unwrap(), file_type.as_str())).collect();
+        assert_eq!(paths, vec![
+            ("/apps", "directory"),
+            ("/apps/tool", "directory"),
+            ("/apps/tool/etc", "directory"),
+            ("/apps/tool/etc/tool.conf", "config"),
+            ("/tool", "data"),
+            ("/tool/db", "data"),
+            ("/tool/db/state", "data"),
+        ]);
+        assert_eq!(files[3].2.as_deref(), Some("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"));
+
+        std::fs::write(&conf, "edited").unwrap();
+        let report = service.uninstall("tool", true).await.unwrap();
+        assert_eq!(report.modified, vec![conf]);
+        assert_eq!(report.kept.len(), 3);
+        assert!(!dir.join("apps").exists());
+        assert!(dir.join("tool/db/state").exists());
+        assert!(dir.join("system.conf").exists());
+        let tracked: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM package_files WHERE package_id = 'tool'")
+            .fetch_one(&service.db)
+            .await
+            .unwrap();
+        assert_eq!(tracked, 0);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_verify_and_repair() {
+        let dir = std::env::temp_dir().join(format!("pinas-packages-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let service = service(&dir).await;
+        let mut app = app("tool", &[]).manifest;
+        app.install.steps = vec![
+            InstallStep::Mkdir { path: "${PACKAGES_DIR}/tool".to_string() },
+            InstallStep::WriteFile { dest: "${DOWNLOADS_DIR}/tool.bin".to_string(), content: "aGVsbG8=".to_string() },
+            InstallStep::Copy { src: "${DOWNLOADS_DIR}/tool.bin".to_string(), dest: "${PACKAGES_DIR}/tool/tool".to_string() },
+            InstallStep::Delete { path: "${DOWNLOADS_DIR}/tool.bin".to_string() },
+            InstallStep::Chmod { path: "${PACKAGES_DIR}/tool/tool".to_string(), mode: "755".to_string() },
+            InstallStep::WriteFile { dest: "${PACKAGES_DIR}/tool/tool.conf".to_string(), content: "aGVsbG8=".to_string() },
+        ];
+        service.install(&app, None).await.unwrap();
+        assert!(service.verify("tool").await.unwrap().is_intact());
+
+        let binary = dir.join("apps/tool/tool");
+        let conf = dir.join("apps/tool/tool.conf");
+        std::fs::remove_file(&binary).unwrap();
+        std::fs::write(&conf, "edited").unwrap();
+        std::fs::write(dir.join("apps/tool/notes.txt"), "").unwrap();
+        let report = service.verify("tool").await.unwrap();
+        let path = |p: &Path| p.to_string_lossy().to_string();
+        assert_eq!(report.missing, vec![path(&binary)]);
+        assert_eq!(report.modified, vec![path(&conf)]);
+        assert_eq!(report.extra, vec![path(&dir.join("apps/tool/notes.txt"))]);
+
+        let (_, pending) = service.prepare_repair("tool").await.unwrap();
+        let pending = pending.unwrap();
+        // The copied file comes back from its deleted source, the directory and the commands are left alone
+        assert_eq!(pending.steps, vec![1, 2, 3, 4, 5]);
+        let task_id = pending.task_id.clone();
+        service.run_repair(pending).await.unwrap();
+
+        assert_eq!(std::fs::read(&binary).unwrap(), b"hello");
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&std::fs::metadata(&binary).unwrap().permissions()) & 0o777, 0o755);
+        assert_eq!(std::fs::read(&conf).unwrap(), b"hello");
+        assert!(!dir.join("downloads/tool.bin").exists());
+        assert!(service.verify("tool").await.unwrap().is_intact());
+        let task = service.get_task(&task_id).await.unwrap().unwrap();
+        assert_eq!((task.task_type.as_str(), task.status.as_str()), ("repair", "completed"));
+        let package = service.get_installed("tool").await.unwrap().unwrap();
+        assert_eq!(package.status, "installed");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_drift_is_notified_once() {
+        let dir = std::env::temp_dir().join(format!("pinas-packages-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let service = service(&dir).await;
+        let events = EventBus::new(16);
+        let mut app = app("tool", &[]).manifest;
+        app.install.steps = vec![
+            InstallStep::WriteFile { dest: "${PACKAGES_DIR}/tool/tool.conf".to_string(), content: "aGVsbG8=".to_string() },
+        ];
+        service.install(&app, None).await.unwrap();
+        let notifications = || async {
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM notifications WHERE category = 'packages'")
+                .fetch_one(&service.db)
+                .await
+                .unwrap()
+        };
+
+        assert!(service.verify_installed(&events).await.unwrap().is_empty());
+        std::fs::write(dir.join("apps/tool/tool.conf"), "edited").unwrap();
+        assert_eq!(service.verify_installed(&events).await.unwrap().len(), 1);
+        assert_eq!(notifications().await, 1);
+        // The same drift found again is not notified twice
+        assert_eq!(service.verify_installed(&events).await.unwrap().len(), 1);
+        assert_eq!(notifications().await, 1);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_tasks_are_settled_at_startup() {
+        let dir = std::env::temp_dir().join(format!("pinas-packages-{}", Uuid::new_v4()));
+        let service = service(&dir).await;
+        let app_dir = service.package_dir("notes");
+        std::fs::create_dir_all(&app_dir).unwrap();
+        std::fs::write(app_dir.join("config"), "v1").unwrap();
+
+        // An update the restart stopped halfway, its snapshot taken
+        let package = service.get_installed("notes").await.unwrap().unwrap();
+        service.take_snapshot(&package).await.unwrap();
+        std::fs::write(app_dir.join("config"), "v2").unwrap();
+        sqlx::raw_sql(
+            r#"UPDATE installed_packages SET status = 'updating', version = '1.3.0' WHERE id = 'notes';
+            INSERT INTO installed_packages (id, name, version, package_type, status, installed_at, updated_at)
+            VALUES ('half', 'Half', '1.0.0', 'binary', 'installing', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z');"#,
+        )
+        .execute(&service.db)
+        .await
+        .unwrap();
+
+        service.recover_interrupted().await.unwrap();
+        assert_eq!(std::fs::read_to_string(app_dir.join("config")).unwrap(), "v1");
+        let notes = service.get_installed("notes").await.unwrap().unwrap();
+        assert_eq!((notes.status.as_str(), notes.version.as_str()), ("installed", "1.2.0"));
+        assert!(!service.snapshot_record("notes").exists());
+        let half = service.get_installed("half").await.unwrap().unwrap();
+        assert_eq!(half.status, "error");
+        assert_eq!(half.error_message.as_deref(), Some("Interrupted by a restart"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
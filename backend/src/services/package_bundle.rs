@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::models::manifest::PackageManifest;
+use crate::services::package_signing::{SigningError, TrustPolicy, SIGNATURE_SUFFIX};
+
+/// Extension of package bundles, gzipped tarballs installed without network access
+pub const BUNDLE_EXTENSION: &str = "pinaspkg";
+
+/// Manifest at the root of a bundle, optionally signed by `manifest.json.sig` next to it
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Files `Download` steps resolve to by the last segment of their URL
+const ARTIFACTS_DIR: &str = "artifacts";
+
+/// `docker save` tarballs, loaded by `DockerPull` steps of an image they contain
+/// when the tarball matches the step's sha256
+const IMAGES_DIR: &str = "images";
+
+/// Download URLs naming a file of the bundle explicitly, e.g. `bundle:artifacts/app.tar.gz`
+const BUNDLE_SCHEME: &str = "bundle:";
+
+/// Package bundle errors
+#[derive(Debug, Error)]
+pub enum BundleError {
+    #[error("Invalid package bundle: {0}")]
+    Invalid(String),
+
+    #[error(transparent)]
+    Signing(#[from] SigningError),
+
+    #[error("Bundle I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// An unpacked bundle, its files stay on disk until it is removed
+#[derive(Debug)]
+pub struct Bundle {
+    root: PathBuf,
+    pub manifest: PackageManifest,
+    /// Normalized image references to the tarball holding them
+    images: HashMap<String, PathBuf>,
+    /// Whether the administrator allows content nothing vouches for,
+    /// bundled images then load without a checksum in the manifest
+    pub allow_unsigned: bool,
+}
+
+impl Bundle {
+    /// Unpack a bundle into `dest` and verify its manifest.
+    /// `dest` is removed again when the bundle is refused.
+    pub async fn open(archive: &Path, dest: &Path, policy: &TrustPolicy) -> Result<Self, BundleError> {
+        let (archive_path, root) = (archive.to_path_buf(), dest.to_path_buf());
+        let unpacked = tokio::task::spawn_blocking(move || {
+            unpack(&archive_path, &root)?;
+            index_images(&root.join(IMAGES_DIR))
+        })
+        .await
+        .map_err(|e| BundleError::Invalid(e.to_string()))
+        .and_then(|result| result);
+
+        let result = match unpacked {
+            Ok(images) => read_manifest(dest, policy).await.map(|manifest| Self {
+                root: dest.to_path_buf(),
+                manifest,
+                images,
+                allow_unsigned: policy.allow_unsigned,
+            }),
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            if let Err(e) = tokio::fs::remove_dir_all(dest).await {
+                tracing::warn!("Failed to remove refused bundle {}: {}", dest.display(), e);
+            }
+        }
+        result
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Bundled file a `Download` step URL resolves to
+    pub fn artifact(&self, url: &str) -> Option<PathBuf> {
+        let path = match url.strip_prefix(BUNDLE_SCHEME) {
+            Some(relative) => {
+                let relative = Path::new(relative.trim_start_matches('/'));
+                if !is_contained(relative) {
+                    return None;
+                }
+                self.root.join(relative)
+            }
+            None => {
+                let name = reqwest::Url::parse(url)
+                    .ok()
+                    .and_then(|u| u.path_segments()?.next_back().map(str::to_string))
+                    .filter(|name| !name.is_empty() && is_contained(Path::new(name)))?;
+                self.root.join(ARTIFACTS_DIR).join(name)
+            }
+        };
+        path.is_file().then_some(path)
+    }
+
+    /// Whether a Download URL must come from the bundle, plain URLs may still be downloaded
+    pub fn is_bundle_url(url: &str) -> bool {
+        url.starts_with(BUNDLE_SCHEME)
+    }
+
+    /// Bundled tarball claiming to hold an image, by the tags it declares itself.
+    /// Check it against the manifest before loading it.
+    pub fn image(&self, reference: &str) -> Option<&Path> {
+        self.images.get(&normalize_image(reference)).map(PathBuf::as_path)
+    }
+
+    /// Delete the unpacked files
+    pub async fn remove(self) {
+        if let Err(e) = tokio::fs::remove_dir_all(&self.root).await {
+            tracing::warn!("Failed to remove unpacked bundle {}: {}", self.root.display(), e);
+        }
+    }
+}
+
+/// Whether a relative path stays inside the directory it is joined to
+fn is_contained(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Extract regular files and directories only, links could point outside of the bundle
+fn unpack(archive: &Path, dest: &Path) -> Result<(), BundleError> {
+    std::fs::create_dir_all(dest)?;
+    let mut tar = tar::Archive::new(GzDecoder::new(std::fs::File::open(archive)?));
+    let entries = tar.entries().map_err(|e| BundleError::Invalid(e.to_string()))?;
+    for entry in entries {
+        let mut entry = entry.map_err(|e| BundleError::Invalid(e.to_string()))?;
+        let path = entry.path().map_err(|e| BundleError::Invalid(e.to_string()))?.into_owned();
+        let kind = entry.header().entry_type();
+        if !(kind.is_file() || kind.is_dir()) {
+            return Err(BundleError::Invalid(format!("{} is not a regular file", path.display())));
+        }
+        let relative: PathBuf = path.components().filter(|c| !matches!(c, Component::CurDir)).collect();
+        if !is_contained(&relative) {
+            return Err(BundleError::Invalid(format!("{} points outside of the bundle", path.display())));
+        }
+        if !entry.unpack_in(dest).map_err(|e| BundleError::Invalid(e.to_string()))? {
+            return Err(BundleError::Invalid(format!("{} points outside of the bundle", path.display())));
+        }
+    }
+    Ok(())
+}
+
+async fn read_manifest(root: &Path, policy: &TrustPolicy) -> Result<PackageManifest, BundleError> {
+    let path = root.join(MANIFEST_FILE);
+    let document = tokio::fs::read(&path)
+        .await
+        .map_err(|_| BundleError::Invalid(format!("{} is missing", MANIFEST_FILE)))?;
+    let signature = tokio::fs::read(root.join(format!("{}{}", MANIFEST_FILE, SIGNATURE_SUFFIX))).await.ok();
+
+    let label = format!("Bundle manifest {}", MANIFEST_FILE);
+    if let Some(key_id) = policy.verify(&label, &document, signature.as_deref())? {
+        tracing::debug!("Verified bundle manifest with key {}", key_id);
+    }
+    serde_json::from_slice(&document).map_err(|e| BundleError::Invalid(format!("{}: {}", MANIFEST_FILE, e)))
+}
+
+/// Entry of the manifest.json written by `docker save`
+#[derive(Deserialize)]
+struct SavedImage {
+    #[serde(rename = "RepoTags", default)]
+    repo_tags: Option<Vec<String>>,
+}
+
+/// Map the tags of every bundled image tarball to its path
+fn index_images(dir: &Path) -> Result<HashMap<String, PathBuf>, BundleError> {
+    let mut images = HashMap::new();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(images);
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let tags = saved_image_tags(&path)
+            .map_err(|e| BundleError::Invalid(format!("{}: {}", path.display(), e)))?;
+        for tag in tags {
+            images.insert(normalize_image(&tag), path.clone());
+        }
+    }
+    Ok(images)
+}
+
+/// Tags listed by a `docker save` tarball, gzipped or not
+fn saved_image_tags(path: &Path) -> anyhow::Result<Vec<String>> {
+    let mut magic = [0u8; 2];
+    let gzipped = std::fs::File::open(path)?.read_exact(&mut magic).is_ok() && magic == [0x1f, 0x8b];
+    let file = std::fs::File::open(path)?;
+    let reader: Box<dyn Read> = if gzipped { Box::new(GzDecoder::new(file)) } else { Box::new(file) };
+
+    let mut tar = tar::Archive::new(reader);
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.as_ref() == Path::new(MANIFEST_FILE) {
+            let saved: Vec<SavedImage> = serde_json::from_reader(&mut entry)?;
+            return Ok(saved.into_iter().flat_map(|image| image.repo_tags.unwrap_or_default()).collect());
+        }
+    }
+    anyhow::bail!("not a docker save archive")
+}
+
+/// Canonical form of an image reference, `nginx` and `docker.io/library/nginx:latest` being the same image
+fn normalize_image(reference: &str) -> String {
+    let mut name = reference.trim();
+    for prefix in ["docker.io/", "index.docker.io/"] {
+        name = name.strip_prefix(prefix).unwrap_or(name);
+    }
+    let name = name.strip_prefix("library/").unwrap_or(name);
+    let last = name.rsplit('/').next().unwrap_or(name);
+    if last.contains(':') || name.contains('@') {
+        name.to_string()
+    } else {
+        format!("{}:latest", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+
+    async fn policy(allow_unsigned: bool) -> TrustPolicy {
+        let db = crate::db::test_pool().await;
+        crate::services::package_signing::set_allow_unsigned(&db, allow_unsigned).await.unwrap();
+        TrustPolicy::load(&db).await.unwrap()
+    }
+
+    fn tar_of(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn write_bundle(dir: &Path, files: &[(&str, &[u8])]) -> PathBuf {
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join(format!("app.{}", BUNDLE_EXTENSION));
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        std::io::Write::write_all(&mut encoder, &tar_of(files)).unwrap();
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+        path
+    }
+
+    const MANIFEST: &[u8] = br#"{
+        "id": "app", "name": "App", "version": "1.0.0", "description": {},
+        "install": { "type": "docker", "steps": [] }
+    }"#;
+
+    #[test]
+    fn test_normalize_image() {
+        assert_eq!(normalize_image("nginx"), "nginx:latest");
+        assert_eq!(normalize_image("docker.io/library/nginx:1.25"), "nginx:1.25");
+        assert_eq!(normalize_image("ghcr.io/org/app"), "ghcr.io/org/app:latest");
+        assert_eq!(normalize_image("localhost:5000/app"), "localhost:5000/app:latest");
+        assert_eq!(normalize_image("app@sha256:abc"), "app@sha256:abc");
+    }
+
+    #[tokio::test]
+    async fn test_open_bundle() {
+        let dir = std::env::temp_dir().join(format!("pinas-bundle-{}", uuid::Uuid::new_v4()));
+        let image = tar_of(&[("manifest.json", br#"[{"Config":"c.json","RepoTags":["nginx:1.25"],"Layers":[]}]"#)]);
+        let archive = write_bundle(&dir, &[
+            ("manifest.json", MANIFEST),
+            ("artifacts/app.bin", b"binary"),
+            ("images/nginx.tar", &image),
+        ]);
+
+        let bundle = Bundle::open(&archive, &dir.join("unpacked"), &policy(true).await).await.unwrap();
+        assert_eq!(bundle.manifest.id, "app");
+        assert_eq!(bundle.artifact("https://example.com/releases/v1/app.bin"), Some(bundle.root().join("artifacts/app.bin")));
+        assert_eq!(bundle.artifact("bundle:artifacts/app.bin"), Some(bundle.root().join("artifacts/app.bin")));
+        assert_eq!(bundle.artifact("bundle:../app.pinaspkg"), None);
+        assert_eq!(bundle.artifact("https://example.com/other.bin"), None);
+        assert_eq!(bundle.image("docker.io/nginx:1.25"), Some(bundle.root().join("images/nginx.tar").as_path()));
+        assert_eq!(bundle.image("nginx"), None);
+
+        let root = bundle.root().to_path_buf();
+        bundle.remove().await;
+        assert!(!root.exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_refuse_bundle() {
+        let dir = std::env::temp_dir().join(format!("pinas-bundle-{}", uuid::Uuid::new_v4()));
+
+        // Unsigned manifests need the administrator opt-in
+        let archive = write_bundle(&dir, &[("manifest.json", MANIFEST)]);
+        let err = Bundle::open(&archive, &dir.join("unsigned"), &policy(false).await).await.unwrap_err();
+        assert!(matches!(err, BundleError::Signing(SigningError::Unsigned(_))), "{}", err);
+        assert!(!dir.join("unsigned").exists());
+
+        let archive = write_bundle(&dir, &[("artifacts/app.bin", b"binary")]);
+        let err = Bundle::open(&archive, &dir.join("empty"), &policy(true).await).await.unwrap_err();
+        assert!(matches!(err, BundleError::Invalid(_)), "{}", err);
+
+        // Entries escaping the bundle, tar::Builder refuses to write them
+        let mut data = tar_of(&[("manifest.json", MANIFEST), ("xx/evil", b"evil")]);
+        let name = data.windows(7).position(|w| w == b"xx/evil").unwrap();
+        data[name..name + 2].copy_from_slice(b"..");
+        let header = &mut data[name - (name % 512)..][..512];
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|b| *b as u32).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        std::io::Write::write_all(&mut encoder, &data).unwrap();
+        std::fs::write(&archive, encoder.finish().unwrap()).unwrap();
+        let err = Bundle::open(&archive, &dir.join("evil"), &policy(true).await).await.unwrap_err();
+        assert!(err.to_string().contains("outside of the bundle"), "{}", err);
+        assert!(!dir.join("evil").exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
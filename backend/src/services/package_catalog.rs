@@ -0,0 +1,707 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use thiserror::Error;
+
+use crate::models::package::CatalogSource;
+use crate::services::package_signing::{self, SigningError, TrustPolicy, SIGNATURE_SUFFIX};
+use crate::services::settings;
+
+/// Settings key of the offline mode, serving cached catalogs without contacting sources
+const KEY_OFFLINE: &str = "packages.catalog_offline";
+
+/// Source seeded by the migrations, its URL can be overridden by PINAS_CATALOG_URL
+pub const OFFICIAL_SOURCE: &str = "official";
+
+/// Cached catalogs younger than this are served without contacting their source
+const REFRESH_INTERVAL_MINUTES: i64 = 15;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Catalog source errors
+#[derive(Debug, Error)]
+pub enum CatalogError {
+    #[error("Catalog source not found")]
+    NotFound,
+
+    #[error("Invalid catalog source: {0}")]
+    Validation(String),
+
+    #[error(transparent)]
+    Signing(#[from] SigningError),
+
+    #[error("Catalog cache error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Settings error: {0}")]
+    Settings(#[from] settings::SettingsError),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// New catalog source
+#[derive(Debug, Clone, Deserialize)]
+pub struct SourceInput {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub priority: i64,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Changes to a catalog source, missing fields are kept
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SourceUpdate {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub priority: Option<i64>,
+    pub enabled: Option<bool>,
+}
+
+/// How the catalog of a source was obtained
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceState {
+    /// Fetched or revalidated just now
+    Fresh,
+    /// Cached copy recent enough to skip the source
+    Cached,
+    /// Last good copy, the source being unreachable or offline mode on
+    Stale,
+    /// No usable catalog
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceStatus {
+    pub id: String,
+    pub name: String,
+    pub state: SourceState,
+    pub fetched_at: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Apps of all enabled sources, each app taken from the highest priority source listing it
+#[derive(Debug, Clone, Serialize)]
+pub struct MergedCatalog {
+    pub version: String,
+    pub updated: String,
+    pub apps: Vec<serde_json::Value>,
+    pub sources: Vec<SourceStatus>,
+    pub offline: bool,
+}
+
+/// Directory holding the last good copy of each source
+pub fn cache_dir(data_dir: &str) -> PathBuf {
+    Path::new(data_dir).join("catalogs")
+}
+
+/// Whether catalogs are served from the cache only
+pub async fn is_offline(db: &SqlitePool) -> Result<bool, CatalogError> {
+    Ok(settings::get_setting(db, KEY_OFFLINE).await?.as_deref() == Some("true"))
+}
+
+pub async fn set_offline(db: &SqlitePool, offline: bool) -> Result<(), CatalogError> {
+    settings::set_setting(db, KEY_OFFLINE, if offline { "true" } else { "false" }).await?;
+    Ok(())
+}
+
+/// List sources, highest priority first
+pub async fn list_sources(db: &SqlitePool) -> Result<Vec<CatalogSource>, CatalogError> {
+    Ok(sqlx::query_as::<_, CatalogSource>("SELECT * FROM catalog_sources ORDER BY priority DESC, name")
+        .fetch_all(db)
+        .await?)
+}
+
+pub async fn get_source(db: &SqlitePool, id: &str) -> Result<CatalogSource, CatalogError> {
+    sqlx::query_as::<_, CatalogSource>("SELECT * FROM catalog_sources WHERE id = ?")
+        .bind(id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(CatalogError::NotFound)
+}
+
+pub async fn create_source(db: &SqlitePool, input: SourceInput) -> Result<CatalogSource, CatalogError> {
+    let name = validate_name(&input.name)?;
+    let url = validate_url(&input.url)?;
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    sqlx::query(
+        r#"INSERT INTO catalog_sources (id, name, url, priority, enabled, created_at, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+    )
+    .bind(&id)
+    .bind(name)
+    .bind(url)
+    .bind(input.priority)
+    .bind(input.enabled)
+    .bind(&now)
+    .bind(&now)
+    .execute(db)
+    .await?;
+
+    get_source(db, &id).await
+}
+
+pub async fn update_source(db: &SqlitePool, id: &str, update: SourceUpdate) -> Result<CatalogSource, CatalogError> {
+    let current = get_source(db, id).await?;
+    let name = match &update.name {
+        Some(name) => validate_name(name)?,
+        None => &current.name,
+    };
+    let url = match &update.url {
+        Some(url) => validate_url(url)?,
+        None => &current.url,
+    };
+    // Validators of another URL would revalidate the wrong document,
+    // the cached copy stays the fallback until the new URL is fetched
+    let url_changed = url != current.url;
+
+    sqlx::query(
+        r#"UPDATE catalog_sources SET name = ?, url = ?, priority = ?, enabled = ?,
+           etag = CASE WHEN ? THEN NULL ELSE etag END,
+           last_modified = CASE WHEN ? THEN NULL ELSE last_modified END,
+           fetched_at = CASE WHEN ? THEN NULL ELSE fetched_at END,
+           updated_at = ?
+           WHERE id = ?"#,
+    )
+    .bind(name)
+    .bind(url)
+    .bind(update.priority.unwrap_or(current.priority))
+    .bind(update.enabled.unwrap_or(current.enabled))
+    .bind(url_changed)
+    .bind(url_changed)
+    .bind(url_changed)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(id)
+    .execute(db)
+    .await?;
+
+    get_source(db, id).await
+}
+
+/// Delete a source and its cached catalog
+pub async fn delete_source(db: &SqlitePool, cache_dir: &Path, id: &str) -> Result<(), CatalogError> {
+    let result = sqlx::query("DELETE FROM catalog_sources WHERE id = ?")
+        .bind(id)
+        .execute(db)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(CatalogError::NotFound);
+    }
+    let (document, signature) = cache_paths(cache_dir, id);
+    for path in [document, signature] {
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("Failed to remove cached catalog {}: {}", path.display(), e);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_name(name: &str) -> Result<&str, CatalogError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(CatalogError::Validation("a name is required".to_string()));
+    }
+    Ok(name)
+}
+
+fn validate_url(url: &str) -> Result<&str, CatalogError> {
+    let url = url.trim();
+    match Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(url),
+        _ => Err(CatalogError::Validation("the URL must be an http(s) URL".to_string())),
+    }
+}
+
+/// URL a source is fetched from
+fn source_url(source: &CatalogSource) -> String {
+    if source.id == OFFICIAL_SOURCE {
+        if let Ok(url) = std::env::var("PINAS_CATALOG_URL") {
+            return url;
+        }
+    }
+    source.url.clone()
+}
+
+fn cache_paths(cache_dir: &Path, id: &str) -> (PathBuf, PathBuf) {
+    (
+        cache_dir.join(format!("{}.json", id)),
+        cache_dir.join(format!("{}.json{}", id, SIGNATURE_SUFFIX)),
+    )
+}
+
+/// Cached document of a source and its signature, if any
+struct CachedCatalog {
+    document: Vec<u8>,
+    signature: Option<Vec<u8>>,
+}
+
+async fn read_cache(cache_dir: &Path, id: &str) -> Option<CachedCatalog> {
+    let (document, signature) = cache_paths(cache_dir, id);
+    let document = tokio::fs::read(document).await.ok()?;
+    Some(CachedCatalog {
+        document,
+        signature: tokio::fs::read(signature).await.ok(),
+    })
+}
+
+/// Replace the cached copy of a source, the document being written last so a partial write is never served
+async fn write_cache(cache_dir: &Path, id: &str, cached: &CachedCatalog) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(cache_dir).await?;
+    let (document, signature) = cache_paths(cache_dir, id);
+    match &cached.signature {
+        Some(bytes) => tokio::fs::write(&signature, bytes).await?,
+        None => match tokio::fs::remove_file(&signature).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        },
+    }
+    let partial = document.with_extension("json.tmp");
+    tokio::fs::write(&partial, &cached.document).await?;
+    tokio::fs::rename(&partial, &document).await
+}
+
+/// Apps listed by a catalog document
+fn parse_apps(document: &[u8]) -> Option<Vec<serde_json::Value>> {
+    let catalog: serde_json::Value = serde_json::from_slice(document).ok()?;
+    catalog.get("apps")?.as_array().cloned()
+}
+
+/// Outcome of loading one source
+struct SourceCatalog {
+    state: SourceState,
+    apps: Vec<serde_json::Value>,
+    error: Option<String>,
+    /// Set when the source was refused by signature verification
+    refused: Option<SigningError>,
+}
+
+impl SourceCatalog {
+    fn failed(error: String) -> Self {
+        Self {
+            state: SourceState::Failed,
+            apps: Vec::new(),
+            error: Some(error),
+            refused: None,
+        }
+    }
+
+    fn refused(error: SigningError) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            refused: Some(error),
+            ..Self::failed(String::new())
+        }
+    }
+}
+
+/// Response of a conditional GET
+enum Fetched {
+    NotModified,
+    Document {
+        body: Vec<u8>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+}
+
+async fn fetch_conditional(url: &str, cached: Option<&CatalogSource>) -> Result<Fetched, String> {
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut request = client.get(url);
+    if let Some(source) = cached {
+        if let Some(etag) = &source.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &source.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    if response.status() == StatusCode::NOT_MODIFIED && cached.is_some() {
+        return Ok(Fetched::NotModified);
+    }
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    Ok(Fetched::Document {
+        body: body.to_vec(),
+        etag,
+        last_modified,
+    })
+}
+
+/// Whether a cached catalog fetched at that time is recent enough to skip its source
+fn is_recent(fetched_at: Option<&str>) -> bool {
+    fetched_at
+        .and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok())
+        .is_some_and(|at| chrono::Utc::now().signed_duration_since(at) < chrono::Duration::minutes(REFRESH_INTERVAL_MINUTES))
+}
+
+/// Catalog of a cached copy, checked against the current trust policy since keys may have been revoked
+fn from_cache(policy: &TrustPolicy, url: &str, cached: &CachedCatalog, state: SourceState, error: Option<String>) -> SourceCatalog {
+    if let Err(e) = policy.verify(url, &cached.document, cached.signature.as_deref()) {
+        return SourceCatalog::refused(e);
+    }
+    match parse_apps(&cached.document) {
+        Some(apps) => SourceCatalog {
+            state,
+            apps,
+            error,
+            refused: None,
+        },
+        None => SourceCatalog::failed("Cached catalog is not valid".to_string()),
+    }
+}
+
+async fn record_fetch(db: &SqlitePool, id: &str, error: Option<&str>, validators: Option<(&Option<String>, &Option<String>)>) {
+    let now = chrono::Utc::now().to_rfc3339();
+    let result = match (error, validators) {
+        (Some(error), _) => {
+            sqlx::query("UPDATE catalog_sources SET last_error = ? WHERE id = ?")
+                .bind(error)
+                .bind(id)
+                .execute(db)
+                .await
+        }
+        (None, Some((etag, last_modified))) => {
+            sqlx::query("UPDATE catalog_sources SET etag = ?, last_modified = ?, fetched_at = ?, last_error = NULL WHERE id = ?")
+                .bind(etag)
+                .bind(last_modified)
+                .bind(&now)
+                .bind(id)
+                .execute(db)
+                .await
+        }
+        (None, None) => {
+            sqlx::query("UPDATE catalog_sources SET fetched_at = ?, last_error = NULL WHERE id = ?")
+                .bind(&now)
+                .bind(id)
+                .execute(db)
+                .await
+        }
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to record catalog fetch of source {}: {}", id, e);
+    }
+}
+
+/// Catalog of one source: cached when recent or offline, revalidated otherwise,
+/// the last good copy being served when the source is unreachable
+async fn load_source(
+    db: &SqlitePool,
+    cache_dir: &Path,
+    policy: &TrustPolicy,
+    source: &CatalogSource,
+    offline: bool,
+    force: bool,
+) -> SourceCatalog {
+    let url = source_url(source);
+    let cached = read_cache(cache_dir, &source.id).await;
+
+    if offline {
+        return match &cached {
+            Some(cached) => from_cache(policy, &url, cached, SourceState::Stale, None),
+            None => SourceCatalog::failed("Offline mode is on and this source was never fetched".to_string()),
+        };
+    }
+    if let Some(cached) = &cached {
+        if !force && is_recent(source.fetched_at.as_deref()) {
+            return from_cache(policy, &url, cached, SourceState::Cached, None);
+        }
+    }
+
+    let unreachable = |error: String| {
+        tracing::warn!("Catalog source {} unreachable: {}", source.name, error);
+        match &cached {
+            Some(cached) => from_cache(policy, &url, cached, SourceState::Stale, Some(error.clone())),
+            None => SourceCatalog::failed(error.clone()),
+        }
+    };
+
+    match fetch_conditional(&url, cached.as_ref().map(|_| source)).await {
+        Err(error) => {
+            let outcome = unreachable(error);
+            record_fetch(db, &source.id, outcome.error.as_deref(), None).await;
+            outcome
+        }
+        Ok(Fetched::NotModified) => {
+            let outcome = from_cache(policy, &url, cached.as_ref().expect("revalidated a cached copy"), SourceState::Fresh, None);
+            record_fetch(db, &source.id, outcome.error.as_deref(), None).await;
+            outcome
+        }
+        Ok(Fetched::Document { body, etag, last_modified }) => {
+            let signature = match package_signing::fetch_signature(&url).await {
+                Ok(signature) => signature,
+                Err(e) => {
+                    let outcome = unreachable(e.to_string());
+                    record_fetch(db, &source.id, outcome.error.as_deref(), None).await;
+                    return outcome;
+                }
+            };
+            // A catalog failing verification is refused, never replaced by the cached copy
+            if let Err(e) = policy.verify(&url, &body, signature.as_deref()) {
+                record_fetch(db, &source.id, Some(&e.to_string()), None).await;
+                return SourceCatalog::refused(e);
+            }
+            let Some(apps) = parse_apps(&body) else {
+                let outcome = unreachable("Catalog is not valid JSON with an apps list".to_string());
+                record_fetch(db, &source.id, outcome.error.as_deref(), None).await;
+                return outcome;
+            };
+
+            let fresh = CachedCatalog { document: body, signature };
+            if let Err(e) = write_cache(cache_dir, &source.id, &fresh).await {
+                tracing::warn!("Failed to cache catalog of source {}: {}", source.name, e);
+            }
+            record_fetch(db, &source.id, None, Some((&etag, &last_modified))).await;
+            SourceCatalog {
+                state: SourceState::Fresh,
+                apps,
+                error: None,
+                refused: None,
+            }
+        }
+    }
+}
+
+/// Manifest URLs relative to their catalog are made absolute, merged apps coming from several sources
+fn resolve_manifest_url(app: &mut serde_json::Value, catalog_url: &str) {
+    let Some(manifest) = app.get("manifest").and_then(|m| m.as_str()) else {
+        return;
+    };
+    if Url::parse(manifest).is_ok() {
+        return;
+    }
+    if let Ok(absolute) = Url::parse(catalog_url).and_then(|base| base.join(manifest)) {
+        app["manifest"] = serde_json::Value::String(absolute.to_string());
+    }
+}
+
+/// Load and merge the catalogs of all enabled sources.
+/// `force` revalidates every source, cached copies are otherwise reused for a while.
+/// Fails only when no source is usable and one of them was refused by signature verification.
+pub async fn load(db: &SqlitePool, cache_dir: &Path, policy: &TrustPolicy, force: bool) -> Result<MergedCatalog, CatalogError> {
+    let offline = is_offline(db).await?;
+    let sources: Vec<CatalogSource> = list_sources(db).await?.into_iter().filter(|s| s.enabled).collect();
+
+    let mut apps = Vec::new();
+    let mut seen = HashSet::new();
+    let mut statuses = Vec::new();
+    let mut refused = None;
+    let mut usable = false;
+
+    for source in &sources {
+        let catalog = load_source(db, cache_dir, policy, source, offline, force).await;
+        usable |= catalog.state != SourceState::Failed;
+        if refused.is_none() {
+            refused = catalog.refused;
+        }
+
+        let url = source_url(source);
+        for mut app in catalog.apps {
+            let Some(id) = app.get("id").and_then(|i| i.as_str()).map(str::to_string) else {
+                continue;
+            };
+            if !seen.insert(id) {
+                continue;
+            }
+            resolve_manifest_url(&mut app, &url);
+            app["source"] = serde_json::Value::String(source.id.clone());
+            apps.push(app);
+        }
+
+        let fetched_at = sqlx::query_scalar::<_, Option<String>>("SELECT fetched_at FROM catalog_sources WHERE id = ?")
+            .bind(&source.id)
+            .fetch_optional(db)
+            .await?
+            .flatten();
+        statuses.push(SourceStatus {
+            id: source.id.clone(),
+            name: source.name.clone(),
+            state: catalog.state,
+            fetched_at,
+            error: catalog.error,
+        });
+    }
+
+    if !usable {
+        if let Some(e) = refused {
+            return Err(e.into());
+        }
+    }
+
+    Ok(MergedCatalog {
+        version: "1.0.0".to_string(),
+        updated: chrono::Utc::now().to_rfc3339(),
+        apps,
+        sources: statuses,
+        offline,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::HeaderMap, routing::get, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    async fn setup_db() -> SqlitePool {
+        let pool = crate::db::test_pool().await;
+        // Tests only use fixture servers, not the public catalog added by the migrations
+        sqlx::query("DELETE FROM catalog_sources").execute(&pool).await.unwrap();
+        package_signing::set_allow_unsigned(&pool, true).await.unwrap();
+        pool
+    }
+
+    /// Fixture server publishing two catalogs, counting full responses of the first one
+    async fn serve(hits: Arc<AtomicUsize>) -> (String, tokio::task::JoinHandle<()>) {
+        let main = move |headers: HeaderMap| {
+            let hits = hits.clone();
+            async move {
+                if headers.get("if-none-match").and_then(|v| v.to_str().ok()) == Some("\"v1\"") {
+                    return (axum::http::StatusCode::NOT_MODIFIED, [("etag", "\"v1\"")], String::new());
+                }
+                hits.fetch_add(1, Ordering::SeqCst);
+                let body = r#"{"apps":[{"id":"notes","version":"1.0.0","manifest":"apps/notes.json"},{"id":"web","version":"2.0.0"}]}"#;
+                (axum::http::StatusCode::OK, [("etag", "\"v1\"")], body.to_string())
+            }
+        };
+        let extra = || async { r#"{"apps":[{"id":"notes","version":"9.0.0"},{"id":"photos","version":"1.0.0"}]}"# };
+        let app = Router::new().route("/main/catalog.json", get(main)).route("/extra/catalog.json", get(extra));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), server)
+    }
+
+    fn input(name: &str, url: String, priority: i64) -> SourceInput {
+        SourceInput {
+            name: name.to_string(),
+            url,
+            priority,
+            enabled: true,
+        }
+    }
+
+    fn app_versions(catalog: &MergedCatalog) -> Vec<(String, String, String)> {
+        catalog
+            .apps
+            .iter()
+            .map(|app| {
+                let field = |name: &str| app[name].as_str().unwrap_or_default().to_string();
+                (field("id"), field("version"), field("source"))
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_merge_sources_by_priority() {
+        let db = setup_db().await;
+        let dir = std::env::temp_dir().join(format!("pinas-catalogs-{}", uuid::Uuid::new_v4()));
+        let (base, _server) = serve(Arc::new(AtomicUsize::new(0))).await;
+        let main = create_source(&db, input("Main", format!("{}/main/catalog.json", base), 10)).await.unwrap();
+        let extra = create_source(&db, input("Extra", format!("{}/extra/catalog.json", base), 0)).await.unwrap();
+        let policy = TrustPolicy::load(&db).await.unwrap();
+
+        let catalog = load(&db, &dir, &policy, false).await.unwrap();
+        assert_eq!(app_versions(&catalog), vec![
+            ("notes".to_string(), "1.0.0".to_string(), main.id.clone()),
+            ("web".to_string(), "2.0.0".to_string(), main.id.clone()),
+            ("photos".to_string(), "1.0.0".to_string(), extra.id.clone()),
+        ]);
+        assert_eq!(catalog.apps[0]["manifest"], format!("{}/main/apps/notes.json", base));
+
+        // Raising the priority of the other source changes which version is offered
+        update_source(&db, &extra.id, SourceUpdate { priority: Some(20), ..Default::default() }).await.unwrap();
+        let catalog = load(&db, &dir, &policy, false).await.unwrap();
+        assert_eq!(app_versions(&catalog)[0], ("notes".to_string(), "9.0.0".to_string(), extra.id.clone()));
+
+        update_source(&db, &extra.id, SourceUpdate { enabled: Some(false), ..Default::default() }).await.unwrap();
+        let catalog = load(&db, &dir, &policy, false).await.unwrap();
+        assert_eq!(catalog.apps.len(), 2);
+        assert_eq!(catalog.sources.len(), 1);
+
+        assert!(matches!(
+            create_source(&db, input("Bad", "file:///etc/passwd".to_string(), 0)).await,
+            Err(CatalogError::Validation(_))
+        ));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_cache_revalidation_and_offline() {
+        let db = setup_db().await;
+        let dir = std::env::temp_dir().join(format!("pinas-catalogs-{}", uuid::Uuid::new_v4()));
+        let hits = Arc::new(AtomicUsize::new(0));
+        let (base, server) = serve(hits.clone()).await;
+        let source = create_source(&db, input("Main", format!("{}/main/catalog.json", base), 0)).await.unwrap();
+        let policy = TrustPolicy::load(&db).await.unwrap();
+
+        let catalog = load(&db, &dir, &policy, false).await.unwrap();
+        assert_eq!(catalog.sources[0].state, SourceState::Fresh);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // Recent enough, the source is not contacted
+        let catalog = load(&db, &dir, &policy, false).await.unwrap();
+        assert_eq!(catalog.sources[0].state, SourceState::Cached);
+
+        // Forced refresh revalidates with the ETag and gets a 304
+        let catalog = load(&db, &dir, &policy, true).await.unwrap();
+        assert_eq!(catalog.sources[0].state, SourceState::Fresh);
+        assert_eq!(catalog.apps.len(), 2);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // Unreachable source, the last good copy is served
+        server.abort();
+        let _ = server.await;
+        let catalog = load(&db, &dir, &policy, true).await.unwrap();
+        assert_eq!(catalog.sources[0].state, SourceState::Stale);
+        assert!(catalog.sources[0].error.is_some());
+        assert_eq!(catalog.apps.len(), 2);
+
+        set_offline(&db, true).await.unwrap();
+        let catalog = load(&db, &dir, &policy, true).await.unwrap();
+        assert!(catalog.offline);
+        assert_eq!(catalog.sources[0].state, SourceState::Stale);
+        assert_eq!(catalog.apps.len(), 2);
+
+        // Cached copies are checked against the current policy
+        package_signing::set_allow_unsigned(&db, false).await.unwrap();
+        let policy = TrustPolicy::load(&db).await.unwrap();
+        assert!(matches!(load(&db, &dir, &policy, false).await, Err(CatalogError::Signing(SigningError::Unsigned(_)))));
+
+        delete_source(&db, &dir, &source.id).await.unwrap();
+        assert!(read_cache(&dir, &source.id).await.is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
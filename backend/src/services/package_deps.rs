@@ -0,0 +1,302 @@
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use semver::VersionReq;
+use thiserror::Error;
+
+use crate::models::manifest::PackageManifest;
+use crate::services::package::parse_version;
+
+/// Dependency resolution errors
+#[derive(Debug, Error)]
+pub enum ResolveError {
+    #[error("Invalid dependency '{0}', expected 'id' or 'id@version requirement'")]
+    InvalidDependency(String),
+
+    #[error("Dependency cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+
+    #[error("{required_by} requires {package} {requirement}, but version {found} is {source_kind}")]
+    Conflict {
+        package: String,
+        requirement: String,
+        found: String,
+        required_by: String,
+        /// "installed" or "available"
+        source_kind: &'static str,
+    },
+
+    #[error("Failed to get manifest of {0}: {1}")]
+    Manifest(String, String),
+}
+
+/// A dependency entry of a manifest, written `id` or `id@requirement` (e.g. `docker@>=24`)
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    pub id: String,
+    pub requirement: Option<VersionReq>,
+}
+
+impl Dependency {
+    pub fn parse(spec: &str) -> Result<Self, ResolveError> {
+        let invalid = || ResolveError::InvalidDependency(spec.to_string());
+        let (id, requirement) = match spec.split_once('@') {
+            Some((id, req)) => (id.trim(), Some(VersionReq::parse(req.trim()).map_err(|_| invalid())?)),
+            None => (spec.trim(), None),
+        };
+        if id.is_empty() || id.contains(char::is_whitespace) {
+            return Err(invalid());
+        }
+        Ok(Self {
+            id: id.to_string(),
+            requirement,
+        })
+    }
+
+    /// Whether a version satisfies the requirement, unparsable versions only satisfy no requirement
+    pub fn accepts(&self, version: &str) -> bool {
+        match &self.requirement {
+            None => true,
+            Some(req) => parse_version(version).is_some_and(|v| req.matches(&v)),
+        }
+    }
+
+    fn conflict(&self, found: &str, required_by: &str, source_kind: &'static str) -> ResolveError {
+        ResolveError::Conflict {
+            package: self.id.clone(),
+            requirement: self.requirement.as_ref().map(ToString::to_string).unwrap_or_default(),
+            found: found.to_string(),
+            required_by: required_by.to_string(),
+            source_kind,
+        }
+    }
+}
+
+/// Parse all dependencies of a manifest
+pub fn dependencies(manifest: &PackageManifest) -> Result<Vec<Dependency>, ResolveError> {
+    manifest.requirements.dependencies.iter().map(|spec| Dependency::parse(spec)).collect()
+}
+
+/// Where manifests of dependencies come from, usually the catalog
+#[async_trait]
+pub trait ManifestSource: Send + Sync {
+    /// The manifest of a package and the URL it was fetched from
+    async fn manifest(&self, package_id: &str) -> anyhow::Result<(PackageManifest, Option<String>)>;
+}
+
+/// A package to install as part of a plan
+#[derive(Debug, Clone)]
+pub struct PlannedPackage {
+    pub manifest: PackageManifest,
+    pub manifest_url: Option<String>,
+}
+
+/// Resolve the packages to install for a manifest, dependencies first and the manifest itself last.
+/// `installed` maps installed package ids to their version, those are never reinstalled.
+pub async fn resolve(
+    root: PlannedPackage,
+    installed: &HashMap<String, String>,
+    source: &dyn ManifestSource,
+) -> Result<Vec<PlannedPackage>, ResolveError> {
+    let mut resolver = Resolver {
+        installed,
+        source,
+        planned: HashMap::new(),
+        visiting: Vec::new(),
+        order: Vec::new(),
+    };
+    resolver.visit(root).await?;
+
+    let Resolver { mut planned, order, .. } = resolver;
+    Ok(order.into_iter().filter_map(|id| planned.remove(&id)).collect())
+}
+
+struct Resolver<'a> {
+    installed: &'a HashMap<String, String>,
+    source: &'a dyn ManifestSource,
+    planned: HashMap<String, PlannedPackage>,
+    /// Current path from the root, for cycle reports
+    visiting: Vec<String>,
+    /// Package ids in installation order
+    order: Vec<String>,
+}
+
+impl Resolver<'_> {
+    /// Depth-first visit, a package is ordered once all its dependencies are
+    fn visit(&mut self, package: PlannedPackage) -> futures_util::future::BoxFuture<'_, Result<(), ResolveError>> {
+        Box::pin(async move {
+            let id = package.manifest.id.clone();
+            self.visiting.push(id.clone());
+            let deps = dependencies(&package.manifest)?;
+            self.planned.insert(id.clone(), package);
+
+            let mut seen = HashSet::new();
+            for dep in deps {
+                if !seen.insert(dep.id.clone()) {
+                    continue;
+                }
+                if let Some(position) = self.visiting.iter().position(|v| *v == dep.id) {
+                    let mut cycle = self.visiting[position..].to_vec();
+                    cycle.push(dep.id.clone());
+                    return Err(ResolveError::Cycle(cycle));
+                }
+                if let Some(version) = self.installed.get(&dep.id) {
+                    if !dep.accepts(version) {
+                        return Err(dep.conflict(version, &id, "installed"));
+                    }
+                    continue;
+                }
+                if let Some(planned) = self.planned.get(&dep.id) {
+                    if !dep.accepts(&planned.manifest.version) {
+                        return Err(dep.conflict(&planned.manifest.version, &id, "available"));
+                    }
+                    continue;
+                }
+
+                let (manifest, manifest_url) = self
+                    .source
+                    .manifest(&dep.id)
+                    .await
+                    .map_err(|e| ResolveError::Manifest(dep.id.clone(), e.to_string()))?;
+                if manifest.id != dep.id {
+                    return Err(ResolveError::Manifest(
+                        dep.id.clone(),
+                        format!("manifest is for package {}", manifest.id),
+                    ));
+                }
+                if !dep.accepts(&manifest.version) {
+                    return Err(dep.conflict(&manifest.version, &id, "available"));
+                }
+                self.visit(PlannedPackage { manifest, manifest_url }).await?;
+            }
+
+            self.visiting.pop();
+            self.order.push(id);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::manifest::{InstallConfig, Requirements, UninstallConfig};
+
+    fn manifest(id: &str, version: &str, deps: &[&str]) -> PackageManifest {
+        PackageManifest {
+            id: id.to_string(),
+            name: id.to_string(),
+            version: version.to_string(),
+            description: HashMap::new(),
+            author: None,
+            license: None,
+            website: None,
+            icon: None,
+            requirements: Requirements {
+                dependencies: deps.iter().map(|d| d.to_string()).collect(),
+                ..Default::default()
+            },
+            install: InstallConfig {
+                install_type: "binary".to_string(),
+                steps: vec![],
+                image: None,
+                container: None,
+            },
+            upgrade: None,
+            uninstall: UninstallConfig::default(),
+            files: HashMap::new(),
+            config: HashMap::new(),
+            frontend: None,
+        }
+    }
+
+    struct Catalog(Vec<PackageManifest>);
+
+    #[async_trait]
+    impl ManifestSource for Catalog {
+        async fn manifest(&self, package_id: &str) -> anyhow::Result<(PackageManifest, Option<String>)> {
+            self.0
+                .iter()
+                .find(|m| m.id == package_id)
+                .map(|m| (m.clone(), Some(format!("https://catalog/{}.json", m.id))))
+                .ok_or_else(|| anyhow::anyhow!("not in catalog"))
+        }
+    }
+
+    fn root(manifest: PackageManifest) -> PlannedPackage {
+        PlannedPackage { manifest, manifest_url: None }
+    }
+
+    async fn plan(app: PackageManifest, catalog: &Catalog, installed: &[(&str, &str)]) -> Result<Vec<String>, ResolveError> {
+        let installed = installed.iter().map(|(id, v)| (id.to_string(), v.to_string())).collect();
+        let planned = resolve(root(app), &installed, catalog).await?;
+        Ok(planned.into_iter().map(|p| p.manifest.id).collect())
+    }
+
+    #[test]
+    fn test_parse_dependency() {
+        let dep = Dependency::parse("docker").unwrap();
+        assert_eq!(dep.id, "docker");
+        assert!(dep.accepts("anything"));
+
+        let dep = Dependency::parse("docker@>=24, <26").unwrap();
+        assert_eq!(dep.id, "docker");
+        assert!(dep.accepts("24.0.7"));
+        assert!(dep.accepts("v25.1"));
+        assert!(!dep.accepts("26.0.0"));
+        assert!(!dep.accepts("latest"));
+
+        for spec in ["", "@1.0", "docker@", "docker@not a version", "my app"] {
+            assert!(Dependency::parse(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_orders_dependencies_first() {
+        let catalog = Catalog(vec![
+            manifest("web", "2.0.0", &["db@^14", "cache"]),
+            manifest("db", "14.2.0", &["runtime"]),
+            manifest("cache", "1.0.0", &["runtime@>=1"]),
+            manifest("runtime", "1.5.0", &[]),
+        ]);
+        let app = manifest("app", "1.0.0", &["web", "db"]);
+
+        let order = plan(app.clone(), &catalog, &[]).await.unwrap();
+        assert_eq!(order, vec!["runtime", "db", "cache", "web", "app"]);
+
+        // Installed packages are not planned again
+        let order = plan(app, &catalog, &[("runtime", "1.6.0"), ("db", "14.0.0")]).await.unwrap();
+        assert_eq!(order, vec!["cache", "web", "app"]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_detects_cycles_and_conflicts() {
+        let catalog = Catalog(vec![
+            manifest("a", "1.0.0", &["b"]),
+            manifest("b", "1.0.0", &["c"]),
+            manifest("c", "1.0.0", &["a"]),
+            manifest("old", "1.0.0", &[]),
+            manifest("needs-new", "1.0.0", &["old@>=2"]),
+        ]);
+
+        match plan(manifest("app", "1.0.0", &["a"]), &catalog, &[]).await {
+            Err(ResolveError::Cycle(cycle)) => assert_eq!(cycle, vec!["a", "b", "c", "a"]),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+
+        // The catalog version is too old
+        let err = plan(manifest("app", "1.0.0", &["needs-new"]), &catalog, &[]).await.unwrap_err();
+        assert!(matches!(err, ResolveError::Conflict { ref package, .. } if package == "old"), "{}", err);
+
+        // Two packages asking for incompatible versions of the same dependency
+        let err = plan(manifest("app", "1.0.0", &["old", "needs-new"]), &catalog, &[]).await.unwrap_err();
+        assert!(matches!(err, ResolveError::Conflict { ref required_by, .. } if required_by == "needs-new"), "{}", err);
+
+        // The installed version is too old, it is not upgraded implicitly
+        let err = plan(manifest("app", "1.0.0", &["old@^2"]), &catalog, &[("old", "1.0.0")]).await.unwrap_err();
+        assert!(matches!(err, ResolveError::Conflict { source_kind: "installed", .. }), "{}", err);
+
+        let err = plan(manifest("app", "1.0.0", &["missing"]), &catalog, &[]).await.unwrap_err();
+        assert!(matches!(err, ResolveError::Manifest(ref id, _) if id == "missing"));
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use sysinfo::System;
+
+use crate::models::manifest::{InstallStep, PackageManifest, PortMapping};
+use crate::models::package::{PreflightCheck, PreflightReport, PreflightStatus};
+use crate::services::docker::DockerService;
+
+const MB: u64 = 1024 * 1024;
+
+/// Host port already taken, and by what
+#[derive(Debug, Clone)]
+pub struct UsedPort {
+    pub port: u16,
+    pub protocol: String,
+    pub owner: String,
+}
+
+/// What the device offers, compared against manifest requirements
+#[derive(Debug, Clone)]
+pub struct HostFacts {
+    pub total_memory_mb: u64,
+    pub available_memory_mb: u64,
+    /// Free space where packages are installed, None when it cannot be read
+    pub free_disk_mb: Option<u64>,
+    pub arch: String,
+    pub used_ports: Vec<UsedPort>,
+}
+
+impl HostFacts {
+    /// Read the facts of this device, `data_dir` being where packages are installed
+    pub async fn gather(data_dir: &Path, docker: &DockerService) -> Self {
+        let mut sys = System::new();
+        sys.refresh_memory();
+
+        let mut used_ports = listening_ports();
+        // Containers publish ports through docker-proxy or iptables, stopped ones claim them too
+        if let Ok(containers) = docker.list_containers(true).await {
+            for container in containers {
+                for port in container.ports {
+                    if let Some(host) = port.host {
+                        used_ports.push(UsedPort {
+                            port: host,
+                            protocol: port.protocol.clone(),
+                            owner: format!("container {}", container.name),
+                        });
+                    }
+                }
+            }
+        }
+
+        Self {
+            total_memory_mb: sys.total_memory() / MB,
+            available_memory_mb: sys.available_memory() / MB,
+            free_disk_mb: free_space_mb(data_dir),
+            arch: std::env::consts::ARCH.to_string(),
+            used_ports,
+        }
+    }
+}
+
+/// Free space of the filesystem holding a path, looking up its closest existing ancestor
+fn free_space_mb(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    let stat = nix::sys::statvfs::statvfs(existing).ok()?;
+    Some(stat.blocks_available() as u64 * stat.fragment_size() as u64 / MB)
+}
+
+/// Ports with a listening socket on this host
+fn listening_ports() -> Vec<UsedPort> {
+    let mut ports = Vec::new();
+    for (file, protocol) in [("tcp", "tcp"), ("tcp6", "tcp"), ("udp", "udp"), ("udp6", "udp")] {
+        if let Ok(content) = std::fs::read_to_string(format!("/proc/net/{}", file)) {
+            for port in parse_proc_net(&content, protocol == "tcp") {
+                ports.push(UsedPort {
+                    port,
+                    protocol: protocol.to_string(),
+                    owner: "a local service".to_string(),
+                });
+            }
+        }
+    }
+    ports
+}
+
+/// Local ports of a /proc/net socket table, only those in LISTEN state for TCP
+fn parse_proc_net(content: &str, listen_only: bool) -> Vec<u16> {
+    const TCP_LISTEN: &str = "0A";
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (local, state) = (fields.get(1)?, fields.get(3)?);
+            if listen_only && *state != TCP_LISTEN {
+                return None;
+            }
+            let (_, port) = local.rsplit_once(':')?;
+            u16::from_str_radix(port, 16).ok()
+        })
+        .collect()
+}
+
+/// Canonical architecture name, manifests may use Docker or Debian names
+fn normalize_arch(arch: &str) -> &str {
+    match arch.to_ascii_lowercase().as_str() {
+        "arm64" | "aarch64" | "armv8" => "aarch64",
+        "amd64" | "x86_64" | "x64" => "x86_64",
+        "arm" | "armhf" | "armv7" | "armv7l" => "arm",
+        "i386" | "i686" | "x86" => "x86",
+        _ => arch,
+    }
+}
+
+/// Host ports a manifest publishes, from its container and its docker_create steps
+fn manifest_ports(manifest: &PackageManifest) -> Vec<&PortMapping> {
+    let mut ports: Vec<&PortMapping> = manifest.install.container.iter().flat_map(|c| &c.ports).collect();
+    for step in &manifest.install.steps {
+        if let InstallStep::DockerCreate { config } = step {
+            ports.extend(&config.ports);
+        }
+    }
+    ports
+}
+
+fn check(check: &str, status: PreflightStatus, required: Option<String>, actual: Option<String>, message: String) -> PreflightCheck {
+    PreflightCheck {
+        check: check.to_string(),
+        status,
+        required,
+        actual,
+        message,
+    }
+}
+
+/// Compare manifest requirements against the device
+pub fn evaluate(manifest: &PackageManifest, facts: &HostFacts) -> PreflightReport {
+    let requirements = &manifest.requirements;
+    let mut checks = Vec::new();
+
+    if let Some(min_ram) = requirements.min_ram {
+        let (status, message) = if facts.total_memory_mb < min_ram {
+            (PreflightStatus::Fail, format!("Needs {} MB of RAM, this device has {} MB", min_ram, facts.total_memory_mb))
+        } else if facts.available_memory_mb < min_ram {
+            (PreflightStatus::Warn, format!("Only {} MB of RAM is free right now, {} MB are needed", facts.available_memory_mb, min_ram))
+        } else {
+            (PreflightStatus::Pass, "Enough memory".to_string())
+        };
+        checks.push(check("memory", status, Some(format!("{} MB", min_ram)), Some(format!("{} MB", facts.total_memory_mb)), message));
+    }
+
+    if let Some(min_disk) = requirements.min_disk {
+        let (status, message, actual) = match facts.free_disk_mb {
+            None => (PreflightStatus::Warn, "Free disk space could not be determined".to_string(), None),
+            Some(free) if free < min_disk => (
+                PreflightStatus::Fail,
+                format!("Needs {} MB of free disk space, {} MB are free", min_disk, free),
+                Some(format!("{} MB", free)),
+            ),
+            Some(free) => (PreflightStatus::Pass, "Enough disk space".to_string(), Some(format!("{} MB", free))),
+        };
+        checks.push(check("disk", status, Some(format!("{} MB", min_disk)), actual, message));
+    }
+
+    if !requirements.arch.is_empty() {
+        let host = normalize_arch(&facts.arch);
+        let supported = requirements.arch.iter().any(|a| normalize_arch(a) == host);
+        let (status, message) = if supported {
+            (PreflightStatus::Pass, "Architecture supported".to_string())
+        } else {
+            (PreflightStatus::Fail, format!("Not available for {}", facts.arch))
+        };
+        checks.push(check("arch", status, Some(requirements.arch.join(", ")), Some(facts.arch.clone()), message));
+    }
+
+    let mut claimed = HashSet::new();
+    for mapping in manifest_ports(manifest) {
+        let required = Some(format!("{}/{}", mapping.host, mapping.protocol));
+        let used = facts
+            .used_ports
+            .iter()
+            .find(|u| u.port == mapping.host && u.protocol.eq_ignore_ascii_case(&mapping.protocol));
+        let (status, actual, message) = if !claimed.insert((mapping.host, mapping.protocol.to_ascii_lowercase())) {
+            (PreflightStatus::Fail, None, format!("Port {}/{} is published twice by the package", mapping.host, mapping.protocol))
+        } else if let Some(used) = used {
+            (
+                PreflightStatus::Fail,
+                Some(used.owner.clone()),
+                format!("Port {}/{} is already used by {}", mapping.host, mapping.protocol, used.owner),
+            )
+        } else {
+            (PreflightStatus::Pass, None, format!("Port {}/{} is free", mapping.host, mapping.protocol))
+        };
+        checks.push(check("port", status, required, actual, message));
+    }
+
+    PreflightReport::new(manifest.id.clone(), checks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::manifest::{ContainerConfig, InstallConfig, Requirements, UninstallConfig};
+    use std::collections::HashMap;
+
+    fn manifest(requirements: Requirements, ports: &[(u16, &str)]) -> PackageManifest {
+        let container = ContainerConfig {
+            name: "app".to_string(),
+            hostname: None,
+            image: Some("app:latest".to_string()),
+            restart: None,
+            network: None,
+            ports: ports
+                .iter()
+                .map(|(port, protocol)| PortMapping {
+                    host: *port,
+                    container: *port,
+                    protocol: protocol.to_string(),
+                })
+                .collect(),
+            volumes: vec![],
+            environment: vec![],
+            devices: vec![],
+            labels: HashMap::new(),
+            privileged: false,
+        };
+        PackageManifest {
+            id: "app".to_string(),
+            name: "App".to_string(),
+            version: "1.0.0".to_string(),
+            description: HashMap::new(),
+            author: None,
+            license: None,
+            website: None,
+            icon: None,
+            requirements,
+            install: InstallConfig {
+                install_type: "docker".to_string(),
+                steps: vec![InstallStep::DockerCreate { config: container }],
+                image: None,
+                container: None,
+            },
+            upgrade: None,
+            uninstall: UninstallConfig::default(),
+            files: HashMap::new(),
+            config: HashMap::new(),
+            frontend: None,
+        }
+    }
+
+    fn facts() -> HostFacts {
+        HostFacts {
+            total_memory_mb: 4096,
+            available_memory_mb: 1024,
+            free_disk_mb: Some(2000),
+            arch: "aarch64".to_string(),
+            used_ports: vec![UsedPort {
+                port: 8080,
+                protocol: "tcp".to_string(),
+                owner: "container web".to_string(),
+            }],
+        }
+    }
+
+    fn statuses(report: &PreflightReport) -> Vec<(&str, PreflightStatus)> {
+        report.checks.iter().map(|c| (c.check.as_str(), c.status)).collect()
+    }
+
+    #[test]
+    fn test_requirements_met() {
+        let requirements = Requirements {
+            min_ram: Some(512),
+            min_disk: Some(1000),
+            arch: vec!["arm64".to_string(), "amd64".to_string()],
+            dependencies: vec![],
+        };
+        let report = evaluate(&manifest(requirements, &[(8080, "udp"), (9000, "tcp")]), &facts());
+        assert!(report.passed);
+        assert_eq!(statuses(&report), vec![
+            ("memory", PreflightStatus::Pass),
+            ("disk", PreflightStatus::Pass),
+            ("arch", PreflightStatus::Pass),
+            ("port", PreflightStatus::Pass),
+            ("port", PreflightStatus::Pass),
+        ]);
+    }
+
+    #[test]
+    fn test_requirements_not_met() {
+        let requirements = Requirements {
+            min_ram: Some(2048),
+            min_disk: Some(5000),
+            arch: vec!["amd64".to_string()],
+            dependencies: vec![],
+        };
+        let report = evaluate(&manifest(requirements, &[(8080, "tcp"), (9000, "tcp"), (9000, "tcp")]), &facts());
+        assert!(!report.passed);
+        assert_eq!(statuses(&report), vec![
+            // Installed memory is enough, only the free memory is short
+            ("memory", PreflightStatus::Warn),
+            ("disk", PreflightStatus::Fail),
+            ("arch", PreflightStatus::Fail),
+            ("port", PreflightStatus::Fail),
+            ("port", PreflightStatus::Pass),
+            ("port", PreflightStatus::Fail),
+        ]);
+        assert!(report.failures().contains(&"Port 8080/tcp is already used by container web"));
+    }
+
+    #[test]
+    fn test_parse_proc_net() {
+        let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+                   \x20  0: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1 1\n\
+                   \x20  1: 0100007F:0050 0100007F:C350 01 00000000:00000000 00:00000000 00000000     0        0 2 1\n";
+        assert_eq!(parse_proc_net(tcp, true), vec![8080]);
+        assert_eq!(parse_proc_net(tcp, false), vec![8080, 80]);
+
+        let tcp6 = "  sl  local_address                         remote_address                        st\n\
+                    \x20  0: 00000000000000000000000000000000:0016 00000000000000000000000000000000:0000 0A\n";
+        assert_eq!(parse_proc_net(tcp6, true), vec![22]);
+    }
+}
@@ -0,0 +1,364 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use thiserror::Error;
+
+use crate::models::package::SigningKey;
+use crate::services::settings;
+
+/// Settings key of the admin opt-in accepting unsigned catalogs and manifests
+const KEY_ALLOW_UNSIGNED: &str = "packages.allow_unsigned";
+
+/// Extension of the detached signature published next to each signed document
+pub const SIGNATURE_SUFFIX: &str = ".sig";
+
+/// Package signing errors
+#[derive(Debug, Error)]
+pub enum SigningError {
+    #[error("{0} is not signed, unsigned catalogs and manifests must be allowed by an administrator")]
+    Unsigned(String),
+
+    #[error("{url} is signed by untrusted key(s) {}", key_ids.join(", "))]
+    UntrustedKey { url: String, key_ids: Vec<String> },
+
+    #[error("Signature of {0} does not match its content")]
+    InvalidSignature(String),
+
+    #[error("Malformed signature file for {0}")]
+    MalformedSignature(String),
+
+    #[error("Invalid public key: {0}")]
+    InvalidKey(String),
+
+    #[error("Signing key not found")]
+    NotFound,
+
+    #[error("Failed to fetch {0}")]
+    Fetch(String),
+
+    #[error("Settings error: {0}")]
+    Settings(#[from] settings::SettingsError),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// Detached signature file, several signatures let publishers sign with old and new keys while rotating
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureFile {
+    pub signatures: Vec<DocumentSignature>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentSignature {
+    pub key_id: String,
+    /// Base64 ed25519 signature of the exact document bytes
+    pub signature: String,
+}
+
+/// New trusted key
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeyInput {
+    pub name: String,
+    /// Base64 of the raw 32-byte ed25519 public key
+    pub public_key: String,
+    /// RFC 3339 date after which the key is no longer trusted
+    #[serde(default)]
+    pub expires_at: Option<String>,
+}
+
+/// Identifier of a public key, the start of its SHA-256 fingerprint
+pub fn key_id(public_key: &[u8]) -> String {
+    hex::encode(&Sha256::digest(public_key)[..8])
+}
+
+/// Keys and settings deciding which documents are accepted
+#[derive(Clone)]
+pub struct TrustPolicy {
+    keys: Vec<(String, VerifyingKey)>,
+    pub allow_unsigned: bool,
+}
+
+impl TrustPolicy {
+    /// Policy from the keys currently trusted, leaving out revoked and expired ones
+    pub async fn load(db: &SqlitePool) -> Result<Self, SigningError> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let rows = sqlx::query_as::<_, SigningKey>(
+            "SELECT * FROM package_signing_keys WHERE revoked_at IS NULL AND (expires_at IS NULL OR expires_at > ?)",
+        )
+        .bind(&now)
+        .fetch_all(db)
+        .await?;
+
+        let mut keys = Vec::new();
+        for row in rows {
+            match decode_key(&row.public_key) {
+                Ok(key) => keys.push((row.id, key)),
+                Err(e) => tracing::warn!("Ignoring signing key {}: {}", row.name, e),
+            }
+        }
+
+        Ok(Self {
+            keys,
+            allow_unsigned: allow_unsigned(db).await?,
+        })
+    }
+
+    /// Check a document against its signature file, returning the ID of the key that signed it.
+    /// A bad signature is refused even when unsigned documents are allowed.
+    pub fn verify(&self, url: &str, document: &[u8], signatures: Option<&[u8]>) -> Result<Option<String>, SigningError> {
+        let Some(signatures) = signatures else {
+            if self.allow_unsigned {
+                tracing::warn!("Accepting unsigned {}", url);
+                return Ok(None);
+            }
+            return Err(SigningError::Unsigned(url.to_string()));
+        };
+
+        let file: SignatureFile = serde_json::from_slice(signatures)
+            .map_err(|_| SigningError::MalformedSignature(url.to_string()))?;
+
+        let mut untrusted = Vec::new();
+        for entry in &file.signatures {
+            let Some((key_id, key)) = self.keys.iter().find(|(id, _)| *id == entry.key_id) else {
+                untrusted.push(entry.key_id.clone());
+                continue;
+            };
+            let signature = STANDARD
+                .decode(&entry.signature)
+                .ok()
+                .and_then(|bytes| Signature::from_slice(&bytes).ok())
+                .ok_or_else(|| SigningError::MalformedSignature(url.to_string()))?;
+            return match key.verify(document, &signature) {
+                Ok(()) => Ok(Some(key_id.clone())),
+                Err(_) => Err(SigningError::InvalidSignature(url.to_string())),
+            };
+        }
+
+        if untrusted.is_empty() {
+            return Err(SigningError::MalformedSignature(url.to_string()));
+        }
+        Err(SigningError::UntrustedKey {
+            url: url.to_string(),
+            key_ids: untrusted,
+        })
+    }
+}
+
+fn decode_key(public_key: &str) -> Result<VerifyingKey, SigningError> {
+    let bytes: [u8; 32] = STANDARD
+        .decode(public_key.trim())
+        .map_err(|_| SigningError::InvalidKey("not base64".to_string()))?
+        .try_into()
+        .map_err(|_| SigningError::InvalidKey("an ed25519 public key is 32 bytes".to_string()))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| SigningError::InvalidKey(e.to_string()))
+}
+
+/// Fetch a document and its detached signature, returning the document once verified
+pub async fn fetch_verified(policy: &TrustPolicy, url: &str) -> Result<Vec<u8>, SigningError> {
+    let document = fetch(url).await?.ok_or_else(|| SigningError::Fetch(format!("{}: HTTP 404", url)))?;
+    let signatures = fetch_signature(url).await?;
+
+    if let Some(key_id) = policy.verify(url, &document, signatures.as_deref())? {
+        tracing::debug!("Verified {} with key {}", url, key_id);
+    }
+    Ok(document)
+}
+
+/// Fetch the detached signature of a document, None when it is unsigned
+pub async fn fetch_signature(url: &str) -> Result<Option<Vec<u8>>, SigningError> {
+    fetch(&format!("{}{}", url, SIGNATURE_SUFFIX)).await
+}
+
+/// GET a URL, None when the server has no such document
+async fn fetch(url: &str) -> Result<Option<Vec<u8>>, SigningError> {
+    let response = reqwest::get(url)
+        .await
+        .map_err(|e| SigningError::Fetch(format!("{}: {}", url, e)))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(SigningError::Fetch(format!("{}: HTTP {}", url, response.status())));
+    }
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| SigningError::Fetch(format!("{}: {}", url, e)))?;
+    Ok(Some(bytes.to_vec()))
+}
+
+/// Whether an administrator allowed unsigned catalogs and manifests
+pub async fn allow_unsigned(db: &SqlitePool) -> Result<bool, SigningError> {
+    Ok(settings::get_setting(db, KEY_ALLOW_UNSIGNED).await?.as_deref() == Some("true"))
+}
+
+pub async fn set_allow_unsigned(db: &SqlitePool, allow: bool) -> Result<(), SigningError> {
+    settings::set_setting(db, KEY_ALLOW_UNSIGNED, if allow { "true" } else { "false" }).await?;
+    Ok(())
+}
+
+/// List trusted keys, revoked ones included
+pub async fn list_keys(db: &SqlitePool) -> Result<Vec<SigningKey>, SigningError> {
+    Ok(sqlx::query_as::<_, SigningKey>("SELECT * FROM package_signing_keys ORDER BY created_at")
+        .fetch_all(db)
+        .await?)
+}
+
+/// Trust a new public key, the key ID is derived from it
+pub async fn add_key(db: &SqlitePool, input: &KeyInput) -> Result<SigningKey, SigningError> {
+    let name = input.name.trim();
+    if name.is_empty() {
+        return Err(SigningError::InvalidKey("a name is required".to_string()));
+    }
+    let key = decode_key(&input.public_key)?;
+    if let Some(expires_at) = &input.expires_at {
+        chrono::DateTime::parse_from_rfc3339(expires_at)
+            .map_err(|_| SigningError::InvalidKey("expires_at must be an RFC 3339 date".to_string()))?;
+    }
+
+    let mut signing_key = SigningKey::new(name.to_string(), key_id(key.as_bytes()), STANDARD.encode(key.as_bytes()));
+    signing_key.expires_at = input.expires_at.clone();
+
+    // Adding a revoked key again trusts it anew
+    sqlx::query(
+        r#"INSERT INTO package_signing_keys (id, name, public_key, created_at, expires_at, revoked_at)
+           VALUES (?, ?, ?, ?, ?, NULL)
+           ON CONFLICT(id) DO UPDATE SET name = excluded.name, expires_at = excluded.expires_at, revoked_at = NULL"#,
+    )
+    .bind(&signing_key.id)
+    .bind(&signing_key.name)
+    .bind(&signing_key.public_key)
+    .bind(&signing_key.created_at)
+    .bind(&signing_key.expires_at)
+    .execute(db)
+    .await?;
+
+    get_key(db, &signing_key.id).await
+}
+
+pub async fn get_key(db: &SqlitePool, id: &str) -> Result<SigningKey, SigningError> {
+    sqlx::query_as::<_, SigningKey>("SELECT * FROM package_signing_keys WHERE id = ?")
+        .bind(id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(SigningError::NotFound)
+}
+
+/// Stop trusting a key, documents it signed are refused from now on
+pub async fn revoke_key(db: &SqlitePool, id: &str) -> Result<SigningKey, SigningError> {
+    // Revoking twice keeps the first revocation date
+    sqlx::query("UPDATE package_signing_keys SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL")
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(id)
+        .execute(db)
+        .await?;
+    get_key(db, id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Signer;
+
+    async fn setup_db() -> SqlitePool {
+        crate::db::test_pool().await
+    }
+
+    fn signer(seed: u8) -> ed25519_dalek::SigningKey {
+        ed25519_dalek::SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn input(name: &str, key: &ed25519_dalek::SigningKey) -> KeyInput {
+        KeyInput {
+            name: name.to_string(),
+            public_key: STANDARD.encode(key.verifying_key().as_bytes()),
+            expires_at: None,
+        }
+    }
+
+    fn sign(document: &[u8], keys: &[&ed25519_dalek::SigningKey]) -> Vec<u8> {
+        let file = SignatureFile {
+            signatures: keys
+                .iter()
+                .map(|key| DocumentSignature {
+                    key_id: key_id(key.verifying_key().as_bytes()),
+                    signature: STANDARD.encode(key.sign(document).to_bytes()),
+                })
+                .collect(),
+        };
+        serde_json::to_vec(&file).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_verify_and_rotate_keys() {
+        let db = setup_db().await;
+        let (old, new) = (signer(1), signer(2));
+        let document = br#"{"apps":[]}"#;
+
+        let key = add_key(&db, &input("Official 2024", &old)).await.unwrap();
+        assert_eq!(key.id, key_id(old.verifying_key().as_bytes()));
+
+        let policy = TrustPolicy::load(&db).await.unwrap();
+        assert_eq!(policy.verify("c", document, Some(&sign(document, &[&old]))).unwrap(), Some(key.id.clone()));
+        assert!(matches!(
+            policy.verify("c", b"{\"apps\":[1]}", Some(&sign(document, &[&old]))),
+            Err(SigningError::InvalidSignature(_))
+        ));
+        assert!(matches!(
+            policy.verify("c", document, Some(&sign(document, &[&new]))),
+            Err(SigningError::UntrustedKey { .. })
+        ));
+        assert!(matches!(policy.verify("c", document, Some(b"nope")), Err(SigningError::MalformedSignature(_))));
+
+        // Rotation: publishers sign with both keys until the old one is revoked
+        let both = sign(document, &[&old, &new]);
+        add_key(&db, &input("Official 2025", &new)).await.unwrap();
+        revoke_key(&db, &key.id).await.unwrap();
+        let policy = TrustPolicy::load(&db).await.unwrap();
+        assert_eq!(
+            policy.verify("c", document, Some(&both)).unwrap(),
+            Some(key_id(new.verifying_key().as_bytes()))
+        );
+        assert!(matches!(
+            policy.verify("c", document, Some(&sign(document, &[&old]))),
+            Err(SigningError::UntrustedKey { .. })
+        ));
+
+        // Expired keys are no longer trusted
+        let mut expired = input("Expired", &signer(3));
+        expired.expires_at = Some("2020-01-01T00:00:00Z".to_string());
+        add_key(&db, &expired).await.unwrap();
+        let policy = TrustPolicy::load(&db).await.unwrap();
+        assert!(policy.verify("c", document, Some(&sign(document, &[&signer(3)]))).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_documents_need_opt_in() {
+        let db = setup_db().await;
+        let policy = TrustPolicy::load(&db).await.unwrap();
+        let err = policy.verify("https://example.com/catalog.json", b"{}", None).unwrap_err();
+        assert!(matches!(err, SigningError::Unsigned(_)));
+        assert!(err.to_string().contains("https://example.com/catalog.json is not signed"));
+
+        set_allow_unsigned(&db, true).await.unwrap();
+        let policy = TrustPolicy::load(&db).await.unwrap();
+        assert_eq!(policy.verify("c", b"{}", None).unwrap(), None);
+        // A signature that does not verify is still refused
+        add_key(&db, &input("Official", &signer(1))).await.unwrap();
+        let policy = TrustPolicy::load(&db).await.unwrap();
+        assert!(policy.verify("c", b"{}", Some(&sign(b"other", &[&signer(1)]))).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_add_key_validation() {
+        let db = setup_db().await;
+        let mut bad = input("Short", &signer(1));
+        bad.public_key = STANDARD.encode([0u8; 16]);
+        assert!(matches!(add_key(&db, &bad).await, Err(SigningError::InvalidKey(_))));
+        assert!(matches!(add_key(&db, &input(" ", &signer(1))).await, Err(SigningError::InvalidKey(_))));
+        assert!(matches!(revoke_key(&db, "missing").await, Err(SigningError::NotFound)));
+    }
+}
@@ -0,0 +1,121 @@
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::watch;
+
+use crate::models::package::PackageTaskLog;
+
+/// Most log lines returned by one request
+pub const MAX_LOG_PAGE: i64 = 1000;
+
+/// Error ending a task that was cancelled, whatever step it stopped at
+#[derive(Debug, Error)]
+#[error("Task cancelled")]
+pub struct TaskCancelled;
+
+/// Whether an error comes from a cancellation
+pub fn is_cancelled(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<TaskCancelled>())
+}
+
+/// Signal telling a running task to stop
+#[derive(Clone)]
+pub struct CancelToken(watch::Receiver<bool>);
+
+impl CancelToken {
+    /// Token of a task nobody can cancel
+    pub fn never() -> Self {
+        let (_, receiver) = watch::channel(false);
+        Self(receiver)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolve once the task is cancelled, never for a token nobody can cancel
+    pub async fn cancelled(&self) {
+        let mut receiver = self.0.clone();
+        if receiver.wait_for(|cancelled| *cancelled).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    /// Fail with `TaskCancelled` once the task is cancelled
+    pub fn check(&self) -> Result<(), TaskCancelled> {
+        if self.is_cancelled() {
+            Err(TaskCancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Package tasks running in the background, by the ID of their top-level task
+#[derive(Default)]
+pub struct PackageTasks {
+    running: Mutex<HashMap<String, watch::Sender<bool>>>,
+}
+
+impl PackageTasks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run a task in the background, it can be cancelled until it returns
+    pub fn spawn<F, Fut>(self: &Arc<Self>, task_id: &str, job: F)
+    where
+        F: FnOnce(CancelToken) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (sender, receiver) = watch::channel(false);
+        self.running.lock().unwrap().insert(task_id.to_string(), sender);
+
+        let job = job(CancelToken(receiver));
+        let tasks = self.clone();
+        let task_id = task_id.to_string();
+        tokio::spawn(async move {
+            job.await;
+            tasks.running.lock().unwrap().remove(&task_id);
+        });
+    }
+
+    /// Ask a running task to stop, false if it is not running
+    pub fn cancel(&self, task_id: &str) -> bool {
+        match self.running.lock().unwrap().get(task_id) {
+            Some(sender) => {
+                sender.send_replace(true);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Tasks cannot survive a restart, fail those left pending or running
+pub async fn fail_interrupted(db: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"UPDATE package_tasks SET status = 'failed', error_message = 'Interrupted by a restart', completed_at = ?
+           WHERE status IN ('pending', 'running')"#,
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Log lines of a task written after a given line, oldest first
+pub async fn list_logs(db: &SqlitePool, task_id: &str, after: i64) -> Result<Vec<PackageTaskLog>, sqlx::Error> {
+    sqlx::query_as::<_, PackageTaskLog>(
+        r#"SELECT id, task_id, step, stream, line, created_at FROM package_task_logs
+           WHERE task_id = ? AND id > ? ORDER BY id LIMIT ?"#,
+    )
+    .bind(task_id)
+    .bind(after)
+    .bind(MAX_LOG_PAGE)
+    .fetch_all(db)
+    .await
+}
@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+use thiserror::Error;
+
+use crate::config::AppConfig;
+
+/// Bundled list of frequently used passwords
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+/// Reasons a password is refused
+#[derive(Debug, Error)]
+pub enum PasswordPolicyError {
+    #[error("Password must be at least {0} characters")]
+    TooShort(usize),
+
+    #[error("Password must mix at least {0} of lowercase, uppercase, digits and symbols")]
+    TooFewClasses(usize),
+
+    #[error("Password is too common")]
+    Common,
+
+    #[error("Password must not contain the username")]
+    ContainsUsername,
+
+    #[error("Password was used recently, choose another one")]
+    Reused,
+}
+
+/// Rules applied whenever a password is set
+#[derive(Debug, Clone, Copy)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    /// Distinct character classes required, out of 4
+    pub min_classes: usize,
+    /// Previous passwords that cannot be reused, the current one included
+    pub history: usize,
+}
+
+impl PasswordPolicy {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            min_length: config.password_min_length as usize,
+            min_classes: (config.password_min_classes as usize).min(4),
+            history: config.password_history as usize,
+        }
+    }
+
+    /// Check a password against the length, character class and common password rules
+    /// Reuse is checked against the stored hashes by the user service
+    pub fn check(&self, username: &str, password: &str) -> Result<(), PasswordPolicyError> {
+        if password.chars().count() < self.min_length {
+            return Err(PasswordPolicyError::TooShort(self.min_length));
+        }
+
+        if character_classes(password) < self.min_classes {
+            return Err(PasswordPolicyError::TooFewClasses(self.min_classes));
+        }
+
+        let lowered = password.to_lowercase();
+        let username = username.trim().to_lowercase();
+        if username.len() >= 3 && lowered.contains(&username) {
+            return Err(PasswordPolicyError::ContainsUsername);
+        }
+
+        if is_common(&lowered) {
+            return Err(PasswordPolicyError::Common);
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of classes among lowercase, uppercase, digits and symbols
+fn character_classes(password: &str) -> usize {
+    let checks: [fn(&char) -> bool; 4] = [
+        |c| c.is_lowercase(),
+        |c| c.is_uppercase(),
+        |c| c.is_ascii_digit(),
+        |c| !c.is_alphanumeric(),
+    ];
+    checks
+        .iter()
+        .filter(|check| password.chars().any(|c| check(&c)))
+        .count()
+}
+
+/// Whether a lowercased password is a common one, also once trailing digits and symbols
+/// are removed (e.g. "Password123!")
+fn is_common(lowered: &str) -> bool {
+    static LIST: OnceLock<HashSet<&'static str>> = OnceLock::new();
+    let list = LIST.get_or_init(|| {
+        COMMON_PASSWORDS
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect()
+    });
+
+    let stem = lowered.trim_end_matches(|c: char| !c.is_alphabetic());
+    list.contains(lowered) || (!stem.is_empty() && list.contains(stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 10,
+            min_classes: 3,
+            history: 5,
+        }
+    }
+
+    #[test]
+    fn test_length_and_classes() {
+        assert!(matches!(policy().check("bob", "Ab1!"), Err(PasswordPolicyError::TooShort(10))));
+        assert!(matches!(
+            policy().check("bob", "alllowercase1"),
+            Err(PasswordPolicyError::TooFewClasses(3))
+        ));
+        assert!(policy().check("bob", "Correct-horse7").is_ok());
+        assert_eq!(character_classes("aB3$"), 4);
+    }
+
+    #[test]
+    fn test_common_passwords_and_username() {
+        assert!(matches!(policy().check("bob", "Password123!"), Err(PasswordPolicyError::Common)));
+        assert!(matches!(policy().check("bob", "Qwerty123456"), Err(PasswordPolicyError::Common)));
+        assert!(matches!(
+            policy().check("alice", "Alice-is-great1"),
+            Err(PasswordPolicyError::ContainsUsername)
+        ));
+        assert!(!is_common("# frequently used passwords"));
+    }
+}
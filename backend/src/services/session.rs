@@ -1,4 +1,6 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
 use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
 use sqlx::SqlitePool;
 use thiserror::Error;
 
@@ -13,73 +15,194 @@ pub enum SessionError {
     #[error("Session expired")]
     Expired,
 
+    #[error("Refresh token was already used, session revoked")]
+    TokenReused,
+
     #[error("Database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
 }
 
-/// Create a new session
+/// Device information recorded with a session
+#[derive(Debug, Clone, Default)]
+pub struct SessionClient {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// User and current role behind an active session
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ActiveSession {
+    pub user_id: String,
+    pub username: String,
+    pub is_admin: bool,
+    pub must_change_password: bool,
+}
+
+/// Create a new session, returns it with its first refresh token
 pub async fn create_session(
     db: &SqlitePool,
     user_id: &str,
-    token: &str,
+    client: &SessionClient,
     expires_at: DateTime<Utc>,
-) -> Result<Session, SessionError> {
+) -> Result<(Session, String), SessionError> {
+    let secret = generate_secret();
     let session = Session::new(
         user_id.to_string(),
-        token.to_string(),
+        hash_secret(&secret),
+        client.ip.clone(),
+        client.user_agent.clone(),
         expires_at.to_rfc3339(),
     );
 
     sqlx::query(
         r#"
-        INSERT INTO sessions (id, user_id, token, expires_at, created_at)
-        VALUES (?, ?, ?, ?, ?)
+        INSERT INTO sessions (id, user_id, refresh_token_hash, ip, user_agent, expires_at, created_at, last_used_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
         "#,
     )
     .bind(&session.id)
     .bind(&session.user_id)
-    .bind(&session.token)
+    .bind(&session.refresh_token_hash)
+    .bind(&session.ip)
+    .bind(&session.user_agent)
     .bind(&session.expires_at)
     .bind(&session.created_at)
+    .bind(&session.last_used_at)
     .execute(db)
     .await?;
 
-    Ok(session)
+    let refresh_token = format_refresh_token(&session.id, &secret);
+    Ok((session, refresh_token))
 }
 
-/// Get a session by token
-pub async fn get_session_by_token(
+/// Exchange a refresh token for a new one, extending the session
+/// Presenting a token that was already rotated revokes the whole session
+pub async fn refresh_session(
     db: &SqlitePool,
-    token: &str,
-) -> Result<Option<Session>, SessionError> {
-    let session = sqlx::query_as::<_, Session>("SELECT * FROM sessions WHERE token = ?")
-        .bind(token)
+    refresh_token: &str,
+    client: &SessionClient,
+    expires_at: DateTime<Utc>,
+) -> Result<(Session, String), SessionError> {
+    let (session_id, secret) = parse_refresh_token(refresh_token).ok_or(SessionError::NotFound)?;
+    let session = get_session(db, session_id).await?.ok_or(SessionError::NotFound)?;
+
+    if is_expired(&session) {
+        delete_session(db, &session.id).await?;
+        return Err(SessionError::Expired);
+    }
+
+    let new_secret = generate_secret();
+    let new_hash = hash_secret(&new_secret);
+    let now = Utc::now().to_rfc3339();
+
+    // Compare-and-swap on the current hash so concurrent refreshes cannot both succeed
+    let result = sqlx::query(
+        r#"UPDATE sessions SET refresh_token_hash = ?, ip = COALESCE(?, ip),
+               user_agent = COALESCE(?, user_agent), expires_at = ?, last_used_at = ?
+           WHERE id = ? AND refresh_token_hash = ?"#,
+    )
+    .bind(&new_hash)
+    .bind(&client.ip)
+    .bind(&client.user_agent)
+    .bind(expires_at.to_rfc3339())
+    .bind(&now)
+    .bind(&session.id)
+    .bind(hash_secret(secret))
+    .execute(db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        // Either the token leaked and was used by someone else, or it was replayed
+        delete_session(db, &session.id).await?;
+        return Err(SessionError::TokenReused);
+    }
+
+    let refresh_token = format_refresh_token(&session.id, &new_secret);
+    let session = get_session(db, &session.id).await?.ok_or(SessionError::NotFound)?;
+    Ok((session, refresh_token))
+}
+
+/// Get a session by id
+pub async fn get_session(db: &SqlitePool, id: &str) -> Result<Option<Session>, SessionError> {
+    let session = sqlx::query_as::<_, Session>("SELECT * FROM sessions WHERE id = ?")
+        .bind(id)
         .fetch_optional(db)
         .await?;
 
     Ok(session)
 }
 
-/// Delete a session by token
-pub async fn delete_session(db: &SqlitePool, token: &str) -> Result<(), SessionError> {
-    sqlx::query("DELETE FROM sessions WHERE token = ?")
-        .bind(token)
+/// Resolve the user of a session that exists and has not expired
+pub async fn get_active_session(
+    db: &SqlitePool,
+    id: &str,
+) -> Result<Option<ActiveSession>, SessionError> {
+    let session = sqlx::query_as::<_, ActiveSession>(
+        r#"SELECT u.id AS user_id, u.username, u.is_admin, u.must_change_password FROM sessions s
+           JOIN users u ON u.id = s.user_id
+           WHERE s.id = ? AND s.expires_at > ?"#,
+    )
+    .bind(id)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_optional(db)
+    .await?;
+
+    Ok(session)
+}
+
+/// List the sessions of a user, most recently used first
+pub async fn list_user_sessions(db: &SqlitePool, user_id: &str) -> Result<Vec<Session>, SessionError> {
+    let sessions = sqlx::query_as::<_, Session>(
+        "SELECT * FROM sessions WHERE user_id = ? AND expires_at > ? ORDER BY last_used_at DESC",
+    )
+    .bind(user_id)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_all(db)
+    .await?;
+
+    Ok(sessions)
+}
+
+/// Delete a session by id
+pub async fn delete_session(db: &SqlitePool, id: &str) -> Result<(), SessionError> {
+    sqlx::query("DELETE FROM sessions WHERE id = ?")
+        .bind(id)
         .execute(db)
         .await?;
 
     Ok(())
 }
 
-/// Delete all sessions for a user
-pub async fn delete_user_sessions(db: &SqlitePool, user_id: &str) -> Result<(), SessionError> {
-    sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+/// Delete a session owned by a user
+pub async fn delete_user_session(db: &SqlitePool, user_id: &str, id: &str) -> Result<(), SessionError> {
+    let result = sqlx::query("DELETE FROM sessions WHERE id = ? AND user_id = ?")
+        .bind(id)
         .bind(user_id)
         .execute(db)
         .await?;
 
+    if result.rows_affected() == 0 {
+        return Err(SessionError::NotFound);
+    }
+
     Ok(())
 }
 
+/// Delete all sessions for a user, except `keep` when given
+pub async fn delete_user_sessions(
+    db: &SqlitePool,
+    user_id: &str,
+    keep: Option<&str>,
+) -> Result<u64, SessionError> {
+    let result = sqlx::query("DELETE FROM sessions WHERE user_id = ? AND id != ?")
+        .bind(user_id)
+        .bind(keep.unwrap_or(""))
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
 /// Cleanup expired sessions
 pub async fn cleanup_expired_sessions(db: &SqlitePool) -> Result<u64, SessionError> {
     let now = Utc::now().to_rfc3339();
@@ -92,20 +215,47 @@ pub async fn cleanup_expired_sessions(db: &SqlitePool) -> Result<u64, SessionErr
     Ok(result.rows_affected())
 }
 
-/// Check if a session is valid (exists and not expired)
-pub async fn is_session_valid(db: &SqlitePool, token: &str) -> Result<bool, SessionError> {
-    let session = get_session_by_token(db, token).await?;
+/// Periodically delete expired sessions
+pub fn spawn_cleanup(db: SqlitePool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            match cleanup_expired_sessions(&db).await {
+                Ok(0) => {}
+                Ok(count) => tracing::debug!("Removed {} expired sessions", count),
+                Err(e) => tracing::error!("Failed to clean up sessions: {}", e),
+            }
+        }
+    });
+}
 
-    match session {
-        Some(s) => {
-            let expires_at = DateTime::parse_from_rfc3339(&s.expires_at)
-                .map(|dt| dt.with_timezone(&Utc))
-                .unwrap_or_else(|_| Utc::now());
+/// Refresh tokens are `<session id>.<secret>`, only the secret hash is stored
+fn format_refresh_token(session_id: &str, secret: &str) -> String {
+    format!("{}.{}", session_id, secret)
+}
 
-            Ok(expires_at > Utc::now())
-        }
-        None => Ok(false),
-    }
+fn parse_refresh_token(token: &str) -> Option<(&str, &str)> {
+    token
+        .split_once('.')
+        .filter(|(id, secret)| !id.is_empty() && !secret.is_empty())
+}
+
+fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Secrets are random, a fast hash is enough
+fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+fn is_expired(session: &Session) -> bool {
+    DateTime::parse_from_rfc3339(&session.expires_at)
+        .map(|dt| dt.with_timezone(&Utc) <= Utc::now())
+        .unwrap_or(true)
 }
 
 #[cfg(test)]
@@ -114,40 +264,34 @@ mod tests {
     use chrono::Duration;
 
     async fn setup_test_db() -> SqlitePool {
-        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
-
-        sqlx::query(
-            r#"
-            CREATE TABLE sessions (
-                id TEXT PRIMARY KEY NOT NULL,
-                user_id TEXT NOT NULL,
-                token TEXT UNIQUE NOT NULL,
-                expires_at TEXT NOT NULL,
-                created_at TEXT NOT NULL
-            )
-            "#,
-        )
-        .execute(&pool)
-        .await
-        .unwrap();
-
+        let pool = crate::db::test_pool().await;
+        crate::db::test_user(&pool, "user-123", "alice").await;
         pool
     }
 
+    fn client() -> SessionClient {
+        SessionClient {
+            ip: Some("10.0.0.1".to_string()),
+            user_agent: Some("test".to_string()),
+        }
+    }
+
     #[tokio::test]
     async fn test_create_and_get_session() {
         let pool = setup_test_db().await;
         let expires_at = Utc::now() + Duration::hours(24);
 
-        let session = create_session(&pool, "user-123", "token-abc", expires_at)
+        let (session, refresh_token) = create_session(&pool, "user-123", &client(), expires_at)
             .await
             .unwrap();
 
         assert_eq!(session.user_id, "user-123");
-        assert_eq!(session.token, "token-abc");
+        assert!(refresh_token.starts_with(&session.id));
+        assert!(!refresh_token.contains(&session.refresh_token_hash));
 
-        let fetched = get_session_by_token(&pool, "token-abc").await.unwrap();
-        assert!(fetched.is_some());
+        let active = get_active_session(&pool, &session.id).await.unwrap().unwrap();
+        assert_eq!(active.username, "alice");
+        assert_eq!(list_user_sessions(&pool, "user-123").await.unwrap().len(), 1);
     }
 
     #[tokio::test]
@@ -155,35 +299,75 @@ mod tests {
         let pool = setup_test_db().await;
         let expires_at = Utc::now() + Duration::hours(24);
 
-        create_session(&pool, "user-123", "token-abc", expires_at)
+        let (session, _) = create_session(&pool, "user-123", &client(), expires_at)
             .await
             .unwrap();
 
-        delete_session(&pool, "token-abc").await.unwrap();
+        assert!(matches!(
+            delete_user_session(&pool, "someone-else", &session.id).await,
+            Err(SessionError::NotFound)
+        ));
+        delete_user_session(&pool, "user-123", &session.id).await.unwrap();
 
-        let fetched = get_session_by_token(&pool, "token-abc").await.unwrap();
-        assert!(fetched.is_none());
+        assert!(get_active_session(&pool, &session.id).await.unwrap().is_none());
     }
 
     #[tokio::test]
-    async fn test_is_session_valid() {
+    async fn test_expired_session_is_inactive() {
         let pool = setup_test_db().await;
 
-        // Valid session
-        let expires_at = Utc::now() + Duration::hours(24);
-        create_session(&pool, "user-123", "valid-token", expires_at)
+        let expired_at = Utc::now() - Duration::hours(1);
+        let (session, refresh_token) = create_session(&pool, "user-123", &client(), expired_at)
             .await
             .unwrap();
-        assert!(is_session_valid(&pool, "valid-token").await.unwrap());
+        assert!(get_active_session(&pool, &session.id).await.unwrap().is_none());
 
-        // Expired session
-        let expired_at = Utc::now() - Duration::hours(1);
-        create_session(&pool, "user-456", "expired-token", expired_at)
+        let expires_at = Utc::now() + Duration::hours(24);
+        assert!(matches!(
+            refresh_session(&pool, &refresh_token, &client(), expires_at).await,
+            Err(SessionError::Expired)
+        ));
+        assert!(get_session(&pool, &session.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rotation_and_reuse_detection() {
+        let pool = setup_test_db().await;
+        let expires_at = Utc::now() + Duration::hours(24);
+
+        let (session, first) = create_session(&pool, "user-123", &client(), expires_at)
+            .await
+            .unwrap();
+        let (rotated, second) = refresh_session(&pool, &first, &SessionClient::default(), expires_at)
             .await
             .unwrap();
-        assert!(!is_session_valid(&pool, "expired-token").await.unwrap());
+        assert_eq!(rotated.id, session.id);
+        assert_ne!(first, second);
+        // Missing client details keep the recorded ones
+        assert_eq!(rotated.ip.as_deref(), Some("10.0.0.1"));
+
+        // Replaying the first token revokes the session, including the latest token
+        assert!(matches!(
+            refresh_session(&pool, &first, &client(), expires_at).await,
+            Err(SessionError::TokenReused)
+        ));
+        assert!(matches!(
+            refresh_session(&pool, &second, &client(), expires_at).await,
+            Err(SessionError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_delete_other_sessions() {
+        let pool = setup_test_db().await;
+        let expires_at = Utc::now() + Duration::hours(24);
+
+        let (current, _) = create_session(&pool, "user-123", &client(), expires_at).await.unwrap();
+        create_session(&pool, "user-123", &client(), expires_at).await.unwrap();
+        create_session(&pool, "user-123", &client(), expires_at).await.unwrap();
 
-        // Non-existent session
-        assert!(!is_session_valid(&pool, "nonexistent").await.unwrap());
+        let removed = delete_user_sessions(&pool, "user-123", Some(&current.id)).await.unwrap();
+        assert_eq!(removed, 2);
+        assert_eq!(list_user_sessions(&pool, "user-123").await.unwrap().len(), 1);
     }
 }
@@ -0,0 +1,498 @@
+use async_trait::async_trait;
+use sqlx::SqlitePool;
+use std::path::Path;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::process::Command;
+
+use crate::models::settings::{DeviceSettings, DeviceSettingsUpdate, Setting};
+
+/// Keys used for device settings in the settings table
+const KEY_HOSTNAME: &str = "device.hostname";
+const KEY_TIMEZONE: &str = "device.timezone";
+const KEY_NTP_ENABLED: &str = "device.ntp_enabled";
+const KEY_NTP_SERVERS: &str = "device.ntp_servers";
+const KEY_LOCALE: &str = "device.locale";
+const KEY_DATE_FORMAT: &str = "device.date_format";
+
+/// UI locales shipped with the frontend
+pub const SUPPORTED_LOCALES: &[&str] = &["en", "fr"];
+
+/// Date formats understood by the frontend
+pub const SUPPORTED_DATE_FORMATS: &[&str] = &["DD/MM/YYYY", "MM/DD/YYYY", "YYYY-MM-DD"];
+
+/// Settings service errors
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error("Invalid value for {field}: {reason}")]
+    Validation { field: &'static str, reason: String },
+
+    #[error("Failed to apply {field}: {reason}")]
+    ApplyFailed { field: &'static str, reason: String },
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// Hooks that push device settings to the operating system
+#[async_trait]
+pub trait SettingsApplier: Send + Sync {
+    async fn apply_hostname(&self, hostname: &str) -> anyhow::Result<()>;
+    async fn apply_timezone(&self, timezone: &str) -> anyhow::Result<()>;
+    async fn apply_ntp(&self, enabled: bool, servers: &[String]) -> anyhow::Result<()>;
+}
+
+/// Applier for systemd based distributions (hostnamectl / timedatectl)
+pub struct SystemdApplier;
+
+#[async_trait]
+impl SettingsApplier for SystemdApplier {
+    async fn apply_hostname(&self, hostname: &str) -> anyhow::Result<()> {
+        run_command("hostnamectl", &["set-hostname", hostname]).await
+    }
+
+    async fn apply_timezone(&self, timezone: &str) -> anyhow::Result<()> {
+        run_command("timedatectl", &["set-timezone", timezone]).await
+    }
+
+    async fn apply_ntp(&self, enabled: bool, servers: &[String]) -> anyhow::Result<()> {
+        let dropin_dir = "/etc/systemd/timesyncd.conf.d";
+        tokio::fs::create_dir_all(dropin_dir).await?;
+        tokio::fs::write(
+            format!("{}/pinas.conf", dropin_dir),
+            format!("[Time]\nNTP={}\n", servers.join(" ")),
+        )
+        .await?;
+
+        run_command("timedatectl", &["set-ntp", if enabled { "true" } else { "false" }]).await?;
+        if enabled {
+            run_command("systemctl", &["restart", "systemd-timesyncd"]).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Applier for LibreELEC, where /etc is read-only and settings live in /storage/.cache
+pub struct LibreElecApplier;
+
+#[async_trait]
+impl SettingsApplier for LibreElecApplier {
+    async fn apply_hostname(&self, hostname: &str) -> anyhow::Result<()> {
+        tokio::fs::write("/storage/.cache/hostname", format!("{}\n", hostname)).await?;
+        run_command("hostname", &[hostname]).await
+    }
+
+    async fn apply_timezone(&self, timezone: &str) -> anyhow::Result<()> {
+        tokio::fs::write("/storage/.cache/timezone", format!("TIMEZONE={}\n", timezone)).await?;
+        run_command("systemctl", &["restart", "tz-data"]).await
+    }
+
+    async fn apply_ntp(&self, enabled: bool, servers: &[String]) -> anyhow::Result<()> {
+        let mut args = vec![
+            "clock",
+            "config",
+            "--timeupdates",
+            if enabled { "auto" } else { "manual" },
+        ];
+        let servers_arg = servers.join(" ");
+        if !servers.is_empty() {
+            args.push("--timeservers");
+            args.push(&servers_arg);
+        }
+        run_command("connmanctl", &args).await
+    }
+}
+
+/// Applier that only logs changes (dev mode)
+pub struct NoopApplier;
+
+#[async_trait]
+impl SettingsApplier for NoopApplier {
+    async fn apply_hostname(&self, hostname: &str) -> anyhow::Result<()> {
+        tracing::info!("Dev mode: skipping hostname change to {}", hostname);
+        Ok(())
+    }
+
+    async fn apply_timezone(&self, timezone: &str) -> anyhow::Result<()> {
+        tracing::info!("Dev mode: skipping timezone change to {}", timezone);
+        Ok(())
+    }
+
+    async fn apply_ntp(&self, enabled: bool, servers: &[String]) -> anyhow::Result<()> {
+        tracing::info!("Dev mode: skipping NTP change (enabled={}, servers={:?})", enabled, servers);
+        Ok(())
+    }
+}
+
+/// Pick the settings applier for the running system
+pub fn detect_applier(dev_mode: bool) -> Arc<dyn SettingsApplier> {
+    if dev_mode {
+        return Arc::new(NoopApplier);
+    }
+
+    let is_libreelec = std::fs::read_to_string("/etc/os-release")
+        .map(|content| content.contains("LibreELEC"))
+        .unwrap_or(false);
+
+    if is_libreelec {
+        Arc::new(LibreElecApplier)
+    } else {
+        Arc::new(SystemdApplier)
+    }
+}
+
+/// Run a system command and fail with its stderr on a non-zero exit
+async fn run_command(program: &str, args: &[&str]) -> anyhow::Result<()> {
+    let output = Command::new(program).args(args).output().await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{} failed: {}", program, stderr.trim());
+    }
+
+    Ok(())
+}
+
+/// Get a raw setting value by key
+pub async fn get_setting(db: &SqlitePool, key: &str) -> Result<Option<String>, SettingsError> {
+    let setting = sqlx::query_as::<_, Setting>("SELECT * FROM settings WHERE key = ?")
+        .bind(key)
+        .fetch_optional(db)
+        .await?;
+
+    Ok(setting.map(|s| s.value))
+}
+
+/// Insert or replace a raw setting value
+pub async fn set_setting(db: &SqlitePool, key: &str, value: &str) -> Result<(), SettingsError> {
+    let now = chrono::Utc::now().to_rfc3339();
+
+    sqlx::query(
+        r#"
+        INSERT INTO settings (key, value, updated_at)
+        VALUES (?, ?, ?)
+        ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(key)
+    .bind(value)
+    .bind(&now)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Get device settings, falling back to defaults for unset keys
+pub async fn get_device_settings(db: &SqlitePool) -> Result<DeviceSettings, SettingsError> {
+    let defaults = DeviceSettings::default();
+
+    let ntp_servers = match get_setting(db, KEY_NTP_SERVERS).await? {
+        Some(value) => serde_json::from_str(&value).unwrap_or(defaults.ntp_servers),
+        None => defaults.ntp_servers,
+    };
+
+    Ok(DeviceSettings {
+        hostname: get_setting(db, KEY_HOSTNAME).await?.unwrap_or(defaults.hostname),
+        timezone: get_setting(db, KEY_TIMEZONE).await?.unwrap_or(defaults.timezone),
+        ntp_enabled: get_setting(db, KEY_NTP_ENABLED)
+            .await?
+            .map(|v| v == "true")
+            .unwrap_or(defaults.ntp_enabled),
+        ntp_servers,
+        locale: get_setting(db, KEY_LOCALE).await?.unwrap_or(defaults.locale),
+        date_format: get_setting(db, KEY_DATE_FORMAT).await?.unwrap_or(defaults.date_format),
+    })
+}
+
+/// Validate, apply to the OS and persist a partial device settings update
+pub async fn update_device_settings(
+    db: &SqlitePool,
+    applier: &dyn SettingsApplier,
+    update: DeviceSettingsUpdate,
+) -> Result<DeviceSettings, SettingsError> {
+    let current = get_device_settings(db).await?;
+
+    let mut updated = current.clone();
+    if let Some(hostname) = update.hostname {
+        updated.hostname = hostname.trim().to_lowercase();
+    }
+    if let Some(timezone) = update.timezone {
+        updated.timezone = timezone.trim().to_string();
+    }
+    if let Some(ntp_enabled) = update.ntp_enabled {
+        updated.ntp_enabled = ntp_enabled;
+    }
+    if let Some(servers) = update.ntp_servers {
+        updated.ntp_servers = servers.into_iter().map(|s| s.trim().to_string()).collect();
+    }
+    if let Some(locale) = update.locale {
+        updated.locale = locale;
+    }
+    if let Some(date_format) = update.date_format {
+        updated.date_format = date_format;
+    }
+
+    validate_device_settings(&updated)?;
+
+    // Each OS-level change is persisted as soon as it is applied, so that a later failure
+    // leaves the stored values matching the system
+    if updated.hostname != current.hostname {
+        applier
+            .apply_hostname(&updated.hostname)
+            .await
+            .map_err(|e| SettingsError::ApplyFailed {
+                field: "hostname",
+                reason: e.to_string(),
+            })?;
+    }
+    set_setting(db, KEY_HOSTNAME, &updated.hostname).await?;
+
+    if updated.timezone != current.timezone {
+        applier
+            .apply_timezone(&updated.timezone)
+            .await
+            .map_err(|e| SettingsError::ApplyFailed {
+                field: "timezone",
+                reason: e.to_string(),
+            })?;
+    }
+    set_setting(db, KEY_TIMEZONE, &updated.timezone).await?;
+
+    if updated.ntp_enabled != current.ntp_enabled || updated.ntp_servers != current.ntp_servers {
+        applier
+            .apply_ntp(updated.ntp_enabled, &updated.ntp_servers)
+            .await
+            .map_err(|e| SettingsError::ApplyFailed {
+                field: "ntp",
+                reason: e.to_string(),
+            })?;
+    }
+    set_setting(db, KEY_NTP_ENABLED, if updated.ntp_enabled { "true" } else { "false" }).await?;
+    set_setting(
+        db,
+        KEY_NTP_SERVERS,
+        &serde_json::to_string(&updated.ntp_servers).unwrap_or_else(|_| "[]".to_string()),
+    )
+    .await?;
+
+    set_setting(db, KEY_LOCALE, &updated.locale).await?;
+    set_setting(db, KEY_DATE_FORMAT, &updated.date_format).await?;
+
+    Ok(updated)
+}
+
+/// Validate a full set of device settings
+fn validate_device_settings(settings: &DeviceSettings) -> Result<(), SettingsError> {
+    if !is_valid_hostname(&settings.hostname) {
+        return Err(SettingsError::Validation {
+            field: "hostname",
+            reason: "must be 1-63 letters, digits or hyphens, not starting or ending with a hyphen"
+                .to_string(),
+        });
+    }
+
+    if !is_valid_timezone(&settings.timezone) {
+        return Err(SettingsError::Validation {
+            field: "timezone",
+            reason: format!("unknown timezone '{}'", settings.timezone),
+        });
+    }
+
+    if settings.ntp_enabled && settings.ntp_servers.is_empty() {
+        return Err(SettingsError::Validation {
+            field: "ntp_servers",
+            reason: "at least one server is required when NTP is enabled".to_string(),
+        });
+    }
+    if let Some(server) = settings.ntp_servers.iter().find(|s| !is_valid_server_name(s)) {
+        return Err(SettingsError::Validation {
+            field: "ntp_servers",
+            reason: format!("invalid server '{}'", server),
+        });
+    }
+
+    if !SUPPORTED_LOCALES.contains(&settings.locale.as_str()) {
+        return Err(SettingsError::Validation {
+            field: "locale",
+            reason: format!("supported locales are {}", SUPPORTED_LOCALES.join(", ")),
+        });
+    }
+
+    if !SUPPORTED_DATE_FORMATS.contains(&settings.date_format.as_str()) {
+        return Err(SettingsError::Validation {
+            field: "date_format",
+            reason: format!("supported formats are {}", SUPPORTED_DATE_FORMATS.join(", ")),
+        });
+    }
+
+    Ok(())
+}
+
+/// Check a hostname is a single valid RFC 1123 label
+fn is_valid_hostname(hostname: &str) -> bool {
+    !hostname.is_empty()
+        && hostname.len() <= 63
+        && !hostname.starts_with('-')
+        && !hostname.ends_with('-')
+        && hostname.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Check a timezone looks like an IANA name and exists in the zoneinfo database if present
+fn is_valid_timezone(timezone: &str) -> bool {
+    if timezone.is_empty()
+        || timezone.contains("..")
+        || timezone.starts_with('/')
+        || !timezone
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '+'))
+    {
+        return false;
+    }
+
+    let zoneinfo = Path::new("/usr/share/zoneinfo");
+    if zoneinfo.is_dir() {
+        zoneinfo.join(timezone).is_file()
+    } else {
+        true
+    }
+}
+
+/// Check an NTP server is a plausible hostname or IP address
+fn is_valid_server_name(server: &str) -> bool {
+    !server.is_empty()
+        && server.len() <= 253
+        && server
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingApplier {
+        calls: Mutex<Vec<String>>,
+        /// Step that fails instead of being recorded
+        failing: Option<&'static str>,
+    }
+
+    impl RecordingApplier {
+        fn record(&self, step: &str, call: String) -> anyhow::Result<()> {
+            if self.failing == Some(step) {
+                anyhow::bail!("{} not applied", step);
+            }
+            self.calls.lock().unwrap().push(call);
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl SettingsApplier for RecordingApplier {
+        async fn apply_hostname(&self, hostname: &str) -> anyhow::Result<()> {
+            self.record("hostname", format!("hostname:{}", hostname))
+        }
+
+        async fn apply_timezone(&self, timezone: &str) -> anyhow::Result<()> {
+            self.record("timezone", format!("timezone:{}", timezone))
+        }
+
+        async fn apply_ntp(&self, enabled: bool, servers: &[String]) -> anyhow::Result<()> {
+            self.record("ntp", format!("ntp:{}:{}", enabled, servers.join(",")))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_defaults_when_unset() {
+        let pool = crate::db::test_pool().await;
+
+        let settings = get_device_settings(&pool).await.unwrap();
+        assert_eq!(settings, DeviceSettings::default());
+    }
+
+    #[tokio::test]
+    async fn test_update_applies_only_changed_fields() {
+        let pool = crate::db::test_pool().await;
+        let applier = RecordingApplier::default();
+
+        let updated = update_device_settings(
+            &pool,
+            &applier,
+            DeviceSettingsUpdate {
+                hostname: Some("My-NAS".to_string()),
+                locale: Some("fr".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(updated.hostname, "my-nas");
+        assert_eq!(updated.locale, "fr");
+        assert_eq!(*applier.calls.lock().unwrap(), vec!["hostname:my-nas".to_string()]);
+
+        let stored = get_device_settings(&pool).await.unwrap();
+        assert_eq!(stored, updated);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_values_are_rejected() {
+        let pool = crate::db::test_pool().await;
+        let applier = RecordingApplier::default();
+
+        let result = update_device_settings(
+            &pool,
+            &applier,
+            DeviceSettingsUpdate {
+                hostname: Some("-bad_host".to_string()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(matches!(result, Err(SettingsError::Validation { field: "hostname", .. })));
+
+        let result = update_device_settings(
+            &pool,
+            &applier,
+            DeviceSettingsUpdate {
+                ntp_servers: Some(vec![]),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(matches!(result, Err(SettingsError::Validation { field: "ntp_servers", .. })));
+
+        assert!(applier.calls.lock().unwrap().is_empty());
+        assert_eq!(get_setting(&pool, KEY_HOSTNAME).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_applied_steps_are_kept_when_a_later_one_fails() {
+        let pool = crate::db::test_pool().await;
+        let applier = RecordingApplier {
+            failing: Some("timezone"),
+            ..Default::default()
+        };
+
+        let result = update_device_settings(
+            &pool,
+            &applier,
+            DeviceSettingsUpdate {
+                hostname: Some("my-nas".to_string()),
+                timezone: Some("Europe/Paris".to_string()),
+                locale: Some("fr".to_string()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(matches!(result, Err(SettingsError::ApplyFailed { field: "timezone", .. })));
+
+        // The hostname was changed on the system, the rest was not
+        let stored = get_device_settings(&pool).await.unwrap();
+        assert_eq!(stored.hostname, "my-nas");
+        assert_eq!(stored.timezone, DeviceSettings::default().timezone);
+        assert_eq!(stored.locale, DeviceSettings::default().locale);
+    }
+}
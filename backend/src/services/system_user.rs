@@ -0,0 +1,222 @@
+use nix::unistd::{getgrouplist, Gid, Uid};
+use sqlx::SqlitePool;
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::services::user::{get_user_by_id, UserError};
+
+/// PATH given to commands run on behalf of a user
+const USER_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+#[derive(Debug, Error)]
+pub enum SystemUserError {
+    #[error("User not found")]
+    UserNotFound,
+
+    #[error("No system account named '{0}'")]
+    NotMapped(String),
+
+    #[error("Commands cannot run as root on behalf of a user")]
+    RootNotAllowed,
+
+    #[error("Failed to look up system account: {0}")]
+    Lookup(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+impl From<UserError> for SystemUserError {
+    fn from(err: UserError) -> Self {
+        match err {
+            UserError::DatabaseError(e) => SystemUserError::DatabaseError(e),
+            _ => SystemUserError::UserNotFound,
+        }
+    }
+}
+
+/// System account a PiNAS user is mapped to
+#[derive(Debug, Clone)]
+pub struct SystemIdentity {
+    pub name: String,
+    pub uid: Uid,
+    pub gid: Gid,
+    /// Supplementary groups, including the primary group
+    pub groups: Vec<Gid>,
+    pub home: PathBuf,
+}
+
+/// Look up a system account with its supplementary groups
+pub fn lookup(name: &str) -> Result<SystemIdentity, SystemUserError> {
+    let account = nix::unistd::User::from_name(name)
+        .map_err(|e| SystemUserError::Lookup(e.to_string()))?
+        .ok_or_else(|| SystemUserError::NotMapped(name.to_string()))?;
+
+    let c_name = CString::new(name).map_err(|e| SystemUserError::Lookup(e.to_string()))?;
+    let groups =
+        getgrouplist(&c_name, account.gid).map_err(|e| SystemUserError::Lookup(e.to_string()))?;
+
+    Ok(SystemIdentity {
+        name: account.name,
+        uid: account.uid,
+        gid: account.gid,
+        groups,
+        home: account.dir,
+    })
+}
+
+/// Resolve the system account of a PiNAS user (`system_user`, or the username)
+pub async fn resolve_for_user(db: &SqlitePool, user_id: &str) -> Result<SystemIdentity, SystemUserError> {
+    let user = get_user_by_id(db, user_id)
+        .await?
+        .ok_or(SystemUserError::UserNotFound)?;
+
+    let name = user.system_user.unwrap_or(user.username);
+    let identity = lookup(&name)?;
+
+    // A mapping to root would defeat the purpose of switching users
+    if identity.uid.is_root() {
+        return Err(SystemUserError::RootNotAllowed);
+    }
+
+    Ok(identity)
+}
+
+/// Directories a user's commands may run in: their home and the shares they are granted
+pub async fn allowed_roots(
+    db: &SqlitePool,
+    user_id: &str,
+    home: Option<&Path>,
+) -> Result<Vec<PathBuf>, SystemUserError> {
+    let share_paths: Vec<(String,)> = sqlx::query_as(
+        r#"SELECT DISTINCT s.path FROM shares s
+           JOIN permissions p ON p.resource_type = 'share' AND p.resource_id = s.id
+           WHERE s.enabled = TRUE AND (
+               (p.principal_type = 'user' AND p.principal_id = ?)
+               OR (p.principal_type = 'group' AND p.principal_id IN (
+                   SELECT group_id FROM user_group_members WHERE user_id = ?
+               ))
+           )
+           ORDER BY s.path"#,
+    )
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    let mut roots: Vec<PathBuf> = home.into_iter().map(Path::to_path_buf).collect();
+    roots.extend(share_paths.into_iter().map(|(path,)| PathBuf::from(path)));
+
+    // Only keep roots that exist, canonicalized for prefix checks
+    Ok(roots
+        .into_iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .collect())
+}
+
+/// Check a path lies within one of the roots
+pub fn is_within_roots(path: &Path, roots: &[PathBuf]) -> bool {
+    match path.canonicalize() {
+        Ok(canonical) => roots.iter().any(|root| canonical.starts_with(root)),
+        Err(_) => false,
+    }
+}
+
+/// Make a command run as the identity, with a minimal environment
+pub fn apply_identity(command: &mut tokio::process::Command, identity: &SystemIdentity) {
+    command
+        .env_clear()
+        .env("PATH", USER_PATH)
+        .env("HOME", &identity.home)
+        .env("USER", &identity.name)
+        .env("LOGNAME", &identity.name);
+
+    let uid = identity.uid;
+    let gid = identity.gid;
+    let groups = identity.groups.clone();
+    // SAFETY: only async-signal-safe calls between fork and exec.
+    // Groups must be dropped before the gid, and the gid before the uid.
+    unsafe {
+        command.pre_exec(move || {
+            nix::unistd::setgroups(&groups)?;
+            nix::unistd::setgid(gid)?;
+            nix::unistd::setuid(uid)?;
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_allowed_roots_from_user_and_group_grants() {
+        let pool = crate::db::test_pool().await;
+        let base = std::env::temp_dir().join(format!("pinas-roots-{}", uuid::Uuid::new_v4()));
+        for dir in ["home", "media", "backups", "private"] {
+            std::fs::create_dir_all(base.join(dir)).unwrap();
+        }
+
+        crate::db::test_user(&pool, "u1", "alice").await;
+        for (id, name) in [("s1", "media"), ("s2", "backups"), ("s3", "private")] {
+            sqlx::query(
+                "INSERT INTO shares (id, name, path, share_type, created_at, updated_at) VALUES (?, ?, ?, 'smb', '', '')",
+            )
+            .bind(id)
+            .bind(name)
+            .bind(base.join(name).to_string_lossy().to_string())
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        // The built-in "users" group
+        let group = "00000000-0000-0000-0000-000000000002";
+        for (id, resource, principal_type, principal, permission) in [
+            ("p1", "s1", "user", "u1", "read"),
+            ("p2", "s2", "group", group, "write"),
+        ] {
+            sqlx::query(
+                r#"INSERT INTO permissions (id, resource_type, resource_id, principal_type, principal_id, permission, created_at)
+                   VALUES (?, 'share', ?, ?, ?, ?, '')"#,
+            )
+            .bind(id)
+            .bind(resource)
+            .bind(principal_type)
+            .bind(principal)
+            .bind(permission)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        sqlx::query("INSERT INTO user_group_members (id, user_id, group_id, created_at) VALUES ('m1', 'u1', ?, '')")
+            .bind(group)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let home = base.join("home");
+        let roots = allowed_roots(&pool, "u1", Some(&home)).await.unwrap();
+        assert_eq!(roots.len(), 3);
+
+        assert!(is_within_roots(&base.join("media"), &roots));
+        assert!(is_within_roots(&base.join("home/../backups"), &roots));
+        assert!(!is_within_roots(&base.join("private"), &roots));
+        assert!(!is_within_roots(&base.join("media/../private"), &roots));
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_lookup_system_account() {
+        let root = lookup("root").unwrap();
+        assert!(root.uid.is_root());
+        assert!(root.groups.contains(&root.gid));
+
+        assert!(matches!(
+            lookup("pinas-no-such-user"),
+            Err(SystemUserError::NotMapped(_))
+        ));
+    }
+}
@@ -0,0 +1,678 @@
+use nix::fcntl::{fcntl, FcntlArg, FdFlag, OFlag};
+use nix::pty::{openpty, Winsize};
+use nix::sys::signal::{killpg, Signal};
+use nix::sys::termios::{tcgetattr, LocalFlags};
+use nix::unistd::Pid;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::{HashMap, VecDeque};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::io::unix::AsyncFd;
+use tokio::process::Command;
+use tokio::sync::{broadcast, watch};
+
+use crate::models::terminal::{TerminalEndReason, TerminalInput, TerminalSessionRecord};
+
+/// Maximum concurrent sessions (tabs) per user
+pub const MAX_SESSIONS_PER_USER: usize = 8;
+
+/// How long a session without any attached client is kept alive
+pub const RECONNECT_GRACE: Duration = Duration::from_secs(60);
+
+/// Output kept to replay when a client reattaches
+const SCROLLBACK_LIMIT: usize = 64 * 1024;
+
+/// Longest input line recorded in the audit log
+const MAX_AUDIT_LINE: usize = 4096;
+
+/// Interval between two checks for idle or abandoned sessions
+const REAPER_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Shell used when the configured one does not exist
+const FALLBACK_SHELL: &str = "/bin/sh";
+
+#[derive(Debug, Error)]
+pub enum TerminalError {
+    #[error("Terminal session not found")]
+    NotFound,
+
+    #[error("Too many open terminal sessions (max {0})")]
+    TooManySessions(usize),
+
+    #[error("Failed to start shell: {0}")]
+    SpawnFailed(String),
+
+    #[error("Terminal I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+impl From<nix::Error> for TerminalError {
+    fn from(err: nix::Error) -> Self {
+        TerminalError::Io(err.into())
+    }
+}
+
+/// Session summary returned to clients
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalSessionInfo {
+    pub id: String,
+    pub shell: String,
+    pub started_at: String,
+    pub attached: bool,
+    pub idle_secs: u64,
+}
+
+/// Mutable bookkeeping of a session
+struct SessionActivity {
+    last_input: Instant,
+    attached: usize,
+    detached_since: Option<Instant>,
+    input: InputRecorder,
+}
+
+/// A running shell attached to a pseudo-terminal
+pub struct TerminalSession {
+    pub id: String,
+    pub user_id: String,
+    pub shell: String,
+    pub started_at: String,
+    db: SqlitePool,
+    master: AsyncFd<OwnedFd>,
+    pgid: Pid,
+    output: broadcast::Sender<Vec<u8>>,
+    scrollback: Mutex<VecDeque<u8>>,
+    activity: Mutex<SessionActivity>,
+    /// Reason given by whoever asked the session to stop
+    end_requested: Mutex<Option<TerminalEndReason>>,
+    /// Set once the shell has exited and the end was recorded
+    ended: watch::Sender<Option<TerminalEndReason>>,
+}
+
+impl TerminalSession {
+    /// Start receiving output, returns the scrollback to replay first
+    pub fn attach(&self) -> (Vec<u8>, broadcast::Receiver<Vec<u8>>, watch::Receiver<Option<TerminalEndReason>>) {
+        // Snapshot and subscribe under the same lock so no output is lost or duplicated
+        let scrollback = self.scrollback.lock().unwrap();
+        let replay: Vec<u8> = scrollback.iter().copied().collect();
+        let output = self.output.subscribe();
+        drop(scrollback);
+
+        let mut activity = self.activity.lock().unwrap();
+        activity.attached += 1;
+        activity.detached_since = None;
+
+        (replay, output, self.ended.subscribe())
+    }
+
+    /// Stop receiving output, the shell keeps running for `RECONNECT_GRACE`
+    pub fn detach(&self) {
+        let mut activity = self.activity.lock().unwrap();
+        activity.attached = activity.attached.saturating_sub(1);
+        if activity.attached == 0 {
+            activity.detached_since = Some(Instant::now());
+        }
+    }
+
+    /// Send keystrokes to the shell and record completed input lines
+    pub async fn write_input(&self, data: &[u8]) -> Result<(), TerminalError> {
+        // Echo is off while a password is read or a full-screen program runs
+        let echo = tcgetattr(self.master.get_ref())
+            .map(|t| t.local_flags.contains(LocalFlags::ECHO))
+            .unwrap_or(false);
+        let lines = {
+            let mut activity = self.activity.lock().unwrap();
+            activity.last_input = Instant::now();
+            activity.input.feed(data, echo)
+        };
+
+        let mut written = 0;
+        while written < data.len() {
+            let mut guard = self.master.writable().await?;
+            match guard.try_io(|fd| {
+                nix::unistd::write(fd.as_raw_fd(), &data[written..]).map_err(std::io::Error::from)
+            }) {
+                Ok(result) => written += result?,
+                Err(_would_block) => continue,
+            }
+        }
+
+        for line in lines {
+            record_input(&self.db, &self.id, &line).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Resize the terminal, the shell receives SIGWINCH
+    pub fn resize(&self, cols: u16, rows: u16) -> Result<(), TerminalError> {
+        let winsize = window_size(cols, rows);
+        // SAFETY: TIOCSWINSZ reads a winsize struct from a valid pointer on an open pty fd
+        let result = unsafe {
+            nix::libc::ioctl(self.master.as_raw_fd(), nix::libc::TIOCSWINSZ, &winsize)
+        };
+        if result < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    /// Ask the shell to stop, the reader task records the end of the session
+    pub fn terminate(&self, reason: TerminalEndReason) {
+        self.end_requested.lock().unwrap().get_or_insert(reason);
+
+        let pgid = self.pgid;
+        let _ = killpg(pgid, Signal::SIGHUP);
+        // Escalate if the shell ignores the hangup
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(3)).await;
+            let _ = killpg(pgid, Signal::SIGKILL);
+        });
+    }
+
+    fn info(&self) -> TerminalSessionInfo {
+        let activity = self.activity.lock().unwrap();
+        TerminalSessionInfo {
+            id: self.id.clone(),
+            shell: self.shell.clone(),
+            started_at: self.started_at.clone(),
+            attached: activity.attached > 0,
+            idle_secs: activity.last_input.elapsed().as_secs(),
+        }
+    }
+
+    fn push_output(&self, data: &[u8]) {
+        let mut scrollback = self.scrollback.lock().unwrap();
+        scrollback.extend(data);
+        let excess = scrollback.len().saturating_sub(SCROLLBACK_LIMIT);
+        scrollback.drain(..excess);
+        // No attached client is fine, the scrollback keeps the output
+        let _ = self.output.send(data.to_vec());
+    }
+}
+
+/// Owns every running terminal session
+pub struct TerminalManager {
+    db: SqlitePool,
+    shell: String,
+    idle_timeout: Option<Duration>,
+    sessions: Arc<Mutex<HashMap<String, Arc<TerminalSession>>>>,
+}
+
+impl TerminalManager {
+    pub fn new(db: SqlitePool, shell: String, idle_timeout_secs: u64) -> Self {
+        Self {
+            db,
+            shell,
+            idle_timeout: (idle_timeout_secs > 0).then(|| Duration::from_secs(idle_timeout_secs)),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Start a new shell for a user in the given directory
+    pub async fn open(
+        &self,
+        user_id: &str,
+        username: &str,
+        cwd: &Path,
+        cols: u16,
+        rows: u16,
+    ) -> Result<Arc<TerminalSession>, TerminalError> {
+        if self.list(user_id).len() >= MAX_SESSIONS_PER_USER {
+            return Err(TerminalError::TooManySessions(MAX_SESSIONS_PER_USER));
+        }
+
+        let shell = if Path::new(&self.shell).exists() {
+            self.shell.clone()
+        } else {
+            tracing::warn!("Terminal shell {} not found, using {}", self.shell, FALLBACK_SHELL);
+            FALLBACK_SHELL.to_string()
+        };
+
+        let pty = openpty(&window_size(cols, rows), None)?;
+        // The master must not leak into the shell, and is polled without blocking
+        fcntl(pty.master.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+        fcntl(pty.master.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+
+        let mut command = Command::new(&shell);
+        command
+            .arg("-l")
+            .current_dir(cwd)
+            .env("TERM", "xterm-256color")
+            .stdin(Stdio::from(pty.slave.try_clone()?))
+            .stdout(Stdio::from(pty.slave.try_clone()?))
+            .stderr(Stdio::from(pty.slave));
+        // SAFETY: only async-signal-safe calls between fork and exec
+        unsafe {
+            command.pre_exec(|| {
+                nix::unistd::setsid()?;
+                if nix::libc::ioctl(0, nix::libc::TIOCSCTTY, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| TerminalError::SpawnFailed(e.to_string()))?;
+        // Close our copies of the slave so reads fail once the shell exits
+        drop(command);
+
+        let pid = child
+            .id()
+            .ok_or_else(|| TerminalError::SpawnFailed("Shell exited immediately".to_string()))?;
+
+        let record = TerminalSessionRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            username: username.to_string(),
+            shell: shell.clone(),
+            cwd: cwd.to_string_lossy().to_string(),
+            started_at: chrono::Utc::now().to_rfc3339(),
+            ended_at: None,
+            end_reason: None,
+        };
+        if let Err(e) = insert_session(&self.db, &record).await {
+            let _ = child.start_kill();
+            return Err(e);
+        }
+
+        let (output, _) = broadcast::channel(256);
+        let (ended, _) = watch::channel(None);
+        let session = Arc::new(TerminalSession {
+            id: record.id.clone(),
+            user_id: record.user_id.clone(),
+            shell,
+            started_at: record.started_at.clone(),
+            db: self.db.clone(),
+            master: AsyncFd::new(pty.master)?,
+            pgid: Pid::from_raw(pid as i32),
+            output,
+            scrollback: Mutex::new(VecDeque::new()),
+            activity: Mutex::new(SessionActivity {
+                last_input: Instant::now(),
+                attached: 0,
+                detached_since: Some(Instant::now()),
+                input: InputRecorder::default(),
+            }),
+            end_requested: Mutex::new(None),
+            ended,
+        });
+
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session.id.clone(), session.clone());
+
+        tracing::info!("Terminal session {} started for {} ({})", session.id, username, session.shell);
+
+        let sessions = self.sessions.clone();
+        let reader = session.clone();
+        tokio::spawn(async move {
+            pump_output(&reader).await;
+            let _ = child.wait().await;
+
+            let reason = reader.end_requested.lock().unwrap().unwrap_or(TerminalEndReason::Exit);
+            sessions.lock().unwrap().remove(&reader.id);
+
+            if let Err(e) = end_session(&reader.db, &reader.id, reason).await {
+                tracing::error!("Failed to record end of terminal session {}: {}", reader.id, e);
+            }
+            reader.ended.send_replace(Some(reason));
+            tracing::info!("Terminal session {} ended ({})", reader.id, reason);
+        });
+
+        Ok(session)
+    }
+
+    /// Get a running session owned by a user
+    pub fn get(&self, id: &str, user_id: &str) -> Result<Arc<TerminalSession>, TerminalError> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(id)
+            .filter(|s| s.user_id == user_id)
+            .cloned()
+            .ok_or(TerminalError::NotFound)
+    }
+
+    /// List running sessions of a user
+    pub fn list(&self, user_id: &str) -> Vec<TerminalSessionInfo> {
+        let mut sessions: Vec<TerminalSessionInfo> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|s| s.user_id == user_id)
+            .map(|s| s.info())
+            .collect();
+        sessions.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        sessions
+    }
+
+    /// Close a session owned by a user
+    pub fn close(&self, id: &str, user_id: &str) -> Result<(), TerminalError> {
+        self.get(id, user_id)?.terminate(TerminalEndReason::Closed);
+        Ok(())
+    }
+
+    /// Periodically close idle sessions and sessions nobody reattached to
+    pub fn spawn_reaper(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REAPER_INTERVAL);
+            loop {
+                interval.tick().await;
+                let sessions: Vec<Arc<TerminalSession>> =
+                    self.sessions.lock().unwrap().values().cloned().collect();
+
+                for session in sessions {
+                    let reason = {
+                        let activity = session.activity.lock().unwrap();
+                        if self.idle_timeout.is_some_and(|t| activity.last_input.elapsed() > t) {
+                            Some(TerminalEndReason::IdleTimeout)
+                        } else if activity.detached_since.is_some_and(|t| t.elapsed() > RECONNECT_GRACE) {
+                            Some(TerminalEndReason::Disconnected)
+                        } else {
+                            None
+                        }
+                    };
+
+                    if let Some(reason) = reason {
+                        tracing::info!("Closing terminal session {} ({})", session.id, reason);
+                        session.terminate(reason);
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Read shell output until the pty is closed
+async fn pump_output(session: &TerminalSession) {
+    let mut buf = [0u8; 8192];
+    loop {
+        let mut guard = match session.master.readable().await {
+            Ok(guard) => guard,
+            Err(_) => return,
+        };
+        match guard.try_io(|fd| {
+            nix::unistd::read(fd.as_raw_fd(), &mut buf).map_err(std::io::Error::from)
+        }) {
+            Ok(Ok(0)) => return,
+            Ok(Ok(n)) => session.push_output(&buf[..n]),
+            // EIO once the shell and its children closed the slave side
+            Ok(Err(_)) => return,
+            Err(_would_block) => continue,
+        }
+    }
+}
+
+fn window_size(cols: u16, rows: u16) -> Winsize {
+    Winsize {
+        ws_row: rows.max(1),
+        ws_col: cols.max(1),
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+/// Splits the raw input stream into lines for the audit log
+/// Nothing is replayed: completion, history recall and cursor moves are kept as
+/// the keys that were pressed (`<Tab>`, `<Up>`, `^R`), so a line is what was typed,
+/// not necessarily what the shell ran
+#[derive(Debug, Default)]
+struct InputRecorder {
+    line: String,
+    escape: EscapeState,
+    /// Part of the current line was typed while the terminal did not echo
+    hidden: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+enum EscapeState {
+    #[default]
+    None,
+    Esc,
+    Sequence(String),
+}
+
+impl InputRecorder {
+    /// Feed raw input and return the completed lines
+    /// Input sent while echo is off (password prompts, full-screen programs) is
+    /// replaced by a marker instead of being recorded
+    fn feed(&mut self, data: &[u8], echo: bool) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for c in String::from_utf8_lossy(data).chars() {
+            if c == '\r' || c == '\n' {
+                self.escape = EscapeState::None;
+                let mut line = std::mem::take(&mut self.line);
+                if std::mem::take(&mut self.hidden) {
+                    line.push_str(NOT_ECHOED);
+                }
+                if !line.is_empty() {
+                    lines.push(line);
+                }
+                continue;
+            }
+
+            if !echo {
+                self.escape = EscapeState::None;
+                self.hidden = true;
+                continue;
+            }
+
+            match std::mem::take(&mut self.escape) {
+                EscapeState::Esc if c == '[' || c == 'O' => {
+                    self.escape = EscapeState::Sequence(String::new());
+                }
+                EscapeState::Esc => self.push(&format!("<Esc>{}", c)),
+                EscapeState::Sequence(mut seq) => {
+                    seq.push(c);
+                    if ('@'..='~').contains(&c) {
+                        self.push(&key_name(&seq));
+                    } else {
+                        self.escape = EscapeState::Sequence(seq);
+                    }
+                }
+                EscapeState::None => match c {
+                    '\x1b' => self.escape = EscapeState::Esc,
+                    '\t' => self.push("<Tab>"),
+                    '\x7f' | '\x08' => self.push("<BS>"),
+                    c if c.is_control() => self.push(&format!("^{}", ((c as u8) ^ 0x40) as char)),
+                    c => self.push(&c.to_string()),
+                },
+            }
+        }
+
+        lines
+    }
+
+    fn push(&mut self, key: &str) {
+        if self.line.len() + key.len() <= MAX_AUDIT_LINE {
+            self.line.push_str(key);
+        }
+    }
+}
+
+/// Marker recorded for input typed while the terminal did not echo
+const NOT_ECHOED: &str = "<not echoed>";
+
+/// Readable name of a CSI/SS3 key sequence, without the leading `ESC [` or `ESC O`
+fn key_name(seq: &str) -> String {
+    match seq {
+        "A" => "<Up>".to_string(),
+        "B" => "<Down>".to_string(),
+        "C" => "<Right>".to_string(),
+        "D" => "<Left>".to_string(),
+        "H" | "1~" => "<Home>".to_string(),
+        "F" | "4~" => "<End>".to_string(),
+        "3~" => "<Del>".to_string(),
+        other => format!("<Esc>[{}", other),
+    }
+}
+
+async fn insert_session(db: &SqlitePool, record: &TerminalSessionRecord) -> Result<(), TerminalError> {
+    sqlx::query(
+        r#"INSERT INTO terminal_sessions (id, user_id, username, shell, cwd, started_at)
+           VALUES (?, ?, ?, ?, ?, ?)"#,
+    )
+    .bind(&record.id)
+    .bind(&record.user_id)
+    .bind(&record.username)
+    .bind(&record.shell)
+    .bind(&record.cwd)
+    .bind(&record.started_at)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+async fn end_session(db: &SqlitePool, id: &str, reason: TerminalEndReason) -> Result<(), TerminalError> {
+    sqlx::query("UPDATE terminal_sessions SET ended_at = ?, end_reason = ? WHERE id = ?")
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(reason.to_string())
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+async fn record_input(db: &SqlitePool, session_id: &str, input: &str) -> Result<(), TerminalError> {
+    sqlx::query("INSERT INTO terminal_input (session_id, input, created_at) VALUES (?, ?, ?)")
+        .bind(session_id)
+        .bind(input)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Mark sessions left open by a previous run as ended
+pub async fn close_stale_sessions(db: &SqlitePool) -> Result<u64, TerminalError> {
+    let result = sqlx::query(
+        "UPDATE terminal_sessions SET ended_at = ?, end_reason = 'shutdown' WHERE ended_at IS NULL",
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// List recorded sessions, newest first
+pub async fn list_session_records(
+    db: &SqlitePool,
+    user_id: Option<&str>,
+    limit: i64,
+) -> Result<Vec<TerminalSessionRecord>, TerminalError> {
+    let records = sqlx::query_as::<_, TerminalSessionRecord>(
+        r#"SELECT id, user_id, username, shell, cwd, started_at, ended_at, end_reason
+           FROM terminal_sessions
+           WHERE (? IS NULL OR user_id = ?)
+           ORDER BY started_at DESC LIMIT ?"#,
+    )
+    .bind(user_id)
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(db)
+    .await?;
+
+    Ok(records)
+}
+
+/// List the input lines recorded for a session
+pub async fn list_session_input(
+    db: &SqlitePool,
+    session_id: &str,
+) -> Result<Vec<TerminalInput>, TerminalError> {
+    let exists: Option<(String,)> = sqlx::query_as("SELECT id FROM terminal_sessions WHERE id = ?")
+        .bind(session_id)
+        .fetch_optional(db)
+        .await?;
+    if exists.is_none() {
+        return Err(TerminalError::NotFound);
+    }
+
+    let input = sqlx::query_as::<_, TerminalInput>(
+        "SELECT id, session_id, input, created_at FROM terminal_input WHERE session_id = ? ORDER BY id",
+    )
+    .bind(session_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_recorder() {
+        let mut recorder = InputRecorder::default();
+        assert_eq!(recorder.feed(b"ls -la\r", true), vec!["ls -la"]);
+        // Editing keys are kept as typed, not replayed
+        assert_eq!(recorder.feed(b"cd /tmpx\x7f\r", true), vec!["cd /tmpx<BS>"]);
+        assert_eq!(recorder.feed(b"\x1b[A\r", true), vec!["<Up>"]);
+        assert_eq!(recorder.feed(b"sys\tstatus\r", true), vec!["sys<Tab>status"]);
+        assert_eq!(recorder.feed(b"\x12ssh\r", true), vec!["^Rssh"]);
+        assert_eq!(recorder.feed(b"rm -rf foo\x03\r", true), vec!["rm -rf foo^C"]);
+        // Lines split across several writes
+        assert!(recorder.feed(b"echo ", true).is_empty());
+        assert_eq!(recorder.feed(b"hi\r", true), vec!["echo hi"]);
+        // Input typed without echo is not recorded
+        assert_eq!(recorder.feed(b"s3cret\r", false), vec![NOT_ECHOED]);
+        assert!(recorder.feed(b"\r", true).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_session_runs_shell_and_records_audit() {
+        let pool = crate::db::test_pool().await;
+        let manager = TerminalManager::new(pool.clone(), "/bin/sh".to_string(), 0);
+        let cwd = std::env::temp_dir();
+
+        let session = manager.open("u1", "admin", &cwd, 80, 24).await.unwrap();
+        let (_, mut output, mut ended) = session.attach();
+        assert_eq!(manager.list("u1").len(), 1);
+        assert!(manager.get(&session.id, "u2").is_err());
+
+        session.resize(120, 40).unwrap();
+        session.write_input(b"echo pinas-$((40+2))\r").await.unwrap();
+
+        let mut received = String::new();
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !received.contains("pinas-42") {
+                let chunk = output.recv().await.unwrap();
+                received.push_str(&String::from_utf8_lossy(&chunk));
+            }
+        })
+        .await
+        .expect("shell output");
+
+        manager.close(&session.id, "u1").unwrap();
+        tokio::time::timeout(Duration::from_secs(10), ended.wait_for(|e| e.is_some()))
+            .await
+            .expect("session end")
+            .unwrap();
+
+        let records = list_session_records(&pool, Some("u1"), 10).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].end_reason.as_deref(), Some("closed"));
+
+        let input = list_session_input(&pool, &session.id).await.unwrap();
+        assert_eq!(input[0].input, "echo pinas-$((40+2))");
+        assert!(manager.list("u1").is_empty());
+    }
+}
@@ -2,7 +2,8 @@ use sqlx::SqlitePool;
 use thiserror::Error;
 
 use crate::models::user::User;
-use crate::services::auth::{hash_password, AuthError};
+use crate::services::auth::{hash_password, verify_password, AuthError};
+use crate::services::password_policy::{PasswordPolicy, PasswordPolicyError};
 
 /// User service errors
 #[derive(Debug, Error)]
@@ -19,6 +20,12 @@ pub enum UserError {
     #[error("Cannot delete the last admin")]
     CannotDeleteLastAdmin,
 
+    #[error("{0}")]
+    WeakPassword(#[from] PasswordPolicyError),
+
+    #[error("Password is managed by the LDAP directory or the identity provider")]
+    ExternalAccount,
+
     #[error("Database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
 
@@ -32,15 +39,17 @@ pub struct UserUpdate {
     pub email: Option<Option<String>>,
     pub description: Option<Option<String>>,
     pub is_admin: Option<bool>,
+    pub system_user: Option<Option<String>>,
 }
 
-/// Create a new user
+/// Create a new user, the password must satisfy the policy
 pub async fn create_user(
     db: &SqlitePool,
     username: &str,
     password: &str,
     email: Option<String>,
     is_admin: bool,
+    policy: &PasswordPolicy,
 ) -> Result<User, UserError> {
     // Check if username already exists
     let existing = get_user_by_username(db, username).await?;
@@ -48,6 +57,8 @@ pub async fn create_user(
         return Err(UserError::DuplicateUsername);
     }
 
+    policy.check(username, password)?;
+
     // Hash the password
     let password_hash = hash_password(password)?;
 
@@ -70,6 +81,8 @@ pub async fn create_user(
     .execute(db)
     .await?;
 
+    remember_password(db, &user.id, &user.password_hash, policy.history).await?;
+
     Ok(user)
 }
 
@@ -120,16 +133,18 @@ pub async fn update_user(
     // updates.email: None = don't change, Some(None) = set to null, Some(Some(v)) = set to v
     let email = updates.email.unwrap_or_else(|| existing.email.clone());
     let is_admin = updates.is_admin.unwrap_or(existing.is_admin);
+    let system_user = updates.system_user.unwrap_or_else(|| existing.system_user.clone());
 
     sqlx::query(
         r#"
         UPDATE users
-        SET email = ?, is_admin = ?, updated_at = ?
+        SET email = ?, is_admin = ?, system_user = ?, updated_at = ?
         WHERE id = ?
         "#,
     )
     .bind(&email)
     .bind(is_admin)
+    .bind(&system_user)
     .bind(&now)
     .bind(id)
     .execute(db)
@@ -171,26 +186,103 @@ pub async fn delete_user(
     Ok(())
 }
 
-/// Change a user's password
+/// Change a user's password, the new one must satisfy the policy and not be a recent one
+/// `must_change` forces another change at next sign-in, e.g. after an admin reset
 pub async fn change_password(
     db: &SqlitePool,
     id: &str,
     new_password: &str,
+    must_change: bool,
+    policy: &PasswordPolicy,
 ) -> Result<(), UserError> {
     // Check if user exists
-    let _ = get_user_by_id(db, id).await?.ok_or(UserError::NotFound)?;
+    let user = get_user_by_id(db, id).await?.ok_or(UserError::NotFound)?;
+    if !user.has_local_password() {
+        return Err(UserError::ExternalAccount);
+    }
+
+    policy.check(&user.username, new_password)?;
+    if policy.history > 0 {
+        let mut recent = recent_password_hashes(db, id, policy.history).await?;
+        recent.push(user.password_hash);
+        for hash in &recent {
+            if verify_password(new_password, hash)? {
+                return Err(PasswordPolicyError::Reused.into());
+            }
+        }
+    }
 
     // Hash the new password
     let password_hash = hash_password(new_password)?;
     let now = chrono::Utc::now().to_rfc3339();
 
-    sqlx::query("UPDATE users SET password_hash = ?, updated_at = ? WHERE id = ?")
+    sqlx::query("UPDATE users SET password_hash = ?, must_change_password = ?, updated_at = ? WHERE id = ?")
         .bind(&password_hash)
+        .bind(must_change)
         .bind(&now)
         .bind(id)
         .execute(db)
         .await?;
 
+    remember_password(db, id, &password_hash, policy.history).await?;
+
+    Ok(())
+}
+
+/// Require the user to change their password at next sign-in, or clear the requirement
+pub async fn set_must_change_password(db: &SqlitePool, id: &str, value: bool) -> Result<(), UserError> {
+    let result = sqlx::query("UPDATE users SET must_change_password = ?, updated_at = ? WHERE id = ?")
+        .bind(value)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(UserError::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Hashes of the last passwords of a user, newest first
+async fn recent_password_hashes(db: &SqlitePool, user_id: &str, limit: usize) -> Result<Vec<String>, UserError> {
+    let hashes: Vec<(String,)> = sqlx::query_as(
+        "SELECT password_hash FROM password_history WHERE user_id = ? ORDER BY id DESC LIMIT ?",
+    )
+    .bind(user_id)
+    .bind(limit as i64)
+    .fetch_all(db)
+    .await?;
+
+    Ok(hashes.into_iter().map(|(hash,)| hash).collect())
+}
+
+/// Keep a password hash in the history, only the last `keep` are retained
+async fn remember_password(db: &SqlitePool, user_id: &str, password_hash: &str, keep: usize) -> Result<(), UserError> {
+    if keep > 0 {
+        sqlx::query("INSERT INTO password_history (user_id, password_hash, created_at) VALUES (?, ?, ?)")
+            .bind(user_id)
+            .bind(password_hash)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(db)
+            .await?;
+    }
+
+    sqlx::query(
+        r#"
+        DELETE FROM password_history
+        WHERE user_id = ? AND id NOT IN (
+            SELECT id FROM password_history WHERE user_id = ? ORDER BY id DESC LIMIT ?
+        )
+        "#,
+    )
+    .bind(user_id)
+    .bind(user_id)
+    .bind(keep as i64)
+    .execute(db)
+    .await?;
+
     Ok(())
 }
 
@@ -222,34 +314,19 @@ pub async fn has_any_users(db: &SqlitePool) -> Result<bool, UserError> {
 mod tests {
     use super::*;
 
-    async fn setup_test_db() -> SqlitePool {
-        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
-
-        sqlx::query(
-            r#"
-            CREATE TABLE users (
-                id TEXT PRIMARY KEY NOT NULL,
-                username TEXT UNIQUE NOT NULL,
-                password_hash TEXT NOT NULL,
-                email TEXT,
-                is_admin BOOLEAN NOT NULL DEFAULT FALSE,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
-            )
-            "#,
-        )
-        .execute(&pool)
-        .await
-        .unwrap();
-
-        pool
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            min_classes: 3,
+            history: 2,
+        }
     }
 
     #[tokio::test]
     async fn test_create_and_get_user() {
-        let pool = setup_test_db().await;
+        let pool = crate::db::test_pool().await;
 
-        let user = create_user(&pool, "testuser", "password123", Some("test@example.com".to_string()), false)
+        let user = create_user(&pool, "testuser", "Blue-Lantern7", Some("test@example.com".to_string()), false, &policy())
             .await
             .unwrap();
 
@@ -264,24 +341,44 @@ mod tests {
 
     #[tokio::test]
     async fn test_duplicate_username() {
-        let pool = setup_test_db().await;
+        let pool = crate::db::test_pool().await;
 
-        create_user(&pool, "testuser", "password123", None, false)
+        create_user(&pool, "testuser", "Blue-Lantern7", None, false, &policy())
             .await
             .unwrap();
 
-        let result = create_user(&pool, "testuser", "password456", None, false).await;
+        let result = create_user(&pool, "testuser", "Red-Lantern8", None, false, &policy()).await;
         assert!(matches!(result, Err(UserError::DuplicateUsername)));
     }
 
     #[tokio::test]
     async fn test_list_users() {
-        let pool = setup_test_db().await;
+        let pool = crate::db::test_pool().await;
 
-        create_user(&pool, "user1", "pass1", None, false).await.unwrap();
-        create_user(&pool, "user2", "pass2", None, true).await.unwrap();
+        create_user(&pool, "user1", "Blue-Lantern7", None, false, &policy()).await.unwrap();
+        create_user(&pool, "user2", "Red-Lantern8", None, true, &policy()).await.unwrap();
 
         let users = list_users(&pool).await.unwrap();
         assert_eq!(users.len(), 2);
     }
+
+    #[tokio::test]
+    async fn test_password_policy_and_reuse() {
+        let pool = crate::db::test_pool().await;
+
+        let result = create_user(&pool, "user1", "Password123!", None, false, &policy()).await;
+        assert!(matches!(result, Err(UserError::WeakPassword(PasswordPolicyError::Common))));
+
+        let user = create_user(&pool, "user1", "Blue-Lantern7", None, false, &policy()).await.unwrap();
+        change_password(&pool, &user.id, "Red-Lantern8", true, &policy()).await.unwrap();
+        assert!(get_user_by_id(&pool, &user.id).await.unwrap().unwrap().must_change_password);
+
+        let result = change_password(&pool, &user.id, "Blue-Lantern7", false, &policy()).await;
+        assert!(matches!(result, Err(UserError::WeakPassword(PasswordPolicyError::Reused))));
+
+        // Only the last two passwords are remembered
+        change_password(&pool, &user.id, "Green-Lantern9", false, &policy()).await.unwrap();
+        change_password(&pool, &user.id, "Blue-Lantern7", false, &policy()).await.unwrap();
+        assert!(!get_user_by_id(&pool, &user.id).await.unwrap().unwrap().must_change_password);
+    }
 }
@@ -136,7 +136,8 @@ export default {
 			guest: 'Guest'
 		},
 		badges: {
-			system: 'System'
+			system: 'System',
+			ldap: 'LDAP'
 		},
 		statuses: {
 			normal: 'Normal',
@@ -170,7 +171,13 @@ export default {
 			changePassword: 'Change password',
 			newPassword: 'New Password',
 			groupName: 'Group Name',
-			selectMembers: 'Select Members'
+			selectMembers: 'Select Members',
+			homeDirectory: 'Home directory'
+		},
+		homeRemoval: {
+			archive: 'Archive, then delete',
+			delete: 'Delete',
+			keep: 'Keep'
 		},
 		permissions: {
 			title: 'Permissions',
@@ -338,7 +345,8 @@ export default {
 		sidebar: {
 			personalFolder: 'Personal folder',
 			sharedFolder: 'Shared folder',
-			userFolder: 'User folder'
+			userFolder: 'User folder',
+			myFiles: 'My Files'
 		},
 		toolbar: {
 			refresh: 'Refresh',
@@ -390,6 +398,26 @@ export default {
 		version: 'Version',
 		description: 'Description',
 		features: 'Features',
+		updates: 'Updates',
+		updatesAvailable: '{count} update(s) available',
+		catalogUntrusted: 'The app catalog failed signature verification',
+		uninstallDependents: '{name} is required by {dependents}. Uninstall them as well?',
+		uninstallKeepData: 'Keep the data of {name}? Cancel removes it along with the app.',
+		taskCancelled: 'Cancelled',
+		verify: {
+			title: 'Integrity',
+			intact: 'All files are as installed',
+			missing: 'Missing',
+			modified: 'Modified',
+			extra: 'Not from the app',
+			missingImage: 'Missing image',
+			missingContainer: 'Missing container'
+		},
+		preflight: {
+			title: 'Requirements',
+			checking: 'Checking requirements...',
+			failed: 'This app cannot be installed on this device'
+		},
 		categories: {
 			all: 'All',
 			containers: 'Containers',
@@ -406,9 +434,15 @@ export default {
 		actions: {
 			install: 'Install',
 			installing: 'Installing...',
+			installFromFile: 'Install from file',
+			cancel: 'Cancel',
 			uninstall: 'Uninstall',
 			open: 'Open',
-			update: 'Update'
+			update: 'Update',
+			updating: 'Updating...',
+			verify: 'Verify',
+			repair: 'Repair',
+			updateAll: 'Update all'
 		},
 		packages: {
 			docker: {
@@ -136,7 +136,8 @@ export default {
 			guest: 'Invité'
 		},
 		badges: {
-			system: 'Système'
+			system: 'Système',
+			ldap: 'LDAP'
 		},
 		statuses: {
 			normal: 'Normal',
@@ -170,7 +171,13 @@ export default {
 			changePassword: 'Changer le mot de passe',
 			newPassword: 'Nouveau mot de passe',
 			groupName: 'Nom du groupe',
-			selectMembers: 'Sélectionner les membres'
+			selectMembers: 'Sélectionner les membres',
+			homeDirectory: 'Dossier personnel'
+		},
+		homeRemoval: {
+			archive: 'Archiver puis supprimer',
+			delete: 'Supprimer',
+			keep: 'Conserver'
 		},
 		permissions: {
 			title: 'Permissions',
@@ -338,7 +345,8 @@ export default {
 		sidebar: {
 			personalFolder: 'Dossier personnel',
 			sharedFolder: 'Dossier partagé',
-			userFolder: 'Dossier utilisateur'
+			userFolder: 'Dossier utilisateur',
+			myFiles: 'Mes fichiers'
 		},
 		toolbar: {
 			refresh: 'Actualiser',
@@ -390,6 +398,26 @@ export default {
 		version: 'Version',
 		description: 'Description',
 		features: 'Fonctionnalités',
+		updates: 'Mises à jour',
+		updatesAvailable: '{count} mise(s) à jour disponible(s)',
+		catalogUntrusted: 'Le catalogue d\'applications n\'a pas passé la vérification de signature',
+		uninstallDependents: '{name} est requis par {dependents}. Les désinstaller également ?',
+		uninstallKeepData: 'Conserver les données de {name} ? Annuler les supprime avec l\'application.',
+		taskCancelled: 'Annulé',
+		verify: {
+			title: 'Intégrité',
+			intact: 'Tous les fichiers sont tels qu\'installés',
+			missing: 'Manquant',
+			modified: 'Modifié',
+			extra: 'Hors application',
+			missingImage: 'Image manquante',
+			missingContainer: 'Conteneur manquant'
+		},
+		preflight: {
+			title: 'Prérequis',
+			checking: 'Vérification des prérequis...',
+			failed: 'Cette application ne peut pas être installée sur cet appareil'
+		},
 		categories: {
 			all: 'Toutes',
 			containers: 'Conteneurs',
@@ -406,9 +434,15 @@ export default {
 		actions: {
 			install: 'Installer',
 			installing: 'Installation...',
+			installFromFile: 'Installer depuis un fichier',
+			cancel: 'Annuler',
 			uninstall: 'Désinstaller',
 			open: 'Ouvrir',
-			update: 'Mettre à jour'
+			update: 'Mettre à jour',
+			updating: 'Mise à jour...',
+			verify: 'Vérifier',
+			repair: 'Réparer',
+			updateAll: 'Tout mettre à jour'
 		},
 		packages: {
 			docker: {
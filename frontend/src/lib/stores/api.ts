@@ -2,6 +2,9 @@ import { writable } from 'svelte/store';
 
 const API_BASE = '/api';
 
+// Endpoints where a 401 means bad credentials rather than an expired access token
+const UNAUTHENTICATED_ENDPOINTS = ['/auth/login', '/auth/refresh', '/auth/logout', '/auth/mfa/verify'];
+
 // Auth state
 interface AuthState {
 	isAuthenticated: boolean;
@@ -9,10 +12,40 @@ interface AuthState {
 		id: string;
 		username: string;
 		role: string;
+		// Set by an admin, the password must be changed before anything else
+		mustChangePassword?: boolean;
 	} | null;
 	token: string | null;
 }
 
+interface TokenPair {
+	token: string;
+	refresh_token: string;
+	expires_in: number;
+}
+
+interface LoginResponse extends TokenPair {
+	user: {
+		id: string;
+		username: string;
+		email: string | null;
+		is_admin: boolean;
+		must_change_password: boolean;
+	};
+}
+
+// Returned by login when a second factor is needed
+export interface OidcLoginOption {
+	id: string;
+	name: string;
+}
+
+export interface MfaChallenge {
+	mfa_required: boolean;
+	mfa_enrolment_required: boolean;
+	mfa_token: string;
+}
+
 export const auth = writable<AuthState>({
 	isAuthenticated: false,
 	user: null,
@@ -37,6 +70,8 @@ if (typeof window !== 'undefined') {
 class ApiClient {
 	private baseUrl: string;
 	private token: string | null = null;
+	// Shared so concurrent 401s trigger a single rotation
+	private refreshing: Promise<boolean> | null = null;
 
 	constructor(baseUrl: string) {
 		this.baseUrl = baseUrl;
@@ -50,7 +85,8 @@ class ApiClient {
 	private async request<T>(
 		method: string,
 		endpoint: string,
-		data?: unknown
+		data?: unknown,
+		retried = false
 	): Promise<T> {
 		const url = `${this.baseUrl}${endpoint}`;
 
@@ -75,15 +111,21 @@ class ApiClient {
 		const response = await fetch(url, options);
 
 		if (!response.ok) {
-			if (response.status === 401) {
-				// Token expired or invalid
-				this.logout();
+			if (response.status === 401 && !UNAUTHENTICATED_ENDPOINTS.includes(endpoint)) {
+				// Access token expired: renew it once, then sign out if that fails
+				if (!retried && (await this.refreshTokens())) {
+					return this.request<T>(method, endpoint, data, true);
+				}
+				this.clearSession();
 			}
 
 			const error = await response.json().catch(() => ({ message: 'Request failed' }));
 			throw new Error(error.message || `HTTP ${response.status}`);
 		}
 
+		if (response.status === 204) {
+			return null as T;
+		}
 		return response.json();
 	}
 
@@ -108,19 +150,110 @@ class ApiClient {
 	}
 
 	// Auth methods
-	async login(username: string, password: string): Promise<void> {
-		const response = await this.post<{
-			token: string;
-			user: { id: string; username: string; email: string | null; is_admin: boolean };
-		}>('/auth/login', { username, password });
+	// Returns a pending two-factor challenge instead of signing in when a code is needed
+	async login(username: string, password: string): Promise<MfaChallenge | null> {
+		const response = await this.post<LoginResponse | MfaChallenge>('/auth/login', {
+			username,
+			password
+		});
+
+		if ('mfa_token' in response) {
+			return response;
+		}
+
+		this.setSession(response);
+		return null;
+	}
+
+	// Identity providers offered on the login page
+	async getOidcProviders(): Promise<OidcLoginOption[]> {
+		return this.get('/auth/oidc');
+	}
+
+	// Page the browser is sent to for single sign-on
+	oidcAuthorizeUrl(providerId: string): string {
+		return `${this.baseUrl}/auth/oidc/${encodeURIComponent(providerId)}/authorize`;
+	}
+
+	// Exchange the ticket handed back by the single sign-on callback, like login
+	async loginWithOidcTicket(ticket: string): Promise<MfaChallenge | null> {
+		const response = await this.post<LoginResponse | MfaChallenge>('/auth/oidc/exchange', {
+			ticket
+		});
+
+		if ('mfa_token' in response) {
+			return response;
+		}
+
+		this.setSession(response);
+		return null;
+	}
+
+	// Second login step with a TOTP or recovery code
+	async verifyMfa(mfaToken: string, code: string): Promise<void> {
+		const response = await this.post<LoginResponse>('/auth/mfa/verify', {
+			mfa_token: mfaToken,
+			code
+		});
+		this.setSession(response);
+	}
+
+	// Enrolment forced at login by a group requiring two-factor authentication
+	async enrollMfa(mfaToken: string): Promise<{ secret: string; otpauth_uri: string }> {
+		return this.post('/auth/mfa/enroll', { mfa_token: mfaToken });
+	}
+
+	async confirmMfaEnrolment(mfaToken: string, code: string): Promise<string[]> {
+		const response = await this.post<LoginResponse & { recovery_codes: string[] }>(
+			'/auth/mfa/enroll/confirm',
+			{ mfa_token: mfaToken, code }
+		);
+		this.setSession(response);
+		return response.recovery_codes;
+	}
+
+	// Exchange the refresh token for a new token pair
+	async refreshTokens(): Promise<boolean> {
+		if (!this.refreshing) {
+			this.refreshing = this.doRefresh().finally(() => {
+				this.refreshing = null;
+			});
+		}
+		return this.refreshing;
+	}
+
+	private async doRefresh(): Promise<boolean> {
+		const refreshToken = localStorage.getItem('refresh_token');
+		if (!refreshToken) {
+			return false;
+		}
+
+		const response = await fetch(`${this.baseUrl}/auth/refresh`, {
+			method: 'POST',
+			headers: { 'Content-Type': 'application/json' },
+			body: JSON.stringify({ refresh_token: refreshToken })
+		});
+		if (!response.ok) {
+			return false;
+		}
+
+		const tokens: TokenPair = await response.json();
+		localStorage.setItem('token', tokens.token);
+		localStorage.setItem('refresh_token', tokens.refresh_token);
+		auth.update((state) => ({ ...state, token: tokens.token }));
+		return true;
+	}
 
+	private setSession(response: LoginResponse): void {
 		const user = {
 			id: response.user.id,
 			username: response.user.username,
-			role: response.user.is_admin ? 'admin' : 'user'
+			role: response.user.is_admin ? 'admin' : 'user',
+			mustChangePassword: response.user.must_change_password
 		};
 
 		localStorage.setItem('token', response.token);
+		localStorage.setItem('refresh_token', response.refresh_token);
 		localStorage.setItem('user', JSON.stringify(user));
 
 		auth.set({
@@ -133,13 +266,20 @@ class ApiClient {
 	async logout(): Promise<void> {
 		// Call backend logout endpoint to invalidate session
 		try {
-			await this.post('/auth/logout');
+			await this.post('/auth/logout', {
+				refresh_token: localStorage.getItem('refresh_token')
+			});
 		} catch (e) {
 			// Ignore errors - we'll clear local state anyway
 			console.warn('Logout API call failed:', e);
 		}
 
+		this.clearSession();
+	}
+
+	private clearSession(): void {
 		localStorage.removeItem('token');
+		localStorage.removeItem('refresh_token');
 		localStorage.removeItem('user');
 
 		auth.set({
@@ -218,12 +358,20 @@ class ApiClient {
 		}>>('/users');
 	}
 
-	async createUser(user: { username: string; password: string; email?: string; is_admin?: boolean }) {
+	async createUser(user: {
+		username: string;
+		password: string;
+		email?: string;
+		is_admin?: boolean;
+		must_change_password?: boolean;
+	}) {
 		return this.post('/users', user);
 	}
 
-	async deleteUser(id: string) {
-		return this.delete(`/users/${id}`);
+	// The user's home directory is archived unless asked otherwise
+	// A warning is returned when the user was deleted but not their home directory
+	async deleteUser(id: string, home: 'archive' | 'delete' | 'keep' = 'archive') {
+		return this.delete<{ warning?: string } | null>(`/users/${id}?home=${home}`);
 	}
 
 	async updateUser(id: string, data: { email?: string; is_admin?: boolean }) {
@@ -239,19 +387,27 @@ class ApiClient {
 		machine_name: string;
 		admin_username: string;
 		admin_password: string;
-	}): Promise<{
-		token: string;
-		user: { id: string; username: string; is_admin: boolean };
-	}> {
+	}): Promise<
+		TokenPair & {
+			user: { id: string; username: string; is_admin: boolean };
+		}
+	> {
 		return this.post('/setup/complete', data);
 	}
 
 	// Auth - change password
 	async changePassword(currentPassword: string, newPassword: string): Promise<void> {
-		return this.post('/auth/change-password', {
+		await this.post('/auth/change-password', {
 			current_password: currentPassword,
 			new_password: newPassword
 		});
+
+		auth.update((state) => {
+			if (!state.user?.mustChangePassword) return state;
+			const user = { ...state.user, mustChangePassword: false };
+			localStorage.setItem('user', JSON.stringify(user));
+			return { ...state, user };
+		});
 	}
 
 	// Get current user profile
@@ -270,6 +426,7 @@ class ApiClient {
 		name: string;
 		description: string | null;
 		is_system: boolean;
+		external_source: string | null;
 		member_count: number;
 		created_at: string;
 		updated_at: string;
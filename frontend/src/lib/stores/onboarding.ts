@@ -115,6 +115,7 @@ function createOnboardingStore() {
 
 				// Auto-login with the returned token
 				localStorage.setItem('token', response.token);
+				localStorage.setItem('refresh_token', response.refresh_token);
 				localStorage.setItem(
 					'user',
 					JSON.stringify({
@@ -1,4 +1,5 @@
 import { systemStats } from './system';
+import { api } from './api';
 
 let ws: WebSocket | null = null;
 let reconnectTimeout: ReturnType<typeof setTimeout> | null = null;
@@ -14,6 +15,11 @@ export function connectWebSocket(): () => void {
 
 			ws.onopen = () => {
 				console.log('[WS] Connected to server');
+				// Authenticate with the JWT before any event is delivered
+				const token = localStorage.getItem('token');
+				if (token) {
+					ws?.send(JSON.stringify({ type: 'auth', token }));
+				}
 				if (reconnectTimeout) {
 					clearTimeout(reconnectTimeout);
 					reconnectTimeout = null;
@@ -54,17 +60,28 @@ export function connectWebSocket(): () => void {
 
 	function handleMessage(data: any) {
 		switch (data.type) {
-			case 'system_stats':
+			case 'authenticated':
+			case 'subscribed':
+			case 'pong':
+				break;
+			case 'error':
+				console.warn('[WS] Server error:', data.code, data.error);
+				if (data.code === 'UNAUTHORIZED') {
+					// Access token expired, renew it before the next reconnect
+					api.refreshTokens();
+				}
+				break;
+			case 'system.stats':
 				systemStats.set({
-					cpuUsage: data.cpu_usage,
-					memoryUsage: data.memory_usage,
-					memoryUsed: data.memory_used,
-					memoryTotal: data.memory_total
+					cpuUsage: data.data.cpu_usage,
+					memoryUsage: data.data.memory_usage,
+					memoryUsed: data.data.memory_used,
+					memoryTotal: data.data.memory_total
 				});
 				break;
 			case 'notification':
 				// Handle notifications
-				console.log('[WS] Notification:', data.message);
+				console.log('[WS] Notification:', data.data.title);
 				break;
 			default:
 				console.log('[WS] Unknown message type:', data.type);